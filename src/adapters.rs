@@ -1,15 +1,91 @@
+pub mod androidbackup;
+pub mod apk;
+pub mod ar;
+pub mod audiotags;
+pub mod avro;
+pub mod cab;
+pub mod cbz;
+pub mod chm;
+pub mod cpio;
 pub mod custom;
+pub mod deb;
 pub mod decompress;
+pub mod decrypt;
+pub mod dicom;
+pub mod djvu;
+pub mod dmg;
+pub mod docx;
+pub mod dxf;
+pub mod eml;
+pub mod encoding;
+pub mod epub;
+pub mod evtx;
+pub mod executable;
+pub mod exif;
+pub mod fb2;
 pub mod ffmpeg;
+pub mod fits;
 pub mod fns;
+pub mod gis;
+pub mod git;
+pub mod gitobject;
+pub mod gps;
+pub mod gron;
+pub mod har;
+pub mod hdf5;
+pub mod html;
+pub mod hwp;
+pub mod iosbackup;
+pub mod iso;
+pub mod javaclass;
+pub mod journal;
+pub mod leveldb;
+pub mod lmdb;
+pub mod maildir;
+pub mod man;
+pub mod mdb;
+pub mod msg;
+pub mod msi;
+pub mod netcdf;
+pub mod onenote;
+pub mod opendocument;
+pub mod parquet;
+pub mod pcap;
+pub mod pdf;
+pub mod plist;
 //pub mod pdfpages;
 pub mod poppler;
+pub mod postscript;
+pub mod pptx;
+pub mod protobuf;
+pub mod psd;
+pub mod pst;
+pub mod pyc;
+pub mod rar;
+pub mod rpm;
+pub mod rtf;
+pub mod serialized;
 pub mod spawning;
 pub mod sqlite;
-//pub mod tar;
-//pub mod tesseract;
+pub mod squashfs;
+pub mod svg;
+pub mod tar;
+pub mod tesseract;
+pub mod torrent;
+pub mod vmdisk;
+pub mod vsdx;
+pub mod warc;
+pub mod wasm;
+pub mod wheel;
+pub mod whisper;
+pub mod wordperfect;
 pub mod writing;
-// pub mod zip;
+pub mod x509;
+pub mod xlsb;
+pub mod xlsx;
+pub mod xml;
+pub mod xps;
+pub mod zip;
 use crate::matching::*;
 use crate::preproc::PreprocConfig;
 use anyhow::*;
@@ -41,6 +117,25 @@ pub struct AdapterMeta {
     pub slow_matchers: Option<Vec<SlowMatcher>>,
     // if true, adapter is only used when user lists it in `--rga-adapters`
     pub disabled_by_default: bool,
+    /// if true, the output of this adapter is never cached, e.g. because it is so cheap to compute that caching only wastes space
+    pub skip_cache: bool,
+    /// the options that change the output of this adapter. they are part of its cache keys, see preproc_cache::cache_key
+    pub options: Vec<AdapterOption>,
+}
+
+/// an option of RgaConfig that changes the output of an adapter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdapterOption {
+    PdfOcr,
+    PdfForms,
+    WhisperModel,
+    ArchivePassword,
+    DecryptPassphraseCommand,
+    AgeIdentity,
+    ProtoDescriptor,
+    ProtoMessage,
+    HtmlLinks,
+    DocxRevisions,
 }
 impl AdapterMeta {
     // todo: this is pretty ugly
@@ -96,12 +191,88 @@ pub fn get_all_adapters(custom_adapters: Option<Vec<CustomAdapterConfig>>) -> Ad
 
     let internal_adapters: Vec<Rc<dyn FileAdapter>> = vec![
         Rc::new(ffmpeg::FFmpegAdapter::new()),
-        //Rc::new(zip::ZipAdapter::new()),
+        Rc::new(audiotags::AudioTagsAdapter::new()),
+        Rc::new(apk::ApkAdapter::new()),
+        Rc::new(androidbackup::AndroidBackupAdapter::new()),
+        Rc::new(iosbackup::IosBackupAdapter::new()),
+        Rc::new(wheel::WheelAdapter::new()),
+        Rc::new(zip::ZipAdapter::new()),
         Rc::new(decompress::DecompressAdapter::new()),
-        // Rc::new(tar::TarAdapter::new()),
+        Rc::new(decrypt::DecryptAdapter::new()),
+        Rc::new(rar::RarAdapter::new()),
+        Rc::new(deb::DebAdapter::new()),
+        Rc::new(ar::ArAdapter::new()),
+        Rc::new(rpm::RpmAdapter::new()),
+        Rc::new(cpio::CpioAdapter::new()),
+        Rc::new(msi::MsiAdapter::new()),
+        Rc::new(cab::CabAdapter::new()),
+        Rc::new(cbz::CbzAdapter::new()),
+        Rc::new(dmg::DmgAdapter::new()),
+        Rc::new(iso::IsoAdapter::new()),
+        Rc::new(vmdisk::VmDiskAdapter::new()),
+        Rc::new(squashfs::SquashfsAdapter::new()),
+        Rc::new(git::GitAdapter::new()),
+        Rc::new(gitobject::GitObjectAdapter::new()),
+        Rc::new(docx::DocxAdapter::new()),
+        Rc::new(pptx::PptxAdapter::new()),
+        Rc::new(opendocument::OpenDocumentAdapter::new()),
+        Rc::new(onenote::OneNoteAdapter::new()),
+        Rc::new(xps::XpsAdapter::new()),
+        Rc::new(xlsb::XlsbAdapter::new()),
+        Rc::new(xlsx::XlsxAdapter::new()),
+        Rc::new(vsdx::VsdxAdapter::new()),
+        Rc::new(dxf::DxfAdapter::new()),
+        Rc::new(eml::EmlAdapter::new()),
+        Rc::new(maildir::MaildirAdapter::new()),
+        Rc::new(pst::PstAdapter::new()),
+        Rc::new(msg::MsgAdapter::new()),
+        Rc::new(parquet::ParquetAdapter::new()),
+        Rc::new(hdf5::Hdf5Adapter::new()),
+        Rc::new(netcdf::NetCdfAdapter::new()),
+        Rc::new(fits::FitsAdapter::new()),
+        Rc::new(pcap::PcapAdapter::new()),
+        Rc::new(avro::AvroAdapter::new()),
+        Rc::new(protobuf::ProtobufAdapter::new()),
+        Rc::new(serialized::SerializedAdapter::new()),
+        Rc::new(plist::PlistAdapter::new()),
+        Rc::new(x509::X509Adapter::new()),
+        Rc::new(exif::ExifAdapter::new()),
+        Rc::new(dicom::DicomAdapter::new()),
+        Rc::new(psd::PsdAdapter::new()),
+        Rc::new(torrent::TorrentAdapter::new()),
+        Rc::new(gron::GronAdapter::new()),
+        Rc::new(xml::XmlAdapter::new()),
+        Rc::new(html::HtmlAdapter::new()),
+        Rc::new(svg::SvgAdapter::new()),
+        Rc::new(gps::GpsAdapter::new()),
+        Rc::new(chm::ChmAdapter::new()),
+        Rc::new(warc::WarcAdapter::new()),
+        Rc::new(har::HarAdapter::new()),
+        Rc::new(evtx::EvtxAdapter::new()),
+        Rc::new(journal::JournalAdapter::new()),
+        Rc::new(rtf::RtfAdapter::new()),
+        Rc::new(wordperfect::WordPerfectAdapter::new()),
+        Rc::new(hwp::HwpAdapter::new()),
+        Rc::new(man::ManAdapter::new()),
+        Rc::new(epub::EpubAdapter::new()),
+        Rc::new(fb2::Fb2Adapter::new()),
+        Rc::new(tar::TarAdapter::new()),
+        Rc::new(gis::GisAdapter::new()),
         Rc::new(sqlite::SqliteAdapter::new()),
+        Rc::new(leveldb::LevelDbAdapter::new()),
+        Rc::new(lmdb::LmdbAdapter::new()),
+        Rc::new(mdb::MdbAdapter::new()),
+        Rc::new(executable::ExecutableAdapter::new()),
+        Rc::new(wasm::WasmAdapter::new()),
+        Rc::new(pyc::PycAdapter::new()),
+        Rc::new(javaclass::JavaClassAdapter::new()),
+        Rc::new(djvu::DjvuAdapter::new()),
+        Rc::new(postscript::PostScriptAdapter::new()),
+        Rc::new(poppler::PopplerAdapter::new()),
         // Rc::new(pdfpages::PdfPagesAdapter::new()),
-        //Rc::new(tesseract::TesseractAdapter::new()),
+        Rc::new(tesseract::TesseractAdapter::new()),
+        Rc::new(whisper::WhisperAdapter::new()),
+        Rc::new(encoding::EncodingAdapter::new()),
     ];
     adapters.extend(
         builtin_spawning_adapters
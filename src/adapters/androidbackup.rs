@@ -0,0 +1,145 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::io::{BufRead, BufReader};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["ab"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "androidbackup".to_owned(),
+        version: 1,
+        description: "Reads Android backups created with `adb backup` and recurses into the files of the contained tar (apps/<package>/...). Encrypted backups are skipped".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct AndroidBackupAdapter;
+
+impl AndroidBackupAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(AndroidBackupAdapter))
+    }
+}
+impl GetMetadata for AndroidBackupAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn header_line(inp: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    inp.read_line(&mut line)?;
+    Ok(line.trim_end().to_string())
+}
+
+impl WritingFileAdapterTrait for AndroidBackupAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let AdaptInfo {
+            filepath_hint,
+            inp,
+            line_prefix,
+            archive_recursion_depth,
+            config,
+            ..
+        } = ai;
+        // the header is `ANDROID BACKUP`, the format version, whether the tar is compressed
+        // and the encryption, each on its own line
+        let mut inp = BufReader::new(inp);
+        if header_line(&mut inp)? != "ANDROID BACKUP" {
+            bail!("not an android backup");
+        }
+        let _version = header_line(&mut inp)?;
+        let compressed = header_line(&mut inp)? == "1";
+        let encryption = header_line(&mut inp)?;
+        if encryption != "none" {
+            writeln!(
+                oup,
+                "{}[rga: skipping android backup with {} encryption]",
+                line_prefix, encryption
+            )?;
+            return Ok(());
+        }
+        let data: ReadBox = if compressed {
+            Box::new(flate2::read::ZlibDecoder::new(inp))
+        } else {
+            Box::new(inp)
+        };
+        tar::adapt_entries(
+            data,
+            &filepath_hint,
+            &line_prefix,
+            archive_recursion_depth,
+            &config,
+            oup,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn adapt(data: Vec<u8>) -> Result<String> {
+        let (mut a, d) = simple_adapt_info(Path::new("backup.ab"), Box::new(Cursor::new(data)));
+        // so that the shared preferences in the backup are flattened
+        a.config.args.adapters = vec!["+xml".to_string()];
+        let mut r = AndroidBackupAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn android_backup() -> Result<()> {
+        let mut builder = ::tar::Builder::new(Vec::new());
+        let content = b"<map><string name=\"username\">alice</string></map>\n";
+        let mut header = ::tar::Header::new_ustar();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o600);
+        header.set_cksum();
+        builder.append_data(
+            &mut header,
+            "apps/com.example.app/sp/prefs.xml",
+            &content[..],
+        )?;
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(&builder.into_inner()?)?;
+        let mut data = b"ANDROID BACKUP\n5\n1\nnone\n".to_vec();
+        data.extend(zlib.finish()?);
+
+        assert_eq!(
+            adapt(data)?,
+            "PREFIX:apps/com.example.app/sp/prefs.xml: /map/string/@name: username
+PREFIX:apps/com.example.app/sp/prefs.xml: /map/string: alice
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted() -> Result<()> {
+        let data = b"ANDROID BACKUP\n5\n1\nAES-256\n0123ABCD\n".to_vec();
+        assert_eq!(
+            adapt(data)?,
+            "PREFIX:[rga: skipping android backup with AES-256 encryption]\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,510 @@
+use super::xml::write_flat_xml;
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use quick_xml::escape::escape;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["apk"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "apk".to_owned(),
+        version: 1,
+        description: "Reads Android apps. Decodes the binary xml files (AndroidManifest.xml, layouts), writes the string tables of the resources and the dex files, and recurses into the other files".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/vnd.android.package-archive".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct ApkAdapter;
+
+impl ApkAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(ApkAdapter))
+    }
+}
+impl GetMetadata for ApkAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_XML_TYPE: u16 = 0x0003;
+const RES_XML_START_NAMESPACE_TYPE: u16 = 0x0100;
+const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+const RES_XML_END_ELEMENT_TYPE: u16 = 0x0103;
+const RES_XML_CDATA_TYPE: u16 = 0x0104;
+const RES_TABLE_TYPE: u16 = 0x0002;
+const NO_ENTRY: u32 = 0xffff_ffff;
+
+fn u16_at(data: &[u8], pos: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(
+        data.get(pos..pos + 2)
+            .context("truncated chunk")?
+            .try_into()?,
+    ))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(
+        data.get(pos..pos + 4)
+            .context("truncated chunk")?
+            .try_into()?,
+    ))
+}
+
+/// the chunks of a resource file: (type, header size, chunk data)
+fn chunks(data: &[u8]) -> Result<Vec<(u16, usize, &[u8])>> {
+    let mut chunks = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = u32_at(data, pos + 4)? as usize;
+        let chunk = data
+            .get(pos..pos + size)
+            .filter(|_| size >= 8)
+            .context("invalid chunk size")?;
+        chunks.push((u16_at(chunk, 0)?, u16_at(chunk, 2)? as usize, chunk));
+        pos += size;
+    }
+    Ok(chunks)
+}
+
+/// the length of a pool string is stored in one or two units, the high bit marks the long form
+fn pool_length(data: &[u8], pos: &mut usize, utf8: bool) -> Result<usize> {
+    if utf8 {
+        let first = *data.get(*pos).context("truncated string")? as usize;
+        *pos += 1;
+        if first & 0x80 == 0 {
+            return Ok(first);
+        }
+        let second = *data.get(*pos).context("truncated string")? as usize;
+        *pos += 1;
+        Ok((first & 0x7f) << 8 | second)
+    } else {
+        let first = u16_at(data, *pos)? as usize;
+        *pos += 2;
+        if first & 0x8000 == 0 {
+            return Ok(first);
+        }
+        let second = u16_at(data, *pos)? as usize;
+        *pos += 2;
+        Ok((first & 0x7fff) << 16 | second)
+    }
+}
+
+fn string_pool(chunk: &[u8]) -> Result<Vec<String>> {
+    let count = u32_at(chunk, 8)? as usize;
+    let utf8 = u32_at(chunk, 16)? & 0x100 != 0;
+    let strings_start = u32_at(chunk, 20)? as usize;
+    let header_size = u16_at(chunk, 2)? as usize;
+    let mut strings = Vec::with_capacity(count.min(chunk.len() / 4));
+    for i in 0..count {
+        let mut pos = strings_start + u32_at(chunk, header_size + i * 4)? as usize;
+        let string = if utf8 {
+            // the utf16 length comes first, but we only need the byte length
+            pool_length(chunk, &mut pos, true)?;
+            let len = pool_length(chunk, &mut pos, true)?;
+            String::from_utf8_lossy(chunk.get(pos..pos + len).context("truncated string")?)
+                .into_owned()
+        } else {
+            let len = pool_length(chunk, &mut pos, false)?;
+            let units = chunk
+                .get(pos..pos + len * 2)
+                .context("truncated string")?
+                .chunks(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]));
+            std::char::decode_utf16(units)
+                .map(|c| c.unwrap_or(std::char::REPLACEMENT_CHARACTER))
+                .collect()
+        };
+        strings.push(string);
+    }
+    Ok(strings)
+}
+
+/// the value of an attribute as aapt would print it
+fn typed_value(strings: &[String], raw: u32, data_type: u8, data: u32) -> String {
+    let string = |i: u32| strings.get(i as usize).cloned().unwrap_or_default();
+    match data_type {
+        _ if raw != NO_ENTRY => string(raw),
+        0x01 => format!("@0x{:08x}", data),
+        0x02 => format!("?0x{:08x}", data),
+        0x03 => string(data),
+        0x04 => f32::from_bits(data).to_string(),
+        0x11 => format!("0x{:x}", data),
+        0x12 => (data != 0).to_string(),
+        0x1c..=0x1f => format!("#{:08x}", data),
+        _ => (data as i32).to_string(),
+    }
+}
+
+/// converts android binary xml back to text
+fn decode_binary_xml(data: &[u8]) -> Result<String> {
+    let (typ, header_size, xml) = *chunks(data)?.first().context("empty binary xml")?;
+    if typ != RES_XML_TYPE {
+        bail!("not a binary xml file");
+    }
+    let mut strings = Vec::new();
+    let mut prefixes: HashMap<u32, u32> = HashMap::new();
+    let mut out = String::new();
+    let string = |strings: &[String], i: u32| strings.get(i as usize).cloned().unwrap_or_default();
+    let body = xml.get(header_size..).context("invalid header size")?;
+    for (typ, header_size, chunk) in chunks(body)? {
+        match typ {
+            RES_STRING_POOL_TYPE => strings = string_pool(chunk)?,
+            // namespace uri -> prefix
+            RES_XML_START_NAMESPACE_TYPE => {
+                prefixes.insert(u32_at(chunk, header_size + 4)?, u32_at(chunk, header_size)?);
+            }
+            RES_XML_START_ELEMENT_TYPE => {
+                let ext = header_size;
+                out.push('<');
+                out.push_str(&string(&strings, u32_at(chunk, ext + 4)?));
+                let attribute_start = u16_at(chunk, ext + 8)? as usize;
+                let attribute_size = u16_at(chunk, ext + 10)? as usize;
+                let attribute_count = u16_at(chunk, ext + 12)? as usize;
+                for i in 0..attribute_count {
+                    let pos = ext + attribute_start + i * attribute_size;
+                    let ns = u32_at(chunk, pos)?;
+                    let name = string(&strings, u32_at(chunk, pos + 4)?);
+                    let raw = u32_at(chunk, pos + 8)?;
+                    let data_type = *chunk.get(pos + 15).context("truncated attribute")?;
+                    let value = typed_value(&strings, raw, data_type, u32_at(chunk, pos + 16)?);
+                    out.push(' ');
+                    if let Some(prefix) = prefixes.get(&ns) {
+                        out.push_str(&string(&strings, *prefix));
+                        out.push(':');
+                    }
+                    out.push_str(&name);
+                    out.push_str("=\"");
+                    out.push_str(&escape(&value));
+                    out.push('"');
+                }
+                out.push('>');
+            }
+            RES_XML_END_ELEMENT_TYPE => {
+                out.push_str("</");
+                out.push_str(&string(&strings, u32_at(chunk, header_size + 4)?));
+                out.push('>');
+            }
+            RES_XML_CDATA_TYPE => {
+                out.push_str(&escape(&string(&strings, u32_at(chunk, header_size)?)))
+            }
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+fn is_binary_xml(data: &[u8]) -> bool {
+    data.starts_with(&[0x03, 0x00, 0x08, 0x00])
+}
+
+/// the global string pool of resources.arsc contains all string resources of the app
+fn resource_strings(data: &[u8]) -> Result<Vec<String>> {
+    let (typ, header_size, table) = *chunks(data)?.first().context("empty resource table")?;
+    if typ != RES_TABLE_TYPE {
+        bail!("not a resource table");
+    }
+    let body = table.get(header_size..).context("invalid header size")?;
+    for (typ, _, chunk) in chunks(body)? {
+        if typ == RES_STRING_POOL_TYPE {
+            return string_pool(chunk);
+        }
+    }
+    Ok(Vec::new())
+}
+
+fn uleb128(data: &[u8], pos: &mut usize) -> Result<u32> {
+    let mut result = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = *data.get(*pos).context("truncated dex string")?;
+        *pos += 1;
+        result |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+/// all strings of a dex file: class and method names, but also every string literal
+fn dex_strings(data: &[u8]) -> Result<Vec<String>> {
+    if !data.starts_with(b"dex\n") {
+        bail!("not a dex file");
+    }
+    let count = u32_at(data, 0x38)? as usize;
+    let offset = u32_at(data, 0x3c)? as usize;
+    let mut strings = Vec::with_capacity(count.min(data.len() / 4));
+    for i in 0..count {
+        let mut pos = u32_at(data, offset + i * 4)? as usize;
+        // the length is in utf16 units, the data ends with a nul byte
+        uleb128(data, &mut pos)?;
+        let rest = data.get(pos..).context("invalid dex string offset")?;
+        let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        // modified utf8 mostly decodes fine as utf8
+        strings.push(String::from_utf8_lossy(&rest[..end]).into_owned());
+    }
+    Ok(strings)
+}
+
+fn write_strings(line_prefix: &str, strings: &[String], oup: &mut dyn Write) -> Result<()> {
+    for line in strings.iter().flat_map(|s| s.lines()) {
+        if !line.trim().is_empty() {
+            writeln!(oup, "{}{}", line_prefix, line)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_dex(name: &str) -> bool {
+    name.rsplit('/')
+        .next()
+        .is_some_and(|n| n.starts_with("classes") && n.ends_with(".dex"))
+}
+
+fn adapt_member(ai: &AdaptInfo, name: &str, data: Vec<u8>, oup: &mut dyn Write) -> Result<()> {
+    let line_prefix = format!("{}{}: ", ai.line_prefix, name);
+    if is_binary_xml(&data) {
+        let xml = decode_binary_xml(&data)?;
+        return write_flat_xml(&line_prefix, xml.as_bytes(), oup);
+    }
+    if name == "resources.arsc" {
+        return write_strings(&line_prefix, &resource_strings(&data)?, oup);
+    }
+    if is_dex(name) {
+        return write_strings(&line_prefix, &dex_strings(&data)?, oup);
+    }
+    let mut inner = rga_preproc(AdaptInfo {
+        filepath_hint: PathBuf::from(name),
+        is_real_file: false,
+        inp: Box::new(Cursor::new(data)),
+        line_prefix,
+        archive_recursion_depth: ai.archive_recursion_depth + 1,
+        config: ai.config.clone(),
+    })?;
+    std::io::copy(&mut inner, oup)?;
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for ApkAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+        // the manifest first, it's what people look for
+        // in the order of the archive, file_names() comes from a hash map
+        let mut names = (0..archive.len())
+            .map(|i| Ok(archive.by_index_raw(i)?.name().to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        names.sort_by_key(|n| n != "AndroidManifest.xml");
+        for name in names {
+            let mut file = archive.by_name(&name)?;
+            if file.is_dir() {
+                continue;
+            }
+            debug!("{}|{}", ai.filepath_hint.display(), name);
+            let mut data = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut data)?;
+            drop(file);
+            adapt_member(&ai, &name, data, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn chunk(typ: u16, header: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut out = typ.to_le_bytes().to_vec();
+        out.extend(&((8 + header.len()) as u16).to_le_bytes());
+        out.extend(&((8 + header.len() + body.len()) as u32).to_le_bytes());
+        out.extend(header);
+        out.extend(body);
+        out
+    }
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn pool(strings: &[&str], utf8: bool) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut data = Vec::new();
+        for s in strings {
+            offsets.push(data.len() as u32);
+            if utf8 {
+                data.extend(&[s.encode_utf16().count() as u8, s.len() as u8]);
+                data.extend(s.as_bytes());
+                data.push(0);
+            } else {
+                data.extend(&(s.encode_utf16().count() as u16).to_le_bytes());
+                data.extend(s.encode_utf16().flat_map(u16::to_le_bytes));
+                data.extend(&[0, 0]);
+            }
+        }
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        let flags = if utf8 { 0x100 } else { 0 };
+        let strings_start = 28 + 4 * strings.len() as u32;
+        let header = u32s(&[strings.len() as u32, 0, flags, strings_start, 0]);
+        chunk(
+            RES_STRING_POOL_TYPE,
+            &header,
+            &[u32s(&offsets), data].concat(),
+        )
+    }
+
+    /// (namespace, name, raw value, type, data)
+    fn element(name: u32, attributes: &[(u32, u32, u32, u8, u32)]) -> Vec<u8> {
+        let mut header = u32s(&[0, NO_ENTRY, NO_ENTRY, name]);
+        header.extend(&[20, 0, 20, 0, attributes.len() as u8, 0, 0, 0, 0, 0, 0, 0]);
+        let mut body = Vec::new();
+        for (ns, name, raw, typ, data) in attributes {
+            body.extend(u32s(&[*ns, *name, *raw]));
+            body.extend(&[8, 0, 0, *typ]);
+            body.extend(u32s(&[*data]));
+        }
+        chunk(
+            RES_XML_START_ELEMENT_TYPE,
+            &header[..8],
+            &[&header[8..], &body[..]].concat(),
+        )
+    }
+
+    fn manifest() -> Vec<u8> {
+        let strings = [
+            "android",
+            "http://schemas.android.com/apk/res/android",
+            "manifest",
+            "package",
+            "com.example.app",
+            "versionCode",
+            "uses-permission",
+            "name",
+            "android.permission.INTERNET",
+        ];
+        let mut body = pool(&strings, false);
+        body.extend(chunk(
+            RES_XML_START_NAMESPACE_TYPE,
+            &u32s(&[1, NO_ENTRY]),
+            &u32s(&[0, 1]),
+        ));
+        body.extend(element(
+            2,
+            &[(NO_ENTRY, 3, 4, 0x03, 4), (1, 5, NO_ENTRY, 0x10, 7)],
+        ));
+        body.extend(element(6, &[(1, 7, 8, 0x03, 8)]));
+        body.extend(chunk(
+            RES_XML_END_ELEMENT_TYPE,
+            &u32s(&[1, NO_ENTRY]),
+            &u32s(&[NO_ENTRY, 6]),
+        ));
+        body.extend(chunk(
+            RES_XML_END_ELEMENT_TYPE,
+            &u32s(&[1, NO_ENTRY]),
+            &u32s(&[NO_ENTRY, 2]),
+        ));
+        chunk(RES_XML_TYPE, &[], &body)
+    }
+
+    fn dex(strings: &[&str]) -> Vec<u8> {
+        let mut out = b"dex\n035\0".to_vec();
+        out.resize(0x38, 0);
+        out.extend(u32s(&[strings.len() as u32, 0x70]));
+        out.resize(0x70, 0);
+        let mut data = Vec::new();
+        let data_start = 0x70 + 4 * strings.len();
+        for s in strings {
+            out.extend(u32s(&[(data_start + data.len()) as u32]));
+            data.push(s.encode_utf16().count() as u8);
+            data.extend(s.as_bytes());
+            data.push(0);
+        }
+        out.extend(data);
+        out
+    }
+
+    #[test]
+    fn malformed_binary_xml() {
+        let xml = manifest();
+        for len in 0..xml.len() {
+            assert!(decode_binary_xml(&xml[..len]).is_err());
+        }
+        let mut header = xml.clone();
+        header[2..4].copy_from_slice(&0xfff0u16.to_le_bytes());
+        assert!(decode_binary_xml(&header).is_err());
+        // offsets and sizes pointing anywhere are errors (or garbage), but never panics
+        for i in 0..xml.len() {
+            let mut corrupt = xml.clone();
+            corrupt[i] = 0xff;
+            let _ = decode_binary_xml(&corrupt);
+        }
+    }
+
+    #[test]
+    fn apk() -> Result<()> {
+        let resources = chunk(
+            RES_TABLE_TYPE,
+            &u32s(&[0]),
+            &pool(&["Example App", "Wählen"], true),
+        );
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        for (name, content) in &[
+            (
+                "classes.dex",
+                dex(&["Lcom/example/app/Main;", "https://api.example.com/v1"]),
+            ),
+            ("AndroidManifest.xml", manifest()),
+            ("resources.arsc", resources),
+            ("assets/config.txt", b"debug=false\n".to_vec()),
+        ] {
+            zip.start_file(*name, options)?;
+            zip.write_all(content)?;
+        }
+        let data = zip.finish()?.into_inner();
+        let (a, d) = simple_adapt_info(Path::new("app-release.apk"), Box::new(Cursor::new(data)));
+        let mut r = ApkAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:AndroidManifest.xml: /manifest/@package: com.example.app
+PREFIX:AndroidManifest.xml: /manifest/@android:versionCode: 7
+PREFIX:AndroidManifest.xml: /manifest/uses-permission/@android:name: android.permission.INTERNET
+PREFIX:classes.dex: Lcom/example/app/Main;
+PREFIX:classes.dex: https://api.example.com/v1
+PREFIX:resources.arsc: Example App
+PREFIX:resources.arsc: Wählen
+debug=false
+"
+        );
+        Ok(())
+    }
+}
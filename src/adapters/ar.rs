@@ -0,0 +1,248 @@
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["a", "ar"];
+static MIME_TYPES: &[&str] = &["application/x-archive"];
+
+pub static AR_MAGIC: &[u8] = b"!<arch>\n";
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "ar".to_owned(),
+        version: 1,
+        description:
+            "Reads Unix ar archives (e.g. static libraries) and recurses down into their members"
+                .to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct ArAdapter;
+
+impl ArAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(ArAdapter))
+    }
+}
+impl GetMetadata for ArAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+pub struct ArMember {
+    pub name: String,
+    pub size: u64,
+    /// the data is padded to an even length, including a bsd name in front of it
+    pub padding: u64,
+}
+
+impl ArMember {
+    /// the symbol table of gnu (`/`, `/SYM64/`) and bsd (`__.SYMDEF`) ar, and the gnu long name table (`//`)
+    pub fn is_index(&self) -> bool {
+        self.name == "/"
+            || self.name == "//"
+            || self.name == "/SYM64/"
+            || self.name.starts_with("__.SYMDEF")
+    }
+}
+
+/// reads the next header of an `ar` archive. the member data follows, padded to an even length.
+///
+/// names longer than 15 bytes are stored in the `//` member by gnu ar (`/123` is the offset in it),
+/// so its data has to be passed as `long_names`. bsd ar writes them in front of the data (`#1/20`)
+pub fn read_ar_header(inp: &mut dyn Read, long_names: &[u8]) -> Result<Option<ArMember>> {
+    let mut header = [0u8; 60];
+    if inp.read(&mut header[..1])? == 0 {
+        return Ok(None);
+    }
+    inp.read_exact(&mut header[1..])?;
+    if &header[58..] != b"`\n" {
+        bail!("invalid ar member header");
+    }
+    let field =
+        |range: std::ops::Range<usize>| String::from_utf8_lossy(&header[range]).trim().to_string();
+    let mut size: u64 = field(48..58).parse().context("invalid ar member size")?;
+    let padding = size % 2;
+    let raw_name = field(0..16);
+    let name = if let Some(len) = raw_name.strip_prefix("#1/") {
+        let len: u64 = len.parse().context("invalid bsd ar name length")?;
+        if len > size {
+            bail!("invalid bsd ar name length");
+        }
+        // the length is only limited by the (claimed) member size, so read it instead of allocating it
+        let mut name = Vec::new();
+        inp.take(len).read_to_end(&mut name)?;
+        if name.len() as u64 != len {
+            bail!("truncated bsd ar name");
+        }
+        size -= len;
+        String::from_utf8_lossy(&name)
+            .trim_end_matches('\0')
+            .to_string()
+    } else if let Some(offset) = raw_name
+        .strip_prefix('/')
+        .and_then(|o| o.parse::<usize>().ok())
+    {
+        let name = long_names
+            .get(offset..)
+            .ok_or_else(|| format_err!("invalid gnu ar long name offset {}", offset))?;
+        let end = name.iter().position(|b| *b == b'\n').unwrap_or(name.len());
+        String::from_utf8_lossy(&name[..end])
+            .trim_end_matches('/')
+            .to_string()
+    } else if raw_name == "/" || raw_name == "//" || raw_name == "/SYM64/" {
+        raw_name
+    } else {
+        // gnu ar terminates names with a slash
+        raw_name.trim_end_matches('/').to_string()
+    };
+    Ok(Some(ArMember {
+        name,
+        size,
+        padding,
+    }))
+}
+
+/// reads the data of a member including its padding
+pub fn read_ar_member(inp: &mut dyn Read, member: &ArMember) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    inp.take(member.size).read_to_end(&mut data)?;
+    if data.len() as u64 != member.size {
+        bail!("ar member {} is truncated", member.name);
+    }
+    if member.padding > 0 {
+        inp.read_exact(&mut [0u8])?;
+    }
+    Ok(data)
+}
+
+impl WritingFileAdapterTrait for ArAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut magic = [0u8; 8];
+        ai.inp.read_exact(&mut magic)?;
+        if magic != AR_MAGIC {
+            bail!("not an ar archive");
+        }
+        let mut long_names = Vec::new();
+        while let Some(member) = read_ar_header(&mut ai.inp, &long_names)? {
+            debug!("{}|{}", ai.filepath_hint.display(), member.name);
+            let data = read_ar_member(&mut ai.inp, &member)?;
+            if member.name == "//" {
+                long_names = data;
+                continue;
+            }
+            if member.is_index() {
+                continue;
+            }
+            let mut inner = rga_preproc(AdaptInfo {
+                filepath_hint: PathBuf::from(&member.name),
+                is_real_file: false,
+                inp: Box::new(Cursor::new(data)),
+                line_prefix: format!("{}{}: ", ai.line_prefix, member.name),
+                archive_recursion_depth: ai.archive_recursion_depth + 1,
+                config: ai.config.clone(),
+            })?;
+            std::io::copy(&mut inner, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn member(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut out = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            name,
+            0,
+            0,
+            0,
+            100644,
+            data.len()
+        )
+        .into_bytes();
+        out.extend(data);
+        if data.len() % 2 == 1 {
+            out.push(b'\n');
+        }
+        out
+    }
+
+    fn adapt(data: Vec<u8>) -> Result<String> {
+        let (mut a, d) = simple_adapt_info(Path::new("libexample.a"), Box::new(Cursor::new(data)));
+        // the html members show that the members are adapted
+        a.config.args.adapters = vec!["+html".to_string()];
+        let mut r = ArAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn gnu() -> Result<()> {
+        let mut data = AR_MAGIC.to_vec();
+        data.extend(member("/", b"\0\0\0\x01\0\0\0\0example_init\0"));
+        data.extend(member("//", b"a_very_long_page_name.html/\n"));
+        data.extend(member("short.html/", b"<b>short name</b>"));
+        data.extend(member("/0", b"<b>long name</b>"));
+        assert_eq!(
+            adapt(data)?,
+            "PREFIX:short.html: short name\nPREFIX:a_very_long_page_name.html: long name\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bsd() -> Result<()> {
+        let mut data = AR_MAGIC.to_vec();
+        data.extend(member("__.SYMDEF SORTED", b"\0\0\0\0"));
+        data.extend(member(
+            "#1/26",
+            b"a_very_long_page_name.html<b>long name</b>",
+        ));
+        assert_eq!(
+            adapt(data)?,
+            "PREFIX:a_very_long_page_name.html: long name\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_bsd_name() {
+        // the claimed name length is not allocated up front
+        let mut data = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            "#1/9999999999", 0, 0, 0, 100644, 9999999999u64
+        )
+        .into_bytes();
+        data.extend(b"short");
+        assert!(read_ar_header(&mut Cursor::new(data), &[]).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::probe::Probe;
+use lofty::tag::{ItemKey, ItemValue};
+use std::collections::HashSet;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &[
+    "mp3", "flac", "ogg", "oga", "opus", "spx", "m4a", "m4b", "aac", "wav", "aiff", "aif", "ape",
+    "wv", "mpc",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "audiotags".to_owned(),
+        version: 1,
+        description: "Reads tags and embedded lyrics of audio files (ID3, Vorbis comments, MP4, APE, RIFF INFO) without needing ffmpeg".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![
+            SlowMatcher::MimeType("audio/mpeg".to_owned()),
+            SlowMatcher::MimeType("audio/flac".to_owned()),
+            SlowMatcher::MimeType("audio/ogg".to_owned())
+        ]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct AudioTagsAdapter;
+
+impl AudioTagsAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(AudioTagsAdapter))
+    }
+}
+impl GetMetadata for AudioTagsAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+impl WritingFileAdapterTrait for AudioTagsAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let file = Probe::new(Cursor::new(data)).guess_file_type()?.read()?;
+        // files often have the same fields in multiple tags (e.g. ID3v1 and ID3v2)
+        let mut seen = HashSet::new();
+        for tag in file.tags() {
+            for item in tag.items() {
+                let value = match item.value() {
+                    ItemValue::Text(text) | ItemValue::Locator(text) => text.trim(),
+                    ItemValue::Binary(_) => continue,
+                };
+                let key = item.key();
+                if value.is_empty() || !seen.insert((key, value.to_string())) {
+                    continue;
+                }
+                if key == ItemKey::Lyrics {
+                    for line in value.lines().filter(|l| !l.trim().is_empty()) {
+                        writeln!(oup, "{}Lyrics: {}", ai.line_prefix, line.trim())?;
+                    }
+                } else {
+                    writeln!(oup, "{}{:?}: {}", ai.line_prefix, key, value)?;
+                }
+            }
+        }
+        let duration = file.properties().duration().as_secs();
+        if duration > 0 {
+            writeln!(
+                oup,
+                "{}Duration: {}:{:02}",
+                ai.line_prefix,
+                duration / 60,
+                duration % 60
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    /// a flac file without audio frames: just the stream info and vorbis comments
+    fn test_flac(comments: &[&str]) -> Vec<u8> {
+        let mut flac = b"fLaC".to_vec();
+        // STREAMINFO: block sizes, frame sizes, 44.1kHz, stereo, 16 bit, 3 minutes 5 seconds of samples, md5
+        flac.extend(&[0x00, 0, 0, 34]);
+        flac.extend(&[0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+        let samples: u64 = 44100 * 185;
+        let packed: u64 = (44100u64 << 44) | (1 << 41) | (15 << 36) | samples;
+        flac.extend(&packed.to_be_bytes());
+        flac.extend(&[0u8; 16]);
+        // VORBIS_COMMENT, last metadata block
+        let mut block: Vec<u8> = Vec::new();
+        let vendor = b"rga test";
+        block.extend(&(vendor.len() as u32).to_le_bytes());
+        block.extend(vendor);
+        block.extend(&(comments.len() as u32).to_le_bytes());
+        for c in comments {
+            block.extend(&(c.len() as u32).to_le_bytes());
+            block.extend(c.as_bytes());
+        }
+        flac.push(0x84);
+        flac.extend(&(block.len() as u32).to_be_bytes()[1..]);
+        flac.extend(block);
+        flac
+    }
+
+    #[test]
+    fn flac() -> Result<()> {
+        let flac = test_flac(&[
+            "TITLE=Harbour Lights",
+            "ARTIST=The Testers",
+            "ALBUM=Greatest Hits",
+            "LYRICS=boats on the water\n\nlights on the shore",
+        ]);
+        let (a, d) = simple_adapt_info(Path::new("song.flac"), Box::new(Cursor::new(flac)));
+        let mut r = AudioTagsAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:TrackTitle: Harbour Lights
+PREFIX:TrackArtist: The Testers
+PREFIX:AlbumTitle: Greatest Hits
+PREFIX:Lyrics: boats on the water
+PREFIX:Lyrics: lights on the shore
+PREFIX:EncoderSoftware: rga test
+PREFIX:Duration: 3:05
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use super::*;
+use anyhow::*;
+use apache_avro::Reader;
+use lazy_static::lazy_static;
+use log::*;
+use std::convert::TryFrom;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["avro"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "avro".to_owned(),
+        version: 1,
+        description: "Reads Avro object container files using the embedded schema and outputs every record as a line of JSON".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct AvroAdapter;
+
+impl AvroAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(AvroAdapter))
+    }
+}
+impl GetMetadata for AvroAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+impl WritingFileAdapterTrait for AvroAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let reader = Reader::new(ai.inp)?;
+        debug!(
+            "{}: avro schema {}",
+            ai.filepath_hint.display(),
+            reader.writer_schema().canonical_form()
+        );
+        for (i, record) in reader.enumerate() {
+            let record = serde_json::Value::try_from(record?)?;
+            writeln!(oup, "{}Record {}: {}", ai.line_prefix, i + 1, record)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use apache_avro::types::Record;
+    use apache_avro::{Codec, Schema, Writer};
+    use std::io::Cursor;
+
+    fn make_avro() -> Result<Vec<u8>> {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "customer", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": ["null", "string"]}
+            ]}"#,
+        )?;
+        let mut writer = Writer::with_codec(&schema, Vec::new(), Codec::Snappy);
+        for (id, name) in [(1, Some("alice")), (2, None)] {
+            let mut record = Record::new(writer.schema()).unwrap();
+            record.put("id", id as i64);
+            record.put("name", name.map(|n| n.to_string()));
+            writer.append(record)?;
+        }
+        Ok(writer.into_inner()?)
+    }
+
+    #[test]
+    fn records() -> Result<()> {
+        let (a, d) = simple_adapt_info(Path::new("test.avro"), Box::new(Cursor::new(make_avro()?)));
+        let mut r = AvroAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            r#"PREFIX:Record 1: {"id":1,"name":"alice"}
+PREFIX:Record 2: {"id":2,"name":null}
+"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["cab"];
+static MIME_TYPES: &[&str] = &["application/vnd.ms-cab-compressed"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "cab".to_owned(),
+        version: 1,
+        description: "Reads Windows cabinet files and recurses down into their contents".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct CabAdapter;
+
+impl CabAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(CabAdapter))
+    }
+}
+impl GetMetadata for CabAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// recurse into all files of a cabinet, also used for the cabinets embedded in msi installers
+pub fn adapt_cabinet(
+    data: Vec<u8>,
+    filepath_hint: &Path,
+    line_prefix: &str,
+    archive_recursion_depth: i32,
+    config: &PreprocConfig,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let mut cabinet = ::cab::Cabinet::new(Cursor::new(data))?;
+    let names: Vec<String> = cabinet
+        .folder_entries()
+        .flat_map(|folder| folder.file_entries())
+        .map(|file| file.name().to_string())
+        .collect();
+    for name in names {
+        debug!("{}|{}", filepath_hint.display(), name);
+        let mut data = Vec::new();
+        cabinet.read_file(&name)?.read_to_end(&mut data)?;
+        // cabinets use windows path separators
+        let path = name.replace('\\', "/");
+        let mut inner = rga_preproc(AdaptInfo {
+            line_prefix: format!("{}{}: ", line_prefix, path),
+            filepath_hint: PathBuf::from(path),
+            is_real_file: false,
+            inp: Box::new(Cursor::new(data)),
+            archive_recursion_depth: archive_recursion_depth + 1,
+            config: config.clone(),
+        })?;
+        std::io::copy(&mut inner, oup)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for CabAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        adapt_cabinet(
+            data,
+            &ai.filepath_hint,
+            &ai.line_prefix,
+            ai.archive_recursion_depth,
+            &ai.config,
+            oup,
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    pub fn test_cabinet() -> Result<Vec<u8>> {
+        let mut builder = ::cab::CabinetBuilder::new();
+        let folder = builder.add_folder(::cab::CompressionType::MsZip);
+        folder.add_file("docs\\readme.html");
+        folder.add_file("setup.ini");
+        let mut writer = builder.build(Cursor::new(Vec::new()))?;
+        while let Some(mut file) = writer.next_file()? {
+            let content: &[u8] = match file.file_name() {
+                "setup.ini" => b"[Setup]\nServer=db.example.com\n",
+                _ => b"<p>Thanks for <b>installing</b></p>",
+            };
+            file.write_all(content)?;
+        }
+        Ok(writer.finish()?.into_inner())
+    }
+
+    #[test]
+    fn cab() -> Result<()> {
+        let (mut a, d) = simple_adapt_info(
+            Path::new("setup.cab"),
+            Box::new(Cursor::new(test_cabinet()?)),
+        );
+        // the html members show that the members are adapted
+        a.config.args.adapters = vec!["+html".to_string()];
+        let mut r = CabAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:docs/readme.html: Thanks for installing
+[Setup]
+Server=db.example.com
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use super::rar::{list_files, spawn_fail};
+use super::xml::{text_of, xml_reader};
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use quick_xml::events::Event;
+use std::io::Cursor;
+use std::process::{Command, Stdio};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["cbz", "cbr"];
+static MIME_TYPES: &[&str] = &[
+    "application/vnd.comicbook+zip",
+    "application/vnd.comicbook-rar",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "cbz".to_owned(),
+        version: 1,
+        description: "Reads comic book archives (.cbz / .cbr, cbr needs unrar). Writes the ComicInfo.xml metadata and, if the ocr adapter is enabled, runs the pages through OCR. Lines are prefixed with the page number".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct CbzAdapter;
+
+impl CbzAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(CbzAdapter))
+    }
+}
+impl GetMetadata for CbzAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the container format is often not what the extension says, so look at the magic bytes
+enum Archive {
+    Zip(::zip::ZipArchive<Cursor<Vec<u8>>>),
+    Rar {
+        path: PathBuf,
+        _tmp_file: Option<tempfile::NamedTempFile>,
+    },
+}
+
+impl Archive {
+    fn open(ai: &AdaptInfo, data: Vec<u8>) -> Result<Archive> {
+        if !data.starts_with(b"Rar!") {
+            return Ok(Archive::Zip(::zip::ZipArchive::new(Cursor::new(data))?));
+        }
+        // unrar can't read from stdin
+        if ai.is_real_file {
+            return Ok(Archive::Rar {
+                path: ai.filepath_hint.clone(),
+                _tmp_file: None,
+            });
+        }
+        let mut tmp = tempfile::Builder::new()
+            .prefix("rga-cbr-")
+            .suffix(".cbr")
+            .tempfile()?;
+        tmp.write_all(&data)?;
+        Ok(Archive::Rar {
+            path: tmp.path().to_owned(),
+            _tmp_file: Some(tmp),
+        })
+    }
+
+    fn file_names(&mut self) -> Result<Vec<String>> {
+        Ok(match self {
+            Archive::Zip(zip) => {
+                let mut names = Vec::new();
+                for i in 0..zip.len() {
+                    let file = zip.by_index_raw(i)?;
+                    if !file.is_dir() {
+                        names.push(file.name().to_string());
+                    }
+                }
+                names
+            }
+            Archive::Rar { path, .. } => list_files(path)?,
+        })
+    }
+
+    fn read(&mut self, name: &str) -> Result<Vec<u8>> {
+        match self {
+            Archive::Zip(zip) => {
+                let mut data = Vec::new();
+                zip.by_name(name)?.read_to_end(&mut data)?;
+                Ok(data)
+            }
+            Archive::Rar { path, .. } => {
+                let out = Command::new("unrar")
+                    .args(["p", "-inul", "-p-", "--"])
+                    .arg(&path)
+                    .arg(name)
+                    .stdin(Stdio::null())
+                    .output()
+                    .map_err(spawn_fail)?;
+                if !out.status.success() {
+                    return Err(format_err!("unrar p {} failed: {:?}", name, out.status));
+                }
+                Ok(out.stdout)
+            }
+        }
+    }
+}
+
+fn is_page(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|e| tesseract::EXTENSIONS.contains(&e.as_str()))
+}
+
+fn is_comic_info(name: &str) -> bool {
+    name.rsplit('/')
+        .next()
+        .unwrap_or(name)
+        .eq_ignore_ascii_case("ComicInfo.xml")
+}
+
+/// writes the fields of a ComicInfo.xml (`Series`, `Number`, `Writer`, `Summary`, ...)
+/// as `Field: value`. the per-page info is skipped
+fn write_comic_info(line_prefix: &str, xml: &[u8], oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(xml);
+    let mut buf = Vec::new();
+    let mut depth = 0;
+    let mut field = String::new();
+    let mut value = String::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                depth += 1;
+                if depth == 2 {
+                    field = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                    value.clear();
+                }
+            }
+            Event::Text(t) if depth == 2 => value.push_str(&text_of(&t)),
+            Event::CData(t) if depth == 2 => value.push_str(&String::from_utf8_lossy(&t)),
+            Event::End(_) => {
+                if depth == 2 && field != "Pages" {
+                    // summaries can span multiple lines
+                    for line in value.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                        writeln!(oup, "{}{}: {}", line_prefix, field, line)?;
+                    }
+                }
+                depth -= 1;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+fn adapt_member(ai: &AdaptInfo, name: &str, line_prefix: String, data: Vec<u8>) -> Result<ReadBox> {
+    rga_preproc(AdaptInfo {
+        filepath_hint: PathBuf::from(name),
+        is_real_file: false,
+        inp: Box::new(Cursor::new(data)),
+        line_prefix,
+        archive_recursion_depth: ai.archive_recursion_depth + 1,
+        config: ai.config.clone(),
+    })
+}
+
+impl WritingFileAdapterTrait for CbzAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut archive = Archive::open(&ai, data)?;
+        let mut names = archive.file_names()?;
+        // comic readers show the pages in file name order
+        names.sort();
+        let args = &ai.config.args;
+        let ocr = get_adapters_filtered(args.custom_adapters.clone(), &args.adapters)?
+            .iter()
+            .any(|a| a.metadata().name == "ocr");
+        if let Some(name) = names.iter().find(|n| is_comic_info(n)) {
+            write_comic_info(&ai.line_prefix, &archive.read(name)?, oup)?;
+        }
+        let mut page = 0;
+        for name in &names {
+            if is_comic_info(name) {
+                continue;
+            }
+            let line_prefix = if is_page(name) {
+                page += 1;
+                if !ocr {
+                    // without ocr the page would just be searched as binary data
+                    continue;
+                }
+                format!("{}Page {}: ", ai.line_prefix, page)
+            } else {
+                format!("{}{}: ", ai.line_prefix, name)
+            };
+            debug!("{}|{}", ai.filepath_hint.display(), name);
+            let data = archive.read(name)?;
+            let mut member = adapt_member(&ai, name, line_prefix, data)?;
+            std::io::copy(&mut member, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    static COMIC_INFO: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Title>The Long Night</Title>
+  <Series>Harbor Lights</Series>
+  <Number>3</Number>
+  <Summary>The lighthouse goes dark.
+Mara investigates.</Summary>
+  <Writer>J. Doe</Writer>
+  <Pages><Page Image="0" Type="FrontCover"/></Pages>
+</ComicInfo>"#;
+
+    #[test]
+    fn cbz() -> Result<()> {
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        for (name, content) in &[
+            ("002.png", &b"\x89PNG page two"[..]),
+            ("001.png", b"\x89PNG page one"),
+            ("ComicInfo.xml", COMIC_INFO.as_bytes()),
+            ("credits.txt", b"lettering by A. Smith\n"),
+        ] {
+            zip.start_file(*name, options)?;
+            zip.write_all(content)?;
+        }
+        let data = zip.finish()?.into_inner();
+        let (a, d) = simple_adapt_info(Path::new("harbor-03.cbz"), Box::new(Cursor::new(data)));
+        let mut r = CbzAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        // the ocr adapter is disabled by default, so the pages are skipped. plain text is passed
+        // through without a prefix, like in zip files
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Title: The Long Night
+PREFIX:Series: Harbor Lights
+PREFIX:Number: 3
+PREFIX:Summary: The lighthouse goes dark.
+PREFIX:Summary: Mara investigates.
+PREFIX:Writer: J. Doe
+lettering by A. Smith
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pages() {
+        assert!(is_page("Vol 1/p001.JPG"));
+        assert!(!is_page("credits.txt"));
+        assert!(is_comic_info("issue/comicinfo.xml"));
+    }
+}
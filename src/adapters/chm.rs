@@ -0,0 +1,141 @@
+use super::html::write_html_text;
+use super::*;
+use anyhow::*;
+use chmlib::{ChmFile, Continuation, Filter};
+use lazy_static::lazy_static;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["chm"];
+static TOPIC_EXTENSIONS: &[&str] = &["htm", "html", "xhtml"];
+/// the length of a topic is read from the file, a crafted one must not allocate gigabytes
+const MAX_TOPIC_LEN: u64 = 64 << 20;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "chm".to_owned(),
+        version: 1,
+        description: "Reads the HTML topics of compiled HTML help (.chm) files. Lines are prefixed with the path of the topic".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/vnd.ms-htmlhelp".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![AdapterOption::HtmlLinks]
+    };
+}
+#[derive(Default, Clone)]
+pub struct ChmAdapter;
+
+impl ChmAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(ChmAdapter))
+    }
+}
+impl GetMetadata for ChmAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn is_topic(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| TOPIC_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// read all html topics in the order they are stored in
+fn read_topics(chm_path: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut chm =
+        ChmFile::open(chm_path).map_err(|e| format_err!("could not open chm file: {}", e))?;
+    let mut topics = Vec::new();
+    chm.for_each(Filter::NORMAL | Filter::FILES, |chm, unit| {
+        let path = match unit.path() {
+            Some(path) if is_topic(path) => path.to_owned(),
+            _ => return Continuation::Continue,
+        };
+        if unit.length() > MAX_TOPIC_LEN {
+            return Continuation::Failure(
+                format_err!(
+                    "topic {} is too large ({})",
+                    path.display(),
+                    crate::print_bytes(unit.length() as f64)
+                )
+                .into(),
+            );
+        }
+        let mut data = vec![0; unit.length() as usize];
+        match chm.read(&unit, 0, &mut data) {
+            Result::Ok(len) => data.truncate(len),
+            Err(e) => return Continuation::Failure(Box::new(e)),
+        }
+        let name = path.to_string_lossy();
+        topics.push((name.trim_start_matches('/').to_string(), data));
+        Continuation::Continue
+    })
+    .map_err(|e| format_err!("could not read chm file: {}", e))?;
+    Ok(topics)
+}
+
+impl WritingFileAdapterTrait for ChmAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // chmlib can only open files by path
+        let _tmp_file;
+        let path = if ai.is_real_file {
+            ai.filepath_hint.clone()
+        } else {
+            let mut tmp = tempfile::Builder::new()
+                .prefix("rga-chm-")
+                .suffix(".chm")
+                .tempfile()?;
+            std::io::copy(&mut ai.inp, &mut tmp)?;
+            let path = tmp.path().to_owned();
+            _tmp_file = tmp;
+            path
+        };
+        for (name, data) in read_topics(&path)? {
+            // help files predate utf-8, most of them are in the windows code page
+            let html = match std::str::from_utf8(&data) {
+                Result::Ok(html) => html.into(),
+                Err(_) => {
+                    encoding_rs::WINDOWS_1252
+                        .decode_without_bom_handling(&data)
+                        .0
+                }
+            };
+            let prefix = format!("{}{}: ", ai.line_prefix, name);
+            write_html_text(&prefix, &html, ai.config.args.html_links, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+
+    #[test]
+    fn topics() -> Result<()> {
+        let fname = test_data_dir().join("short.chm");
+        let (a, d) = simple_adapt_info(&fname, Box::new(File::open(&fname)?));
+        let mut r = ChmAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:index.html: Manual\nPREFIX:index.html: hello world\nPREFIX:index.html: this is just a test.\nPREFIX:sub/page.htm: second topic\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use super::decompress::decompress_any;
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::io::{BufReader, Cursor};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["cpio", "cpgz"];
+static MIME_TYPES: &[&str] = &["application/x-cpio"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "cpio".to_owned(),
+        version: 1,
+        description: "Reads cpio archives and initramfs images (also compressed ones, and the concatenated archives of an initramfs) and recurses down into their contents. initrd images without a .cpio extension are only detected with --rga-accurate".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct CpioAdapter;
+
+impl CpioAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(CpioAdapter))
+    }
+}
+impl GetMetadata for CpioAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the extension decompress_any needs for a compressed archive, None if it is a plain cpio archive
+fn compression(magic: &[u8]) -> Result<Option<&'static str>> {
+    Ok(Some(if magic.starts_with(b"\x1f\x8b") {
+        "gz"
+    } else if magic.starts_with(b"\xfd7zXZ\x00") {
+        "xz"
+    } else if magic.starts_with(b"\x28\xb5\x2f\xfd") {
+        "zst"
+    } else if magic.starts_with(b"BZh") {
+        "bz2"
+    } else if magic.starts_with(b"0707") {
+        return Ok(None);
+    } else {
+        bail!("not a (supported) cpio archive");
+    }))
+}
+
+/// reads the entries up to the trailer and recurses into the regular files
+fn adapt_archive<R: Read>(
+    mut inp: R,
+    filepath_hint: &Path,
+    line_prefix: &str,
+    archive_recursion_depth: i32,
+    config: &PreprocConfig,
+    oup: &mut dyn Write,
+) -> Result<R> {
+    loop {
+        let mut entry = ::cpio::NewcReader::new(inp)?;
+        if entry.entry().is_trailer() {
+            return Ok(entry.finish()?);
+        }
+        let name = entry.entry().name().to_string();
+        // only regular files, hard links only have data in their last entry
+        let is_file = entry.entry().mode() & 0o170000 == 0o100000;
+        if is_file && entry.entry().file_size() > 0 {
+            debug!("{}|{}", filepath_hint.display(), name);
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            let mut inner = rga_preproc(AdaptInfo {
+                line_prefix: format!("{}{}: ", line_prefix, name),
+                filepath_hint: PathBuf::from(&name),
+                is_real_file: false,
+                archive_recursion_depth: archive_recursion_depth + 1,
+                inp: Box::new(Cursor::new(data)),
+                config: config.clone(),
+            })?;
+            std::io::copy(&mut inner, oup)?;
+        }
+        inp = entry.finish()?;
+    }
+}
+
+/// an initramfs can consist of several archives, usually an uncompressed one with cpu microcode
+/// followed by the compressed root file system. each archive is padded with zeroes
+pub fn adapt_entries(
+    inp: ReadBox,
+    filepath_hint: &Path,
+    line_prefix: &str,
+    archive_recursion_depth: i32,
+    config: &PreprocConfig,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let mut inp = BufReader::new(inp);
+    loop {
+        match compression(inp.fill_buf()?)? {
+            None => {
+                inp = adapt_archive(
+                    inp,
+                    filepath_hint,
+                    line_prefix,
+                    archive_recursion_depth,
+                    config,
+                    oup,
+                )?;
+            }
+            Some(ext) => {
+                // the decompressor does not hand back the rest of the input, so a compressed archive has to be the last one
+                let decompressed = decompress_any(
+                    &FastMatcher::FileExtension(ext.to_string()).into(),
+                    Box::new(inp),
+                )?;
+                adapt_archive(
+                    decompressed,
+                    filepath_hint,
+                    line_prefix,
+                    archive_recursion_depth,
+                    config,
+                    oup,
+                )?;
+                return Ok(());
+            }
+        }
+        loop {
+            let buf = inp.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(());
+            }
+            let zeroes = buf.iter().take_while(|b| **b == 0).count();
+            let at_data = zeroes < buf.len();
+            inp.consume(zeroes);
+            if at_data {
+                break;
+            }
+        }
+    }
+}
+
+impl WritingFileAdapterTrait for CpioAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        adapt_entries(
+            ai.inp,
+            &ai.filepath_hint,
+            &ai.line_prefix,
+            ai.archive_recursion_depth,
+            &ai.config,
+            oup,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn archive<W: Write>(oup: W, files: &[(&str, &str)]) -> Result<W> {
+        let mut oup = oup;
+        for (name, content) in files {
+            let mut file = ::cpio::NewcBuilder::new(name)
+                .mode(0o100644)
+                .write(oup, content.len() as u32);
+            file.write_all(content.as_bytes())?;
+            oup = file.finish()?;
+        }
+        Ok(::cpio::newc::trailer(oup)?)
+    }
+
+    #[test]
+    fn initramfs() -> Result<()> {
+        // early microcode archive padded to 512 bytes, then the gzipped root file system
+        let mut data = archive(
+            Vec::new(),
+            &[("kernel/x86/microcode/GenuineIntel.bin", "microcode 0x2f\n")],
+        )?;
+        data.resize(512, 0);
+        let gz = flate2::write::GzEncoder::new(data, flate2::Compression::default());
+        let data = archive(
+            gz,
+            &[
+                ("init", "#!/bin/sh\nmount -t proc proc /proc\n"),
+                ("etc/hostname", "buildbox\n"),
+            ],
+        )?
+        .finish()?;
+
+        let (a, d) = simple_adapt_info(Path::new("initrd.cpio"), Box::new(Cursor::new(data)));
+        let mut r = CpioAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "microcode 0x2f
+#!/bin/sh
+mount -t proc proc /proc
+buildbox
+"
+        );
+        Ok(())
+    }
+}
@@ -19,6 +19,8 @@ pub struct CustomAdapterConfig {
     pub description: String,
     /// if true, the adapter will be disabled by default
     pub disabled_by_default: Option<bool>,
+    /// if true, the output of the adapter is not cached, e.g. because the program is fast
+    pub skip_cache: Option<bool>,
     /// version identifier. used to key cache entries, change if the configuration or program changes
     pub version: i32,
     /// the file extensions this adapter supports. For example ["epub", "mobi"]
@@ -77,8 +79,9 @@ lazy_static! {
         CustomAdapterConfig {
             name: "pandoc".to_string(),
             description: "Uses pandoc to convert binary/unreadable text documents to plain markdown-like text".to_string(),
-            version: 3,
-            extensions: strs(&["epub", "odt", "docx", "fb2", "ipynb"]),
+            version: 6,
+            // docx, epub, odt and fb2 are handled natively by their own adapters
+            extensions: strs(&["ipynb"]),
             binary: "pandoc".to_string(),
             mimetypes: None,
             // simpler markown (with more information loss but plainer text)
@@ -89,21 +92,8 @@ lazy_static! {
                 "--wrap=none",
                 "--atx-headers"
             ]),
-            disabled_by_default: None
-        },
-        CustomAdapterConfig {
-            name: "poppler".to_owned(),
-            version: 1,
-            description: "Uses pdftotext (from poppler-utils) to extract plain text from PDF files"
-                .to_owned(),
-
-            extensions: strs(&["pdf"]),
-            mimetypes: Some(strs(&["application/pdf"])),
-
-            binary: "pdftotext".to_string(),
-            args: strs(&["-", "-"]),
             disabled_by_default: None,
-            // postprocessors: [{name: "add_page_numbers_by_pagebreaks"}]
+            skip_cache: None
         }
     ];
 }
@@ -125,11 +115,11 @@ fn arg_replacer(arg: &str, filepath_hint: &Path) -> Result<String> {
     let mut err = None;
     let r = ARG_REP.replace_all(arg, |m: &Captures| -> String {
         let idx = m.get(0).unwrap().range();
-        if arg.chars().nth(idx.start - 1) == Some('{') {
+        if arg[..idx.start].ends_with('{') {
             // skip
             return m.get(0).unwrap().as_str().to_string();
         }
-        if arg.chars().nth(idx.end + 1) == Some('}') {
+        if arg[idx.end..].starts_with('}') {
             // skip
             return m.get(0).unwrap().as_str().to_string();
         }
@@ -200,6 +190,9 @@ impl CustomAdapterConfig {
                         .collect()
                 }),
                 disabled_by_default: self.disabled_by_default.unwrap_or(false),
+                skip_cache: self.skip_cache.unwrap_or(false),
+                // the definition of the adapter is part of its cache keys instead
+                options: vec![],
             },
         };
         SpawningFileAdapter::new(Box::new(ad))
@@ -208,36 +201,19 @@ impl CustomAdapterConfig {
 
 #[cfg(test)]
 mod test {
-    use super::super::FileAdapter;
     use super::*;
-    use crate::test_utils::*;
     use anyhow::Result;
-    use std::fs::File;
+    use std::path::PathBuf;
 
     #[test]
-    fn poppler() -> Result<()> {
-        let adapter = builtin_spawning_adapters
-            .iter()
-            .find(|e| e.name == "poppler")
-            .expect("no poppler adapter");
-
-        let adapter = adapter.to_adapter();
-
-        let filepath = test_data_dir().join("short.pdf");
-
-        let (a, d) = simple_adapt_info(&filepath, Box::new(File::open(&filepath)?));
-        let mut r = adapter.adapt(a, &d)?;
-        let mut o = Vec::new();
-        r.read_to_end(&mut o)?;
+    fn replace_args() -> Result<()> {
+        let path = PathBuf::from("hi/test.epub");
         assert_eq!(
-            String::from_utf8(o)?,
-            "hello world
-this is just a test.
-
-1
-
-\u{c}"
+            arg_replacer("--from={file_extension}", &path)?,
+            "--from=epub"
         );
+        assert_eq!(arg_replacer("--to=plain", &path)?, "--to=plain");
+        assert!(arg_replacer("{foo}", &path).is_err());
         Ok(())
     }
 }
@@ -0,0 +1,218 @@
+use super::ar::{read_ar_header, read_ar_member, AR_MAGIC};
+use super::decompress::decompress_any;
+use super::*;
+use crate::preproc::rga_preproc;
+use ::tar::EntryType::Regular;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["deb", "udeb", "ddeb"];
+static MIME_TYPES: &[&str] = &[
+    "application/vnd.debian.binary-package",
+    "application/x-debian-package",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "deb".to_owned(),
+        version: 1,
+        description: "Reads Debian packages. Writes the control metadata, and recurses into the maintainer scripts and the packaged files".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct DebAdapter;
+
+impl DebAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(DebAdapter))
+    }
+}
+impl GetMetadata for DebAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// `control.tar.xz` -> decompressed tar stream
+fn tar_stream(name: &str, inp: ReadBox) -> Result<ReadBox> {
+    match Path::new(name).extension().and_then(|e| e.to_str()) {
+        Some("tar") => Ok(inp),
+        Some(ext) => decompress_any(&FastMatcher::FileExtension(ext.to_string()).into(), inp),
+        None => bail!("unknown member {} in debian package", name),
+    }
+}
+
+/// writes the fields of the `control` file, recurses into the maintainer scripts
+fn adapt_control(ai: &AdaptInfo, member: &str, data: Vec<u8>, oup: &mut dyn Write) -> Result<()> {
+    let mut archive = ::tar::Archive::new(tar_stream(member, Box::new(Cursor::new(data)))?);
+    for entry in archive.entries()? {
+        let mut file = entry?;
+        if Regular != file.header().entry_type() {
+            continue;
+        }
+        let path = file
+            .path()?
+            .to_string_lossy()
+            .trim_start_matches("./")
+            .to_string();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        if path == "control" {
+            for line in String::from_utf8_lossy(&content).lines() {
+                // " ." is an empty line in the extended description
+                let line = line.trim();
+                if !line.is_empty() && line != "." {
+                    writeln!(oup, "{}{}", ai.line_prefix, line)?;
+                }
+            }
+            continue;
+        }
+        let mut inner = rga_preproc(AdaptInfo {
+            filepath_hint: PathBuf::from(&path),
+            is_real_file: false,
+            inp: Box::new(Cursor::new(content)),
+            line_prefix: format!("{}control/{}: ", ai.line_prefix, path),
+            archive_recursion_depth: ai.archive_recursion_depth + 1,
+            config: ai.config.clone(),
+        })?;
+        std::io::copy(&mut inner, oup)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for DebAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut magic = [0u8; 8];
+        ai.inp.read_exact(&mut magic)?;
+        if magic != AR_MAGIC {
+            bail!("not a debian package");
+        }
+        while let Some(member) = read_ar_header(&mut ai.inp, &[])? {
+            debug!("{}|{}", ai.filepath_hint.display(), member.name);
+            if member.name.starts_with("data.tar") {
+                // the data is always the last member, so it can be streamed into the tar adapter
+                let AdaptInfo {
+                    filepath_hint,
+                    inp,
+                    line_prefix,
+                    archive_recursion_depth,
+                    config,
+                    ..
+                } = ai;
+                let data = tar_stream(&member.name, Box::new(inp.take(member.size)))?;
+                return tar::adapt_entries(
+                    data,
+                    &filepath_hint,
+                    &line_prefix,
+                    archive_recursion_depth,
+                    &config,
+                    oup,
+                );
+            }
+            // debian-binary only contains the format version
+            let data = read_ar_member(&mut ai.inp, &member)?;
+            if member.name.starts_with("control.tar") {
+                adapt_control(&ai, &member.name, data, oup)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn tar(files: &[(&str, &str)]) -> Result<Vec<u8>> {
+        let mut builder = ::tar::Builder::new(Vec::new());
+        for (name, content) in files {
+            let mut header = ::tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content.as_bytes())?;
+        }
+        Ok(builder.into_inner()?)
+    }
+
+    fn ar_member(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut out = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            format!("{}/", name),
+            0,
+            0,
+            0,
+            100644,
+            data.len()
+        )
+        .into_bytes();
+        out.extend(data);
+        if data.len() % 2 == 1 {
+            out.push(b'\n');
+        }
+        out
+    }
+
+    #[test]
+    fn deb() -> Result<()> {
+        let control = tar(&[
+            (
+                "./control",
+                "Package: hello\nVersion: 2.10-3\nDescription: example package\n the classic greeting\n .\n and more\n",
+            ),
+            ("./postinst", "#!/bin/sh\nupdate-alternatives --install hello\n"),
+        ])?;
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&control)?;
+        let data = tar(&[("./usr/share/doc/hello/copyright", "Copyright 1992 FSF\n")])?;
+        let mut deb = b"!<arch>\n".to_vec();
+        deb.extend(ar_member("debian-binary", b"2.0\n"));
+        deb.extend(ar_member("control.tar.gz", &gz.finish()?));
+        deb.extend(ar_member("data.tar", &data));
+
+        let (a, d) = simple_adapt_info(
+            Path::new("hello_2.10-3_amd64.deb"),
+            Box::new(Cursor::new(deb)),
+        );
+        let mut r = DebAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        // plain text members are passed through as is
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Package: hello
+PREFIX:Version: 2.10-3
+PREFIX:Description: example package
+PREFIX:the classic greeting
+PREFIX:and more
+#!/bin/sh
+update-alternatives --install hello
+Copyright 1992 FSF
+"
+        );
+        Ok(())
+    }
+}
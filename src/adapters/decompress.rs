@@ -9,6 +9,7 @@ static EXTENSIONS: &[&str] = &["tgz", "tbz", "tbz2", "gz", "bz2", "xz", "zst"];
 static MIME_TYPES: &[&str] = &[
     "application/gzip",
     "application/x-bzip",
+    "application/x-bzip2",
     "application/x-xz",
     "application/zstd",
 ];
@@ -30,7 +31,9 @@ lazy_static! {
                 .map(|s| SlowMatcher::MimeType(s.to_string()))
                 .collect()
         ),
-        disabled_by_default: false
+        disabled_by_default: false,
+        skip_cache: true,
+        options: vec![]
     };
 }
 #[derive(Default)]
@@ -47,7 +50,7 @@ impl GetMetadata for DecompressAdapter {
     }
 }
 
-fn decompress_any(reason: &SlowMatcher, inp: ReadBox) -> Result<ReadBox> {
+pub fn decompress_any(reason: &SlowMatcher, inp: ReadBox) -> Result<ReadBox> {
     use FastMatcher::*;
     use SlowMatcher::*;
     let gz = |inp: ReadBox| Box::new(flate2::read::MultiGzDecoder::new(inp));
@@ -63,9 +66,10 @@ fn decompress_any(reason: &SlowMatcher, inp: ReadBox) -> Result<ReadBox> {
             "zst" => Box::new(zst(inp)?),
             ext => Err(format_err!("don't know how to decompress {}", ext))?,
         },
+        Fast(FileName(name)) => Err(format_err!("don't know how to decompress {}", name))?,
         MimeType(mime) => match mime.as_ref() {
             "application/gzip" => gz(inp),
-            "application/x-bzip" => bz2(inp),
+            "application/x-bzip" | "application/x-bzip2" => bz2(inp),
             "application/x-xz" => xz(inp),
             "application/zstd" => Box::new(zst(inp)?),
             mime => Err(format_err!("don't know how to decompress mime {}", mime))?,
@@ -114,6 +118,7 @@ impl FileAdapter for DecompressAdapter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::args::RgaConfig;
     use crate::test_utils::*;
     use std::fs::File;
     #[test]
@@ -157,13 +162,54 @@ mod tests {
         r.read_to_end(&mut o)?;
         assert_eq!(
             String::from_utf8(o)?,
-            "hello world
-this is just a test.
+            "PREFIX:Page 1: hello world
+PREFIX:Page 1: this is just a test.
+PREFIX:Page 1: 1
+"
+        );
+        Ok(())
+    }
 
-1
+    fn decompress_file(fname: &str) -> Result<String> {
+        let filepath = test_data_dir().join("../decompress").join(fname);
+        let (a, d) = simple_adapt_info(&filepath, Box::new(File::open(&filepath)?));
+        let mut r = DecompressAdapter.adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
 
-\u{c}"
-        );
+    #[test]
+    fn all_formats() -> Result<()> {
+        for fname in &["test.log.gz", "test.log.bz2", "test.log.xz", "test.log.zst"] {
+            assert_eq!(
+                decompress_file(fname)?,
+                "hello world\nthis is a test\n",
+                "{}",
+                fname
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn mime_without_extension() -> Result<()> {
+        let filepath = test_data_dir().join("../decompress/testlogbutwithoutextension");
+        let args = RgaConfig {
+            accurate: true,
+            ..Default::default()
+        };
+        let mut r = rga_preproc(AdaptInfo {
+            filepath_hint: filepath.clone(),
+            is_real_file: true,
+            archive_recursion_depth: 0,
+            inp: Box::new(File::open(&filepath)?),
+            line_prefix: "".to_string(),
+            config: PreprocConfig { cache: None, args },
+        })?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(String::from_utf8(o)?, "hello world\nthis is a test\n");
         Ok(())
     }
 }
@@ -0,0 +1,207 @@
+use super::*;
+use crate::args::RgaConfig;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use spawning::pipe_output_in_thread;
+use std::io::{BufRead, BufReader, Cursor};
+use std::process::{Command, Stdio};
+
+static EXTENSIONS: &[&str] = &["gpg", "pgp", "asc", "age"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "decrypt".to_owned(),
+        version: 1,
+        description: "Decrypts gpg and age encrypted files and runs a different extractor on the plaintext. Uses the gpg-agent or --rga-decrypt-passphrase-command for gpg, --rga-age-identity for age".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/pgp-encrypted".to_owned()
+        )]),
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![AdapterOption::DecryptPassphraseCommand, AdapterOption::AgeIdentity]
+    };
+}
+#[derive(Default)]
+pub struct DecryptAdapter;
+
+impl DecryptAdapter {
+    pub fn new() -> DecryptAdapter {
+        DecryptAdapter
+    }
+}
+impl GetMetadata for DecryptAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// runs the configured command and returns the first line of its output
+fn get_passphrase(command: &str) -> Result<String> {
+    let out = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .output()
+        .context("could not run passphrase command")?;
+    if !out.status.success() {
+        return Err(format_err!("passphrase command failed: {:?}", out.status));
+    }
+    let out = String::from_utf8(out.stdout).context("passphrase is not valid utf8")?;
+    Ok(out.lines().next().unwrap_or("").to_string())
+}
+
+fn decrypt_gpg(config: &RgaConfig, inp: ReadBox) -> Result<ReadBox> {
+    let mut cmd = Command::new("gpg");
+    // --batch so gpg never prompts on the terminal rg is running in
+    cmd.args(["--batch", "--quiet", "--decrypt"]);
+    let inp: ReadBox = match &config.decrypt_passphrase_command {
+        Some(command) => {
+            // gpg only reads the first line from the passphrase fd, the rest is the ciphertext
+            cmd.args(["--pinentry-mode", "loopback", "--passphrase-fd", "0"]);
+            let passphrase = format!("{}\n", get_passphrase(command)?);
+            Box::new(Cursor::new(passphrase.into_bytes()).chain(inp))
+        }
+        None => inp,
+    };
+    debug!("executing {:?}", cmd);
+    pipe_output_in_thread(cmd, inp, "gpg", "Make sure you have GnuPG installed.")
+}
+
+fn decrypt_age(config: &RgaConfig, inp: ReadBox) -> Result<ReadBox> {
+    let identity = config.age_identity.as_ref().ok_or_else(|| {
+        format_err!("age files can only be decrypted with an identity file, see --rga-age-identity")
+    })?;
+    let mut cmd = Command::new("age");
+    cmd.arg("--decrypt").arg("--identity").arg(identity);
+    debug!("executing {:?}", cmd);
+    pipe_output_in_thread(cmd, inp, "age", "Make sure you have age installed.")
+}
+
+impl FileAdapter for DecryptAdapter {
+    fn adapt(&self, ai: AdaptInfo, detection_reason: &SlowMatcher) -> Result<ReadBox> {
+        let AdaptInfo {
+            filepath_hint,
+            inp,
+            line_prefix,
+            archive_recursion_depth,
+            config,
+            ..
+        } = ai;
+        let extension = match detection_reason {
+            SlowMatcher::Fast(FastMatcher::FileExtension(ext)) => ext.as_str(),
+            _ => "gpg",
+        };
+        let mut inp = BufReader::new(inp);
+        // .asc is also used for signatures, keys and clearsigned text, which don't need decrypting
+        if extension == "asc" {
+            let head = inp.fill_buf()?;
+            let marker = b"-----BEGIN PGP MESSAGE-----";
+            if !head.windows(marker.len()).any(|w| w == marker) {
+                return Ok(Box::new(inp));
+            }
+        }
+        let inp: ReadBox = Box::new(inp);
+        let plaintext = match extension {
+            "age" => decrypt_age(&config.args, inp)?,
+            _ => decrypt_gpg(&config.args, inp)?,
+        };
+        let ai2 = AdaptInfo {
+            // notes.txt.gpg -> notes.txt
+            filepath_hint: filepath_hint.with_extension(""),
+            is_real_file: false,
+            archive_recursion_depth: archive_recursion_depth + 1,
+            inp: plaintext,
+            line_prefix,
+            config: config.clone(),
+        };
+        rga_preproc(ai2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    fn adapt(filepath: &Path, inp: ReadBox, config: RgaConfig) -> Result<String> {
+        let (mut a, d) = simple_adapt_info(filepath, inp);
+        a.config.args = config;
+        let mut r = DecryptAdapter.adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn gpg_passphrase() -> Result<()> {
+        let filepath = test_data_dir().join("secret.txt.gpg");
+        let config = RgaConfig {
+            decrypt_passphrase_command: Some("echo test".to_string()),
+            ..Default::default()
+        };
+        let o = adapt(&filepath, Box::new(File::open(&filepath)?), config)?;
+        assert_eq!(o, "hello from a secret note\n");
+        Ok(())
+    }
+
+    #[test]
+    fn gpg_large() -> Result<()> {
+        // larger than the pipe buffers, gpg writes output before it read all of the ciphertext
+        let plaintext: String = (0..300_000).map(|i| format!("line {}\n", i)).collect();
+        let home = tempfile::tempdir()?;
+        let mut gpg = Command::new("gpg")
+            .arg("--homedir")
+            .arg(home.path())
+            .args(["--batch", "--quiet", "--no-permission-warning"])
+            .args(["--pinentry-mode", "loopback"])
+            .args([
+                "--passphrase",
+                "test",
+                "--compress-algo",
+                "none",
+                "--symmetric",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = gpg.stdin.take().unwrap();
+        let input = plaintext.clone();
+        let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let ciphertext = gpg.wait_with_output()?.stdout;
+        writer.join().unwrap()?;
+        assert!(ciphertext.len() > 2 << 20);
+        let config = RgaConfig {
+            decrypt_passphrase_command: Some("echo test".to_string()),
+            ..Default::default()
+        };
+        let o = adapt(
+            Path::new("large.txt.gpg"),
+            Box::new(Cursor::new(ciphertext)),
+            config,
+        )?;
+        assert_eq!(o, plaintext);
+        Ok(())
+    }
+
+    #[test]
+    fn asc_signature() -> Result<()> {
+        let signature =
+            "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----\n";
+        let o = adapt(
+            Path::new("release.tar.gz.asc"),
+            Box::new(Cursor::new(signature.as_bytes().to_vec())),
+            RgaConfig::default(),
+        )?;
+        assert_eq!(o, signature);
+        Ok(())
+    }
+}
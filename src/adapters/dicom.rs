@@ -0,0 +1,572 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::{BufReader, Cursor};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["dcm", "dicom"];
+static MIME_TYPES: &[&str] = &["application/dicom"];
+
+const PIXEL_DATA: u32 = 0x7fe0_0010;
+const ITEM: u32 = 0xfffe_e000;
+const ITEM_DELIMITER: u32 = 0xfffe_e00d;
+const SEQUENCE_DELIMITER: u32 = 0xfffe_e0dd;
+const UNDEFINED_LENGTH: u32 = 0xffff_ffff;
+
+/// more values than this are usually lookup tables or similar
+const MAX_NUMBERS: usize = 16;
+
+/// the tags that are interesting for searching, and the ones needed to read files with implicit value representations
+static DICTIONARY: &[(u32, &str, &str)] = &[
+    (0x0002_0002, "UI", "MediaStorageSOPClassUID"),
+    (0x0002_0003, "UI", "MediaStorageSOPInstanceUID"),
+    (0x0002_0010, "UI", "TransferSyntaxUID"),
+    (0x0002_0012, "UI", "ImplementationClassUID"),
+    (0x0002_0013, "SH", "ImplementationVersionName"),
+    (0x0002_0016, "AE", "SourceApplicationEntityTitle"),
+    (0x0008_0005, "CS", "SpecificCharacterSet"),
+    (0x0008_0008, "CS", "ImageType"),
+    (0x0008_0012, "DA", "InstanceCreationDate"),
+    (0x0008_0013, "TM", "InstanceCreationTime"),
+    (0x0008_0016, "UI", "SOPClassUID"),
+    (0x0008_0018, "UI", "SOPInstanceUID"),
+    (0x0008_0020, "DA", "StudyDate"),
+    (0x0008_0021, "DA", "SeriesDate"),
+    (0x0008_0022, "DA", "AcquisitionDate"),
+    (0x0008_0023, "DA", "ContentDate"),
+    (0x0008_0030, "TM", "StudyTime"),
+    (0x0008_0031, "TM", "SeriesTime"),
+    (0x0008_0032, "TM", "AcquisitionTime"),
+    (0x0008_0033, "TM", "ContentTime"),
+    (0x0008_0050, "SH", "AccessionNumber"),
+    (0x0008_0060, "CS", "Modality"),
+    (0x0008_0064, "CS", "ConversionType"),
+    (0x0008_0070, "LO", "Manufacturer"),
+    (0x0008_0080, "LO", "InstitutionName"),
+    (0x0008_0081, "ST", "InstitutionAddress"),
+    (0x0008_0090, "PN", "ReferringPhysicianName"),
+    (0x0008_0100, "SH", "CodeValue"),
+    (0x0008_0102, "SH", "CodingSchemeDesignator"),
+    (0x0008_0104, "LO", "CodeMeaning"),
+    (0x0008_1010, "SH", "StationName"),
+    (0x0008_1030, "LO", "StudyDescription"),
+    (0x0008_1032, "SQ", "ProcedureCodeSequence"),
+    (0x0008_103e, "LO", "SeriesDescription"),
+    (0x0008_1040, "LO", "InstitutionalDepartmentName"),
+    (0x0008_1050, "PN", "PerformingPhysicianName"),
+    (0x0008_1060, "PN", "NameOfPhysiciansReadingStudy"),
+    (0x0008_1070, "PN", "OperatorsName"),
+    (0x0008_1080, "LO", "AdmittingDiagnosesDescription"),
+    (0x0008_1090, "LO", "ManufacturerModelName"),
+    (0x0008_1140, "SQ", "ReferencedImageSequence"),
+    (0x0008_1150, "UI", "ReferencedSOPClassUID"),
+    (0x0008_1155, "UI", "ReferencedSOPInstanceUID"),
+    (0x0008_2111, "ST", "DerivationDescription"),
+    (0x0010_0010, "PN", "PatientName"),
+    (0x0010_0020, "LO", "PatientID"),
+    (0x0010_0030, "DA", "PatientBirthDate"),
+    (0x0010_0040, "CS", "PatientSex"),
+    (0x0010_1010, "AS", "PatientAge"),
+    (0x0010_1020, "DS", "PatientSize"),
+    (0x0010_1030, "DS", "PatientWeight"),
+    (0x0010_2160, "SH", "EthnicGroup"),
+    (0x0010_4000, "LT", "PatientComments"),
+    (0x0018_0010, "LO", "ContrastBolusAgent"),
+    (0x0018_0015, "CS", "BodyPartExamined"),
+    (0x0018_0050, "DS", "SliceThickness"),
+    (0x0018_0060, "DS", "KVP"),
+    (0x0018_1000, "LO", "DeviceSerialNumber"),
+    (0x0018_1020, "LO", "SoftwareVersions"),
+    (0x0018_1030, "LO", "ProtocolName"),
+    (0x0018_5100, "CS", "PatientPosition"),
+    (0x0020_000d, "UI", "StudyInstanceUID"),
+    (0x0020_000e, "UI", "SeriesInstanceUID"),
+    (0x0020_0010, "SH", "StudyID"),
+    (0x0020_0011, "IS", "SeriesNumber"),
+    (0x0020_0013, "IS", "InstanceNumber"),
+    (0x0020_4000, "LT", "ImageComments"),
+    (0x0028_0002, "US", "SamplesPerPixel"),
+    (0x0028_0004, "CS", "PhotometricInterpretation"),
+    (0x0028_0010, "US", "Rows"),
+    (0x0028_0011, "US", "Columns"),
+    (0x0028_0100, "US", "BitsAllocated"),
+    (0x0032_1032, "PN", "RequestingPhysician"),
+    (0x0032_1033, "LO", "RequestingService"),
+    (0x0032_1060, "LO", "RequestedProcedureDescription"),
+    (0x0040_0254, "LO", "PerformedProcedureStepDescription"),
+    (0x0040_a040, "CS", "ValueType"),
+    (0x0040_a043, "SQ", "ConceptNameCodeSequence"),
+    (0x0040_a160, "UT", "TextValue"),
+    (0x0040_a730, "SQ", "ContentSequence"),
+    (0x7fe0_0010, "OW", "PixelData"),
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "dicom".to_owned(),
+        version: 1,
+        description: "Writes the tags of DICOM files (patient, study and series descriptions, dates, device info, structured reports), without the pixel data".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+    static ref TAGS: HashMap<u32, (&'static str, &'static str)> = DICTIONARY
+        .iter()
+        .map(|(tag, vr, name)| (*tag, (*vr, *name)))
+        .collect();
+}
+#[derive(Default, Clone)]
+pub struct DicomAdapter;
+
+impl DicomAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(DicomAdapter))
+    }
+}
+impl GetMetadata for DicomAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// how the data set after the file meta information is encoded
+#[derive(Clone, Copy)]
+struct Syntax {
+    /// implicit VR files leave out the value representation, it has to be looked up in the dictionary
+    explicit: bool,
+    big_endian: bool,
+}
+
+impl Syntax {
+    fn from_uid(uid: &str) -> Syntax {
+        match uid {
+            "1.2.840.10008.1.2" => Syntax {
+                explicit: false,
+                big_endian: false,
+            },
+            "1.2.840.10008.1.2.2" => Syntax {
+                explicit: true,
+                big_endian: true,
+            },
+            _ => Syntax {
+                explicit: true,
+                big_endian: false,
+            },
+        }
+    }
+
+    fn u16(&self, b: [u8; 2]) -> u16 {
+        if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    }
+
+    fn u32(&self, b: [u8; 4]) -> u32 {
+        if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+}
+
+struct Element {
+    tag: u32,
+    vr: String,
+    length: u32,
+}
+
+/// reads the tag, value representation and length. None at the end of the input
+fn read_element(inp: &mut dyn Read, syntax: Syntax) -> Result<Option<Element>> {
+    let mut header = [0u8; 8];
+    if inp.read(&mut header[..1])? == 0 {
+        return Ok(None);
+    }
+    inp.read_exact(&mut header[1..])?;
+    let group = syntax.u16([header[0], header[1]]);
+    let element = syntax.u16([header[2], header[3]]);
+    let tag = (group as u32) << 16 | element as u32;
+    let dictionary_vr = || TAGS.get(&tag).map(|t| t.0).unwrap_or("UN").to_string();
+    // items and delimiters never have a value representation
+    if group == 0xfffe || !syntax.explicit {
+        let length = syntax.u32([header[4], header[5], header[6], header[7]]);
+        return Ok(Some(Element {
+            tag,
+            vr: dictionary_vr(),
+            length,
+        }));
+    }
+    let vr = String::from_utf8_lossy(&header[4..6]).to_string();
+    let length = match vr.as_str() {
+        "OB" | "OD" | "OF" | "OL" | "OV" | "OW" | "SQ" | "SV" | "UC" | "UN" | "UR" | "UT"
+        | "UV" => {
+            let mut length = [0u8; 4];
+            inp.read_exact(&mut length)?;
+            syntax.u32(length)
+        }
+        _ => syntax.u16([header[6], header[7]]) as u32,
+    };
+    Ok(Some(Element { tag, vr, length }))
+}
+
+fn read_value(inp: &mut dyn Read, length: u32) -> Result<Vec<u8>> {
+    let mut value = Vec::new();
+    inp.take(length as u64).read_to_end(&mut value)?;
+    if value.len() as u64 != length as u64 {
+        bail!("truncated dicom element");
+    }
+    Ok(value)
+}
+
+fn is_text_vr(vr: &str) -> bool {
+    matches!(
+        vr,
+        "AE" | "AS"
+            | "CS"
+            | "DA"
+            | "DS"
+            | "DT"
+            | "IS"
+            | "LO"
+            | "LT"
+            | "PN"
+            | "SH"
+            | "ST"
+            | "TM"
+            | "UC"
+            | "UI"
+            | "UR"
+            | "UT"
+    )
+}
+
+/// the text in the file's character set. utf-8 and ascii are most common, everything else is read as latin-1
+fn decode_text(value: &[u8]) -> String {
+    let text = match std::str::from_utf8(value) {
+        std::result::Result::Ok(s) => s.to_string(),
+        Err(_) => value.iter().map(|b| *b as char).collect(),
+    };
+    text.trim_end_matches(&[' ', '\0'][..])
+        .trim_start()
+        .to_string()
+}
+
+/// the text of a value, or None if it is binary
+fn format_value(vr: &str, value: &[u8], syntax: Syntax) -> Option<String> {
+    let numbers = |size: usize, format: &dyn Fn(&[u8]) -> String| -> Option<String> {
+        if value.is_empty() || value.len() / size > MAX_NUMBERS {
+            return None;
+        }
+        Some(
+            value
+                .chunks_exact(size)
+                .map(format)
+                .collect::<Vec<_>>()
+                .join("\\"),
+        )
+    };
+    let text = match vr {
+        // `Doe^Jane`
+        "PN" => decode_text(value).replace('^', " "),
+        "DA" => {
+            let date = decode_text(value);
+            if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) {
+                format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+            } else {
+                date
+            }
+        }
+        vr if is_text_vr(vr) => decode_text(value),
+        "US" => numbers(2, &|b| syntax.u16([b[0], b[1]]).to_string())?,
+        "SS" => numbers(2, &|b| (syntax.u16([b[0], b[1]]) as i16).to_string())?,
+        "UL" => numbers(4, &|b| syntax.u32([b[0], b[1], b[2], b[3]]).to_string())?,
+        "SL" => numbers(4, &|b| {
+            (syntax.u32([b[0], b[1], b[2], b[3]]) as i32).to_string()
+        })?,
+        "FL" => numbers(4, &|b| {
+            f32::from_bits(syntax.u32([b[0], b[1], b[2], b[3]])).to_string()
+        })?,
+        // private tags in implicit vr files, which are often text
+        "UN" if value
+            .iter()
+            .all(|b| b.is_ascii_graphic() || *b == b' ' || *b == 0) =>
+        {
+            decode_text(value)
+        }
+        _ => return None,
+    };
+    Some(text).filter(|t| !t.is_empty())
+}
+
+fn tag_name(tag: u32) -> String {
+    match TAGS.get(&tag) {
+        Some((_, name)) => name.to_string(),
+        None => format!("({:04X},{:04X})", tag >> 16, tag & 0xffff),
+    }
+}
+
+/// the items of a sequence contain data sets themselves, either with a length or up to a delimiter
+fn adapt_sequence(
+    inp: &mut dyn Read,
+    length: u32,
+    syntax: Syntax,
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    if length != UNDEFINED_LENGTH {
+        let value = read_value(inp, length)?;
+        return adapt_sequence(
+            &mut Cursor::new(value),
+            UNDEFINED_LENGTH,
+            syntax,
+            line_prefix,
+            oup,
+        );
+    }
+    while let Some(item) = read_element(inp, syntax)? {
+        match item.tag {
+            SEQUENCE_DELIMITER => break,
+            ITEM if item.length == UNDEFINED_LENGTH => {
+                adapt_data_set(inp, syntax, false, line_prefix, oup)?;
+            }
+            ITEM => {
+                let value = read_value(inp, item.length)?;
+                adapt_data_set(&mut Cursor::new(value), syntax, false, line_prefix, oup)?;
+            }
+            _ => bail!("invalid dicom sequence item"),
+        }
+    }
+    Ok(())
+}
+
+/// encapsulated (compressed) pixel data is split into items up to a sequence delimiter. sequences of
+/// unknown tags in implicit vr files look the same, but their items can have an undefined length
+fn skip_fragments(inp: &mut dyn Read, syntax: Syntax) -> Result<()> {
+    while let Some(item) = read_element(inp, syntax)? {
+        match item.tag {
+            SEQUENCE_DELIMITER => break,
+            ITEM if item.length == UNDEFINED_LENGTH => {
+                adapt_data_set(inp, syntax, false, "", &mut std::io::sink())?;
+            }
+            ITEM => {
+                std::io::copy(&mut inp.take(item.length as u64), &mut std::io::sink())?;
+            }
+            _ => bail!("invalid dicom fragment"),
+        }
+    }
+    Ok(())
+}
+
+/// writes the elements up to the end of the input or an item delimiter. the main data set is only read
+/// up to the pixel data, which mostly comes last and can be large
+fn adapt_data_set(
+    inp: &mut dyn Read,
+    syntax: Syntax,
+    is_main: bool,
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    while let Some(element) = read_element(inp, syntax)? {
+        if element.tag == ITEM_DELIMITER || (is_main && element.tag == PIXEL_DATA) {
+            break;
+        }
+        let name = tag_name(element.tag);
+        if element.vr == "SQ" {
+            let line_prefix = format!("{}{}: ", line_prefix, name);
+            adapt_sequence(inp, element.length, syntax, &line_prefix, oup)?;
+            continue;
+        }
+        if element.length == UNDEFINED_LENGTH {
+            skip_fragments(inp, syntax)?;
+            continue;
+        }
+        let value = read_value(inp, element.length)?;
+        // group lengths
+        if element.tag & 0xffff == 0 {
+            continue;
+        }
+        if let Some(text) = format_value(&element.vr, &value, syntax) {
+            writeln!(oup, "{}{}: {}", line_prefix, name, text)?;
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for DicomAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut inp = BufReader::new(ai.inp);
+        let mut preamble = [0u8; 132];
+        inp.read_exact(&mut preamble)?;
+        if &preamble[128..] != b"DICM" {
+            bail!("not a DICOM file (old ACR-NEMA files without a header are not supported)");
+        }
+        // the file meta information is always explicit little endian, and starts with its length
+        let meta_syntax = Syntax::from_uid("");
+        let group_length = read_element(&mut inp, meta_syntax)?
+            .filter(|e| e.tag == 0x0002_0000)
+            .ok_or_else(|| format_err!("DICOM file without meta information"))?;
+        let length = u32::from_le_bytes(
+            read_value(&mut inp, group_length.length)?
+                .get(..4)
+                .and_then(|l| l.try_into().ok())
+                .ok_or_else(|| format_err!("invalid meta information length"))?,
+        );
+        let meta = read_value(&mut inp, length)?;
+        let mut transfer_syntax = String::new();
+        let mut meta_inp = Cursor::new(&meta);
+        while let Some(element) = read_element(&mut meta_inp, meta_syntax)? {
+            let value = read_value(&mut meta_inp, element.length)?;
+            if element.tag == 0x0002_0010 {
+                transfer_syntax = decode_text(&value);
+            }
+        }
+        adapt_data_set(
+            &mut Cursor::new(&meta),
+            meta_syntax,
+            false,
+            &ai.line_prefix,
+            oup,
+        )?;
+        let syntax = Syntax::from_uid(&transfer_syntax);
+        // deflated explicit vr little endian
+        if transfer_syntax == "1.2.840.10008.1.2.1.99" {
+            let mut inflated = flate2::read::DeflateDecoder::new(inp);
+            return adapt_data_set(&mut inflated, syntax, true, &ai.line_prefix, oup);
+        }
+        adapt_data_set(&mut inp, syntax, true, &ai.line_prefix, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn element(syntax: Syntax, tag: u32, vr: &str, value: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(((tag >> 16) as u16).to_le_bytes().iter());
+        out.extend(((tag & 0xffff) as u16).to_le_bytes().iter());
+        let long = matches!(vr, "OB" | "OW" | "SQ" | "UN" | "UT");
+        if syntax.explicit && tag >> 16 != 0xfffe {
+            out.extend(vr.as_bytes());
+            if long {
+                out.extend(&[0, 0]);
+            } else {
+                out.extend((value.len() as u16).to_le_bytes().iter());
+            }
+        }
+        if !syntax.explicit || long || tag >> 16 == 0xfffe {
+            let length = if vr == "SQ" {
+                UNDEFINED_LENGTH
+            } else {
+                value.len() as u32
+            };
+            out.extend(length.to_le_bytes().iter());
+        }
+        out.extend(value);
+        out
+    }
+
+    fn file(transfer_syntax: &str, data_set: Vec<u8>) -> Vec<u8> {
+        let explicit = Syntax::from_uid("");
+        let meta = [
+            element(explicit, 0x0002_0010, "UI", transfer_syntax.as_bytes()),
+            element(explicit, 0x0002_0013, "SH", b"RGA_TEST"),
+        ]
+        .concat();
+        let mut out = vec![0u8; 128];
+        out.extend(b"DICM");
+        out.extend(element(
+            explicit,
+            0x0002_0000,
+            "UL",
+            &(meta.len() as u32).to_le_bytes(),
+        ));
+        out.extend(meta);
+        out.extend(data_set);
+        out
+    }
+
+    fn adapt(data: Vec<u8>) -> Result<String> {
+        let (a, d) = simple_adapt_info(Path::new("image.dcm"), Box::new(Cursor::new(data)));
+        let mut r = DicomAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn explicit() -> Result<()> {
+        let syntax = Syntax::from_uid("");
+        let item = element(syntax, 0x0008_1155, "UI", b"1.2.3.4\0");
+        let data_set = [
+            element(syntax, 0x0008_0020, "DA", b"20200131"),
+            element(syntax, 0x0008_0060, "CS", b"MR"),
+            element(syntax, 0x0008_1030, "LO", b"Brain MRI "),
+            element(syntax, 0x0008_1140, "SQ", &[]),
+            element(syntax, ITEM, "", &item),
+            element(syntax, SEQUENCE_DELIMITER, "", &[]),
+            element(syntax, 0x0009_1001, "LO", b"private"),
+            element(syntax, 0x0010_0010, "PN", b"Doe^Jane"),
+            element(syntax, 0x0028_0010, "US", &512u16.to_le_bytes()),
+            element(syntax, PIXEL_DATA, "OW", &[0xff; 16]),
+        ]
+        .concat();
+        assert_eq!(
+            adapt(file("1.2.840.10008.1.2.1", data_set))?,
+            "PREFIX:TransferSyntaxUID: 1.2.840.10008.1.2.1
+PREFIX:ImplementationVersionName: RGA_TEST
+PREFIX:StudyDate: 2020-01-31
+PREFIX:Modality: MR
+PREFIX:StudyDescription: Brain MRI
+PREFIX:ReferencedImageSequence: ReferencedSOPInstanceUID: 1.2.3.4
+PREFIX:(0009,1001): private
+PREFIX:PatientName: Doe Jane
+PREFIX:Rows: 512
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn implicit() -> Result<()> {
+        let syntax = Syntax::from_uid("1.2.840.10008.1.2");
+        let data_set = [
+            element(syntax, 0x0008_0070, "LO", b"ACME Medical"),
+            element(syntax, 0x0009_0010, "LO", b"ACME 1.0"),
+            element(syntax, 0x0009_1002, "OB", &[0, 1, 2, 3]),
+            element(syntax, 0x0028_0011, "US", &256u16.to_le_bytes()),
+        ]
+        .concat();
+        assert_eq!(
+            adapt(file("1.2.840.10008.1.2", data_set))?,
+            "PREFIX:TransferSyntaxUID: 1.2.840.10008.1.2
+PREFIX:ImplementationVersionName: RGA_TEST
+PREFIX:Manufacturer: ACME Medical
+PREFIX:(0009,0010): ACME 1.0
+PREFIX:Columns: 256
+"
+        );
+        Ok(())
+    }
+}
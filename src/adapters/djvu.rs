@@ -0,0 +1,112 @@
+use super::spawning::map_exe_error;
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::process::{Command, Stdio};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["djvu", "djv"];
+static MIME_TYPES: &[&str] = &["image/vnd.djvu", "image/x-djvu"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "djvu".to_owned(),
+        version: 1,
+        description: "Uses djvutxt (from djvulibre) to extract the hidden text layer of DjVu scans. Lines are prefixed with the page number".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct DjvuAdapter;
+
+impl DjvuAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(DjvuAdapter))
+    }
+}
+impl GetMetadata for DjvuAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// djvutxt separates pages with form feeds
+fn write_pages(line_prefix: &str, text: &str, oup: &mut dyn Write) -> Result<()> {
+    let text = text.strip_suffix('\x0c').unwrap_or(text);
+    for (i, page) in text.split('\x0c').enumerate() {
+        for line in page.lines().filter(|l| !l.trim().is_empty()) {
+            writeln!(oup, "{}Page {}: {}", line_prefix, i + 1, line.trim_end())?;
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for DjvuAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // djvutxt can't read from stdin, since the pages of indirect documents are separate files
+        let _tmp_file;
+        let path = if ai.is_real_file {
+            ai.filepath_hint.clone()
+        } else {
+            let mut tmp = tempfile::Builder::new()
+                .prefix("rga-djvu-")
+                .suffix(".djvu")
+                .tempfile()?;
+            std::io::copy(&mut ai.inp, &mut tmp)?;
+            let path = tmp.path().to_owned();
+            _tmp_file = tmp;
+            path
+        };
+        let out = Command::new("djvutxt")
+            .arg(&path)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| map_exe_error(e, "djvutxt", "Make sure you have djvulibre installed."))?;
+        if !out.status.success() {
+            return Err(format_err!(
+                "djvutxt failed: {:?}: {}",
+                out.status,
+                String::from_utf8_lossy(&out.stderr)
+            ));
+        }
+        write_pages(&ai.line_prefix, &String::from_utf8_lossy(&out.stdout), oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages() -> Result<()> {
+        let mut o = Vec::new();
+        write_pages(
+            "PREFIX:",
+            "hello world\nthis is just a test.\n\x0c\x0cthird page\n\x0c",
+            &mut o,
+        )?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Page 1: hello world\nPREFIX:Page 1: this is just a test.\nPREFIX:Page 3: third page\n"
+        );
+        Ok(())
+    }
+}
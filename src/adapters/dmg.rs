@@ -0,0 +1,226 @@
+use super::spawning::map_exe_error;
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::process::{Command, Stdio};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+// 7z lists the partitions of a disk image as `2.hfs`, `disk image.apfs` etc.
+// so claiming the file system extensions makes the partitions recurse into this adapter again
+static EXTENSIONS: &[&str] = &["dmg", "hfs", "hfsx", "apfs"];
+static MIME_TYPES: &[&str] = &["application/x-apple-diskimage"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "dmg".to_owned(),
+        version: 1,
+        description: "Uses 7z to read Apple disk images (and the HFS+/APFS file systems in them) and recurses down into their contents"
+            .to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct DmgAdapter;
+
+impl DmgAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(DmgAdapter))
+    }
+}
+impl GetMetadata for DmgAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn spawn_fail(e: std::io::Error) -> Error {
+    map_exe_error(e, "7z", "Make sure you have 7-Zip (p7zip-full) installed.")
+}
+
+/// parses the technical listing of `7z l -slt`. the entries are blocks of `Key = Value` lines,
+/// the first block after the `----------` separator is the first entry
+fn parse_listing(listing: &str) -> Vec<String> {
+    let entries = match listing.split_once("\n----------\n") {
+        Some((_, entries)) => entries,
+        None => return Vec::new(),
+    };
+    let mut files = Vec::new();
+    let mut path = None;
+    let mut is_dir = false;
+    for line in entries.lines().chain(std::iter::once("")) {
+        if line.trim().is_empty() {
+            if let Some(path) = path.take() {
+                if !is_dir {
+                    files.push(path);
+                }
+            }
+            is_dir = false;
+        } else if let Some(p) = line.strip_prefix("Path = ") {
+            path = Some(p.to_string());
+        } else if line == "Folder = +" {
+            is_dir = true;
+        }
+    }
+    files
+}
+
+fn list_files(archive_path: &Path) -> Result<Vec<String>> {
+    // -p- prevents 7z from asking for a password
+    let list = Command::new("7z")
+        .args(["l", "-slt", "-p-", "--"])
+        .arg(archive_path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(spawn_fail)?;
+    if !list.status.success() {
+        return Err(format_err!(
+            "7z failed: {:?}: {}",
+            list.status,
+            String::from_utf8_lossy(&list.stderr)
+        ));
+    }
+    Ok(parse_listing(&String::from_utf8_lossy(&list.stdout)))
+}
+
+/// extracts every file that 7z finds in the archive and recurses into it.
+/// `member_hint` gives the file name that the adapter of a member is chosen by
+pub fn adapt_with_7z(
+    archive_path: &Path,
+    line_prefix: &str,
+    archive_recursion_depth: i32,
+    config: &PreprocConfig,
+    member_hint: fn(&str) -> PathBuf,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for name in list_files(archive_path)? {
+        debug!("{}|{}", archive_path.display(), name);
+        // -spd disables wildcard matching so the name is taken literally
+        let mut cmd = Command::new("7z")
+            .args(["x", "-so", "-spd", "-p-", "--"])
+            .arg(archive_path)
+            .arg(&name)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(spawn_fail)?;
+        let stdo = cmd.stdout.take().expect("is piped");
+        let mut member = rga_preproc(AdaptInfo {
+            filepath_hint: member_hint(&name),
+            is_real_file: false,
+            archive_recursion_depth: archive_recursion_depth + 1,
+            inp: Box::new(stdo),
+            line_prefix: format!("{}{}: ", line_prefix, name),
+            config: config.clone(),
+        })?;
+        std::io::copy(&mut member, oup)?;
+        drop(member);
+        // fails with a broken pipe if the inner adapter did not read the whole file, which is fine
+        let status = cmd.wait()?;
+        if !status.success() {
+            debug!("7z x {} exited with {:?}", name, status);
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for DmgAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let AdaptInfo {
+            filepath_hint,
+            is_real_file,
+            mut inp,
+            line_prefix,
+            archive_recursion_depth,
+            config,
+        } = ai;
+        // 7z needs to seek in the image, so a partition or an image within an archive has to be written to disk first
+        let _tmp_file;
+        let archive_path = if is_real_file {
+            filepath_hint.clone()
+        } else {
+            let extension = filepath_hint
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy()))
+                .unwrap_or_default();
+            let mut tmp = tempfile::Builder::new()
+                .prefix("rga-dmg-")
+                .suffix(&extension)
+                .tempfile()?;
+            std::io::copy(&mut inp, &mut tmp)?;
+            let path = tmp.path().to_owned();
+            _tmp_file = tmp;
+            path
+        };
+        adapt_with_7z(
+            &archive_path,
+            &line_prefix,
+            archive_recursion_depth,
+            &config,
+            |name| PathBuf::from(name),
+            oup,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing() {
+        let listing = "
+7-Zip [64] 16.02 : Copyright (c) 1999-2016 Igor Pavlov : 2016-05-21
+
+Scanning the drive for archives:
+1 file, 48836 bytes (48 KiB)
+
+Listing archive: Installer.dmg
+
+--
+Path = Installer.dmg
+Type = Dmg
+Physical Size = 48836
+
+----------
+Path = Installer/Installer.app
+Size = 0
+Folder = +
+
+Path = Installer/Installer.app/Contents/Info.plist
+Size = 1420
+Folder = -
+
+Path = Installer/.background/background.png
+Size = 30211
+Folder = -
+";
+        assert_eq!(
+            parse_listing(listing),
+            vec![
+                "Installer/Installer.app/Contents/Info.plist",
+                "Installer/.background/background.png"
+            ]
+        );
+    }
+}
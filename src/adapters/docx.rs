@@ -0,0 +1,356 @@
+use super::xps::attribute;
+use super::*;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use quick_xml::events::{BytesStart, Event};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufReader, Cursor};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["docx"];
+
+/// parts of the document that contain text, in output order. missing parts are skipped
+static TEXT_PARTS: &[&str] = &[
+    "word/document.xml",
+    "word/footnotes.xml",
+    "word/endnotes.xml",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "docx".to_owned(),
+        version: 1,
+        description: "Reads the text of Word documents directly from the OOXML container (does not need pandoc). With --rga-docx-revisions, tracked changes and comments are written after the paragraph they are in, as `Deleted (author): text`".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        // docx files are usually detected as plain zip files by tree_magic
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![AdapterOption::DocxRevisions]
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct DocxAdapter;
+
+impl DocxAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(DocxAdapter))
+    }
+}
+impl GetMetadata for DocxAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// reviewer comments by id, with their author and paragraphs
+type Comments = HashMap<String, (String, Vec<String>)>;
+
+fn author(e: &BytesStart) -> String {
+    match attribute(e, b"author") {
+        Some(author) if !author.is_empty() => format!(" ({})", author),
+        _ => String::new(),
+    }
+}
+
+/// word/comments.xml has the text of the comments, the document only marks the commented range
+fn read_comments(inp: impl BufRead) -> Result<Comments> {
+    let mut reader = quick_xml::Reader::from_reader(inp);
+    let mut buf = Vec::new();
+    let mut comments = Comments::new();
+    let mut current: Option<(String, String, Vec<String>)> = None;
+    let mut line = String::new();
+    let mut in_text = false;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"comment" => {
+                    current = Some((
+                        attribute(&e, b"id").unwrap_or_default(),
+                        author(&e),
+                        Vec::new(),
+                    ))
+                }
+                b"t" => in_text = true,
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => {
+                    if let Some((_, _, paragraphs)) = &mut current {
+                        paragraphs.push(std::mem::take(&mut line));
+                    }
+                }
+                b"comment" => {
+                    if let Some((id, author, paragraphs)) = current.take() {
+                        comments.insert(id, (author, paragraphs));
+                    }
+                }
+                _ => {}
+            },
+            Event::Empty(e) if e.local_name().as_ref() == b"tab" => line.push('\t'),
+            Event::Text(t) if in_text => line.push_str(&t.unescape()?),
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(comments)
+}
+
+/// the text of the current paragraph, and the revisions and comments in it,
+/// which are written after it as `Inserted (author): text`
+#[derive(Default)]
+struct Paragraph {
+    line: String,
+    /// the lines to write after the paragraph
+    annotations: Vec<String>,
+    /// the tracked change we are in: its kind, author and text so far
+    revision: Option<(&'static str, String, String)>,
+    /// the commented ranges that are open, by comment id, with their text so far
+    commented: Vec<(String, String)>,
+}
+
+impl Paragraph {
+    fn push_str(&mut self, text: &str) {
+        self.line.push_str(text);
+        for (_, quote) in &mut self.commented {
+            quote.push_str(text);
+        }
+        if let Some((_, _, revision)) = &mut self.revision {
+            revision.push_str(text);
+        }
+    }
+
+    fn end_comment(&mut self, id: &str, comments: &Comments, written: &mut HashSet<String>) {
+        let quote = match self.commented.iter().position(|(i, _)| i == id) {
+            Some(i) => self.commented.remove(i).1,
+            None => String::new(),
+        };
+        if !written.insert(id.to_string()) {
+            return;
+        }
+        if let Some((author, paragraphs)) = comments.get(id) {
+            let quote = quote.split_whitespace().collect::<Vec<_>>().join(" ");
+            let on = if quote.is_empty() {
+                String::new()
+            } else {
+                format!(" on \"{}\"", quote)
+            };
+            for paragraph in paragraphs.iter().filter(|p| !p.trim().is_empty()) {
+                self.annotations
+                    .push(format!("Comment{}{}: {}", author, on, paragraph));
+            }
+        }
+    }
+
+    fn write(&mut self, line_prefix: &str, oup: &mut dyn Write) -> Result<()> {
+        writeln!(oup, "{}{}", line_prefix, self.line)?;
+        self.line.clear();
+        for annotation in self.annotations.drain(..) {
+            writeln!(oup, "{}{}", line_prefix, annotation)?;
+        }
+        // commented ranges can span paragraphs
+        for (_, quote) in &mut self.commented {
+            quote.push(' ');
+        }
+        Ok(())
+    }
+}
+
+/// write the text of one WordprocessingML part, one paragraph per line.
+/// with comments, tracked changes and comments are written after the paragraph they are in
+fn write_part_text(
+    line_prefix: &str,
+    inp: impl BufRead,
+    comments: Option<&Comments>,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let mut reader = quick_xml::Reader::from_reader(inp);
+    let mut buf = Vec::new();
+    let mut paragraph = Paragraph::default();
+    let mut in_text = false;
+    let mut in_deleted_text = false;
+    let mut written_comments = HashSet::new();
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        if let (Some(comments), Event::Start(e) | Event::Empty(e)) = (comments, &event) {
+            match e.local_name().as_ref() {
+                b"commentRangeStart" => paragraph
+                    .commented
+                    .push((attribute(e, b"id").unwrap_or_default(), String::new())),
+                b"commentRangeEnd" | b"commentReference" => paragraph.end_comment(
+                    &attribute(e, b"id").unwrap_or_default(),
+                    comments,
+                    &mut written_comments,
+                ),
+                _ => {}
+            }
+        }
+        match event {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"t" => in_text = true,
+                b"delText" => in_deleted_text = true,
+                // an empty ins or del in the run properties marks a changed paragraph mark
+                b"ins" if comments.is_some() => {
+                    paragraph.revision = Some(("Inserted", author(&e), String::new()))
+                }
+                b"del" if comments.is_some() => {
+                    paragraph.revision = Some(("Deleted", author(&e), String::new()))
+                }
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"delText" => in_deleted_text = false,
+                b"ins" | b"del" => {
+                    if let Some((kind, author, text)) = paragraph.revision.take() {
+                        if !text.trim().is_empty() {
+                            paragraph.annotations.push(format!(
+                                "{}{}: {}",
+                                kind,
+                                author,
+                                text.trim()
+                            ));
+                        }
+                    }
+                }
+                b"p" => paragraph.write(line_prefix, oup)?,
+                _ => {}
+            },
+            Event::Empty(e) => match e.local_name().as_ref() {
+                b"tab" => paragraph.push_str("\t"),
+                b"p" | b"br" | b"cr" => paragraph.write(line_prefix, oup)?,
+                _ => {}
+            },
+            Event::Text(t) if in_text => paragraph.push_str(&t.unescape()?),
+            // deleted text is not part of the document, only of the revision
+            Event::Text(t) if in_deleted_text => {
+                if let Some((_, _, text)) = &mut paragraph.revision {
+                    text.push_str(&t.unescape()?);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    if !paragraph.line.is_empty() || !paragraph.annotations.is_empty() {
+        paragraph.write(line_prefix, oup)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for DocxAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let AdaptInfo {
+            mut inp,
+            line_prefix,
+            config,
+            ..
+        } = ai;
+        // zip needs to seek to the central directory, so read the whole file to memory
+        let mut data = Vec::new();
+        inp.read_to_end(&mut data)?;
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+        let comments = if config.args.docx_revisions {
+            match archive.by_name("word/comments.xml") {
+                Ok(file) => Some(read_comments(BufReader::new(file))?),
+                Err(::zip::result::ZipError::FileNotFound) => Some(Comments::new()),
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            None
+        };
+        for part in TEXT_PARTS {
+            let file = match archive.by_name(part) {
+                Ok(file) => file,
+                Err(::zip::result::ZipError::FileNotFound) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            write_part_text(&line_prefix, BufReader::new(file), comments.as_ref(), oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+
+    #[test]
+    fn simple() -> Result<()> {
+        let adapter: Box<dyn FileAdapter> = Box::new(DocxAdapter::new());
+        let fname = test_data_dir().join("short.docx");
+        let rd = File::open(&fname)?;
+        let (a, d) = simple_adapt_info(&fname, Box::new(rd));
+        let mut res = adapter.adapt(a, &d)?;
+
+        let mut buf = Vec::new();
+        res.read_to_end(&mut buf)?;
+
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "PREFIX:hello world\nPREFIX:this is just a\ttest.\nPREFIX:\nPREFIX:a footnote\n",
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn revisions() -> Result<()> {
+        let document = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+<w:p><w:r><w:t xml:space="preserve">The tenant pays </w:t></w:r><w:del w:id="1" w:author="Alice" w:date="2020-01-01T00:00:00Z"><w:r><w:delText>500</w:delText></w:r></w:del><w:ins w:id="2" w:author="Bob"><w:r><w:t>600</w:t></w:r></w:ins><w:r><w:t xml:space="preserve"> EUR </w:t></w:r><w:commentRangeStart w:id="0"/><w:r><w:t>per month</w:t></w:r><w:commentRangeEnd w:id="0"/><w:r><w:commentReference w:id="0"/></w:r>.</w:p>
+<w:p><w:r><w:t>end</w:t></w:r></w:p>
+</w:body></w:document>"#;
+        let comments = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:comments xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:comment w:id="0" w:author="Carol" w:initials="C"><w:p><w:r><w:t>too vague</w:t></w:r></w:p><w:p><w:r><w:t>which month?</w:t></w:r></w:p></w:comment>
+</w:comments>"#;
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        zip.start_file("word/document.xml", options)?;
+        zip.write_all(document.as_bytes())?;
+        zip.start_file("word/comments.xml", options)?;
+        zip.write_all(comments.as_bytes())?;
+        let data = zip.finish()?.into_inner();
+
+        let adapt = |revisions: bool| -> Result<String> {
+            let (mut a, d) =
+                simple_adapt_info(Path::new("lease.docx"), Box::new(Cursor::new(data.clone())));
+            a.config.args.docx_revisions = revisions;
+            let mut res = DocxAdapter::new().adapt(a, &d)?;
+            let mut buf = Vec::new();
+            res.read_to_end(&mut buf)?;
+            Ok(String::from_utf8(buf)?)
+        };
+        assert_eq!(
+            adapt(false)?,
+            "PREFIX:The tenant pays 600 EUR per month\nPREFIX:end\n"
+        );
+        assert_eq!(
+            adapt(true)?,
+            "PREFIX:The tenant pays 600 EUR per month
+PREFIX:Deleted (Alice): 500
+PREFIX:Inserted (Bob): 600
+PREFIX:Comment (Carol) on \"per month\": too vague
+PREFIX:Comment (Carol) on \"per month\": which month?
+PREFIX:end
+"
+        );
+        Ok(())
+    }
+}
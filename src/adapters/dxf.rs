@@ -0,0 +1,311 @@
+use super::spawning::map_exe_error;
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::process::{Command, Stdio};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["dxf", "dwg"];
+static MIME_TYPES: &[&str] = &["image/vnd.dxf", "image/vnd.dwg", "application/acad"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "dxf".to_owned(),
+        version: 1,
+        description: "Extracts the layer names and the text, multiline text, attribute and dimension entities of CAD drawings. Lines are prefixed with the layer. DWG files are converted with dwg2dxf (from LibreDWG)".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct DxfAdapter;
+
+impl DxfAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(DxfAdapter))
+    }
+}
+impl GetMetadata for DxfAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// replaces the `%%` control codes of TEXT entities
+fn clean_text(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find("%%") {
+        out.push_str(&rest[..pos]);
+        let code = rest[pos + 2..].chars().next();
+        match code.map(|c| c.to_ascii_lowercase()) {
+            Some('c') => out.push('Ø'),
+            Some('d') => out.push('°'),
+            Some('p') => out.push('±'),
+            Some('%') => out.push('%'),
+            // under- and overline toggles
+            Some('u') | Some('o') => {}
+            _ => {
+                out.push_str("%%");
+                rest = &rest[pos + 2..];
+                continue;
+            }
+        }
+        rest = &rest[pos + 2 + code.map_or(0, char::len_utf8)..];
+    }
+    out.push_str(rest);
+    decode_unicode_escapes(&out)
+}
+
+/// `\U+00E9` is used for characters outside of the drawing's code page
+fn decode_unicode_escapes(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(pos) = rest.find("\\U+") {
+        out.push_str(&rest[..pos]);
+        let c = rest
+            .get(pos + 3..pos + 7)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(std::char::from_u32);
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[pos + 7..];
+            }
+            None => {
+                out.push_str("\\U+");
+                rest = &rest[pos + 3..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// strips the inline formatting of MTEXT entities, `\P` starts a new paragraph
+fn clean_mtext(text: &str) -> String {
+    let text = decode_unicode_escapes(text);
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '\\' => match chars.next() {
+                Some('P') | Some('X') => out.push('\n'),
+                Some('~') => out.push(' '),
+                Some(c @ '\\') | Some(c @ '{') | Some(c @ '}') => out.push(c),
+                // stacked fractions: \S1/2; or \S1^2;
+                Some('S') => {
+                    for c in chars.by_ref() {
+                        match c {
+                            ';' => break,
+                            '^' | '#' => out.push('/'),
+                            c => out.push(c),
+                        }
+                    }
+                }
+                // font, height, color, alignment, ... up to the semicolon
+                Some('f') | Some('F') | Some('H') | Some('h') | Some('C') | Some('c')
+                | Some('A') | Some('a') | Some('Q') | Some('q') | Some('W') | Some('w')
+                | Some('T') | Some('t') | Some('p') => {
+                    for c in chars.by_ref() {
+                        if c == ';' {
+                            break;
+                        }
+                    }
+                }
+                // underline, overline, strike-through toggles
+                Some('L') | Some('l') | Some('O') | Some('o') | Some('K') | Some('k') => {}
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    clean_text(&out)
+}
+
+#[derive(Default)]
+struct Entity {
+    kind: String,
+    layer: String,
+    name: String,
+    text: String,
+    /// mtext longer than 250 characters is split into chunks
+    chunks: String,
+}
+
+fn write_entity(
+    line_prefix: &str,
+    section: &str,
+    entity: &Entity,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let text = match (section, entity.kind.as_str()) {
+        ("TABLES", "LAYER") => {
+            writeln!(oup, "{}Layer: {}", line_prefix, entity.name)?;
+            return Ok(());
+        }
+        (_, "TEXT") | (_, "ATTRIB") | (_, "ATTDEF") => clean_text(&entity.text),
+        (_, "MTEXT") => clean_mtext(&format!("{}{}", entity.chunks, entity.text)),
+        (_, "MULTILEADER") | (_, "MLEADER") => clean_mtext(&entity.text),
+        // "<>" is the measured value
+        (_, "DIMENSION") => clean_mtext(&entity.text.replace("<>", "")),
+        _ => return Ok(()),
+    };
+    let layer = if entity.layer.is_empty() {
+        "0"
+    } else {
+        &entity.layer
+    };
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        writeln!(oup, "{}{}: {}", line_prefix, layer, line)?;
+    }
+    Ok(())
+}
+
+/// ascii DXF is a list of group code / value line pairs
+fn write_dxf(line_prefix: &str, dxf: &str, oup: &mut dyn Write) -> Result<()> {
+    let mut lines = dxf.lines();
+    let mut section = String::new();
+    let mut entity = Entity::default();
+    while let (Some(code), Some(value)) = (lines.next(), lines.next()) {
+        let code: i32 = code
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid group code {:?}", code))?;
+        let value = value.trim_end_matches('\r');
+        match code {
+            0 => {
+                write_entity(line_prefix, &section, &entity, oup)?;
+                if value == "ENDSEC" {
+                    section.clear();
+                }
+                entity = Entity {
+                    kind: value.trim().to_string(),
+                    ..Default::default()
+                };
+            }
+            2 if entity.kind == "SECTION" => section = value.trim().to_string(),
+            2 => entity.name = value.trim().to_string(),
+            8 => entity.layer = value.trim().to_string(),
+            1 => entity.text = value.to_string(),
+            3 if entity.kind == "MTEXT" => entity.chunks.push_str(value),
+            304 => entity.text = value.to_string(),
+            _ => {}
+        }
+    }
+    write_entity(line_prefix, &section, &entity, oup)
+}
+
+/// DXF before AutoCAD 2007 is in the windows code page of the drawing, later versions are utf8
+fn decode(data: &[u8]) -> Result<String> {
+    if data.starts_with(b"AutoCAD Binary DXF") {
+        bail!("binary DXF files are not supported");
+    }
+    Ok(match std::str::from_utf8(data) {
+        Result::Ok(s) => s.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(data).0.into_owned(),
+    })
+}
+
+/// uses dwg2dxf from LibreDWG to convert a DWG drawing
+fn dwg_to_dxf(ai: &AdaptInfo, data: &[u8]) -> Result<Vec<u8>> {
+    let tmp_dir = tempfile::Builder::new().prefix("rga-dwg-").tempdir()?;
+    let input = if ai.is_real_file {
+        ai.filepath_hint.clone()
+    } else {
+        let input = tmp_dir.path().join("input.dwg");
+        std::fs::write(&input, data)?;
+        input
+    };
+    let output = tmp_dir.path().join("output.dxf");
+    let out = Command::new("dwg2dxf")
+        .arg("-y")
+        .arg("-o")
+        .arg(&output)
+        .arg(&input)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| map_exe_error(e, "dwg2dxf", "Make sure you have LibreDWG installed."))?;
+    if !out.status.success() {
+        return Err(format_err!(
+            "dwg2dxf failed: {:?}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr)
+        ));
+    }
+    Ok(std::fs::read(&output)?)
+}
+
+impl WritingFileAdapterTrait for DxfAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        // dwg files start with the version, e.g. AC1032
+        if data.starts_with(b"AC") {
+            data = dwg_to_dxf(&ai, &data)?;
+        }
+        write_dxf(&ai.line_prefix, &decode(&data)?, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    static DXF: &str = "  0\r\nSECTION\r\n  2\r\nHEADER\r\n  9\r\n$ACADVER\r\n  1\r\nAC1015\r\n  0\r\nENDSEC\r\n  0\r\nSECTION\r\n  2\r\nTABLES\r\n  0\r\nTABLE\r\n  2\r\nLAYER\r\n  0\r\nLAYER\r\n  2\r\n0\r\n 70\r\n0\r\n  0\r\nLAYER\r\n  2\r\nTITLEBLOCK\r\n 70\r\n0\r\n  0\r\nENDTAB\r\n  0\r\nENDSEC\r\n  0\r\nSECTION\r\n  2\r\nENTITIES\r\n  0\r\nTEXT\r\n  8\r\nTITLEBLOCK\r\n 10\r\n0.0\r\n 20\r\n0.0\r\n  1\r\nPART NO. 4711-A %%c12\r\n  0\r\nMTEXT\r\n  8\r\nNOTES\r\n  3\r\n{\\fArial|b1;Material:} Stainless\r\n  1\r\n steel\\PFinish: \\H2.5;polished\r\n  0\r\nDIMENSION\r\n  8\r\nDIM\r\n  1\r\n<> TYP.\r\n  0\r\nLINE\r\n  8\r\n0\r\n  0\r\nENDSEC\r\n  0\r\nEOF\r\n";
+
+    #[test]
+    fn dxf() -> Result<()> {
+        let (a, d) = simple_adapt_info(
+            Path::new("bracket.dxf"),
+            Box::new(Cursor::new(DXF.as_bytes().to_vec())),
+        );
+        let mut r = DxfAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Layer: 0
+PREFIX:Layer: TITLEBLOCK
+PREFIX:TITLEBLOCK: PART NO. 4711-A Ø12
+PREFIX:NOTES: Material: Stainless steel
+PREFIX:NOTES: Finish: polished
+PREFIX:DIM: TYP.
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn mtext() {
+        assert_eq!(
+            clean_mtext("\\A1;{\\C1;red} \\Lunder\\l 1\\S1/2; \\U+00B1\\{x\\}"),
+            "red under 11/2 ±{x}"
+        );
+        assert_eq!(clean_text("100%%d %%%"), "100° %");
+    }
+}
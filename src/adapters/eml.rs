@@ -0,0 +1,180 @@
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["eml"];
+
+/// headers that are worth searching in. the rest is mostly routing information
+static HEADERS: &[&str] = &["From", "To", "Cc", "Date", "Subject"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "mail".to_owned(),
+        version: 1,
+        description: "Reads RFC822 email messages (.eml). Outputs the main headers and the text body and recurses into attachments".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType("message/rfc822".to_owned())]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![AdapterOption::HtmlLinks]
+    };
+}
+#[derive(Default, Clone)]
+pub struct EmlAdapter;
+
+impl EmlAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(EmlAdapter))
+    }
+}
+impl GetMetadata for EmlAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn write_lines(line_prefix: &str, text: &str, oup: &mut dyn Write) -> Result<()> {
+    // the line break before a mime boundary belongs to the boundary, not the body
+    for line in text.trim_end().lines() {
+        writeln!(oup, "{}{}", line_prefix, line)?;
+    }
+    Ok(())
+}
+
+/// pick the part of a multipart/alternative that is best suited for searching
+fn best_alternative<'a, 'b>(alternatives: &'b [ParsedMail<'a>]) -> Option<&'b ParsedMail<'a>> {
+    alternatives
+        .iter()
+        .find(|p| p.ctype.mimetype == "text/plain")
+        .or_else(|| alternatives.first())
+}
+
+fn write_part(
+    part: &ParsedMail,
+    ai: &AdaptInfo,
+    attachment_index: &mut usize,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let mimetype = part.ctype.mimetype.as_str();
+    if mimetype == "multipart/alternative" {
+        if let Some(part) = best_alternative(&part.subparts) {
+            write_part(part, ai, attachment_index, oup)?;
+        }
+        return Ok(());
+    }
+    if mimetype.starts_with("multipart/") {
+        for subpart in &part.subparts {
+            write_part(subpart, ai, attachment_index, oup)?;
+        }
+        return Ok(());
+    }
+    let disposition = part.get_content_disposition();
+    let filename = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"));
+    let is_attachment = disposition.disposition == DispositionType::Attachment
+        || filename.is_some()
+        || !(mimetype == "text/plain" || mimetype == "text/html");
+    if !is_attachment {
+        let body = part.get_body()?;
+        if mimetype == "text/html" {
+            super::html::write_html_text(&ai.line_prefix, &body, ai.config.args.html_links, oup)?;
+        } else {
+            write_lines(&ai.line_prefix, &body, oup)?;
+        }
+        return Ok(());
+    }
+    *attachment_index += 1;
+    let name = match filename {
+        Some(filename) => filename.clone(),
+        None if mimetype == "message/rfc822" => format!("attachment{}.eml", attachment_index),
+        None => format!("attachment{}", attachment_index),
+    };
+    let data = part.get_body_raw()?;
+    debug!(
+        "{}|{}: {} ({})",
+        ai.filepath_hint.display(),
+        name,
+        mimetype,
+        crate::print_bytes(data.len() as f64)
+    );
+    let mut inner = rga_preproc(AdaptInfo {
+        filepath_hint: PathBuf::from(&name),
+        is_real_file: false,
+        archive_recursion_depth: ai.archive_recursion_depth + 1,
+        inp: Box::new(Cursor::new(data)),
+        line_prefix: format!("{}{}: ", ai.line_prefix, name),
+        config: ai.config.clone(),
+    })?;
+    std::io::copy(&mut inner, oup)?;
+    Ok(())
+}
+
+/// write the headers, text body and attachments of a single message
+pub fn write_mail(message: &[u8], ai: &AdaptInfo, oup: &mut dyn Write) -> Result<()> {
+    let mail = mailparse::parse_mail(message)?;
+    for &header in HEADERS {
+        if let Some(value) = mail.headers.get_first_value(header) {
+            writeln!(oup, "{}{}: {}", ai.line_prefix, header, value)?;
+        }
+    }
+    write_part(&mail, ai, &mut 0, oup)
+}
+
+impl WritingFileAdapterTrait for EmlAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut message = Vec::new();
+        ai.inp.read_to_end(&mut message)?;
+        write_mail(&message, &ai, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+
+    fn adapt(filepath: &Path, inp: ReadBox) -> Result<String> {
+        let (a, d) = simple_adapt_info(filepath, inp);
+        let mut r = EmlAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn simple() -> Result<()> {
+        let filepath = test_data_dir().join("short.eml");
+        assert_eq!(
+            adapt(&filepath, Box::new(File::open(&filepath)?))?,
+            "PREFIX:From: Alice <alice@example.com>\nPREFIX:To: bob@example.com\nPREFIX:Date: Mon, 01 Jun 2020 12:00:00 +0000\nPREFIX:Subject: Grüße from the test suite\nPREFIX:hello world\nPREFIX:this is the plain text body.\nPREFIX:short.docx: hello world\nPREFIX:short.docx: this is just a\ttest.\nPREFIX:short.docx: \nPREFIX:short.docx: a footnote\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn html_only() -> Result<()> {
+        let message = b"Subject: hi\r\nContent-Type: text/html; charset=iso-8859-1\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n<p>sch=F6ne</p><p>Gr=FC=DFe<br>bob</p>\r\n";
+        assert_eq!(
+            adapt(Path::new("hi.eml"), Box::new(Cursor::new(&message[..])))?,
+            "PREFIX:Subject: hi\nPREFIX:schöne\nPREFIX:Grüße\nPREFIX:bob\n"
+        );
+        Ok(())
+    }
+}
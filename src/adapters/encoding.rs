@@ -0,0 +1,162 @@
+use super::spawning::postproc_line_prefix;
+use super::*;
+use anyhow::*;
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use lazy_static::lazy_static;
+use log::*;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &[
+    "txt", "text", "csv", "tsv", "log", "ini", "inf", "reg", "nfo", "srt",
+];
+
+/// the encoding is guessed from the start of the file
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "encoding".to_owned(),
+        version: 1,
+        description: "Detects the encoding of text files (UTF-16 without a byte order mark, Shift-JIS, GBK, KOI8-R, Windows-1252, ...) and converts them to UTF-8. Disabled by default since most text files are already UTF-8 and rg reads them faster by itself".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType("text/plain".to_owned())]),
+        disabled_by_default: true,
+        skip_cache: true,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct EncodingAdapter;
+
+impl EncodingAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(EncodingAdapter))
+    }
+}
+impl GetMetadata for EncodingAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// chardetng doesn't detect UTF-16, but text in it without a byte order mark is easy to
+/// recognize: most characters are ascii, so every other byte is zero
+fn utf16_without_bom(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs < 4 {
+        return None;
+    }
+    let zeros_at = |offset: usize| {
+        sample
+            .chunks_exact(2)
+            .filter(|pair| pair[offset] == 0 && pair[1 - offset] != 0)
+            .count()
+    };
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    if odd * 10 > pairs * 4 && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 10 > pairs * 4 && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// the encoding of a text, None if it starts with a byte order mark
+fn detect(sample: &[u8], complete: bool) -> Option<&'static Encoding> {
+    if Encoding::for_bom(sample).is_some() {
+        return None;
+    }
+    if let Some(encoding) = utf16_without_bom(sample) {
+        return Some(encoding);
+    }
+    let mut detector = EncodingDetector::new(Iso2022JpDetection::Allow);
+    detector.feed(sample, complete);
+    Some(detector.guess(None, Utf8Detection::Allow))
+}
+
+impl WritingFileAdapterTrait for EncodingAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut sample = Vec::new();
+        (&mut ai.inp).take(SAMPLE_SIZE).read_to_end(&mut sample)?;
+        let encoding = detect(&sample, (sample.len() as u64) < SAMPLE_SIZE);
+        debug!(
+            "{}: detected encoding {}",
+            ai.filepath_hint.display(),
+            encoding.map(|e| e.name()).unwrap_or("from byte order mark")
+        );
+        let inp = Cursor::new(sample).chain(ai.inp);
+        let mut decoded = DecodeReaderBytesBuilder::new()
+            .encoding(encoding)
+            .bom_sniffing(true)
+            .strip_bom(true)
+            .build(inp);
+        postproc_line_prefix(&ai.line_prefix, &mut decoded, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn adapt(data: Vec<u8>) -> Result<String> {
+        let (a, d) = simple_adapt_info(Path::new("export.csv"), Box::new(Cursor::new(data)));
+        let mut r = EncodingAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn utf16() -> Result<()> {
+        let text = "name;city\nJürgen;Köln\n";
+        let with_bom: Vec<u8> = std::iter::once(0xfeff)
+            .chain(text.encode_utf16())
+            .flat_map(|u: u16| u.to_le_bytes())
+            .collect();
+        let without_bom: Vec<u8> = text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+        let expected = "PREFIX:name;city\nPREFIX:Jürgen;Köln\n";
+        assert_eq!(adapt(with_bom)?, expected);
+        assert_eq!(adapt(without_bom)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_encodings() -> Result<()> {
+        for (text, encoding) in &[
+            (
+                "東京都千代田区の天気予報です。明日は晴れでしょう。",
+                encoding_rs::SHIFT_JIS,
+            ),
+            (
+                "Привет, как дела? Это проверка кодировки текста.",
+                encoding_rs::KOI8_R,
+            ),
+            (
+                "Ölförbrukning och säkerhet på vägarna i Sverige.",
+                encoding_rs::WINDOWS_1252,
+            ),
+        ] {
+            let (data, _, _) = encoding.encode(text);
+            assert_eq!(adapt(data.into_owned())?, format!("PREFIX:{}\n", text));
+        }
+        assert_eq!(
+            adapt("already utf-8: ✓\n".into())?,
+            "PREFIX:already utf-8: ✓\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use super::xml::{text_of, xml_reader};
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use quick_xml::events::{BytesStart, Event};
+use std::io::{BufReader, Cursor};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["epub"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "epub".to_owned(),
+        version: 1,
+        description:
+            "Extracts the text of the chapters of EPUB ebooks, prefixed with the chapter name"
+                .to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/epub+zip".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct EpubAdapter;
+
+impl EpubAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(EpubAdapter))
+    }
+}
+impl GetMetadata for EpubAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+type Archive = ::zip::ZipArchive<Cursor<Vec<u8>>>;
+
+/// elements that start a new line in the output
+pub static BLOCK_ELEMENTS: &[&[u8]] = &[
+    b"address",
+    b"blockquote",
+    b"br",
+    b"dd",
+    b"div",
+    b"dt",
+    b"figcaption",
+    b"h1",
+    b"h2",
+    b"h3",
+    b"h4",
+    b"h5",
+    b"h6",
+    b"hr",
+    b"li",
+    b"p",
+    b"pre",
+    b"section",
+    b"td",
+    b"th",
+    b"tr",
+];
+/// elements whose contents are not part of the text
+static SKIP_ELEMENTS: &[&[u8]] = &[b"head", b"script", b"style"];
+
+fn attr(e: &BytesStart, name: &str) -> Result<Option<String>> {
+    Ok(match e.try_get_attribute(name)? {
+        Some(a) => Some(a.unescape_value()?.into_owned()),
+        None => None,
+    })
+}
+
+/// resolve a href relative to the directory of the file it appears in. drops the #fragment
+fn resolve_href(base_file: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or("");
+    match base_file.rfind('/') {
+        Some(i) => path_clean::clean(&format!("{}/{}", &base_file[..i], href)),
+        None => path_clean::clean(href),
+    }
+}
+
+fn read_xml(archive: &mut Archive, name: &str) -> Result<quick_xml::Reader<Cursor<Vec<u8>>>> {
+    let mut file = archive
+        .by_name(name)
+        .with_context(|| format!("epub is missing {}", name))?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(xml_reader(Cursor::new(data)))
+}
+
+/// read META-INF/container.xml to find the path of the package document
+fn find_rootfile(archive: &mut Archive) -> Result<String> {
+    let mut reader = read_xml(archive, "META-INF/container.xml")?;
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"rootfile" => {
+                if let Some(path) = attr(&e, "full-path")? {
+                    return Ok(path);
+                }
+            }
+            Event::Eof => return Err(format_err!("no rootfile in epub container")),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+struct Package {
+    /// paths of the content documents in reading order
+    spine: Vec<String>,
+    /// path of the epub2 table of contents (toc.ncx)
+    ncx: Option<String>,
+    /// path of the epub3 navigation document
+    nav: Option<String>,
+}
+
+fn read_package(archive: &mut Archive, opf_path: &str) -> Result<Package> {
+    let mut reader = read_xml(archive, opf_path)?;
+    let mut buf = Vec::new();
+    let mut manifest = HashMap::new();
+    let mut spine_ids = vec![];
+    let mut ncx_id = None;
+    let mut ncx = None;
+    let mut nav = None;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"item" => {
+                    let (id, href) = match (attr(&e, "id")?, attr(&e, "href")?) {
+                        (Some(id), Some(href)) => (id, resolve_href(opf_path, &href)),
+                        _ => continue,
+                    };
+                    if attr(&e, "media-type")?.as_deref() == Some("application/x-dtbncx+xml") {
+                        ncx = Some(href.clone());
+                    }
+                    if attr(&e, "properties")?.is_some_and(|p| p.split(' ').any(|p| p == "nav")) {
+                        nav = Some(href.clone());
+                    }
+                    manifest.insert(id, href);
+                }
+                b"spine" => ncx_id = attr(&e, "toc")?,
+                b"itemref" => {
+                    if let Some(idref) = attr(&e, "idref")? {
+                        spine_ids.push(idref);
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    if let Some(ncx_id) = ncx_id {
+        ncx = manifest.get(&ncx_id).cloned().or(ncx);
+    }
+    let spine = spine_ids
+        .iter()
+        .filter_map(|id| {
+            let href = manifest.get(id);
+            if href.is_none() {
+                debug!("epub spine references unknown item {}", id);
+            }
+            href.cloned()
+        })
+        .collect();
+    Ok(Package { spine, ncx, nav })
+}
+
+/// get (document path, chapter title) pairs from the table of contents, in order
+fn read_toc(archive: &mut Archive, package: &Package) -> Result<Vec<(String, String)>> {
+    let (toc_path, is_ncx) = match (&package.nav, &package.ncx) {
+        (Some(nav), _) => (nav, false),
+        (None, Some(ncx)) => (ncx, true),
+        (None, None) => return Ok(vec![]),
+    };
+    let mut reader = read_xml(archive, toc_path)?;
+    let mut buf = Vec::new();
+    let mut toc = vec![];
+    // ncx: <navLabel><text>title</text></navLabel><content src=".."/>
+    // nav: <a href="..">title</a>
+    let (label_tag, link_tag, link_attr): (&[u8], &[u8], _) = if is_ncx {
+        (b"navLabel", b"content", "src")
+    } else {
+        (b"a", b"a", "href")
+    };
+    let mut label: Option<String> = None;
+    let mut in_label = false;
+    let mut href = None;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) => {
+                let name = e.local_name();
+                if name.as_ref() == label_tag {
+                    in_label = true;
+                    label = Some(String::new());
+                }
+                if name.as_ref() == link_tag {
+                    href = attr(&e, link_attr)?;
+                }
+                // in ncx files, the label comes before the content element
+                if let (true, Some(l), Some(h)) = (is_ncx, &label, &href) {
+                    toc.push((resolve_href(toc_path, h), l.trim().to_string()));
+                    label = None;
+                    href = None;
+                }
+            }
+            Event::Text(t) if in_label => {
+                if let Some(l) = label.as_mut() {
+                    l.push_str(&text_of(&t))
+                }
+            }
+            Event::End(e) if e.local_name().as_ref() == label_tag => {
+                in_label = false;
+                if let (false, Some(l), Some(h)) = (is_ncx, &label, &href) {
+                    toc.push((resolve_href(toc_path, h), l.trim().to_string()));
+                    label = None;
+                    href = None;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(toc)
+}
+
+/// convert a (x)html document to plain text, one block element per line
+pub fn write_xhtml_text(line_prefix: &str, inp: impl BufRead, oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(inp);
+    let mut buf = Vec::new();
+    let mut line = String::new();
+    let mut skip_depth = 0;
+    let flush = |line: &mut String, oup: &mut dyn Write| -> Result<()> {
+        let text = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            writeln!(oup, "{}{}", line_prefix, text)?;
+        }
+        line.clear();
+        Ok(())
+    };
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                let name = e.local_name();
+                if SKIP_ELEMENTS.contains(&name.as_ref()) {
+                    skip_depth += 1;
+                } else if BLOCK_ELEMENTS.contains(&name.as_ref()) {
+                    flush(&mut line, oup)?;
+                }
+            }
+            Event::End(e) => {
+                let name = e.local_name();
+                if SKIP_ELEMENTS.contains(&name.as_ref()) {
+                    skip_depth -= 1;
+                } else if BLOCK_ELEMENTS.contains(&name.as_ref()) {
+                    flush(&mut line, oup)?;
+                }
+            }
+            Event::Empty(e) if BLOCK_ELEMENTS.contains(&e.local_name().as_ref()) => {
+                flush(&mut line, oup)?;
+            }
+            Event::Text(t) if skip_depth == 0 => {
+                line.push_str(&text_of(&t));
+            }
+            Event::CData(t) if skip_depth == 0 => {
+                line.push_str(&String::from_utf8_lossy(&t));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    flush(&mut line, oup)
+}
+
+impl WritingFileAdapterTrait for EpubAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let AdaptInfo {
+            mut inp,
+            line_prefix,
+            ..
+        } = ai;
+        let mut data = Vec::new();
+        inp.read_to_end(&mut data)?;
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+        let opf_path = find_rootfile(&mut archive)?;
+        let package = read_package(&mut archive, &opf_path)?;
+        let toc = read_toc(&mut archive, &package).unwrap_or_else(|e| {
+            debug!("could not read epub table of contents: {:?}", e);
+            vec![]
+        });
+        let mut chapter: Option<&str> = None;
+        for doc in &package.spine {
+            // documents not in the toc are usually the continuation of the previous chapter
+            if let Some((_, title)) = toc.iter().find(|(href, _)| href == doc) {
+                chapter = Some(title);
+            }
+            let file = match archive.by_name(doc) {
+                Ok(file) => file,
+                Err(e) => {
+                    debug!("skipping epub spine document {}: {}", doc, e);
+                    continue;
+                }
+            };
+            let chapter_prefix = format!("{}{}: ", line_prefix, chapter.unwrap_or(doc.as_str()));
+            write_xhtml_text(&chapter_prefix, BufReader::new(file), oup)
+                .with_context(|| format!("could not read {}", doc))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+
+    #[test]
+    fn simple() -> Result<()> {
+        let adapter: Box<dyn FileAdapter> = Box::new(EpubAdapter::new());
+        let fname = test_data_dir().join("short.epub");
+        let rd = File::open(&fname)?;
+        let (a, d) = simple_adapt_info(&fname, Box::new(rd));
+        let mut res = adapter.adapt(a, &d)?;
+
+        let mut buf = Vec::new();
+        res.read_to_end(&mut buf)?;
+
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "PREFIX:Chapter One: Chapter One
+PREFIX:Chapter One: hello world, this is just a test.
+PREFIX:Chapter One: a second file of the first chapter
+PREFIX:The End: fin & done :)
+",
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn hrefs() {
+        assert_eq!(
+            resolve_href("OEBPS/content.opf", "text/ch1.xhtml#x"),
+            "OEBPS/text/ch1.xhtml"
+        );
+        assert_eq!(
+            resolve_href("OEBPS/toc/toc.ncx", "../text/ch1.xhtml"),
+            "OEBPS/text/ch1.xhtml"
+        );
+        assert_eq!(resolve_href("content.opf", "ch1.xhtml"), "ch1.xhtml");
+    }
+}
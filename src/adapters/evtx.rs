@@ -0,0 +1,165 @@
+use super::*;
+use ::evtx::EvtxParser;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use serde_json::Value;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["evtx"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "evtx".to_owned(),
+        version: 1,
+        description: "Reads Windows event logs (.evtx). Outputs one line per event with timestamp, provider, event id and the event data".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct EvtxAdapter;
+
+impl EvtxAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(EvtxAdapter))
+    }
+}
+impl GetMetadata for EvtxAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// elements with attributes are rendered as `{"#attributes": {..}, "#text": value}`
+fn text_value(value: &Value) -> &Value {
+    value.get("#text").unwrap_or(value)
+}
+
+fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// flatten the event data to `name=value` pairs
+fn flatten(path: &str, value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                // namespaces etc. are not interesting
+                if key == "#attributes" {
+                    continue;
+                }
+                let path = if key == "#text" {
+                    path.to_string()
+                } else if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                flatten(&path, value, out);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                flatten(path, item, out);
+            }
+        }
+        Value::Null => {}
+        value => {
+            let value = scalar_to_string(value);
+            if path.is_empty() {
+                out.push(value);
+            } else {
+                out.push(format!("{}={}", path, value));
+            }
+        }
+    }
+}
+
+fn render_event(timestamp: &str, record: &Value) -> String {
+    let event = record.get("Event").unwrap_or(record);
+    let system = &event["System"];
+    let provider = system["Provider"]["#attributes"]["Name"]
+        .as_str()
+        .unwrap_or("");
+    let event_id = scalar_to_string(text_value(&system["EventID"]));
+    let mut data = Vec::new();
+    for section in &["EventData", "UserData"] {
+        if let Some(value) = event.get(section) {
+            flatten("", value, &mut data);
+        }
+    }
+    format!(
+        "{} {} {}: {}",
+        timestamp,
+        provider,
+        event_id,
+        data.join(", ")
+    )
+}
+
+impl WritingFileAdapterTrait for EvtxAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut parser = EvtxParser::from_buffer(data)?;
+        for record in parser.records_json_value() {
+            match record {
+                Result::Ok(record) => {
+                    let line = render_event(&record.timestamp.to_string(), &record.data);
+                    writeln!(oup, "{}{}", ai.line_prefix, line.trim_end())?;
+                }
+                // logs of crashed systems often have some broken records, keep going
+                Err(e) => warn!("{}: skipping record: {}", ai.filepath_hint.display(), e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() -> Result<()> {
+        let logon: Value = serde_json::from_str(
+            r##"{"Event": {"#attributes": {"xmlns": "http://schemas.microsoft.com/win/2004/08/events/event"},
+  "System": {"Provider": {"#attributes": {"Name": "Microsoft-Windows-Security-Auditing", "Guid": "54849625-5478-4994-A5BA-3E3B0328C30D"}},
+    "EventID": 4624, "Channel": "Security", "Computer": "WIN-TEST"},
+  "EventData": {"SubjectUserSid": "S-1-5-18", "TargetUserName": "alice", "LogonType": 2, "IpAddress": null}}}"##,
+        )?;
+        assert_eq!(
+            render_event("2020-09-13T12:26:40.123456Z", &logon),
+            "2020-09-13T12:26:40.123456Z Microsoft-Windows-Security-Auditing 4624: SubjectUserSid=S-1-5-18, TargetUserName=alice, LogonType=2"
+        );
+
+        let service: Value = serde_json::from_str(
+            r##"{"Event": {"System": {"Provider": {"#attributes": {"Name": "Service Control Manager"}},
+    "EventID": {"#attributes": {"Qualifiers": 16384}, "#text": 7036}},
+  "EventData": {"Data": [{"#attributes": {"Name": "param1"}, "#text": "Windows Update"}, "running"]},
+  "UserData": {"Log": {"#attributes": {"xmlns": "x"}, "Reason": "test"}}}}"##,
+        )?;
+        assert_eq!(
+            render_event("2020-09-13T12:26:41Z", &service),
+            "2020-09-13T12:26:41Z Service Control Manager 7036: Data=Windows Update, Data=running, Log.Reason=test"
+        );
+        Ok(())
+    }
+}
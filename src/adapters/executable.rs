@@ -0,0 +1,439 @@
+use super::*;
+use ::goblin::elf::section_header::{SHN_UNDEF, SHT_PROGBITS};
+use ::goblin::elf::Elf;
+use ::goblin::mach::constants::cputype::get_arch_name_from_types;
+use ::goblin::mach::load_command::cmd_to_str;
+use ::goblin::mach::{Mach, MachO, SingleArch};
+use ::goblin::pe::PE;
+use ::goblin::Object;
+use anyhow::*;
+use lazy_static::lazy_static;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &[
+    "so", "o", "ko", "elf", "exe", "dll", "sys", "ocx", "efi", "dylib", "bundle",
+];
+static MIME_TYPES: &[&str] = &[
+    "application/x-executable",
+    "application/x-pie-executable",
+    "application/x-sharedlib",
+    "application/x-object",
+    "application/vnd.microsoft.portable-executable",
+    "application/x-dosexec",
+    "application/x-msdownload",
+    "application/x-mach-binary",
+];
+
+/// like the default of `strings`
+const MIN_STRING_LENGTH: usize = 4;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "executable".to_owned(),
+        version: 1,
+        description: "Writes the linked libraries, imported and exported symbols, section names and the strings in the data sections of ELF, PE and Mach-O binaries (the load commands too for Mach-O, and every architecture of fat binaries). Executables without an extension are only detected with --rga-accurate".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct ExecutableAdapter;
+
+impl ExecutableAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(ExecutableAdapter))
+    }
+}
+impl GetMetadata for ExecutableAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// runs of printable ascii characters, like `strings`
+pub fn ascii_strings(data: &[u8]) -> Vec<String> {
+    data.split(|b| !(b.is_ascii_graphic() || *b == b' ' || *b == b'\t'))
+        .filter(|s| s.len() >= MIN_STRING_LENGTH)
+        .map(|s| String::from_utf8_lossy(s).to_string())
+        .collect()
+}
+
+/// runs of printable utf-16le characters, windows binaries store most of their strings this way
+pub fn utf16_strings(data: &[u8]) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut current = String::new();
+    for unit in data.chunks_exact(2) {
+        let c = u16::from_le_bytes([unit[0], unit[1]]);
+        match std::char::from_u32(c.into()) {
+            Some(c) if c == ' ' || c == '\t' || c.is_ascii_graphic() => current.push(c),
+            _ => {
+                if current.len() >= MIN_STRING_LENGTH {
+                    strings.push(current.clone());
+                }
+                current.clear();
+            }
+        }
+    }
+    if current.len() >= MIN_STRING_LENGTH {
+        strings.push(current);
+    }
+    strings
+}
+
+fn write_elf(line_prefix: &str, elf: &Elf, data: &[u8], oup: &mut dyn Write) -> Result<()> {
+    if let Some(soname) = elf.soname {
+        writeln!(oup, "{}Soname: {}", line_prefix, soname)?;
+    }
+    if let Some(interpreter) = elf.interpreter {
+        writeln!(oup, "{}Interpreter: {}", line_prefix, interpreter)?;
+    }
+    for library in &elf.libraries {
+        writeln!(oup, "{}Library: {}", line_prefix, library)?;
+    }
+    for sym in elf.dynsyms.iter() {
+        let name = elf.dynstrtab.get_at(sym.st_name).unwrap_or_default();
+        if name.is_empty() {
+            continue;
+        }
+        let kind = if sym.st_shndx == SHN_UNDEF as usize {
+            "Import"
+        } else {
+            "Export"
+        };
+        writeln!(oup, "{}{}: {}", line_prefix, kind, name)?;
+    }
+    for section in &elf.section_headers {
+        let name = elf.shdr_strtab.get_at(section.sh_name).unwrap_or_default();
+        if !name.is_empty() {
+            writeln!(oup, "{}Section: {}", line_prefix, name)?;
+        }
+    }
+    // the other sections are code, tables or compiler metadata that only give noise
+    for section in &elf.section_headers {
+        let name = elf.shdr_strtab.get_at(section.sh_name).unwrap_or_default();
+        let has_strings = section.sh_type == SHT_PROGBITS
+            && !section.is_executable()
+            && (name.starts_with(".rodata") || name.starts_with(".data") || name == ".comment");
+        if !has_strings {
+            continue;
+        }
+        if let Some(content) = section.file_range().and_then(|r| data.get(r)) {
+            for s in ascii_strings(content) {
+                writeln!(oup, "{}{}: {}", line_prefix, name, s)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_pe(line_prefix: &str, pe: &PE, data: &[u8], oup: &mut dyn Write) -> Result<()> {
+    for library in &pe.libraries {
+        writeln!(oup, "{}Library: {}", line_prefix, library)?;
+    }
+    for import in &pe.imports {
+        writeln!(oup, "{}Import: {}!{}", line_prefix, import.dll, import.name)?;
+    }
+    for name in pe.exports.iter().filter_map(|e| e.name) {
+        writeln!(oup, "{}Export: {}", line_prefix, name)?;
+    }
+    for section in &pe.sections {
+        writeln!(oup, "{}Section: {}", line_prefix, section.name()?)?;
+    }
+    for section in &pe.sections {
+        let name = section.name()?;
+        if !(name.starts_with(".rdata") || name.starts_with(".data") || name == ".rsrc") {
+            continue;
+        }
+        let start = section.pointer_to_raw_data as usize;
+        let end = start.saturating_add(section.size_of_raw_data as usize);
+        if let Some(content) = data.get(start..end) {
+            for s in ascii_strings(content)
+                .into_iter()
+                .chain(utf16_strings(content))
+            {
+                writeln!(oup, "{}{}: {}", line_prefix, name, s)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_macho(line_prefix: &str, macho: &MachO, oup: &mut dyn Write) -> Result<()> {
+    if let Some(name) = macho.name {
+        writeln!(oup, "{}Install name: {}", line_prefix, name)?;
+    }
+    // the first library is always `self`
+    for library in macho.libs.iter().skip(1) {
+        writeln!(oup, "{}Library: {}", line_prefix, library)?;
+    }
+    for rpath in &macho.rpaths {
+        writeln!(oup, "{}Rpath: {}", line_prefix, rpath)?;
+    }
+    for command in &macho.load_commands {
+        writeln!(
+            oup,
+            "{}Load command: {}",
+            line_prefix,
+            cmd_to_str(command.command.cmd())
+        )?;
+    }
+    for (name, nlist) in macho.symbols().flatten() {
+        if name.is_empty() || nlist.is_stab() || !nlist.is_global() {
+            continue;
+        }
+        let kind = if nlist.is_undefined() {
+            "Import"
+        } else {
+            "Export"
+        };
+        writeln!(oup, "{}{}: {}", line_prefix, kind, name)?;
+    }
+    let mut sections = Vec::new();
+    for segment in macho.segments.iter() {
+        sections.extend(segment.sections()?);
+    }
+    for (section, _) in &sections {
+        writeln!(
+            oup,
+            "{}Section: {},{}",
+            line_prefix,
+            section.segname()?,
+            section.name()?
+        )?;
+    }
+    for (section, content) in &sections {
+        let name = section.name()?;
+        let strings = match name {
+            "__cstring" | "__oslogstring" => ascii_strings(content),
+            "__ustring" => utf16_strings(content),
+            _ => continue,
+        };
+        for s in strings {
+            writeln!(oup, "{}{}: {}", line_prefix, name, s)?;
+        }
+    }
+    Ok(())
+}
+
+/// fat binaries contain the same program for several architectures, prefixed with the architecture
+fn write_mach(line_prefix: &str, mach: &Mach, oup: &mut dyn Write) -> Result<()> {
+    let multi = match mach {
+        Mach::Binary(macho) => return write_macho(line_prefix, macho, oup),
+        Mach::Fat(multi) => multi,
+    };
+    for (i, arch) in multi.iter_arches().enumerate() {
+        let arch = arch?;
+        let name = get_arch_name_from_types(arch.cputype, arch.cpusubtype).unwrap_or("unknown");
+        if let SingleArch::MachO(macho) = multi.get(i)? {
+            write_macho(&format!("{}{}: ", line_prefix, name), &macho, oup)?;
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for ExecutableAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        match Object::parse(&data)? {
+            Object::Elf(elf) => write_elf(&ai.line_prefix, &elf, &data, oup),
+            Object::PE(pe) => write_pe(&ai.line_prefix, &pe, &data, oup),
+            Object::Mach(mach) => write_mach(&ai.line_prefix, &mach, oup),
+            _ => bail!("not an ELF, PE or Mach-O binary"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+
+    #[test]
+    fn elf() -> Result<()> {
+        let filepath = test_data_dir().join("short.so");
+        let (a, d) = simple_adapt_info(&filepath, Box::new(File::open(&filepath)?));
+        let mut r = ExecutableAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        let o = String::from_utf8(o)?;
+        for line in &[
+            "PREFIX:Library: libm.so.6",
+            "PREFIX:Library: libc.so.6",
+            "PREFIX:Import: sqrt",
+            "PREFIX:Export: short_hypot",
+            "PREFIX:Section: .text",
+            "PREFIX:.rodata: Usage: short [--verbose] FILE",
+        ] {
+            assert!(o.lines().any(|l| l == *line), "missing {} in {}", line, o);
+        }
+        Ok(())
+    }
+
+    fn name16(name: &str) -> Vec<u8> {
+        let mut out = name.as_bytes().to_vec();
+        out.resize(16, 0);
+        out
+    }
+
+    fn dylib_command(cmd: u32, name: &str) -> Vec<u8> {
+        let mut path = name.as_bytes().to_vec();
+        path.resize((name.len() + 8) / 8 * 8, 0);
+        let mut out = Vec::new();
+        for v in &[cmd, 24 + path.len() as u32, 24, 2, 0x10000, 0x10000] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out.extend(path);
+        out
+    }
+
+    /// a 64 bit x86 dylib with one __cstring section and a symbol table
+    fn macho() -> Vec<u8> {
+        let cstrings = b"Usage: greet NAME\0hello %s\0\0\0\0\0\0".to_vec();
+        let strtab = b"\0_greet\0_printf\0".to_vec();
+        let id = dylib_command(0xd, "@rpath/libgreet.dylib");
+        let load = dylib_command(0xc, "/usr/lib/libSystem.B.dylib");
+        let commands_size = 152 + id.len() + load.len() + 24;
+        let cstring_offset = 32 + commands_size;
+        let symoff = cstring_offset + cstrings.len();
+        let stroff = symoff + 32;
+        let total = stroff + strtab.len();
+
+        let mut data = Vec::new();
+        for v in &[
+            0xfeed_facf,
+            0x0100_0007,
+            3,
+            6,
+            4,
+            commands_size as u32,
+            0,
+            0,
+        ] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        // LC_SEGMENT_64 with one section
+        data.extend_from_slice(&0x19u32.to_le_bytes());
+        data.extend_from_slice(&152u32.to_le_bytes());
+        data.extend(name16("__TEXT"));
+        for v in &[0, total as u64, 0, total as u64] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in &[5u32, 5, 1, 0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend(name16("__cstring"));
+        data.extend(name16("__TEXT"));
+        for v in &[cstring_offset as u64, cstrings.len() as u64] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in &[cstring_offset as u32, 0, 0, 0, 2, 0, 0, 0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend(id);
+        data.extend(load);
+        // LC_SYMTAB
+        for v in &[
+            2u32,
+            24,
+            symoff as u32,
+            2,
+            stroff as u32,
+            strtab.len() as u32,
+        ] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend(cstrings);
+        // an exported function in the first section and an undefined import
+        for (strx, n_type, n_sect, value) in &[(1u32, 0x0fu8, 1u8, 0u64), (8, 0x01, 0, 0)] {
+            data.extend_from_slice(&strx.to_le_bytes());
+            data.extend_from_slice(&[*n_type, *n_sect, 0, 0]);
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend(strtab);
+        assert_eq!(data.len(), total);
+        data
+    }
+
+    fn adapt(data: Vec<u8>) -> Result<String> {
+        let (a, d) = simple_adapt_info(
+            Path::new("libgreet.dylib"),
+            Box::new(std::io::Cursor::new(data)),
+        );
+        let mut r = ExecutableAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn mach_o() -> Result<()> {
+        let expected = "PREFIX:Install name: @rpath/libgreet.dylib
+PREFIX:Library: /usr/lib/libSystem.B.dylib
+PREFIX:Load command: LC_SEGMENT_64
+PREFIX:Load command: LC_ID_DYLIB
+PREFIX:Load command: LC_LOAD_DYLIB
+PREFIX:Load command: LC_SYMTAB
+PREFIX:Export: _greet
+PREFIX:Import: _printf
+PREFIX:Section: __TEXT,__cstring
+PREFIX:__cstring: Usage: greet NAME
+PREFIX:__cstring: hello %s
+";
+        assert_eq!(adapt(macho())?, expected);
+
+        // a fat binary with the same dylib for one architecture, aligned to 4096 bytes
+        let single = macho();
+        let mut fat = Vec::new();
+        for v in &[
+            0xcafe_babe,
+            1,
+            0x0100_0007,
+            3,
+            4096,
+            single.len() as u32,
+            12,
+        ] {
+            fat.extend_from_slice(&v.to_be_bytes());
+        }
+        fat.resize(4096, 0);
+        fat.extend(single);
+        let expected_fat: String = expected
+            .lines()
+            .map(|l| format!("PREFIX:x86_64: {}\n", l.trim_start_matches("PREFIX:")))
+            .collect();
+        assert_eq!(adapt(fat)?, expected_fat);
+        Ok(())
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            ascii_strings(b"\x01\x02abc\0libssl.so.3\0\xffhello world\n"),
+            vec!["libssl.so.3", "hello world"]
+        );
+        let utf16: Vec<u8> = "\u{1}FileVersion\u{0}1.2\u{0}ProductName"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect();
+        assert_eq!(utf16_strings(&utf16), vec!["FileVersion", "ProductName"]);
+    }
+}
@@ -0,0 +1,419 @@
+use super::xml::{text_of, xml_reader};
+use super::*;
+use ::exif::{Context, Exif, Field, In, Reader, Tag, Value};
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use memchr::memmem;
+use quick_xml::events::Event;
+use std::convert::TryInto;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "tif", "tiff", "heic", "heif", "avif", "png", "webp", "dng", "cr2", "nef",
+    "arw", "pef", "srw",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "exif".to_owned(),
+        version: 1,
+        description: "Extracts EXIF, IPTC and XMP metadata of photos (camera, GPS position, captions, keywords) as `key: value` lines".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![
+            SlowMatcher::MimeType("image/jpeg".to_owned()),
+            SlowMatcher::MimeType("image/tiff".to_owned()),
+            SlowMatcher::MimeType("image/heic".to_owned())
+        ]),
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct ExifAdapter;
+
+impl ExifAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(ExifAdapter))
+    }
+}
+impl GetMetadata for ExifAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the XP* tags windows explorer writes are UTF-16 bytes
+fn xp_tag_name(tag: Tag) -> Option<&'static str> {
+    match tag {
+        Tag(Context::Tiff, 0x9c9b) => Some("XPTitle"),
+        Tag(Context::Tiff, 0x9c9c) => Some("XPComment"),
+        Tag(Context::Tiff, 0x9c9d) => Some("XPAuthor"),
+        Tag(Context::Tiff, 0x9c9e) => Some("XPKeywords"),
+        Tag(Context::Tiff, 0x9c9f) => Some("XPSubject"),
+        _ => None,
+    }
+}
+
+fn decode_utf16le(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// degrees, minutes, seconds to signed decimal degrees
+fn gps_coordinate(exif: &Exif, value: Tag, reference: Tag) -> Option<f64> {
+    let parts = match &exif.get_field(value, In::PRIMARY)?.value {
+        Value::Rational(parts) if parts.len() == 3 => parts.clone(),
+        _ => return None,
+    };
+    let degrees = parts[0].to_f64() + parts[1].to_f64() / 60.0 + parts[2].to_f64() / 3600.0;
+    let negative = match &exif.get_field(reference, In::PRIMARY)?.value {
+        Value::Ascii(r) => r
+            .first()
+            .map(|r| r.starts_with(b"S") || r.starts_with(b"W")),
+        _ => None,
+    };
+    Some(if negative == Some(true) {
+        -degrees
+    } else {
+        degrees
+    })
+}
+
+fn field_value(exif: &Exif, field: &Field) -> Option<String> {
+    let value = match &field.value {
+        // the default display puts quotes around strings
+        Value::Ascii(strings) => strings
+            .iter()
+            .map(|s| String::from_utf8_lossy(s).trim().to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::Byte(bytes) if xp_tag_name(field.tag).is_some() => decode_utf16le(bytes),
+        _ => field.display_value().with_unit(exif).to_string(),
+    };
+    // binary blobs (maker notes, embedded profiles) are not useful
+    if value.is_empty() || value.len() > 500 {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn write_exif(line_prefix: &str, exif: &Exif, oup: &mut dyn Write) -> Result<()> {
+    for field in exif.fields() {
+        // the thumbnail ifd mostly repeats the primary one
+        if field.ifd_num != In::PRIMARY || field.tag == Tag::MakerNote {
+            continue;
+        }
+        if let Some(value) = field_value(exif, field) {
+            match xp_tag_name(field.tag) {
+                Some(name) => writeln!(oup, "{}{}: {}", line_prefix, name, value)?,
+                None => writeln!(oup, "{}{}: {}", line_prefix, field.tag, value)?,
+            }
+        }
+    }
+    if let (Some(lat), Some(lon)) = (
+        gps_coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef),
+        gps_coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef),
+    ) {
+        writeln!(oup, "{}GPSPosition: {:.6}, {:.6}", line_prefix, lat, lon)?;
+    }
+    Ok(())
+}
+
+fn iptc_dataset_name(dataset: u8) -> Option<&'static str> {
+    Some(match dataset {
+        5 => "ObjectName",
+        15 => "Category",
+        20 => "SupplementalCategories",
+        25 => "Keywords",
+        40 => "SpecialInstructions",
+        55 => "DateCreated",
+        80 => "By-line",
+        85 => "By-lineTitle",
+        90 => "City",
+        92 => "Sub-location",
+        95 => "Province-State",
+        100 => "Country-PrimaryLocationCode",
+        101 => "Country-PrimaryLocationName",
+        103 => "OriginalTransmissionReference",
+        105 => "Headline",
+        110 => "Credit",
+        115 => "Source",
+        116 => "CopyrightNotice",
+        118 => "Contact",
+        120 => "Caption-Abstract",
+        122 => "Writer-Editor",
+        _ => return None,
+    })
+}
+
+/// the IPTC-IIM block is stored as image resource 0x0404 in the photoshop APP13 segment of jpegs
+fn find_iptc(data: &[u8]) -> Option<&[u8]> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xff {
+        let marker = data[pos + 1];
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xda {
+            // start of scan, the metadata comes before it
+            return None;
+        }
+        if marker == 0xed {
+            if let Some(mut resources) = segment.strip_prefix(b"Photoshop 3.0\0") {
+                while resources.len() >= 12 && resources.starts_with(b"8BIM") {
+                    let id = u16::from_be_bytes([resources[4], resources[5]]);
+                    // pascal string padded to an even length
+                    let name_len = (resources[6] as usize + 2) & !1;
+                    let size_pos = 6 + name_len;
+                    let size =
+                        u32::from_be_bytes(resources.get(size_pos..size_pos + 4)?.try_into().ok()?)
+                            as usize;
+                    let content = resources.get(size_pos + 4..size_pos + 4 + size)?;
+                    if id == 0x0404 {
+                        return Some(content);
+                    }
+                    resources = resources.get(size_pos + 4 + ((size + 1) & !1)..)?;
+                }
+            }
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+fn write_iptc(line_prefix: &str, mut iim: &[u8], oup: &mut dyn Write) -> Result<()> {
+    while iim.len() >= 5 && iim[0] == 0x1c {
+        let (record, dataset) = (iim[1], iim[2]);
+        let len = u16::from_be_bytes([iim[3], iim[4]]) as usize;
+        let value = match iim.get(5..5 + len) {
+            Some(value) => value,
+            None => break,
+        };
+        // record 2 is the application record with the descriptive fields
+        if record == 2 {
+            if let Some(name) = iptc_dataset_name(dataset) {
+                let value = String::from_utf8_lossy(value);
+                let value = value.trim();
+                if !value.is_empty() {
+                    writeln!(oup, "{}IPTC:{}: {}", line_prefix, name, value)?;
+                }
+            }
+        }
+        iim = &iim[5 + len..];
+    }
+    Ok(())
+}
+
+/// jpeg, tiff, png, heic and the raw formats all embed the XMP packet as plain xml
+fn find_xmp(data: &[u8]) -> Option<&[u8]> {
+    let start = memmem::find(data, b"<x:xmpmeta")?;
+    let end = memmem::find(&data[start..], b"</x:xmpmeta>")?;
+    Some(&data[start..start + end + b"</x:xmpmeta>".len()])
+}
+
+/// rdf containers and descriptions are just structure, the properties are what we want
+fn is_structural(name: &[u8]) -> bool {
+    name.starts_with(b"rdf:") || name.starts_with(b"x:")
+}
+
+fn write_xmp(line_prefix: &str, xmp: &[u8], oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(xmp);
+    let mut buf = Vec::new();
+    let mut properties: Vec<String> = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"rdf:Description" => {
+                // simple properties are often written as attributes of the description
+                for a in e.attributes().with_checks(false).flatten() {
+                    let key = a.key.as_ref();
+                    if key.starts_with(b"xmlns") || is_structural(key) {
+                        continue;
+                    }
+                    let value = a.unescape_value()?;
+                    if !value.trim().is_empty() {
+                        writeln!(
+                            oup,
+                            "{}{}: {}",
+                            line_prefix,
+                            String::from_utf8_lossy(key),
+                            value.trim()
+                        )?;
+                    }
+                }
+            }
+            Event::Start(e) if !is_structural(e.name().as_ref()) => {
+                properties.push(String::from_utf8_lossy(e.name().as_ref()).into_owned())
+            }
+            Event::End(e) if !is_structural(e.name().as_ref()) => {
+                properties.pop();
+            }
+            Event::Text(t) => {
+                let text = text_of(&t);
+                let text = text.trim();
+                if let (Some(property), false) = (properties.last(), text.is_empty()) {
+                    writeln!(oup, "{}{}: {}", line_prefix, property, text)?;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for ExifAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        match Reader::new().read_from_container(&mut Cursor::new(&data)) {
+            Result::Ok(exif) => write_exif(&ai.line_prefix, &exif, oup)?,
+            Err(e) => debug!("{}: no exif data: {}", ai.filepath_hint.display(), e),
+        }
+        if let Some(iim) = find_iptc(&data) {
+            write_iptc(&ai.line_prefix, iim, oup)?;
+        }
+        if let Some(xmp) = find_xmp(&data) {
+            if let Err(e) = write_xmp(&ai.line_prefix, xmp, oup) {
+                warn!("{}: invalid xmp: {}", ai.filepath_hint.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use ::exif::experimental::Writer;
+    use ::exif::Rational;
+
+    fn segment(marker: u8, content: &[u8]) -> Vec<u8> {
+        let mut s = vec![0xff, marker];
+        s.extend(&((content.len() + 2) as u16).to_be_bytes());
+        s.extend(content);
+        s
+    }
+
+    fn test_jpeg() -> Result<Vec<u8>> {
+        let ascii = |tag, s: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![s.as_bytes().to_vec()]),
+        };
+        let rational = |tag, parts: &[u32]| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(
+                parts
+                    .iter()
+                    .map(|&n| Rational { num: n, denom: 1 })
+                    .collect(),
+            ),
+        };
+        let fields = vec![
+            ascii(Tag::Make, "Canon"),
+            ascii(Tag::Model, "Canon EOS 5D"),
+            ascii(Tag::ImageDescription, "Sunset at the harbour"),
+            ascii(Tag::GPSLatitudeRef, "N"),
+            rational(Tag::GPSLatitude, &[48, 51, 30]),
+            ascii(Tag::GPSLongitudeRef, "W"),
+            rational(Tag::GPSLongitude, &[2, 17, 24]),
+            Field {
+                tag: Tag(Context::Tiff, 0x9c9e),
+                ifd_num: In::PRIMARY,
+                value: Value::Byte(
+                    "holiday;sea\0"
+                        .encode_utf16()
+                        .flat_map(|u| u.to_le_bytes().to_vec())
+                        .collect(),
+                ),
+            },
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false)?;
+
+        let mut iim = Vec::new();
+        for (dataset, value) in &[(25u8, "harbour"), (25, "boats"), (120, "Evening light")] {
+            iim.extend(&[0x1c, 2, *dataset]);
+            iim.extend(&(value.len() as u16).to_be_bytes());
+            iim.extend(value.as_bytes());
+        }
+        let mut app13 = b"Photoshop 3.0\08BIM\x04\x04\0\0".to_vec();
+        app13.extend(&(iim.len() as u32).to_be_bytes());
+        app13.extend(&iim);
+
+        let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmp:Rating="4">
+<dc:subject><rdf:Bag><rdf:li>harbour</rdf:li><rdf:li>sunset</rdf:li></rdf:Bag></dc:subject>
+<dc:description><rdf:Alt><rdf:li xml:lang="x-default">Boats &amp; sunset</rdf:li></rdf:Alt></dc:description>
+</rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+        let mut jpeg = vec![0xff, 0xd8];
+        jpeg.extend(segment(
+            0xe1,
+            &[b"Exif\0\0".as_ref(), tiff.get_ref()].concat(),
+        ));
+        jpeg.extend(segment(0xed, &app13));
+        jpeg.extend(segment(
+            0xe1,
+            &[b"http://ns.adobe.com/xap/1.0/\0".as_ref(), xmp.as_bytes()].concat(),
+        ));
+        jpeg.extend(&[0xff, 0xd9]);
+        Ok(jpeg)
+    }
+
+    #[test]
+    fn jpeg() -> Result<()> {
+        let (a, d) = simple_adapt_info(Path::new("photo.jpg"), Box::new(Cursor::new(test_jpeg()?)));
+        let mut r = ExifAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:ImageDescription: Sunset at the harbour
+PREFIX:Make: Canon
+PREFIX:Model: Canon EOS 5D
+PREFIX:GPSLatitudeRef: N
+PREFIX:GPSLatitude: 48 deg 51 min 30 sec N
+PREFIX:GPSLongitudeRef: W
+PREFIX:GPSLongitude: 2 deg 17 min 24 sec W
+PREFIX:XPKeywords: holiday;sea
+PREFIX:GPSPosition: 48.858333, -2.290000
+PREFIX:IPTC:Keywords: harbour
+PREFIX:IPTC:Keywords: boats
+PREFIX:IPTC:Caption-Abstract: Evening light
+PREFIX:xmp:Rating: 4
+PREFIX:dc:subject: harbour
+PREFIX:dc:subject: sunset
+PREFIX:dc:description: Boats & sunset
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+use super::xml::{text_of, xml_reader};
+use super::xps::attribute;
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use quick_xml::events::Event;
+use std::io::BufReader;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["fb2"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "fb2".to_owned(),
+        version: 1,
+        description: "Extracts the title info and the body text of FictionBook ebooks (.fb2). Lines are prefixed with the title of the section. Zipped books (.fb2.zip) are handled by the zip adapter".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/x-fictionbook+xml".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct Fb2Adapter;
+
+impl Fb2Adapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(Fb2Adapter))
+    }
+}
+impl GetMetadata for Fb2Adapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// elements that end a line of text
+static BLOCK_ELEMENTS: &[&[u8]] = &[
+    b"p",
+    b"v",
+    b"subtitle",
+    b"text-author",
+    b"td",
+    b"th",
+    b"empty-line",
+];
+
+/// title-info fields that are written as `Label: value`
+static INFO_FIELDS: &[(&[u8], &str)] = &[
+    (b"book-title", "Title"),
+    (b"genre", "Genre"),
+    (b"keywords", "Keywords"),
+    (b"date", "Date"),
+];
+
+static NAME_PARTS: &[&[u8]] = &[b"first-name", b"middle-name", b"last-name", b"nickname"];
+
+struct Fb2Writer<'a> {
+    line_prefix: &'a str,
+    oup: &'a mut dyn Write,
+    /// the local names of the open elements
+    stack: Vec<Vec<u8>>,
+    line: String,
+    /// the innermost title of every open section, the first entry is the title of the body
+    titles: Vec<String>,
+    /// the paragraphs of the title that is currently being read
+    title: Option<Vec<String>>,
+    author: Vec<String>,
+}
+
+impl<'a> Fb2Writer<'a> {
+    fn inside(&self, name: &[u8]) -> bool {
+        self.stack.iter().any(|n| n == name)
+    }
+
+    fn take_line(&mut self) -> String {
+        let text = self.line.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.line.clear();
+        text
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let text = self.take_line();
+        if text.is_empty() {
+            return Ok(());
+        }
+        if let Some(title) = &mut self.title {
+            title.push(text);
+        } else if self.inside(b"annotation") {
+            writeln!(self.oup, "{}Annotation: {}", self.line_prefix, text)?;
+        } else if self.inside(b"body") {
+            self.write_body_line(&text)?;
+        }
+        Ok(())
+    }
+
+    fn write_body_line(&mut self, text: &str) -> Result<()> {
+        match self.titles.last().filter(|t| !t.is_empty()) {
+            Some(title) => writeln!(self.oup, "{}{}: {}", self.line_prefix, title, text)?,
+            None => writeln!(self.oup, "{}{}", self.line_prefix, text)?,
+        }
+        Ok(())
+    }
+
+    fn start(&mut self, name: &[u8]) -> Result<()> {
+        match name {
+            b"section" => {
+                self.flush()?;
+                let parent = self.titles.last().cloned().unwrap_or_default();
+                self.titles.push(parent);
+            }
+            b"body" => self.titles = vec![String::new()],
+            b"title" if self.inside(b"body") => {
+                self.flush()?;
+                self.title = Some(Vec::new());
+            }
+            b"author" => self.author.clear(),
+            _ if BLOCK_ELEMENTS.contains(&name) => self.flush()?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, name: &[u8]) -> Result<()> {
+        let in_title_info = self.inside(b"title-info");
+        match name {
+            b"title" if self.title.is_some() => {
+                self.flush()?;
+                let heading = self.title.take().unwrap_or_default().join(" ");
+                if !heading.is_empty() {
+                    // the heading itself is prefixed with the title of the enclosing section
+                    self.write_body_line(&heading)?;
+                    if let Some(title) = self.titles.last_mut() {
+                        *title = heading;
+                    }
+                }
+            }
+            b"section" => {
+                self.flush()?;
+                self.titles.pop();
+            }
+            _ if in_title_info && NAME_PARTS.contains(&name) => {
+                let text = self.take_line();
+                if !text.is_empty() {
+                    self.author.push(text);
+                }
+            }
+            b"author" if in_title_info => {
+                if !self.author.is_empty() {
+                    writeln!(
+                        self.oup,
+                        "{}Author: {}",
+                        self.line_prefix,
+                        self.author.join(" ")
+                    )?;
+                }
+            }
+            _ if in_title_info && !self.inside(b"annotation") => {
+                let text = self.take_line();
+                if let Some((_, label)) = INFO_FIELDS.iter().find(|(n, _)| *n == name) {
+                    if !text.is_empty() {
+                        writeln!(self.oup, "{}{}: {}", self.line_prefix, label, text)?;
+                    }
+                }
+            }
+            _ if BLOCK_ELEMENTS.contains(&name) => self.flush()?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) {
+        // skip the base64 encoded images and the metadata outside of the title info
+        if self.inside(b"binary") || !(self.inside(b"body") || self.inside(b"title-info")) {
+            return;
+        }
+        self.line.push_str(text);
+    }
+}
+
+fn write_fb2(line_prefix: &str, inp: impl BufRead, oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(inp);
+    let mut buf = Vec::new();
+    let mut writer = Fb2Writer {
+        line_prefix,
+        oup,
+        stack: Vec::new(),
+        line: String::new(),
+        titles: Vec::new(),
+        title: None,
+        author: Vec::new(),
+    };
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                writer.start(&name)?;
+                writer.stack.push(name);
+            }
+            Event::Empty(e) => {
+                let name = e.local_name();
+                if BLOCK_ELEMENTS.contains(&name.as_ref()) {
+                    writer.flush()?;
+                } else if name.as_ref() == b"sequence" && writer.inside(b"title-info") {
+                    // <sequence name="..." number="3"/>
+                    let attr = |key: &[u8]| attribute(&e, key);
+                    if let Some(series) = attr(b"name") {
+                        let number = attr(b"number")
+                            .map(|n| format!(" #{}", n))
+                            .unwrap_or_default();
+                        writeln!(writer.oup, "{}Series: {}{}", line_prefix, series, number)?;
+                    }
+                }
+            }
+            Event::End(e) => {
+                let name = e.local_name();
+                writer.end(name.as_ref())?;
+                writer.stack.pop();
+            }
+            Event::Text(t) => writer.text(&text_of(&t)),
+            Event::CData(t) => writer.text(&String::from_utf8_lossy(&t)),
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    writer.flush()
+}
+
+impl WritingFileAdapterTrait for Fb2Adapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        write_fb2(&ai.line_prefix, BufReader::new(ai.inp), oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    static BOOK: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+  <description>
+    <title-info>
+      <genre>sf_space</genre>
+      <author><first-name>Ursula</first-name><middle-name>K.</middle-name><last-name>Le Guin</last-name></author>
+      <book-title>The Dispossessed</book-title>
+      <annotation><p>An ambiguous utopia.</p></annotation>
+      <keywords>anarchy, physics</keywords>
+      <sequence name="Hainish Cycle" number="5"/>
+      <lang>en</lang>
+    </title-info>
+    <document-info><program-used>FictionBook Editor</program-used></document-info>
+  </description>
+  <body>
+    <title><p>The Dispossessed</p></title>
+    <epigraph><p>True journey is return.</p></epigraph>
+    <section>
+      <title><p>Chapter One</p><p>Anarres</p></title>
+      <p>There was a wall.</p>
+      <empty-line/>
+      <p>It did not look <emphasis>important</emphasis>.</p>
+      <section>
+        <title><p>The Wall</p></title>
+        <poem><stanza><v>Like all walls</v><v>it was ambiguous</v></stanza></poem>
+      </section>
+      <p>Back to chapter one.</p>
+    </section>
+  </body>
+  <binary id="cover.jpg" content-type="image/jpeg">/9j/4AAQSkZJRgABAQEASABIAAD</binary>
+</FictionBook>"#;
+
+    static EXPECTED: &str = "PREFIX:Genre: sf_space
+PREFIX:Author: Ursula K. Le Guin
+PREFIX:Title: The Dispossessed
+PREFIX:Annotation: An ambiguous utopia.
+PREFIX:Keywords: anarchy, physics
+PREFIX:Series: Hainish Cycle #5
+PREFIX:The Dispossessed
+PREFIX:The Dispossessed: True journey is return.
+PREFIX:The Dispossessed: Chapter One Anarres
+PREFIX:Chapter One Anarres: There was a wall.
+PREFIX:Chapter One Anarres: It did not look important.
+PREFIX:Chapter One Anarres: The Wall
+PREFIX:The Wall: Like all walls
+PREFIX:The Wall: it was ambiguous
+PREFIX:Chapter One Anarres: Back to chapter one.
+";
+
+    #[test]
+    fn fb2() -> Result<()> {
+        let (a, d) = simple_adapt_info(
+            Path::new("dispossessed.fb2"),
+            Box::new(Cursor::new(BOOK.as_bytes().to_vec())),
+        );
+        let mut r = Fb2Adapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(String::from_utf8(o)?, EXPECTED);
+        Ok(())
+    }
+
+    #[test]
+    fn chosen_over_pandoc() -> Result<()> {
+        let adapters = get_adapters_filtered::<String>(None, &vec![])?;
+        let matcher = crate::matching::adapter_matcher(&adapters, false)?;
+        let (adapter, _) = matcher(crate::matching::FileMeta {
+            lossy_filename: "book.fb2".to_string(),
+            mimetype: None,
+        })
+        .unwrap();
+        assert_eq!(adapter.metadata().name, "fb2");
+        Ok(())
+    }
+}
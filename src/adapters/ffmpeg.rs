@@ -24,7 +24,9 @@ lazy_static! {
             .map(|s| FastMatcher::FileExtension(s.to_string()))
             .collect(),
         slow_matchers: None,
-        disabled_by_default: false
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
     };
 }
 
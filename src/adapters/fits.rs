@@ -0,0 +1,279 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["fits", "fit", "fts"];
+static MIME_TYPES: &[&str] = &["image/fits", "application/fits"];
+
+/// headers and data are stored in blocks of 36 cards
+const BLOCK_SIZE: usize = 2880;
+const CARD_SIZE: usize = 80;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "fits".to_owned(),
+        version: 1,
+        description: "Writes the header cards of each HDU in FITS files, as `HDU n: KEYWORD = value / comment`. The image and table data is skipped".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct FitsAdapter;
+
+impl FitsAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(FitsAdapter))
+    }
+}
+impl GetMetadata for FitsAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+struct Card {
+    keyword: String,
+    value: Option<String>,
+    comment: Option<String>,
+}
+
+/// a quoted string, with `''` for a quote. returns the rest of the card after the closing quote
+fn parse_string(s: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = s.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\'' {
+            if chars.peek().map(|(_, c)| *c) == Some('\'') {
+                chars.next();
+            } else {
+                return (value.trim_end().to_string(), &s[i + 1..]);
+            }
+        }
+        value.push(c);
+    }
+    (value.trim_end().to_string(), "")
+}
+
+fn parse_card(card: &str) -> Card {
+    let keyword = card.get(..8).unwrap_or(card).trim_end().to_string();
+    let rest = card.get(8..).unwrap_or_default();
+    // long strings are continued in CONTINUE cards, which have a value but no `= `
+    let value_field = match rest.strip_prefix("= ") {
+        Some(field) => field,
+        None if keyword == "CONTINUE" => rest,
+        None => {
+            let text = rest.trim();
+            return Card {
+                keyword,
+                value: None,
+                comment: Some(text.to_string()).filter(|t| !t.is_empty()),
+            };
+        }
+    };
+    let trimmed = value_field.trim_start();
+    let (value, rest) = if trimmed.starts_with('\'') {
+        parse_string(trimmed)
+    } else {
+        match trimmed.split_once('/') {
+            Some((value, comment)) => (value.trim().to_string(), comment),
+            None => (trimmed.trim().to_string(), ""),
+        }
+    };
+    let comment = rest
+        .trim_start()
+        .strip_prefix('/')
+        .unwrap_or(rest)
+        .trim()
+        .to_string();
+    Card {
+        keyword,
+        value: Some(value),
+        comment: Some(comment).filter(|c| !c.is_empty()),
+    }
+}
+
+/// reads header blocks until the END card. None at the end of the file
+fn read_header(inp: &mut dyn Read) -> Result<Option<Vec<Card>>> {
+    let mut cards: Vec<Card> = Vec::new();
+    let mut block = vec![0u8; BLOCK_SIZE];
+    loop {
+        let mut filled = 0;
+        while filled < BLOCK_SIZE {
+            match inp.read(&mut block[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled == 0 && cards.is_empty() {
+            return Ok(None);
+        }
+        if filled < BLOCK_SIZE {
+            bail!("truncated FITS header");
+        }
+        for card in block.chunks(CARD_SIZE) {
+            let card = parse_card(&String::from_utf8_lossy(card));
+            if card.keyword == "END" {
+                return Ok(Some(cards));
+            }
+            match cards.last_mut() {
+                Some(previous)
+                    if card.keyword == "CONTINUE"
+                        && previous.value.as_deref().is_some_and(|v| v.ends_with('&')) =>
+                {
+                    let value = previous.value.as_mut().unwrap();
+                    value.pop();
+                    value.push_str(card.value.as_deref().unwrap_or_default());
+                    if card.comment.is_some() {
+                        previous.comment = card.comment;
+                    }
+                }
+                _ if card.keyword.is_empty() && card.comment.is_none() => {}
+                _ => cards.push(card),
+            }
+        }
+    }
+}
+
+/// `|BITPIX| * GCOUNT * (PCOUNT + NAXIS1 * ... * NAXISn)` bits, padded to whole blocks
+fn data_size(cards: &[Card]) -> u64 {
+    let number = |keyword: &str| {
+        cards
+            .iter()
+            .find(|c| c.keyword == keyword)
+            .and_then(|c| c.value.as_deref())
+            .and_then(|v| v.parse::<i64>().ok())
+    };
+    let naxis = number("NAXIS").unwrap_or(0);
+    if naxis == 0 {
+        return 0;
+    }
+    // random groups have NAXIS1 = 0
+    let random_groups = number("NAXIS1") == Some(0);
+    let axes: i64 = (1..=naxis)
+        .filter(|i| !(random_groups && *i == 1))
+        .map(|i| number(&format!("NAXIS{}", i)).unwrap_or(0))
+        .product();
+    let bits = number("BITPIX").unwrap_or(8).abs()
+        * number("GCOUNT").unwrap_or(1)
+        * (number("PCOUNT").unwrap_or(0) + axes);
+    let bytes = (bits / 8).max(0) as u64;
+    bytes.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64
+}
+
+impl WritingFileAdapterTrait for FitsAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut hdu = 0;
+        while let Some(cards) = read_header(&mut ai.inp)? {
+            debug!("{}|HDU {}", ai.filepath_hint.display(), hdu);
+            for card in &cards {
+                let mut line = card.keyword.clone();
+                if let Some(value) = &card.value {
+                    line.push_str(" = ");
+                    line.push_str(value);
+                }
+                if let Some(comment) = &card.comment {
+                    line.push_str(if card.value.is_some() { " / " } else { " " });
+                    line.push_str(comment);
+                }
+                writeln!(oup, "{}HDU {}: {}", ai.line_prefix, hdu, line.trim_start())?;
+            }
+            let size = data_size(&cards);
+            let skipped = std::io::copy(&mut (&mut ai.inp).take(size), &mut std::io::sink())?;
+            if skipped < size {
+                break;
+            }
+            hdu += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn header(cards: &[&str]) -> Vec<u8> {
+        let mut data: Vec<u8> = cards
+            .iter()
+            .chain(std::iter::once(&"END"))
+            .flat_map(|c| format!("{:80}", c).into_bytes())
+            .collect();
+        data.resize(data.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, b' ');
+        data
+    }
+
+    #[test]
+    fn fits() -> Result<()> {
+        let mut data = header(&[
+            "SIMPLE  =                    T / conforms to FITS standard",
+            "BITPIX  =                   16",
+            "NAXIS   =                    2",
+            "NAXIS1  =                   40",
+            "NAXIS2  =                   50",
+            "OBJECT  = 'M31     '           / target name",
+            "OBSERVER= 'O''Brien & Smith&'",
+            "CONTINUE  ' (remote)'",
+            "COMMENT   taken during the 2019 campaign",
+        ]);
+        data.extend(vec![0u8; BLOCK_SIZE * 2]);
+        data.extend(header(&[
+            "XTENSION= 'BINTABLE'           / binary table extension",
+            "BITPIX  =                    8",
+            "NAXIS   =                    2",
+            "NAXIS1  =                    4",
+            "NAXIS2  =                    1",
+            "PCOUNT  =                    0",
+            "GCOUNT  =                    1",
+            "EXTNAME = 'EVENTS  '",
+        ]));
+        data.extend(vec![0u8; BLOCK_SIZE]);
+
+        let (a, d) = simple_adapt_info(Path::new("m31.fits"), Box::new(Cursor::new(data)));
+        let mut r = FitsAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:HDU 0: SIMPLE = T / conforms to FITS standard
+PREFIX:HDU 0: BITPIX = 16
+PREFIX:HDU 0: NAXIS = 2
+PREFIX:HDU 0: NAXIS1 = 40
+PREFIX:HDU 0: NAXIS2 = 50
+PREFIX:HDU 0: OBJECT = M31 / target name
+PREFIX:HDU 0: OBSERVER = O'Brien & Smith (remote)
+PREFIX:HDU 0: COMMENT taken during the 2019 campaign
+PREFIX:HDU 1: XTENSION = BINTABLE / binary table extension
+PREFIX:HDU 1: BITPIX = 8
+PREFIX:HDU 1: NAXIS = 2
+PREFIX:HDU 1: NAXIS1 = 4
+PREFIX:HDU 1: NAXIS2 = 1
+PREFIX:HDU 1: PCOUNT = 0
+PREFIX:HDU 1: GCOUNT = 1
+PREFIX:HDU 1: EXTNAME = EVENTS
+"
+        );
+        Ok(())
+    }
+}
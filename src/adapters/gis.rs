@@ -0,0 +1,303 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use rusqlite::{Connection, OpenFlags, NO_PARAMS};
+use std::convert::TryInto;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["shp", "dbf", "gpkg"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "gis".to_owned(),
+        version: 1,
+        description: "Writes the attribute tables of shapefiles (.dbf) and GeoPackage layers, as `layer: column=value, ...`. For the geometry in .shp files only the shape type and bounding box are written".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/geopackage+sqlite3".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct GisAdapter;
+
+impl GisAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(GisAdapter))
+    }
+}
+impl GetMetadata for GisAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn layer_name(ai: &AdaptInfo) -> String {
+    ai.filepath_hint
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+struct Field {
+    name: String,
+    kind: u8,
+    length: usize,
+}
+
+/// the encoding is in a .cpg file next to the .dbf, which is not available in archives
+fn decode(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        std::result::Result::Ok(s) => s.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(data).0.into_owned(),
+    }
+}
+
+fn format_value(field: &Field, data: &[u8]) -> String {
+    let value = decode(data);
+    let value = value.trim_matches(|c: char| c == ' ' || c == '\0');
+    match field.kind {
+        b'C' => format!("'{}'", value.replace('\'', "''")),
+        b'D' if value.len() == 8 => format!("{}-{}-{}", &value[..4], &value[4..6], &value[6..]),
+        b'L' => match value {
+            "T" | "t" | "Y" | "y" => "true".to_string(),
+            "F" | "f" | "N" | "n" => "false".to_string(),
+            _ => "NULL".to_string(),
+        },
+        _ if value.is_empty() => "NULL".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// dBASE tables: a header with the field descriptors, followed by fixed width records
+fn adapt_dbf(ai: &mut AdaptInfo, oup: &mut dyn Write) -> Result<()> {
+    let mut header = [0u8; 32];
+    ai.inp.read_exact(&mut header)?;
+    let records = u32::from_le_bytes(header[4..8].try_into()?);
+    let header_size = u16::from_le_bytes(header[8..10].try_into()?) as usize;
+    let record_size = u16::from_le_bytes(header[10..12].try_into()?) as usize;
+    let mut descriptors = vec![0u8; header_size.saturating_sub(32)];
+    ai.inp.read_exact(&mut descriptors)?;
+    let mut fields = Vec::new();
+    for descriptor in descriptors.chunks_exact(32) {
+        if descriptor[0] == 0x0d {
+            break;
+        }
+        let name = &descriptor[..11];
+        let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(11)];
+        fields.push(Field {
+            name: decode(name),
+            kind: descriptor[11],
+            length: descriptor[16] as usize,
+        });
+    }
+    let layer = layer_name(ai);
+    debug!("{}: {} fields, {} records", layer, fields.len(), records);
+    let mut record = vec![0u8; record_size.max(1)];
+    for _ in 0..records {
+        ai.inp.read_exact(&mut record)?;
+        // deleted records are marked with `*`
+        if record[0] == b'*' {
+            continue;
+        }
+        let mut offset = 1;
+        let mut values = Vec::new();
+        for field in &fields {
+            let data = record
+                .get(offset..offset + field.length)
+                .ok_or_else(|| format_err!("record is shorter than its fields"))?;
+            values.push(format!("{}={}", field.name, format_value(field, data)));
+            offset += field.length;
+        }
+        writeln!(oup, "{}{}: {}", ai.line_prefix, layer, values.join(", "))?;
+    }
+    Ok(())
+}
+
+fn shape_type(code: u32) -> &'static str {
+    match code {
+        0 => "null",
+        1 => "point",
+        3 => "polyline",
+        5 => "polygon",
+        8 => "multipoint",
+        11 => "pointz",
+        13 => "polylinez",
+        15 => "polygonz",
+        18 => "multipointz",
+        21 => "pointm",
+        23 => "polylinem",
+        25 => "polygonm",
+        28 => "multipointm",
+        31 => "multipatch",
+        _ => "unknown",
+    }
+}
+
+/// the attributes of the shapes are in the .dbf file with the same name
+fn adapt_shp(ai: &mut AdaptInfo, oup: &mut dyn Write) -> Result<()> {
+    let mut header = [0u8; 100];
+    ai.inp.read_exact(&mut header)?;
+    if header[..4] != [0, 0, 0x27, 0x0a] {
+        bail!("not a shapefile");
+    }
+    let kind = u32::from_le_bytes(header[32..36].try_into()?);
+    let bbox: Vec<String> = header[36..68]
+        .chunks_exact(8)
+        .map(|b| f64::from_le_bytes(b.try_into().unwrap()).to_string())
+        .collect();
+    writeln!(
+        oup,
+        "{}{}: {} bbox={}",
+        ai.line_prefix,
+        layer_name(ai),
+        shape_type(kind),
+        bbox.join(" ")
+    )?;
+    Ok(())
+}
+
+/// the layers are listed in gpkg_contents, the other tables are metadata and spatial indexes
+fn adapt_gpkg(ai: &mut AdaptInfo, oup: &mut dyn Write) -> Result<()> {
+    if !ai.is_real_file {
+        writeln!(
+            oup,
+            "{}[rga: skipping geopackage in archive]",
+            ai.line_prefix
+        )?;
+        return Ok(());
+    }
+    let conn = Connection::open_with_flags(&ai.filepath_hint, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let layers: Vec<String> = conn
+        .prepare(
+            "select table_name from gpkg_contents where data_type in ('features', 'attributes')",
+        )?
+        .query_map(NO_PARAMS, |r| r.get::<_, String>(0))?
+        .filter_map(|e| e.ok())
+        .collect();
+    for layer in layers {
+        sqlite::write_table(&conn, &layer, &ai.line_prefix, oup)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for GisAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let extension = ai
+            .filepath_hint
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("shp") => adapt_shp(&mut ai, oup),
+            Some("dbf") => adapt_dbf(&mut ai, oup),
+            _ => adapt_gpkg(&mut ai, oup),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+    use std::io::Cursor;
+
+    fn adapt(filepath: &Path, data: Vec<u8>) -> Result<String> {
+        let (a, d) = simple_adapt_info(filepath, Box::new(Cursor::new(data)));
+        let mut r = GisAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn dbf() -> Result<()> {
+        let fields: &[(&str, u8, u8)] =
+            &[("NAME", b'C', 10), ("POP", b'N', 8), ("FOUNDED", b'D', 8)];
+        let mut data = vec![0x03, 120, 1, 1];
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(&(32 + 32 * fields.len() as u16 + 1).to_le_bytes());
+        data.extend_from_slice(&27u16.to_le_bytes());
+        data.resize(32, 0);
+        for (name, kind, length) in fields {
+            let mut descriptor = name.as_bytes().to_vec();
+            descriptor.resize(11, 0);
+            descriptor.push(*kind);
+            descriptor.resize(16, 0);
+            descriptor.push(*length);
+            descriptor.resize(32, 0);
+            data.extend(descriptor);
+        }
+        data.push(0x0d);
+        data.extend_from_slice(b" Berlin     3664088");
+        data.extend_from_slice(b"12370101");
+        data.extend_from_slice(b"*Atlantis  0       ");
+        data.extend_from_slice(b"        ");
+        data.extend_from_slice(b" K\xf6ln       1083498        ");
+        data.push(0x1a);
+
+        assert_eq!(
+            adapt(Path::new("cities.dbf"), data)?,
+            "PREFIX:cities: NAME='Berlin', POP=3664088, FOUNDED=1237-01-01
+PREFIX:cities: NAME='Köln', POP=1083498, FOUNDED=NULL
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shp() -> Result<()> {
+        let mut data = vec![0, 0, 0x27, 0x0a];
+        data.resize(24, 0);
+        data.extend_from_slice(&50u32.to_be_bytes());
+        data.extend_from_slice(&1000u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        for v in &[13.0f64, 52.25, 13.75, 52.5, 0.0, 0.0, 0.0, 0.0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        assert_eq!(
+            adapt(Path::new("cities.shp"), data)?,
+            "PREFIX:cities: point bbox=13 52.25 13.75 52.5\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn gpkg() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let filepath = dir.path().join("berlin.gpkg");
+        let conn = Connection::open(&filepath)?;
+        conn.execute_batch(
+            "create table gpkg_contents (table_name text, data_type text);
+            insert into gpkg_contents values ('districts', 'features'), ('tiles', 'tiles');
+            create table districts (fid integer primary key, geom blob, name text);
+            insert into districts values (1, x'4750000100000000', 'Kreuzberg');
+            create table tiles (id integer, tile_data blob);
+            insert into tiles values (1, x'89504e47');",
+        )?;
+        drop(conn);
+
+        let (a, d) = simple_adapt_info(&filepath, Box::new(File::open(&filepath)?));
+        let mut r = GisAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:districts: fid=1, geom=[blob 8B], name='Kreuzberg'\n"
+        );
+        Ok(())
+    }
+}
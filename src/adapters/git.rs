@@ -0,0 +1,252 @@
+use super::*;
+use crate::preproc::rga_preproc;
+use ::git2::{Commit, Delta, ObjectType, Patch, Repository, RepositoryOpenFlags, Sort};
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::ffi::OsStr;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static FILE_NAMES: &[&str] = &["HEAD"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "git".to_owned(),
+        version: 1,
+        description: "Searches the history of git repositories: the commit messages and authors and the lines added by each commit, written as `commit:path: line`. Matches the HEAD file of the repository, so use --hidden to search in a .git directory (not needed for bare repositories)".to_owned(),
+        recurses: true,
+        fast_matchers: FILE_NAMES
+            .iter()
+            .map(|s| FastMatcher::FileName(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        // a large history produces a lot of output
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct GitAdapter;
+
+impl GitAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(GitAdapter))
+    }
+}
+impl GetMetadata for GitAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// writes what changed compared to the first parent. text files are written line by line,
+/// binary files (pdfs, office documents, ...) go through the other adapters
+fn adapt_commit(
+    repo: &Repository,
+    commit: &Commit,
+    ai: &AdaptInfo,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let short_id = commit.as_object().short_id()?;
+    let short_id = short_id.as_str().unwrap_or_default();
+    let author = commit.author();
+    writeln!(
+        oup,
+        "{}{}: Author: {} <{}>",
+        ai.line_prefix,
+        short_id,
+        author.name().unwrap_or_default(),
+        author.email().unwrap_or_default()
+    )?;
+    for line in String::from_utf8_lossy(commit.message_bytes()).lines() {
+        if !line.trim().is_empty() {
+            writeln!(oup, "{}{}: {}", ai.line_prefix, short_id, line)?;
+        }
+    }
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(parent.tree()?),
+        None => None,
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    for (idx, delta) in diff.deltas().enumerate() {
+        match delta.status() {
+            Delta::Added | Delta::Modified | Delta::Renamed | Delta::Copied | Delta::Typechange => {
+            }
+            _ => continue,
+        }
+        let file = delta.new_file();
+        // submodules are commits, not blobs
+        let blob = match repo.find_object(file.id(), None)?.into_blob() {
+            std::result::Result::Ok(blob) => blob,
+            Err(_) => continue,
+        };
+        let path = file.path().unwrap_or_else(|| Path::new(""));
+        debug!(
+            "{}|{}:{}",
+            ai.filepath_hint.display(),
+            short_id,
+            path.display()
+        );
+        let line_prefix = format!("{}{}:{}: ", ai.line_prefix, short_id, path.display());
+        if blob.is_binary() {
+            let mut inner = rga_preproc(AdaptInfo {
+                filepath_hint: path.to_path_buf(),
+                is_real_file: false,
+                inp: Box::new(Cursor::new(blob.content().to_vec())),
+                line_prefix,
+                archive_recursion_depth: ai.archive_recursion_depth + 1,
+                config: ai.config.clone(),
+            })?;
+            std::io::copy(&mut inner, oup)?;
+            continue;
+        }
+        if let Some(patch) = Patch::from_diff(&diff, idx)? {
+            for hunk in 0..patch.num_hunks() {
+                for l in 0..patch.num_lines_in_hunk(hunk)? {
+                    let line = patch.line_in_hunk(hunk, l)?;
+                    if line.origin() == '+' {
+                        let content = String::from_utf8_lossy(line.content());
+                        writeln!(oup, "{}{}", line_prefix, content.trim_end_matches('\n'))?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for GitAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // HEAD files also exist in `.git/logs/` and `.git/refs/remotes/*/`, and in archives
+        let git_dir = ai.filepath_hint.parent().filter(|_| ai.is_real_file);
+        let repo = git_dir.and_then(|dir| {
+            Repository::open_ext(dir, RepositoryOpenFlags::NO_SEARCH, &[] as &[&OsStr]).ok()
+        });
+        let repo = match repo {
+            Some(repo) => repo,
+            None => {
+                std::io::copy(&mut ai.inp, oup)?;
+                return Ok(());
+            }
+        };
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(Sort::TIME)?;
+        revwalk.push_glob("*")?;
+        // a new repository does not have any commits yet
+        if repo.head().is_ok() {
+            revwalk.push_head()?;
+        }
+        for id in revwalk {
+            let commit = repo
+                .find_object(id?, Some(ObjectType::Commit))?
+                .peel_to_commit()?;
+            adapt_commit(&repo, &commit, &ai, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::RgaConfig;
+    use ::git2::{Oid, Signature, Time};
+    use std::fs::File;
+
+    fn commit(repo: &Repository, files: &[(&str, &[u8])], message: &str, time: i64) -> Result<Oid> {
+        let mut index = repo.index()?;
+        for (path, content) in files {
+            let mut entry = ::git2::IndexEntry {
+                ctime: ::git2::IndexTime::new(0, 0),
+                mtime: ::git2::IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: 0o100644,
+                uid: 0,
+                gid: 0,
+                file_size: content.len() as u32,
+                id: Oid::zero(),
+                flags: 0,
+                flags_extended: 0,
+                path: path.as_bytes().to_vec(),
+            };
+            entry.id = repo.blob(content)?;
+            index.add_frombuffer(&entry, content)?;
+        }
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let signature = Signature::new("Jane Doe", "jane@example.com", &Time::new(time, 0))?;
+        let parents = match repo.head() {
+            std::result::Result::Ok(head) => vec![head.peel_to_commit()?],
+            Err(_) => vec![],
+        };
+        let parents: Vec<&Commit> = parents.iter().collect();
+        Ok(repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )?)
+    }
+
+    #[test]
+    fn history() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let repo = Repository::init(dir.path())?;
+        let first = commit(
+            &repo,
+            &[("README.md", b"# example\n"), ("logo.bin", b"\0\x01\x02")],
+            "initial commit",
+            1_600_000_000,
+        )?;
+        let second = commit(
+            &repo,
+            &[("README.md", b"# example\napi_key = hunter2\n")],
+            "add configuration\n\nthe key is only for testing",
+            1_600_000_100,
+        )?;
+
+        let head = repo.path().join("HEAD");
+        // simple_adapt_info needs a file extension
+        let a = AdaptInfo {
+            filepath_hint: head.clone(),
+            is_real_file: true,
+            archive_recursion_depth: 0,
+            inp: Box::new(File::open(&head)?),
+            line_prefix: "PREFIX:".to_string(),
+            config: PreprocConfig {
+                cache: None,
+                args: RgaConfig::default(),
+            },
+        };
+        let d = FastMatcher::FileName("HEAD".to_string()).into();
+        let mut r = GitAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        let (first, second) = (&first.to_string()[..7], &second.to_string()[..7]);
+        assert_eq!(
+            String::from_utf8(o)?,
+            format!(
+                "PREFIX:{second}: Author: Jane Doe <jane@example.com>
+PREFIX:{second}: add configuration
+PREFIX:{second}: the key is only for testing
+PREFIX:{second}:README.md: api_key = hunter2
+PREFIX:{first}: Author: Jane Doe <jane@example.com>
+PREFIX:{first}: initial commit
+PREFIX:{first}:README.md: # example
+\0\x01\x02",
+                first = first,
+                second = second
+            )
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use super::*;
+use crate::preproc::rga_preproc;
+use ::git2::{Indexer, ObjectType, Odb, Oid};
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["pack", "idx"];
+
+/// git only looks at the start of a blob to decide whether it is binary
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "gitobject".to_owned(),
+        version: 1,
+        description: "Inflates the objects in git packfiles and loose object files (.git/objects/ab/cdef...) and writes their content prefixed with the object id. Index files only contain the ids of the objects in their pack".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            // loose objects are named after the last 38 hex digits of their id
+            .chain(std::iter::once(FastMatcher::FileName("[0-9a-f]".repeat(38))))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct GitObjectAdapter;
+
+impl GitObjectAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(GitObjectAdapter))
+    }
+}
+impl GetMetadata for GitObjectAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the entries of a tree object: `<mode> <name>\0<20 byte id>`
+fn tree_entries(data: &[u8]) -> Result<Vec<(String, Oid)>> {
+    let mut entries = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let nul = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| format_err!("invalid tree entry"))?;
+        let id = rest
+            .get(nul + 1..nul + 21)
+            .ok_or_else(|| format_err!("truncated tree entry"))?;
+        let header = String::from_utf8_lossy(&rest[..nul]);
+        let name = header.split_once(' ').map(|x| x.1).unwrap_or_default();
+        entries.push((name.to_string(), Oid::from_bytes(id)?));
+        rest = &rest[nul + 21..];
+    }
+    Ok(entries)
+}
+
+/// writes trees as the names of their entries and commits, tags and text blobs line by line.
+/// binary blobs go through the other adapters, using their name in a tree of the same pack if there is one
+fn adapt_object(
+    id: Oid,
+    kind: ObjectType,
+    data: &[u8],
+    name: Option<&str>,
+    ai: &AdaptInfo,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    debug!("{}|{}", ai.filepath_hint.display(), id);
+    let line_prefix = format!("{}{}: ", ai.line_prefix, id);
+    match kind {
+        ObjectType::Tree => {
+            for (name, _) in tree_entries(data)? {
+                writeln!(oup, "{}{}", line_prefix, name)?;
+            }
+        }
+        ObjectType::Blob if data[..data.len().min(BINARY_CHECK_LENGTH)].contains(&0) => {
+            let mut inner = rga_preproc(AdaptInfo {
+                filepath_hint: PathBuf::from(name.map(str::to_string).unwrap_or(id.to_string())),
+                is_real_file: false,
+                inp: Box::new(Cursor::new(data.to_vec())),
+                line_prefix,
+                archive_recursion_depth: ai.archive_recursion_depth + 1,
+                config: ai.config.clone(),
+            })?;
+            std::io::copy(&mut inner, oup)?;
+        }
+        _ => {
+            for line in String::from_utf8_lossy(data).lines() {
+                if !line.trim().is_empty() {
+                    writeln!(oup, "{}{}", line_prefix, line)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// libgit2 resolves the deltas while building an index for the pack, which is then read like an object database
+fn adapt_pack(ai: &mut AdaptInfo, oup: &mut dyn Write) -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let pack_dir = dir.path().join("pack");
+    std::fs::create_dir(&pack_dir)?;
+    let mut indexer = Indexer::new(None, &pack_dir, 0, false)?;
+    std::io::copy(&mut ai.inp, &mut indexer)?;
+    indexer.commit()?;
+    let odb = Odb::new()?;
+    odb.add_disk_alternate(
+        dir.path()
+            .to_str()
+            .ok_or_else(|| format_err!("temp dir is not utf8"))?,
+    )?;
+    let mut ids = Vec::new();
+    odb.foreach(|id| {
+        ids.push(*id);
+        true
+    })?;
+    let mut names = HashMap::new();
+    for id in &ids {
+        let object = odb.read(*id)?;
+        if object.kind() == ObjectType::Tree {
+            names.extend(
+                tree_entries(object.data())?
+                    .into_iter()
+                    .map(|(name, id)| (id, name)),
+            );
+        }
+    }
+    for id in ids {
+        let object = odb.read(id)?;
+        let name = names.get(&id).map(String::as_str);
+        adapt_object(id, object.kind(), object.data(), name, ai, oup)?;
+    }
+    Ok(())
+}
+
+/// version 2 starts with a magic number, version 1 stores an offset in front of each id
+fn adapt_index(ai: &mut AdaptInfo, oup: &mut dyn Write) -> Result<()> {
+    let mut data = Vec::new();
+    ai.inp.read_to_end(&mut data)?;
+    let (fanout, entry_size) = if data.starts_with(b"\xfftOc") {
+        (8, 20)
+    } else {
+        (0, 24)
+    };
+    let count = data
+        .get(fanout + 255 * 4..fanout + 256 * 4)
+        .ok_or_else(|| format_err!("truncated pack index"))?;
+    let count = u32::from_be_bytes(count.try_into()?) as usize;
+    let entries = &data[fanout + 256 * 4..];
+    for i in 0..count {
+        let offset = i * entry_size + entry_size - 20;
+        let id = entries
+            .get(offset..offset + 20)
+            .ok_or_else(|| format_err!("truncated pack index"))?;
+        writeln!(oup, "{}{}", ai.line_prefix, Oid::from_bytes(id)?)?;
+    }
+    Ok(())
+}
+
+/// a zlib stream of `<type> <size>\0<content>`. files that only look like loose objects are copied through
+fn adapt_loose(ai: &mut AdaptInfo, oup: &mut dyn Write) -> Result<()> {
+    let mut compressed = Vec::new();
+    ai.inp.read_to_end(&mut compressed)?;
+    let mut data = Vec::new();
+    let inflated = flate2::read::ZlibDecoder::new(&compressed[..]).read_to_end(&mut data);
+    let header_end = data.iter().position(|b| *b == 0);
+    let object = match (inflated, header_end) {
+        (std::result::Result::Ok(_), Some(end)) => String::from_utf8_lossy(&data[..end])
+            .split(' ')
+            .next()
+            .and_then(ObjectType::from_str)
+            .map(|kind| (kind, &data[end + 1..])),
+        _ => None,
+    };
+    match object {
+        Some((kind, content)) => {
+            let id = Oid::hash_object(kind, content)?;
+            adapt_object(id, kind, content, None, ai, oup)
+        }
+        None => {
+            oup.write_all(&compressed)?;
+            Ok(())
+        }
+    }
+}
+
+impl WritingFileAdapterTrait for GitObjectAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let extension = ai
+            .filepath_hint
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("pack") => adapt_pack(&mut ai, oup),
+            Some("idx") => adapt_index(&mut ai, oup),
+            _ => adapt_loose(&mut ai, oup),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use ::git2::{Repository, Signature, Time};
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn pack() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let repo = Repository::init_bare(dir.path().join("repo.git"))?;
+        let readme = repo.blob(b"# example\napi_key = hunter2\n")?;
+        let logo = repo.blob(b"\0\x01\x02")?;
+        let mut tree = repo.treebuilder(None)?;
+        tree.insert("README.md", readme, 0o100644)?;
+        tree.insert("logo.bin", logo, 0o100644)?;
+        let tree = repo.find_tree(tree.write()?)?;
+        let signature = Signature::new("Jane Doe", "jane@example.com", &Time::new(0, 0))?;
+        let commit = repo.commit(None, &signature, &signature, "initial commit", &tree, &[])?;
+        let mut builder = repo.packbuilder()?;
+        builder.insert_commit(commit)?;
+        let pack_dir = dir.path().join("pack");
+        std::fs::create_dir(&pack_dir)?;
+        builder.write(&pack_dir, 0)?;
+        let name = builder.name().unwrap().to_string();
+
+        let filepath = pack_dir.join(format!("pack-{}.pack", name));
+        let (a, d) = simple_adapt_info(&filepath, Box::new(File::open(&filepath)?));
+        let mut r = GitObjectAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        let o = String::from_utf8(o)?;
+        for line in &[
+            format!("PREFIX:{}: api_key = hunter2", readme),
+            format!("PREFIX:{}: README.md", tree.id()),
+            format!("PREFIX:{}: logo.bin", tree.id()),
+            format!("PREFIX:{}: tree {}", commit, tree.id()),
+            format!("PREFIX:{}: initial commit", commit),
+        ] {
+            assert!(o.lines().any(|l| l == line), "missing {} in {}", line, o);
+        }
+        // binary blobs without an adapter are passed through
+        assert!(o.contains("\0\x01\x02"));
+
+        let filepath = pack_dir.join(format!("pack-{}.idx", name));
+        let (a, d) = simple_adapt_info(&filepath, Box::new(File::open(&filepath)?));
+        let mut r = GitObjectAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        let mut ids = [readme, logo, tree.id(), commit];
+        ids.sort();
+        let expected: String = ids.iter().map(|id| format!("PREFIX:{}\n", id)).collect();
+        assert_eq!(String::from_utf8(o)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn loose() -> Result<()> {
+        let mut z = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        z.write_all(b"blob 23\0hello\n\npassword = 1234\n")?;
+        let data = z.finish()?;
+        let id = Oid::hash_object(ObjectType::Blob, b"hello\n\npassword = 1234\n")?;
+        let id = id.to_string();
+
+        let filepath = PathBuf::from(&id[..2]).join(&id[2..]);
+        let mut a = simple_adapt_info(Path::new("placeholder.txt"), Box::new(Cursor::new(data))).0;
+        a.filepath_hint = filepath;
+        let d = FastMatcher::FileName("[0-9a-f]".repeat(38)).into();
+        let mut r = GitObjectAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            format!(
+                "PREFIX:{id}: hello\nPREFIX:{id}: password = 1234\n",
+                id = id
+            )
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+use super::html::write_html_text;
+use super::xml::{text_of, xml_reader};
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use quick_xml::events::{BytesStart, Event};
+use std::io::{BufRead, BufReader, Cursor};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["gpx", "kml", "kmz"];
+static MIME_TYPES: &[&str] = &[
+    "application/gpx+xml",
+    "application/vnd.google-earth.kml+xml",
+    "application/vnd.google-earth.kmz",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "gps".to_owned(),
+        version: 1,
+        description: "Extracts the names and descriptions of waypoints, routes and tracks in GPX files and of placemarks in KML and KMZ files, as `wpt: name`. The coordinates are skipped".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct GpsAdapter;
+
+impl GpsAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(GpsAdapter))
+    }
+}
+impl GetMetadata for GpsAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the things that have a name: waypoints, routes and tracks in gpx, features in kml
+static FEATURE_ELEMENTS: &[&[u8]] = &[
+    b"metadata",
+    b"wpt",
+    b"rte",
+    b"rtept",
+    b"trk",
+    b"trkpt",
+    b"Document",
+    b"Folder",
+    b"Placemark",
+    b"NetworkLink",
+    b"GroundOverlay",
+    b"ScreenOverlay",
+    b"PhotoOverlay",
+];
+
+static TEXT_ELEMENTS: &[&[u8]] = &[
+    b"name",
+    b"desc",
+    b"cmt",
+    b"keywords",
+    b"description",
+    b"Snippet",
+    b"address",
+];
+
+fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+/// kml descriptions are often html
+fn write_text(line_prefix: &str, text: &str, oup: &mut dyn Write) -> Result<()> {
+    if text.contains('<') {
+        return write_html_text(line_prefix, text, false, oup);
+    }
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() {
+            writeln!(oup, "{}{}", line_prefix, line)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_gps_text(line_prefix: &str, inp: impl BufRead, oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(inp);
+    let mut buf = Vec::new();
+    // the innermost feature around the current element
+    let mut features: Vec<(usize, String)> = Vec::new();
+    let mut depth = 0;
+    // the depth of the text element we are in, and its text so far
+    let mut text: Option<(usize, String)> = None;
+    // the name of the current kml `<Data>` or `<SimpleData>`
+    let mut data_name: Option<String> = None;
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        let feature_prefix = match features.last() {
+            Some((_, kind)) => format!("{}{}: ", line_prefix, kind),
+            None => line_prefix.to_string(),
+        };
+        match &event {
+            Event::Start(e) => {
+                depth += 1;
+                let name = e.local_name();
+                if FEATURE_ELEMENTS.contains(&name.as_ref()) {
+                    let kind = String::from_utf8_lossy(name.as_ref()).into_owned();
+                    features.push((depth, kind));
+                } else if name.as_ref() == b"Data" || name.as_ref() == b"SimpleData" {
+                    data_name = attribute(e, b"name");
+                    if name.as_ref() == b"SimpleData" {
+                        text = Some((depth, String::new()));
+                    }
+                } else if text.is_none()
+                    && (TEXT_ELEMENTS.contains(&name.as_ref())
+                        || (name.as_ref() == b"value" && data_name.is_some()))
+                {
+                    text = Some((depth, String::new()));
+                }
+            }
+            Event::Text(t) => {
+                if let Some((_, s)) = &mut text {
+                    s.push_str(&text_of(t));
+                }
+            }
+            Event::CData(t) => {
+                if let Some((_, s)) = &mut text {
+                    s.push_str(&String::from_utf8_lossy(t));
+                }
+            }
+            Event::End(e) => {
+                if text.as_ref().map(|(d, _)| *d) == Some(depth) {
+                    let (_, s) = text.take().unwrap();
+                    match &data_name {
+                        // extended data of a placemark, as `name=value`
+                        Some(data) => {
+                            write_text(&format!("{}{}=", feature_prefix, data), s.trim(), oup)?
+                        }
+                        None => write_text(&feature_prefix, &s, oup)?,
+                    }
+                }
+                let name = e.local_name();
+                if name.as_ref() == b"Data" || name.as_ref() == b"SimpleData" {
+                    data_name = None;
+                }
+                if features.last().map(|(d, _)| *d) == Some(depth) {
+                    features.pop();
+                }
+                depth -= 1;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for GpsAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut inp = BufReader::new(ai.inp);
+        if !inp.fill_buf()?.starts_with(b"PK\x03\x04") {
+            return write_gps_text(&ai.line_prefix, inp, oup);
+        }
+        // kmz is a zip file with the kml document (usually doc.kml) and the images it uses
+        let mut data = Vec::new();
+        inp.read_to_end(&mut data)?;
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+        for i in 0..archive.len() {
+            let file = archive.by_index(i)?;
+            if !file.name().to_ascii_lowercase().ends_with(".kml") {
+                continue;
+            }
+            debug!("{}|{}", ai.filepath_hint.display(), file.name());
+            write_gps_text(&ai.line_prefix, BufReader::new(file), oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    static KML: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2">
+  <Document>
+    <name>Berlin trip</name>
+    <Placemark>
+      <name>Brandenburger Tor</name>
+      <description><![CDATA[<p>Meet at the <b>north</b> side</p><p>10:00</p>]]></description>
+      <ExtendedData>
+        <Data name="visited"><value>2019-05-01</value></Data>
+        <SchemaData schemaUrl="#s"><SimpleData name="rating">5</SimpleData></SchemaData>
+      </ExtendedData>
+      <Point><coordinates>13.377704,52.516275,0</coordinates></Point>
+    </Placemark>
+  </Document>
+</kml>
+"##;
+
+    fn adapt(filename: &str, data: Vec<u8>) -> Result<String> {
+        let (a, d) = simple_adapt_info(Path::new(filename), Box::new(Cursor::new(data)));
+        let mut r = GpsAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn gpx() -> Result<()> {
+        let gpx = r#"<?xml version="1.0"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+  <metadata><name>Alps 2020</name></metadata>
+  <wpt lat="47.42" lon="10.98"><ele>2962</ele><name>Zugspitze</name><desc>highest peak
+    of Germany</desc></wpt>
+  <trk><name>Day 1 &amp; 2</name><trkseg><trkpt lat="47.1" lon="11.0"/></trkseg></trk>
+</gpx>
+"#;
+        assert_eq!(
+            adapt("alps.gpx", gpx.as_bytes().to_vec())?,
+            "PREFIX:metadata: Alps 2020
+PREFIX:wpt: Zugspitze
+PREFIX:wpt: highest peak
+PREFIX:wpt: of Germany
+PREFIX:trk: Day 1 & 2
+"
+        );
+        Ok(())
+    }
+
+    static KML_EXPECTED: &str = "PREFIX:Document: Berlin trip
+PREFIX:Placemark: Brandenburger Tor
+PREFIX:Placemark: Meet at the north side
+PREFIX:Placemark: 10:00
+PREFIX:Placemark: visited=2019-05-01
+PREFIX:Placemark: rating=5
+";
+
+    #[test]
+    fn kml() -> Result<()> {
+        assert_eq!(adapt("berlin.kml", KML.as_bytes().to_vec())?, KML_EXPECTED);
+        Ok(())
+    }
+
+    #[test]
+    fn kmz() -> Result<()> {
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        zip.start_file("doc.kml", options)?;
+        zip.write_all(KML.as_bytes())?;
+        zip.start_file("files/photo.jpg", options)?;
+        zip.write_all(b"\xff\xd8\xff")?;
+        let data = zip.finish()?.into_inner();
+        assert_eq!(adapt("berlin.kmz", data)?, KML_EXPECTED);
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use serde_json::Value;
+use std::io::BufReader;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["json", "jsonl", "ndjson"];
+/// files with one document per line
+static LINES_EXTENSIONS: &[&str] = &["jsonl", "ndjson"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "gron".to_owned(),
+        version: 1,
+        description: "Flattens JSON documents into `json.path.to.key = value` lines (like gron), so matches show the full path of the value. Disabled by default since JSON is already text".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType("application/json".to_owned())]),
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct GronAdapter;
+
+impl GronAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(GronAdapter))
+    }
+}
+impl GetMetadata for GronAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+fn write_flat(
+    line_prefix: &str,
+    path: &mut String,
+    value: &Value,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    match value {
+        Value::Object(map) => {
+            writeln!(oup, "{}{} = {{}}", line_prefix, path)?;
+            for (key, value) in map {
+                let len = path.len();
+                if is_identifier(key) {
+                    path.push('.');
+                    path.push_str(key);
+                } else {
+                    path.push('[');
+                    path.push_str(&Value::String(key.clone()).to_string());
+                    path.push(']');
+                }
+                write_flat(line_prefix, path, value, oup)?;
+                path.truncate(len);
+            }
+        }
+        Value::Array(items) => {
+            writeln!(oup, "{}{} = []", line_prefix, path)?;
+            for (i, value) in items.iter().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("[{}]", i));
+                write_flat(line_prefix, path, value, oup)?;
+                path.truncate(len);
+            }
+        }
+        // numbers, strings etc. are written as json, so strings stay quoted and escaped
+        value => writeln!(oup, "{}{} = {}", line_prefix, path, value)?,
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for GronAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let lines = match detection_reason {
+            SlowMatcher::Fast(FastMatcher::FileExtension(ext)) => {
+                LINES_EXTENSIONS.contains(&ext.as_str())
+            }
+            _ => false,
+        };
+        let inp = BufReader::new(ai.inp);
+        if lines {
+            let documents = serde_json::Deserializer::from_reader(inp).into_iter::<Value>();
+            for (i, document) in documents.enumerate() {
+                let mut path = format!("json[{}]", i);
+                write_flat(&ai.line_prefix, &mut path, &document?, oup)?;
+            }
+        } else {
+            let document: Value = serde_json::from_reader(inp)?;
+            write_flat(&ai.line_prefix, &mut "json".to_string(), &document, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn adapt(ext: &str, json: &str) -> Result<String> {
+        let (a, _) = simple_adapt_info(
+            Path::new("test.json"),
+            Box::new(Cursor::new(json.as_bytes().to_vec())),
+        );
+        let mut r = GronAdapter::new().adapt(
+            a,
+            &SlowMatcher::Fast(FastMatcher::FileExtension(ext.to_string())),
+        )?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn flatten() -> Result<()> {
+        assert_eq!(
+            adapt(
+                "json",
+                r#"{"customer": {"id": 3, "first name": "Bob"}, "tags": ["a", null]}"#
+            )?,
+            r#"PREFIX:json = {}
+PREFIX:json.customer = {}
+PREFIX:json.customer.id = 3
+PREFIX:json.customer["first name"] = "Bob"
+PREFIX:json.tags = []
+PREFIX:json.tags[0] = "a"
+PREFIX:json.tags[1] = null
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn json_lines() -> Result<()> {
+        assert_eq!(
+            adapt("jsonl", "{\"a\": 1}\n{\"a\": \"x\\ny\"}\n")?,
+            r#"PREFIX:json[0] = {}
+PREFIX:json[0].a = 1
+PREFIX:json[1] = {}
+PREFIX:json[1].a = "x\ny"
+"#
+        );
+        Ok(())
+    }
+}
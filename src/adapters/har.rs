@@ -0,0 +1,179 @@
+use super::warc::write_payload;
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::io::BufReader;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["har"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "har".to_owned(),
+        version: 1,
+        description: "Reads HAR browser network captures. Request and response bodies are decoded and converted to text, prefixed with the method and URL".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct HarAdapter;
+
+impl HarAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(HarAdapter))
+    }
+}
+impl GetMetadata for HarAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+// the parts of http://www.softwareishard.com/blog/har-12-spec/ we need
+#[derive(Deserialize)]
+struct Har {
+    log: Log,
+}
+
+#[derive(Deserialize)]
+struct Log {
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    request: Request,
+    response: Response,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    method: String,
+    url: String,
+    #[serde(rename = "postData")]
+    post_data: Option<PostData>,
+}
+
+#[derive(Deserialize)]
+struct PostData {
+    #[serde(rename = "mimeType")]
+    mime_type: Option<String>,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    status: i64,
+    content: Content,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    #[serde(rename = "mimeType")]
+    mime_type: Option<String>,
+    text: Option<String>,
+    encoding: Option<String>,
+}
+
+/// the text is already decoded, so the charset of the original response doesn't apply anymore
+fn strip_charset(mime_type: Option<&str>) -> Option<&str> {
+    mime_type.map(|m| m.split(';').next().unwrap_or("").trim())
+}
+
+fn write_entry(ai: &AdaptInfo, entry: Entry, oup: &mut dyn Write) -> Result<()> {
+    let Entry { request, response } = entry;
+    let url = &request.url;
+    if let Some(PostData {
+        mime_type,
+        text: Some(text),
+    }) = request.post_data
+    {
+        let prefix = format!("{}{} {} request: ", ai.line_prefix, request.method, url);
+        let mime_type = strip_charset(mime_type.as_deref());
+        write_payload(ai, &prefix, url, mime_type, text.into_bytes(), oup)?;
+    }
+    let content = response.content;
+    if let Some(text) = content.text {
+        let prefix = format!(
+            "{}{} {} {}: ",
+            ai.line_prefix, request.method, url, response.status
+        );
+        let (mime_type, body) = if content.encoding.as_deref() == Some("base64") {
+            // binary or compressed responses are stored base64 encoded
+            let body = base64::decode(text.trim())
+                .with_context(|| format!("invalid base64 response body of {}", url))?;
+            (content.mime_type.as_deref(), body)
+        } else {
+            (
+                strip_charset(content.mime_type.as_deref()),
+                text.into_bytes(),
+            )
+        };
+        write_payload(ai, &prefix, url, mime_type, body, oup)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for HarAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let inp = std::mem::replace(&mut ai.inp, Box::new(std::io::empty()));
+        let har: Har = serde_json::from_reader(BufReader::new(inp))?;
+        for (i, entry) in har.log.entries.into_iter().enumerate() {
+            write_entry(&ai, entry, oup).with_context(|| format!("entry {}", i + 1))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn entries() -> Result<()> {
+        let har = format!(
+            r#"{{"log": {{"version": "1.2", "creator": {{"name": "test", "version": "1"}}, "entries": [
+  {{"request": {{"method": "GET", "url": "https://example.com/", "headers": []}},
+    "response": {{"status": 200, "content": {{"size": 40, "mimeType": "text/html; charset=iso-8859-1", "text": "<html><p>hello wörld</p><script>x()</script></html>"}}}}}},
+  {{"request": {{"method": "POST", "url": "https://example.com/api/login", "headers": [],
+      "postData": {{"mimeType": "application/json", "text": "{{\"user\": \"alice\"}}"}}}},
+    "response": {{"status": 401, "content": {{"size": 20, "mimeType": "application/json", "text": "{}", "encoding": "base64"}}}}}},
+  {{"request": {{"method": "GET", "url": "https://example.com/empty"}},
+    "response": {{"status": 204, "content": {{"size": 0}}}}}}
+]}}}}"#,
+            base64::encode("{\"error\": \"bad token\"}")
+        );
+        let (a, d) = simple_adapt_info(
+            Path::new("test.har"),
+            Box::new(Cursor::new(har.into_bytes())),
+        );
+        let mut r = HarAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:GET https://example.com/ 200: hello wörld
+PREFIX:POST https://example.com/api/login request: {\"user\": \"alice\"}
+PREFIX:POST https://example.com/api/login 401: {\"error\": \"bad token\"}
+"
+        );
+        Ok(())
+    }
+}
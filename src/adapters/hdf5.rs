@@ -0,0 +1,260 @@
+use super::*;
+use ::hdf5_reader::group::Group;
+use ::hdf5_reader::{Attribute, ByteOrder, Datatype, Hdf5File, VarLenKind};
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::collections::HashSet;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["h5", "hdf5", "he5"];
+
+/// string datasets with more elements are only listed, not dumped
+const MAX_STRING_ELEMENTS: u64 = 1000;
+/// numeric attributes can be whole arrays
+const MAX_ATTRIBUTE_VALUES: usize = 16;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "hdf5".to_owned(),
+        version: 1,
+        description: "Lists the groups and datasets in HDF5 files with their attributes, as `/path/to/dataset@attribute: value`. The content of small string datasets is included, numeric data is not".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/x-hdf5".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct Hdf5Adapter;
+
+impl Hdf5Adapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(Hdf5Adapter))
+    }
+}
+impl GetMetadata for Hdf5Adapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn type_name(datatype: &Datatype) -> String {
+    match datatype {
+        Datatype::FixedPoint { size, signed, .. } => {
+            format!("{}{}", if *signed { "i" } else { "u" }, *size as u32 * 8)
+        }
+        Datatype::FloatingPoint { size, .. } => format!("f{}", *size as u32 * 8),
+        Datatype::String { .. }
+        | Datatype::VarLen {
+            kind: VarLenKind::String,
+            ..
+        } => "string".to_string(),
+        Datatype::Array { base, dims } => format!("{}{:?}", type_name(base), dims),
+        Datatype::VarLen { base, .. } => format!("vlen {}", type_name(base)),
+        Datatype::Enum { .. } => "enum".to_string(),
+        Datatype::Compound { .. } => "compound".to_string(),
+        Datatype::Opaque { .. } => "opaque".to_string(),
+        Datatype::Reference { .. } => "reference".to_string(),
+        Datatype::Bitfield { .. } => "bitfield".to_string(),
+    }
+}
+
+fn is_string(datatype: &Datatype) -> bool {
+    matches!(
+        datatype,
+        Datatype::String { .. }
+            | Datatype::VarLen {
+                kind: VarLenKind::String,
+                ..
+            }
+    )
+}
+
+/// integers and floats of any size and byte order
+fn numbers(datatype: &Datatype, data: &[u8]) -> Option<Vec<String>> {
+    let (size, byte_order) = match datatype {
+        Datatype::FixedPoint {
+            size, byte_order, ..
+        }
+        | Datatype::FloatingPoint { size, byte_order } => (*size as usize, byte_order),
+        _ => return None,
+    };
+    if !matches!(size, 1 | 2 | 4 | 8) {
+        return None;
+    }
+    let values = data.chunks_exact(size).take(MAX_ATTRIBUTE_VALUES).map(|b| {
+        let mut bytes = [0u8; 8];
+        match byte_order {
+            ByteOrder::LittleEndian => bytes[..size].copy_from_slice(b),
+            ByteOrder::BigEndian => {
+                for (i, byte) in b.iter().rev().enumerate() {
+                    bytes[i] = *byte;
+                }
+            }
+        }
+        let raw = u64::from_le_bytes(bytes);
+        match datatype {
+            Datatype::FloatingPoint { .. } if size == 4 => f32::from_bits(raw as u32).to_string(),
+            Datatype::FloatingPoint { .. } if size == 8 => f64::from_bits(raw).to_string(),
+            Datatype::FixedPoint { signed: true, .. } => {
+                // sign extend
+                let shift = 64 - size * 8;
+                (((raw << shift) as i64) >> shift).to_string()
+            }
+            _ => raw.to_string(),
+        }
+    });
+    Some(values.collect())
+}
+
+fn write_attributes(
+    line_prefix: &str,
+    path: &str,
+    attributes: &[Attribute],
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for attribute in attributes {
+        let line_prefix = format!("{}{}@{}: ", line_prefix, path, attribute.name);
+        if is_string(&attribute.datatype) {
+            if let std::result::Result::Ok(strings) = attribute.read_strings() {
+                for line in strings.iter().flat_map(|s| s.lines()) {
+                    writeln!(oup, "{}{}", line_prefix, line)?;
+                }
+                continue;
+            }
+        }
+        match numbers(&attribute.datatype, &attribute.raw_data) {
+            Some(values) => writeln!(oup, "{}{}", line_prefix, values.join(", "))?,
+            None => writeln!(oup, "{}{}", line_prefix, type_name(&attribute.datatype))?,
+        }
+    }
+    Ok(())
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    format!("{}/{}", parent.trim_end_matches('/'), name)
+}
+
+fn adapt_group(
+    group: &Group,
+    path: &str,
+    visited: &mut HashSet<u64>,
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    // hard links can point back to a parent group
+    if !visited.insert(group.address()) {
+        return Ok(());
+    }
+    write_attributes(line_prefix, path, &group.attributes()?, oup)?;
+    let (groups, datasets) = group.members()?;
+    for dataset in datasets {
+        let path = join_path(path, dataset.name());
+        writeln!(
+            oup,
+            "{}{}: dataset {} {:?}",
+            line_prefix,
+            path,
+            type_name(dataset.dtype()),
+            dataset.shape()
+        )?;
+        write_attributes(line_prefix, &path, &dataset.attributes(), oup)?;
+        if is_string(dataset.dtype()) && dataset.num_elements()? <= MAX_STRING_ELEMENTS {
+            match dataset.read_strings() {
+                std::result::Result::Ok(strings) => {
+                    for line in strings.iter().flat_map(|s| s.lines()) {
+                        writeln!(oup, "{}{}: {}", line_prefix, path, line)?;
+                    }
+                }
+                Err(e) => debug!("{}: {}", path, e),
+            }
+        }
+    }
+    for child in groups {
+        let path = join_path(path, child.name());
+        writeln!(oup, "{}{}: group", line_prefix, path)?;
+        adapt_group(&child, &path, visited, line_prefix, oup)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for Hdf5Adapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let file = if ai.is_real_file {
+            Hdf5File::open(&ai.filepath_hint)?
+        } else {
+            let mut data = Vec::new();
+            ai.inp.read_to_end(&mut data)?;
+            Hdf5File::from_vec(data)?
+        };
+        adapt_group(
+            &file.root_group()?,
+            "/",
+            &mut HashSet::new(),
+            &ai.line_prefix,
+            oup,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use ::hdf5_pure::{AttrValue, FileBuilder};
+    use std::io::Cursor;
+
+    #[test]
+    fn hdf5() -> Result<()> {
+        let mut builder = FileBuilder::new();
+        builder.set_attr(
+            "title",
+            AttrValue::String("rat hippocampus recordings".into()),
+        );
+        builder
+            .create_dataset("subjects")
+            .with_vlen_strings(&["rat-01", "rat-02"]);
+        let mut session = builder.create_group("session1");
+        session.set_attr("trials", AttrValue::I64(12));
+        session
+            .create_dataset("voltage")
+            .with_f64_data(&[0.5, -1.25, 2.0])
+            .set_attr("units", AttrValue::String("mV".into()))
+            .set_attr("gain", AttrValue::I32Array(vec![-2, 300]));
+        builder.add_group(session.finish());
+        let data = builder.finish()?;
+
+        let (mut a, d) = simple_adapt_info(Path::new("recordings.h5"), Box::new(Cursor::new(data)));
+        a.is_real_file = false;
+        let mut r = Hdf5Adapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:/@title: rat hippocampus recordings
+PREFIX:/subjects: dataset string [2]
+PREFIX:/subjects: rat-01
+PREFIX:/subjects: rat-02
+PREFIX:/session1: group
+PREFIX:/session1@trials: 12
+PREFIX:/session1/voltage: dataset f64 [3]
+PREFIX:/session1/voltage@units: mV
+PREFIX:/session1/voltage@gain: -2, 300
+"
+        );
+        Ok(())
+    }
+}
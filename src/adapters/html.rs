@@ -0,0 +1,156 @@
+use super::epub::BLOCK_ELEMENTS;
+use super::*;
+use anyhow::*;
+use ego_tree::iter::Edge;
+use lazy_static::lazy_static;
+use scraper::{Html, Node};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["html", "htm", "xhtml"];
+static MIME_TYPES: &[&str] = &["text/html", "application/xhtml+xml"];
+/// elements whose contents are not part of the text
+static SKIP_ELEMENTS: &[&str] = &["script", "style", "template"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "html".to_owned(),
+        version: 1,
+        description: "Converts HTML documents to plain text, one block element per line. Scripts and styles are removed. With --rga-html-links, link targets are kept".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![AdapterOption::HtmlLinks]
+    };
+}
+#[derive(Default, Clone)]
+pub struct HtmlAdapter;
+
+impl HtmlAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(HtmlAdapter))
+    }
+}
+impl GetMetadata for HtmlAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn starts_line(name: &str) -> bool {
+    name == "title" || BLOCK_ELEMENTS.contains(&name.as_bytes())
+}
+
+/// convert a html document to plain text, one block element per line
+pub fn write_html_text(
+    line_prefix: &str,
+    html: &str,
+    show_links: bool,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let document = Html::parse_document(html);
+    let mut line = String::new();
+    let mut skip_depth = 0;
+    let flush = |line: &mut String, oup: &mut dyn Write| -> Result<()> {
+        let text = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            writeln!(oup, "{}{}", line_prefix, text)?;
+        }
+        line.clear();
+        Ok(())
+    };
+    for edge in document.tree.root().traverse() {
+        match edge {
+            Edge::Open(node) => match node.value() {
+                Node::Element(e) if skip_depth > 0 || SKIP_ELEMENTS.contains(&e.name()) => {
+                    skip_depth += 1
+                }
+                Node::Element(e) if starts_line(e.name()) => flush(&mut line, oup)?,
+                Node::Text(t) if skip_depth == 0 => line.push_str(t),
+                _ => {}
+            },
+            Edge::Close(node) => {
+                if let Node::Element(e) = node.value() {
+                    if skip_depth > 0 {
+                        skip_depth -= 1;
+                    } else if starts_line(e.name()) {
+                        flush(&mut line, oup)?;
+                    } else if show_links && e.name() == "a" {
+                        if let Some(href) = e.attr("href") {
+                            line.push_str(&format!(" ({})", href));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    flush(&mut line, oup)
+}
+
+impl WritingFileAdapterTrait for HtmlAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut html = Vec::new();
+        ai.inp.read_to_end(&mut html)?;
+        write_html_text(
+            &ai.line_prefix,
+            &String::from_utf8_lossy(&html),
+            ai.config.args.html_links,
+            oup,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static HTML: &str = r#"<!DOCTYPE html>
+<html><head><title>Test page</title><style>p { color: red }</style>
+<script>var hidden = "<p>not text</p>";</script></head>
+<body><h1>Hello&nbsp;world</h1><p>this is <b>just</b>
+a <a href="https://example.com/test">test</a>.<br>second line
+<ul><li>one<li>two</ul><p>unclosed <i>tags
+</body></html>"#;
+
+    fn convert(show_links: bool) -> Result<String> {
+        let mut o = Vec::new();
+        write_html_text("PREFIX:", HTML, show_links, &mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn simple() -> Result<()> {
+        assert_eq!(
+            convert(false)?,
+            "PREFIX:Test page
+PREFIX:Hello world
+PREFIX:this is just a test.
+PREFIX:second line
+PREFIX:one
+PREFIX:two
+PREFIX:unclosed tags
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn links() -> Result<()> {
+        assert!(convert(true)?.contains("PREFIX:this is just a test (https://example.com/test).\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+use super::xml::{text_of, xml_reader};
+use super::*;
+use anyhow::*;
+use cfb::CompoundFile;
+use lazy_static::lazy_static;
+use log::*;
+use quick_xml::events::Event;
+use std::convert::TryInto;
+use std::io::{Cursor, Seek};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["hwp", "hwpx"];
+static MIME_TYPES: &[&str] = &["application/x-hwp", "application/haansofthwp"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "hwp".to_owned(),
+        version: 1,
+        description: "Extracts the body text of Hangul Word Processor documents (.hwp 5.0 and .hwpx), one paragraph per line".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct HwpAdapter;
+
+impl HwpAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(HwpAdapter))
+    }
+}
+impl GetMetadata for HwpAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+const FLAG_COMPRESSED: u32 = 1;
+const FLAG_PASSWORD: u32 = 2;
+/// distribution documents have their body text encrypted in ViewText/ instead of BodyText/
+const FLAG_DISTRIBUTION: u32 = 4;
+
+const HWPTAG_PARA_TEXT: u32 = 0x10 + 51;
+
+fn read_stream<F: Read + Seek>(cfb: &mut CompoundFile<F>, path: &str) -> Result<Option<Vec<u8>>> {
+    if !cfb.is_stream(path) {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    cfb.open_stream(path)?.read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+fn utf16(data: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    data.chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+}
+
+/// the text of a paragraph. control characters below 32 are either one character (line
+/// and paragraph breaks, special spaces and hyphens) or eight, with a pointer to a table,
+/// picture or field that is stored in its own record
+fn para_text(data: &[u8]) -> String {
+    let mut chars = Vec::new();
+    let mut units = utf16(data);
+    while let Some(c) = units.next() {
+        match c {
+            0 | 13 => {}
+            10 => chars.push(u16::from(b'\n')),
+            24 => chars.push(u16::from(b'-')),
+            25..=31 => chars.push(u16::from(b' ')),
+            1..=31 => {
+                if c == 9 {
+                    chars.push(u16::from(b'\t'));
+                }
+                for _ in 0..7 {
+                    units.next();
+                }
+            }
+            c => chars.push(c),
+        }
+    }
+    String::from_utf16_lossy(&chars)
+}
+
+/// a section is a list of records: a header with the tag, level and size, then the data
+fn write_section(line_prefix: &str, data: &[u8], oup: &mut dyn Write) -> Result<()> {
+    let mut offset = 0;
+    while offset + 4 <= data.len() {
+        let header = u32::from_le_bytes(data[offset..offset + 4].try_into()?);
+        offset += 4;
+        let tag = header & 0x3ff;
+        let mut size = (header >> 20) as usize;
+        if size == 0xfff {
+            size = u32::from_le_bytes(
+                data.get(offset..offset + 4)
+                    .context("truncated record")?
+                    .try_into()?,
+            ) as usize;
+            offset += 4;
+        }
+        let record = data
+            .get(offset..offset + size)
+            .context("truncated record")?;
+        offset += size;
+        if tag == HWPTAG_PARA_TEXT {
+            for line in para_text(record).lines() {
+                writeln!(oup, "{}{}", line_prefix, line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// hwp 5.0 is a compound file, with one stream per section of the body text
+fn adapt_hwp(data: Vec<u8>, line_prefix: &str, oup: &mut dyn Write) -> Result<()> {
+    let mut cfb = CompoundFile::open(Cursor::new(data))?;
+    let header = read_stream(&mut cfb, "/FileHeader")?.context("not a hwp 5 document")?;
+    if header.len() < 40 || !header.starts_with(b"HWP Document File") {
+        bail!("not a hwp 5 document");
+    }
+    let flags = u32::from_le_bytes(header[36..40].try_into()?);
+    if flags & (FLAG_PASSWORD | FLAG_DISTRIBUTION) != 0 {
+        // the preview text (the first part of the document) is not encrypted
+        debug!("hwp document is encrypted, using the preview text");
+        if let Some(preview) = read_stream(&mut cfb, "/PrvText")? {
+            let preview = String::from_utf16_lossy(&utf16(&preview).collect::<Vec<_>>());
+            for line in preview.lines() {
+                writeln!(oup, "{}{}", line_prefix, line)?;
+            }
+        }
+        return Ok(());
+    }
+    for i in 0.. {
+        let section = match read_stream(&mut cfb, &format!("/BodyText/Section{}", i))? {
+            Some(section) => section,
+            None => break,
+        };
+        let section = if flags & FLAG_COMPRESSED != 0 {
+            // raw deflate, without a zlib header
+            let mut decompressed = Vec::new();
+            flate2::read::DeflateDecoder::new(&section[..]).read_to_end(&mut decompressed)?;
+            decompressed
+        } else {
+            section
+        };
+        write_section(line_prefix, &section, oup)?;
+    }
+    Ok(())
+}
+
+/// the sections of a hwpx (OWPML) document are xml files in Contents/
+fn adapt_hwpx(data: Vec<u8>, line_prefix: &str, oup: &mut dyn Write) -> Result<()> {
+    let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+    let mut sections: Vec<(usize, String)> = archive
+        .file_names()
+        .filter_map(|name| {
+            let number = name
+                .strip_prefix("Contents/section")?
+                .strip_suffix(".xml")?
+                .parse()
+                .ok()?;
+            Some((number, name.to_string()))
+        })
+        .collect();
+    sections.sort();
+    for (_, name) in sections {
+        let mut xml = Vec::new();
+        archive.by_name(&name)?.read_to_end(&mut xml)?;
+        let mut reader = xml_reader(&xml[..]);
+        let mut buf = Vec::new();
+        // paragraphs in tables are nested in the paragraph that contains the table
+        let mut paragraphs: Vec<String> = Vec::new();
+        let mut in_text = false;
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(e) => match e.local_name().as_ref() {
+                    b"p" => paragraphs.push(String::new()),
+                    b"t" => in_text = true,
+                    _ => {}
+                },
+                Event::Empty(e) => match (e.local_name().as_ref(), paragraphs.last_mut()) {
+                    (b"tab", Some(p)) => p.push('\t'),
+                    (b"lineBreak", Some(p)) => p.push('\n'),
+                    _ => {}
+                },
+                Event::Text(t) if in_text => {
+                    if let Some(p) = paragraphs.last_mut() {
+                        p.push_str(&text_of(&t));
+                    }
+                }
+                Event::End(e) => match e.local_name().as_ref() {
+                    b"t" => in_text = false,
+                    b"p" => {
+                        if let Some(p) = paragraphs.pop() {
+                            for line in p.lines() {
+                                writeln!(oup, "{}{}", line_prefix, line)?;
+                            }
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for HwpAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        if data.starts_with(b"PK\x03\x04") {
+            adapt_hwpx(data, &ai.line_prefix, oup)
+        } else {
+            adapt_hwp(data, &ai.line_prefix, oup)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use flate2::write::DeflateEncoder;
+
+    fn adapt(filename: &str, data: Vec<u8>) -> Result<String> {
+        let (a, d) = simple_adapt_info(Path::new(filename), Box::new(Cursor::new(data)));
+        let mut r = HwpAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    fn record(tag: u32, data: &[u8]) -> Vec<u8> {
+        let mut out = (tag | (data.len() as u32) << 20).to_le_bytes().to_vec();
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn wide(units: &[u16]) -> Vec<u8> {
+        units.iter().flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn hwp() -> Result<()> {
+        let mut header = b"HWP Document File".to_vec();
+        header.resize(32, 0);
+        header.extend_from_slice(&0x0501_0000u32.to_le_bytes());
+        header.extend_from_slice(&FLAG_COMPRESSED.to_le_bytes());
+        header.resize(256, 0);
+
+        let mut text: Vec<u16> = "공문서 제".encode_utf16().collect();
+        // a field in the middle of the text
+        text.extend(&[3, 0x6c64, 0x6665, 0, 0, 0, 0, 3]);
+        text.extend("12호\n".encode_utf16());
+        text.push(9);
+        text.extend(&[0; 7]);
+        text.extend("끝".encode_utf16());
+        text.push(13);
+        let mut section = record(0x10 + 50, &[0; 22]);
+        section.extend(record(HWPTAG_PARA_TEXT, &wide(&text)));
+        section.extend(record(0x10 + 52, &[0; 8]));
+        let mut compressed = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        compressed.write_all(&section)?;
+
+        let mut cfb = CompoundFile::create(Cursor::new(Vec::new()))?;
+        cfb.create_stream("/FileHeader")?.write_all(&header)?;
+        cfb.create_storage("/BodyText")?;
+        cfb.create_stream("/BodyText/Section0")?
+            .write_all(&compressed.finish()?)?;
+        cfb.flush()?;
+        let data = cfb.into_inner().into_inner();
+
+        assert_eq!(
+            adapt("notice.hwp", data)?,
+            "PREFIX:공문서 제12호\nPREFIX:\t끝\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hwpx() -> Result<()> {
+        let section = r#"<?xml version="1.0" encoding="UTF-8"?>
+<hs:sec xmlns:hs="http://www.hancom.co.kr/hwpml/2011/section" xmlns:hp="http://www.hancom.co.kr/hwpml/2011/paragraph">
+<hp:p><hp:run><hp:t>제목: 예산</hp:t></hp:run></hp:p>
+<hp:p><hp:run><hp:t>합계<hp:tab/>1,000원</hp:t></hp:run></hp:p>
+</hs:sec>"#;
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        zip.start_file("mimetype", options)?;
+        zip.write_all(b"application/hwp+zip")?;
+        zip.start_file("Contents/section0.xml", options)?;
+        zip.write_all(section.as_bytes())?;
+        let data = zip.finish()?.into_inner();
+
+        assert_eq!(
+            adapt("budget.hwpx", data)?,
+            "PREFIX:제목: 예산\nPREFIX:합계\t1,000원\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use rusqlite::{Connection, OpenFlags, NO_PARAMS};
+use std::fs::File;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static FILE_NAMES: &[&str] = &["Manifest.db"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "iosbackup".to_owned(),
+        version: 1,
+        description: "Reads iTunes / Finder backups of iOS devices. Matches the Manifest.db of the backup and recurses into the backed up files, prefixed with their real path (`HomeDomain/Library/SMS/sms.db: `). The hashed files are also searched on their own, so use `-g Manifest.db` to only search through the manifest. Encrypted backups are skipped".to_owned(),
+        recurses: true,
+        fast_matchers: FILE_NAMES
+            .iter()
+            .map(|s| FastMatcher::FileName(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct IosBackupAdapter;
+
+impl IosBackupAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(IosBackupAdapter))
+    }
+}
+impl GetMetadata for IosBackupAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the files in the manifest as (file id, domain, relative path). the flags are 1 for files,
+/// 2 for directories and 4 for symlinks
+fn manifest_files(manifest: &Path) -> rusqlite::Result<Vec<(String, String, String)>> {
+    let conn = Connection::open_with_flags(manifest, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "select fileID, domain, relativePath from Files where flags = 1 order by domain, relativePath",
+    )?;
+    let files = stmt
+        .query_map(NO_PARAMS, |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+        .collect();
+    files
+}
+
+/// adapters of real files open them by their path, so the hashed file is linked to its real name
+fn real_name_link(
+    tmp_dir: &Path,
+    file_id: &str,
+    relative_path: &str,
+    file: &Path,
+) -> Result<PathBuf> {
+    let name = Path::new(relative_path)
+        .file_name()
+        .context("empty relative path")?;
+    let dir = tmp_dir.join(file_id);
+    std::fs::create_dir(&dir)?;
+    let link = dir.join(name);
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(file, &link)?;
+    #[cfg(not(unix))]
+    std::fs::copy(file, &link)?;
+    Ok(link)
+}
+
+impl WritingFileAdapterTrait for IosBackupAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let backup_dir = match ai.filepath_hint.parent().filter(|_| ai.is_real_file) {
+            Some(dir) => dir.to_owned(),
+            None => return sqlite::SqliteAdapter.adapt_write(ai, detection_reason, oup),
+        };
+        let files = match manifest_files(&ai.filepath_hint) {
+            Result::Ok(files) => files,
+            // the manifest of encrypted backups is encrypted too
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::NotADatabase =>
+            {
+                writeln!(
+                    oup,
+                    "{}[rga: skipping encrypted iOS backup]",
+                    ai.line_prefix
+                )?;
+                return Ok(());
+            }
+            // some other database that is called Manifest.db
+            Err(e) => {
+                debug!("{}: not an iOS backup: {}", ai.filepath_hint.display(), e);
+                return sqlite::SqliteAdapter.adapt_write(ai, detection_reason, oup);
+            }
+        };
+        // like files in archives, the backed up files are passed through if no adapter matches.
+        // matching by mime type also finds the databases that don't have a .db extension (.sqlitedb)
+        let mut config = ai.config.clone();
+        config.args.accurate = true;
+        let tmp_dir = tempfile::Builder::new()
+            .prefix("rga-iosbackup-")
+            .tempdir()?;
+        for (file_id, domain, relative_path) in files {
+            // files are stored as `ab/abcdef...`, by the sha1 of their domain and path
+            let file = backup_dir
+                .join(file_id.get(..2).unwrap_or_default())
+                .join(&file_id);
+            if !file.is_file() {
+                debug!("{} is missing from the backup", file.display());
+                continue;
+            }
+            let link = real_name_link(tmp_dir.path(), &file_id, &relative_path, &file)?;
+            let mut inner = rga_preproc(AdaptInfo {
+                inp: Box::new(File::open(&link)?),
+                filepath_hint: link,
+                is_real_file: true,
+                archive_recursion_depth: ai.archive_recursion_depth + 1,
+                line_prefix: format!("{}{}/{}: ", ai.line_prefix, domain, relative_path),
+                config: config.clone(),
+            })?;
+            std::io::copy(&mut inner, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn ios_backup() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let manifest = dir.path().join("Manifest.db");
+        let conn = Connection::open(&manifest)?;
+        conn.execute_batch(
+            "create table Files (fileID text primary key, domain text, relativePath text, flags integer, file blob);
+            insert into Files values ('3d0d7e5fb2ce288813306e4d4636395e047a3d28', 'HomeDomain', 'Library/SMS/sms.db', 1, null);
+            insert into Files values ('ca3bc056d4da0bbf88b5fb3be254f3b7147e639c', 'HomeDomain', 'Library/Notes/todo.txt', 1, null);
+            insert into Files values ('0000000000000000000000000000000000000000', 'HomeDomain', 'Library/Notes', 2, null);
+            insert into Files values ('1111111111111111111111111111111111111111', 'HomeDomain', 'Library/missing.txt', 1, null);",
+        )?;
+        drop(conn);
+        std::fs::create_dir(dir.path().join("3d"))?;
+        let sms = Connection::open(
+            dir.path()
+                .join("3d/3d0d7e5fb2ce288813306e4d4636395e047a3d28"),
+        )?;
+        sms.execute_batch(
+            "create table message (text text); insert into message values ('see you at 8');",
+        )?;
+        drop(sms);
+        std::fs::create_dir(dir.path().join("ca"))?;
+        std::fs::write(
+            dir.path()
+                .join("ca/ca3bc056d4da0bbf88b5fb3be254f3b7147e639c"),
+            "buy milk\n",
+        )?;
+
+        let (a, d) = simple_adapt_info(&manifest, Box::new(File::open(&manifest)?));
+        let mut r = IosBackupAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "buy milk
+PREFIX:HomeDomain/Library/SMS/sms.db: message: text='see you at 8'
+"
+        );
+        Ok(())
+    }
+}
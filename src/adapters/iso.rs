@@ -0,0 +1,579 @@
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["iso", "udf"];
+static MIME_TYPES: &[&str] = &["application/x-iso9660-image", "application/x-cd-image"];
+
+/// the volume descriptors are always in 2048 byte sectors, starting at sector 16
+const SECTOR_SIZE: u64 = 2048;
+const FIRST_DESCRIPTOR: u64 = 16;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "iso".to_owned(),
+        version: 1,
+        description: "Reads ISO 9660 disk images (CDs, DVDs, OS installers) and recurses down into their contents. Long file names are read from the Rock Ridge or Joliet extensions. Images with only a UDF file system are read with 7z".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct IsoAdapter;
+
+impl IsoAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(IsoAdapter))
+    }
+}
+impl GetMetadata for IsoAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Names {
+    /// upper case 8.3 names with a version, `README.TXT;1`
+    Plain,
+    /// utf-16 names in a separate directory tree, used by windows
+    Joliet,
+    /// posix names in the system use area of the records, used by unix tools
+    RockRidge { skip: usize },
+}
+
+/// a file in the image. files larger than 4 GiB are split into several extents
+#[derive(Debug, PartialEq)]
+struct IsoFile {
+    path: String,
+    extents: Vec<(u64, u64)>,
+}
+
+struct DirRecord<'a> {
+    extent: u32,
+    size: u32,
+    is_dir: bool,
+    multi_extent: bool,
+    name: &'a [u8],
+    system_use: &'a [u8],
+}
+
+fn u32_le(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn parse_record(record: &[u8]) -> Option<DirRecord<'_>> {
+    let name_len = *record.get(32)? as usize;
+    let name = record.get(33..33 + name_len)?;
+    // the name is padded to an even length
+    let system_use = record.get(33 + name_len + (1 - name_len % 2)..)?;
+    Some(DirRecord {
+        extent: u32_le(record, 2),
+        size: u32_le(record, 10),
+        is_dir: record[25] & 2 != 0,
+        multi_extent: record[25] & 0x80 != 0,
+        name,
+        system_use,
+    })
+}
+
+/// the directory records, which are not split across sectors
+fn records(data: &[u8]) -> Vec<&[u8]> {
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let len = data[pos] as usize;
+        if len == 0 {
+            pos = (pos / SECTOR_SIZE as usize + 1) * SECTOR_SIZE as usize;
+            continue;
+        }
+        match data.get(pos..pos + len) {
+            Some(record) if len >= 33 => records.push(record),
+            _ => break,
+        }
+        pos += len;
+    }
+    records
+}
+
+fn plain_name(name: &[u8]) -> String {
+    let name = String::from_utf8_lossy(name);
+    let name = name.split(';').next().unwrap_or_default();
+    name.strip_suffix('.').unwrap_or(name).to_string()
+}
+
+fn joliet_name(name: &[u8]) -> String {
+    let units: Vec<u16> = name
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect();
+    let name = String::from_utf16_lossy(&units);
+    name.split(';').next().unwrap_or_default().to_string()
+}
+
+struct Image {
+    file: File,
+    block_size: u64,
+}
+
+impl Image {
+    fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![0; len];
+        self.file.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// the SUSP entries of a record, following the continuation areas
+    fn susp_entries(&mut self, system_use: &[u8]) -> Result<Vec<([u8; 2], Vec<u8>)>> {
+        let mut entries = Vec::new();
+        let mut area = system_use.to_vec();
+        // limited, since an invalid image could make continuations loop
+        for _ in 0..16 {
+            let mut continuation = None;
+            let mut pos = 0;
+            while pos + 4 <= area.len() {
+                let len = area[pos + 2] as usize;
+                if len < 4 || pos + len > area.len() {
+                    break;
+                }
+                let sig = [area[pos], area[pos + 1]];
+                let body = &area[pos + 4..pos + len];
+                match &sig {
+                    b"CE" if body.len() >= 24 => {
+                        continuation = Some((u32_le(body, 0), u32_le(body, 8), u32_le(body, 16)))
+                    }
+                    b"ST" => break,
+                    _ => entries.push((sig, body.to_vec())),
+                }
+                pos += len;
+            }
+            match continuation {
+                Some((block, offset, len)) => {
+                    area =
+                        self.read_at(block as u64 * self.block_size + offset as u64, len as usize)?
+                }
+                None => break,
+            }
+        }
+        Ok(entries)
+    }
+
+    fn walk(
+        &mut self,
+        names: Names,
+        dir: &DirRecord,
+        dir_path: &str,
+        visited: &mut HashSet<u32>,
+        files: &mut Vec<IsoFile>,
+    ) -> Result<()> {
+        if !visited.insert(dir.extent) {
+            return Ok(());
+        }
+        let data = self.read_at(dir.extent as u64 * self.block_size, dir.size as usize)?;
+        let mut pending: Option<IsoFile> = None;
+        // the first two records are the directory itself and its parent
+        for record in records(&data).into_iter().skip(2) {
+            let mut record = match parse_record(record) {
+                Some(record) => record,
+                None => continue,
+            };
+            let mut name = match names {
+                Names::Joliet => joliet_name(record.name),
+                _ => plain_name(record.name),
+            };
+            if let Names::RockRidge { skip } = names {
+                let system_use = record.system_use.get(skip..).unwrap_or_default();
+                let mut rock_ridge_name = String::new();
+                let mut relocated = false;
+                for (sig, body) in self.susp_entries(system_use)? {
+                    match &sig {
+                        b"NM" if !body.is_empty() => {
+                            rock_ridge_name.push_str(&String::from_utf8_lossy(&body[1..]))
+                        }
+                        // directories deeper than 8 levels are moved elsewhere and
+                        // left behind as a file that links to them
+                        b"RE" => relocated = true,
+                        b"CL" if body.len() >= 4 => {
+                            record.extent = u32_le(&body, 0);
+                            record.is_dir = true;
+                            let own = self.read_at(record.extent as u64 * self.block_size, 34)?;
+                            record.size = u32_le(&own, 10);
+                        }
+                        _ => {}
+                    }
+                }
+                if relocated {
+                    continue;
+                }
+                if !rock_ridge_name.is_empty() {
+                    name = rock_ridge_name;
+                }
+            }
+            if name.is_empty() {
+                continue;
+            }
+            let path = format!("{}{}", dir_path, name);
+            if record.is_dir {
+                self.walk(names, &record, &format!("{}/", path), visited, files)?;
+                continue;
+            }
+            let extent = (record.extent as u64 * self.block_size, record.size as u64);
+            match pending.as_mut().filter(|p| p.path == path) {
+                Some(file) => file.extents.push(extent),
+                None => {
+                    files.extend(pending.take());
+                    pending = Some(IsoFile {
+                        path,
+                        extents: vec![extent],
+                    });
+                }
+            }
+            if !record.multi_extent {
+                files.extend(pending.take());
+            }
+        }
+        files.extend(pending);
+        Ok(())
+    }
+}
+
+enum Volume {
+    Iso {
+        names: Names,
+        root: Vec<u8>,
+    },
+    /// no ISO 9660 file system, but the descriptors of UDF
+    Udf,
+}
+
+/// reads the volume descriptors and picks the directory tree with the best names
+fn volume(image: &mut Image) -> Result<Volume> {
+    let mut primary = None;
+    let mut joliet = None;
+    let mut udf = false;
+    for sector in FIRST_DESCRIPTOR..FIRST_DESCRIPTOR + 64 {
+        let descriptor = match image.read_at(sector * SECTOR_SIZE, SECTOR_SIZE as usize) {
+            Result::Ok(descriptor) => descriptor,
+            Err(_) => break,
+        };
+        match &descriptor[1..6] {
+            b"CD001" => match descriptor[0] {
+                1 if primary.is_none() => primary = Some(descriptor),
+                2 if [b"%/@", b"%/C", b"%/E"]
+                    .iter()
+                    .any(|escape| descriptor[88..91] == escape[..]) =>
+                {
+                    joliet = Some(descriptor)
+                }
+                _ => {}
+            },
+            b"NSR02" | b"NSR03" => udf = true,
+            b"BEA01" | b"BOOT2" => {}
+            _ => break,
+        }
+    }
+    let primary = match primary {
+        Some(primary) => primary,
+        None if udf => return Ok(Volume::Udf),
+        None => bail!("not an ISO 9660 image"),
+    };
+    image.block_size = u16::from_le_bytes([primary[128], primary[129]]) as u64;
+    // rock ridge is announced by an SP entry in the first record of the root directory
+    let root = primary[156..190].to_vec();
+    let root_record = parse_record(&root).context("invalid root directory record")?;
+    let first = image.read_at(root_record.extent as u64 * image.block_size, 255)?;
+    let rock_ridge = records(&first)
+        .first()
+        .and_then(|r| parse_record(r))
+        .and_then(|r| {
+            let sp = r.system_use;
+            if sp.len() >= 7 && &sp[0..2] == b"SP" && sp[4..6] == [0xbe, 0xef] {
+                Some(sp[6] as usize)
+            } else {
+                None
+            }
+        });
+    Ok(match (rock_ridge, joliet) {
+        (Some(skip), _) => Volume::Iso {
+            names: Names::RockRidge { skip },
+            root,
+        },
+        (None, Some(joliet)) => Volume::Iso {
+            names: Names::Joliet,
+            root: joliet[156..190].to_vec(),
+        },
+        (None, None) => Volume::Iso {
+            names: Names::Plain,
+            root,
+        },
+    })
+}
+
+impl WritingFileAdapterTrait for IsoAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // the image is read at random positions, so an image within an archive has to be written to disk first
+        let _tmp_file;
+        let image_path = if ai.is_real_file {
+            ai.filepath_hint.clone()
+        } else {
+            let mut tmp = tempfile::Builder::new()
+                .prefix("rga-iso-")
+                .suffix(".iso")
+                .tempfile()?;
+            std::io::copy(&mut ai.inp, &mut tmp)?;
+            let path = tmp.path().to_owned();
+            _tmp_file = tmp;
+            path
+        };
+        let mut image = Image {
+            file: File::open(&image_path)?,
+            block_size: SECTOR_SIZE,
+        };
+        let (names, root) = match volume(&mut image)? {
+            Volume::Iso { names, root } => (names, root),
+            Volume::Udf => {
+                return dmg::adapt_with_7z(
+                    &image_path,
+                    &ai.line_prefix,
+                    ai.archive_recursion_depth,
+                    &ai.config,
+                    |name| PathBuf::from(name),
+                    oup,
+                )
+            }
+        };
+        debug!("{}: reading {:?} names", ai.filepath_hint.display(), names);
+        let root = parse_record(&root).context("invalid root directory record")?;
+        let mut files = Vec::new();
+        image.walk(names, &root, "", &mut HashSet::new(), &mut files)?;
+        for file in files {
+            let mut inp: ReadBox = Box::new(std::io::empty());
+            for (offset, len) in file.extents {
+                let mut extent = File::open(&image_path)?;
+                extent.seek(SeekFrom::Start(offset))?;
+                inp = Box::new(inp.chain(extent.take(len)));
+            }
+            let mut inner = rga_preproc(AdaptInfo {
+                filepath_hint: PathBuf::from(&file.path),
+                is_real_file: false,
+                archive_recursion_depth: ai.archive_recursion_depth + 1,
+                inp,
+                line_prefix: format!("{}{}: ", ai.line_prefix, file.path),
+                config: ai.config.clone(),
+            })?;
+            std::io::copy(&mut inner, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn record(extent: u32, size: u32, is_dir: bool, name: &[u8], system_use: &[u8]) -> Vec<u8> {
+        let mut r = vec![0; 33];
+        r[2..6].copy_from_slice(&extent.to_le_bytes());
+        r[6..10].copy_from_slice(&extent.to_be_bytes());
+        r[10..14].copy_from_slice(&size.to_le_bytes());
+        r[14..18].copy_from_slice(&size.to_be_bytes());
+        r[25] = if is_dir { 2 } else { 0 };
+        r[32] = name.len() as u8;
+        r.extend_from_slice(name);
+        if r.len() % 2 == 1 {
+            r.push(0);
+        }
+        r.extend_from_slice(system_use);
+        if r.len() % 2 == 1 {
+            r.push(0);
+        }
+        r[0] = r.len() as u8;
+        r
+    }
+
+    fn sector(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut s = records.concat();
+        s.resize(SECTOR_SIZE as usize, 0);
+        s
+    }
+
+    fn descriptor(kind: u8, escape: &[u8], root: u32) -> Vec<u8> {
+        let mut d = vec![0; SECTOR_SIZE as usize];
+        d[0] = kind;
+        d[1..6].copy_from_slice(b"CD001");
+        d[6] = 1;
+        d[88..88 + escape.len()].copy_from_slice(escape);
+        d[128..130].copy_from_slice(&2048u16.to_le_bytes());
+        d[130..132].copy_from_slice(&2048u16.to_be_bytes());
+        d[156..190].copy_from_slice(&record(root, 2048, true, &[0], &[]));
+        d
+    }
+
+    fn rock_ridge_name(name: &str) -> Vec<u8> {
+        let mut nm = vec![b'N', b'M', 5 + name.len() as u8, 1, 0];
+        nm.extend_from_slice(name.as_bytes());
+        nm
+    }
+
+    fn utf16(name: &str) -> Vec<u8> {
+        name.encode_utf16().flat_map(|u| u.to_be_bytes()).collect()
+    }
+
+    /// a root directory with `Read Me.txt` and `docs/release notes.md`, optionally with
+    /// rock ridge entries and a joliet tree
+    fn image(rock_ridge: bool, joliet: bool) -> Vec<u8> {
+        let readme = b"the quick brown fox\n";
+        let notes = b"fixed the frobnicator\n";
+        let sp: &[u8] = if rock_ridge {
+            &[b'S', b'P', 7, 1, 0xbe, 0xef, 0]
+        } else {
+            &[]
+        };
+        let nm = |name: &str| {
+            if rock_ridge {
+                rock_ridge_name(name)
+            } else {
+                vec![]
+            }
+        };
+        let mut data = vec![0; 16 * SECTOR_SIZE as usize];
+        data.extend(descriptor(1, b"", 19));
+        if joliet {
+            data.extend(descriptor(2, b"%/E", 21));
+        } else {
+            data.extend(sector(&[]));
+        }
+        let mut terminator = descriptor(255, b"", 0);
+        terminator[156..190].fill(0);
+        data.extend(terminator);
+        // 19: plain root, 20: plain docs
+        data.extend(sector(&[
+            record(19, 2048, true, &[0], sp),
+            record(19, 2048, true, &[1], &[]),
+            record(20, 2048, true, b"DOCS", &nm("docs")),
+            record(
+                22,
+                readme.len() as u32,
+                false,
+                b"READ_ME.TXT;1",
+                &nm("Read Me.txt"),
+            ),
+        ]));
+        data.extend(sector(&[
+            record(20, 2048, true, &[0], &[]),
+            record(19, 2048, true, &[1], &[]),
+            record(
+                23,
+                notes.len() as u32,
+                false,
+                b"RELEASE_.MD;1",
+                &nm("release notes.md"),
+            ),
+        ]));
+        // 21: joliet root, 24: joliet docs
+        data.extend(sector(&[
+            record(21, 2048, true, &[0], &[]),
+            record(21, 2048, true, &[1], &[]),
+            record(24, 2048, true, &utf16("docs"), &[]),
+            record(22, readme.len() as u32, false, &utf16("Read Me.txt;1"), &[]),
+        ]));
+        data.extend(sector(&[readme.to_vec()]));
+        data.extend(sector(&[notes.to_vec()]));
+        data.extend(sector(&[
+            record(24, 2048, true, &[0], &[]),
+            record(21, 2048, true, &[1], &[]),
+            record(
+                23,
+                notes.len() as u32,
+                false,
+                &utf16("release notes.md;1"),
+                &[],
+            ),
+        ]));
+        data
+    }
+
+    fn paths(data: Vec<u8>) -> Result<(Names, Vec<String>)> {
+        let mut tmp = tempfile::NamedTempFile::new()?;
+        tmp.write_all(&data)?;
+        let mut image = Image {
+            file: File::open(tmp.path())?,
+            block_size: SECTOR_SIZE,
+        };
+        let (names, root) = match volume(&mut image)? {
+            Volume::Iso { names, root } => (names, root),
+            Volume::Udf => bail!("not iso"),
+        };
+        let mut files = Vec::new();
+        let root = parse_record(&root).unwrap();
+        image.walk(names, &root, "", &mut HashSet::new(), &mut files)?;
+        Ok((names, files.into_iter().map(|f| f.path).collect()))
+    }
+
+    #[test]
+    fn names() -> Result<()> {
+        assert_eq!(
+            paths(image(false, false))?,
+            (
+                Names::Plain,
+                vec!["DOCS/RELEASE_.MD".to_string(), "READ_ME.TXT".to_string()]
+            )
+        );
+        let long_names = vec![
+            "docs/release notes.md".to_string(),
+            "Read Me.txt".to_string(),
+        ];
+        assert_eq!(
+            paths(image(false, true))?,
+            (Names::Joliet, long_names.clone())
+        );
+        assert_eq!(
+            paths(image(true, true))?,
+            (Names::RockRidge { skip: 0 }, long_names)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn recurse() -> Result<()> {
+        let (mut a, d) = simple_adapt_info(
+            Path::new("drivers.iso"),
+            Box::new(std::io::Cursor::new(image(true, false))),
+        );
+        a.is_real_file = false;
+        let mut r = IsoAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "fixed the frobnicator\nthe quick brown fox\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::convert::TryInto;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["class"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "javaclass".to_owned(),
+        version: 1,
+        description: "Reads compiled Java classes (.class, also in jar and war files). Outputs the class declaration, the names of fields and methods and the string literals from the constant pool, prefixed with the class name".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/x-java-applet".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct JavaClassAdapter;
+
+impl JavaClassAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(JavaClassAdapter))
+    }
+}
+impl GetMetadata for JavaClassAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+const CONSTANT_UTF8: u8 = 1;
+const CONSTANT_CLASS: u8 = 7;
+const CONSTANT_STRING: u8 = 8;
+
+#[derive(Clone)]
+enum Constant {
+    Utf8(String),
+    Class(u16),
+    String(u16),
+    Other,
+}
+
+struct ClassReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ClassReader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .context("truncated class file")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn constant_pool(&mut self) -> Result<Vec<Constant>> {
+        let count = self.u16()? as usize;
+        // the pool starts at index 1
+        let mut pool = vec![Constant::Other];
+        while pool.len() < count {
+            let tag = self.bytes(1)?[0];
+            let constant = match tag {
+                CONSTANT_UTF8 => {
+                    let len = self.u16()? as usize;
+                    Constant::Utf8(modified_utf8(self.bytes(len)?))
+                }
+                CONSTANT_CLASS => Constant::Class(self.u16()?),
+                CONSTANT_STRING => Constant::String(self.u16()?),
+                // method type, module and package
+                16 | 19 | 20 => {
+                    self.u16()?;
+                    Constant::Other
+                }
+                // method handle
+                15 => {
+                    self.bytes(3)?;
+                    Constant::Other
+                }
+                // integer, float, field and method references, name and type, dynamic
+                3 | 4 | 9 | 10 | 11 | 12 | 17 | 18 => {
+                    self.bytes(4)?;
+                    Constant::Other
+                }
+                // long and double take up two entries
+                5 | 6 => {
+                    self.bytes(8)?;
+                    pool.push(Constant::Other);
+                    Constant::Other
+                }
+                _ => bail!("invalid constant pool tag {}", tag),
+            };
+            pool.push(constant);
+        }
+        Ok(pool)
+    }
+
+    /// the names of the fields or methods, skipping their attributes
+    fn members(&mut self, pool: &[Constant]) -> Result<Vec<String>> {
+        let count = self.u16()?;
+        let mut names = Vec::new();
+        for _ in 0..count {
+            let _access_flags = self.u16()?;
+            let name = utf8(pool, self.u16()?);
+            let _descriptor = self.u16()?;
+            let attributes = self.u16()?;
+            for _ in 0..attributes {
+                let _name = self.u16()?;
+                let len = self.u32()? as usize;
+                self.bytes(len)?;
+            }
+            names.push(name.to_string());
+        }
+        Ok(names)
+    }
+}
+
+/// java stores strings in a variant of utf-8 where the null character and characters
+/// outside of the BMP are encoded like surrogates in UTF-16
+fn modified_utf8(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Result::Ok(s) => s.to_string(),
+        Err(_) => {
+            let mut units = Vec::new();
+            let mut i = 0;
+            while i < bytes.len() {
+                let b = bytes[i] as u16;
+                let (unit, len) = match bytes[i] {
+                    0x00..=0x7f => (b, 1),
+                    0xc0..=0xdf if i + 1 < bytes.len() => {
+                        (((b & 0x1f) << 6) | (bytes[i + 1] as u16 & 0x3f), 2)
+                    }
+                    0xe0..=0xef if i + 2 < bytes.len() => (
+                        ((b & 0x0f) << 12)
+                            | ((bytes[i + 1] as u16 & 0x3f) << 6)
+                            | (bytes[i + 2] as u16 & 0x3f),
+                        3,
+                    ),
+                    _ => (0xfffd, 1),
+                };
+                units.push(unit);
+                i += len;
+            }
+            String::from_utf16_lossy(&units)
+        }
+    }
+}
+
+fn utf8(pool: &[Constant], index: u16) -> &str {
+    match pool.get(index as usize) {
+        Some(Constant::Utf8(s)) => s,
+        _ => "",
+    }
+}
+
+/// `com/example/Client` -> `com.example.Client`
+fn class_name(pool: &[Constant], index: u16) -> Option<String> {
+    match pool.get(index as usize) {
+        Some(Constant::Class(name)) => Some(utf8(pool, *name).replace('/', ".")),
+        _ => None,
+    }
+}
+
+fn write_class(line_prefix: &str, data: &[u8], oup: &mut dyn Write) -> Result<()> {
+    if !data.starts_with(&[0xca, 0xfe, 0xba, 0xbe]) {
+        bail!("not a java class file");
+    }
+    let mut reader = ClassReader { data, pos: 8 };
+    let pool = reader.constant_pool()?;
+    let _access_flags = reader.u16()?;
+    let this_class = class_name(&pool, reader.u16()?).unwrap_or_default();
+    let prefix = format!("{}{}: ", line_prefix, this_class);
+    let mut declaration = format!("class {}", this_class);
+    if let Some(super_class) = class_name(&pool, reader.u16()?) {
+        declaration.push_str(&format!(" extends {}", super_class));
+    }
+    let interfaces = (0..reader.u16()?)
+        .map(|_| Ok(class_name(&pool, reader.u16()?).unwrap_or_default()))
+        .collect::<Result<Vec<_>>>()?;
+    if !interfaces.is_empty() {
+        declaration.push_str(&format!(" implements {}", interfaces.join(", ")));
+    }
+    writeln!(oup, "{}{}", prefix, declaration)?;
+    for field in reader.members(&pool)? {
+        writeln!(oup, "{}field {}", prefix, field)?;
+    }
+    for method in reader.members(&pool)? {
+        writeln!(oup, "{}method {}", prefix, method)?;
+    }
+    // string literals, like urls, sql queries and messages
+    for constant in &pool {
+        if let Constant::String(index) = constant {
+            for line in utf8(&pool, *index).lines().filter(|l| !l.trim().is_empty()) {
+                writeln!(oup, "{}{}", prefix, line)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for JavaClassAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        write_class(&ai.line_prefix, &data, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf8_constant(s: &[u8]) -> Vec<u8> {
+        let mut out = vec![CONSTANT_UTF8];
+        out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+        out.extend_from_slice(s);
+        out
+    }
+
+    fn member(name: u16, descriptor: u16) -> Vec<u8> {
+        let mut out = vec![0, 1];
+        out.extend_from_slice(&name.to_be_bytes());
+        out.extend_from_slice(&descriptor.to_be_bytes());
+        // one attribute with two bytes, e.g. ConstantValue
+        out.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 2, 0, 0]);
+        out
+    }
+
+    #[test]
+    fn class() -> Result<()> {
+        let pool: Vec<Vec<u8>> = vec![
+            utf8_constant(b"com/example/ApiClient"),
+            vec![CONSTANT_CLASS, 0, 1],
+            utf8_constant(b"java/lang/Object"),
+            vec![CONSTANT_CLASS, 0, 3],
+            utf8_constant(b"java/lang/AutoCloseable"),
+            vec![CONSTANT_CLASS, 0, 5],
+            // a long, which takes two slots
+            vec![5, 0, 0, 0, 0, 0, 0, 0, 42],
+            utf8_constant(b"https://api.example.com/v2"),
+            vec![CONSTANT_STRING, 0, 9],
+            // a null character and an emoji in modified utf-8
+            utf8_constant(b"ok \xc0\x80 \xed\xa0\xbd\xed\xb8\x80"),
+            vec![CONSTANT_STRING, 0, 11],
+            utf8_constant(b"timeout"),
+            utf8_constant(b"J"),
+            utf8_constant(b"connect"),
+            utf8_constant(b"()V"),
+        ];
+        let mut data = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52];
+        data.extend_from_slice(&(pool.len() as u16 + 2).to_be_bytes());
+        data.extend(pool.concat());
+        data.extend_from_slice(&[0, 0x21, 0, 2, 0, 4]);
+        data.extend_from_slice(&[0, 1, 0, 6]);
+        data.extend_from_slice(&[0, 1]);
+        data.extend(member(13, 14));
+        data.extend_from_slice(&[0, 1]);
+        data.extend(member(15, 16));
+        data.extend_from_slice(&[0, 0]);
+
+        let mut o = Vec::new();
+        write_class("PREFIX:", &data, &mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:com.example.ApiClient: class com.example.ApiClient extends java.lang.Object implements java.lang.AutoCloseable
+PREFIX:com.example.ApiClient: field timeout
+PREFIX:com.example.ApiClient: method connect
+PREFIX:com.example.ApiClient: https://api.example.com/v2
+PREFIX:com.example.ApiClient: ok \0 😀
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::convert::TryInto;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["journal", "journal~"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "journal".to_owned(),
+        version: 1,
+        description: "Reads systemd journal files without journalctl. Outputs one line per entry as `timestamp unit: MESSAGE`, followed by the host name, command line and errno if they are set".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct JournalAdapter;
+
+impl JournalAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(JournalAdapter))
+    }
+}
+impl GetMetadata for JournalAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the fields that are written after the message
+static EXTRA_FIELDS: &[&str] = &["_HOSTNAME", "_CMDLINE", "ERRNO"];
+
+/// the fields that name the source of an entry, in order of preference
+static UNIT_FIELDS: &[&str] = &[
+    "_SYSTEMD_UNIT",
+    "_SYSTEMD_USER_UNIT",
+    "SYSLOG_IDENTIFIER",
+    "_COMM",
+];
+
+const OBJECT_DATA: u8 = 1;
+const OBJECT_ENTRY: u8 = 3;
+
+const OBJECT_COMPRESSED_XZ: u8 = 1;
+const OBJECT_COMPRESSED_LZ4: u8 = 2;
+const OBJECT_COMPRESSED_ZSTD: u8 = 4;
+
+/// compact journals (systemd 252 and later) use 32 bit offsets in entries
+const HEADER_INCOMPATIBLE_COMPACT: u32 = 16;
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(
+        data.get(offset..offset + 4)
+            .context("truncated journal")?
+            .try_into()?,
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(
+        data.get(offset..offset + 8)
+            .context("truncated journal")?
+            .try_into()?,
+    ))
+}
+
+struct Journal<'a> {
+    data: &'a [u8],
+    compact: bool,
+}
+
+impl<'a> Journal<'a> {
+    /// an object is a header with the type, flags and size, then the contents
+    fn object(&self, offset: usize) -> Result<(u8, u8, &'a [u8])> {
+        let size = u64_at(self.data, offset + 8)? as usize;
+        if size < 16 {
+            bail!("invalid object size at {}", offset);
+        }
+        let object = self
+            .data
+            .get(offset..offset.saturating_add(size))
+            .context("truncated journal")?;
+        Ok((object[0], object[1], object))
+    }
+
+    /// the `FIELD=value` payload of a data object
+    fn payload(&self, offset: usize) -> Result<Vec<u8>> {
+        let (kind, flags, object) = self.object(offset)?;
+        if kind != OBJECT_DATA {
+            bail!("entry item at {} is not a data object", offset);
+        }
+        let payload = object
+            .get(if self.compact { 72 } else { 64 }..)
+            .context("truncated data object")?;
+        Ok(if flags & OBJECT_COMPRESSED_XZ != 0 {
+            let mut out = Vec::new();
+            xz2::read::XzDecoder::new(payload).read_to_end(&mut out)?;
+            out
+        } else if flags & OBJECT_COMPRESSED_LZ4 != 0 {
+            // lz4 blocks are preceded by the uncompressed size
+            let size = u64_at(payload, 0)? as usize;
+            lz4_flex::block::decompress(&payload[8..], size)?
+        } else if flags & OBJECT_COMPRESSED_ZSTD != 0 {
+            zstd::stream::decode_all(payload)?
+        } else {
+            payload.to_vec()
+        })
+    }
+
+    /// the timestamp (in microseconds) and the fields of an entry object
+    fn entry(&self, object: &[u8]) -> Result<(u64, Vec<(String, String)>)> {
+        let realtime = u64_at(object, 24)?;
+        let item_size = if self.compact { 4 } else { 16 };
+        let mut fields = Vec::new();
+        for item in object.get(64..).unwrap_or_default().chunks_exact(item_size) {
+            let offset = if self.compact {
+                u32_at(item, 0)? as usize
+            } else {
+                u64_at(item, 0)? as usize
+            };
+            let payload = self.payload(offset)?;
+            let payload = String::from_utf8_lossy(&payload);
+            if let Some((field, value)) = payload.split_once('=') {
+                fields.push((field.to_string(), value.to_string()));
+            }
+        }
+        Ok((realtime, fields))
+    }
+}
+
+fn timestamp(micros: u64) -> String {
+    let secs = (micros / 1_000_000) as i64;
+    match chrono::DateTime::from_timestamp(secs, (micros % 1_000_000) as u32 * 1000) {
+        Some(t) => t.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string(),
+        None => micros.to_string(),
+    }
+}
+
+fn write_entry(
+    line_prefix: &str,
+    realtime: u64,
+    fields: &[(String, String)],
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let message = match field("MESSAGE") {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let unit = UNIT_FIELDS
+        .iter()
+        .find_map(|name| field(name))
+        .unwrap_or("-");
+    let extra: Vec<String> = EXTRA_FIELDS
+        .iter()
+        .filter_map(|name| field(name).map(|value| format!("{}={}", name, value)))
+        .collect();
+    let header = format!("{}{} {}: ", line_prefix, timestamp(realtime), unit);
+    let mut lines = message.trim_end().lines();
+    write!(oup, "{}{}", header, lines.next().unwrap_or_default())?;
+    if !extra.is_empty() {
+        write!(oup, "\t{}", extra.join(", "))?;
+    }
+    writeln!(oup)?;
+    // continuation lines of multi-line messages (e.g. stack traces) get the same prefix
+    for line in lines {
+        writeln!(oup, "{}{}", header, line)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for JournalAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        if !data.starts_with(b"LPKSHHRH") {
+            bail!("not a systemd journal file");
+        }
+        let journal = Journal {
+            data: &data,
+            compact: u32_at(&data, 12)? & HEADER_INCOMPATIBLE_COMPACT != 0,
+        };
+        let header_size = u64_at(&data, 88)? as usize;
+        let arena_size = u64_at(&data, 96)? as usize;
+        let end = header_size.saturating_add(arena_size).min(data.len());
+        // the file is append only, so the entries are in the order they were written
+        let mut offset = header_size;
+        while offset + 16 <= end {
+            let (kind, _, object) = match journal.object(offset) {
+                Result::Ok(object) => object,
+                // journals that were not closed cleanly can end with a partially written object
+                Err(e) => {
+                    warn!("{}: {}", ai.filepath_hint.display(), e);
+                    break;
+                }
+            };
+            if kind == OBJECT_ENTRY {
+                match journal.entry(object) {
+                    Result::Ok((realtime, fields)) => {
+                        write_entry(&ai.line_prefix, realtime, &fields, oup)?
+                    }
+                    Err(e) => warn!("{}: skipping entry: {}", ai.filepath_hint.display(), e),
+                }
+            }
+            // objects are aligned to 8 bytes
+            offset += (object.len() + 7) & !7;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    const HEADER_SIZE: usize = 256;
+
+    fn object(kind: u8, flags: u8, body: &[u8]) -> Vec<u8> {
+        let mut object = vec![kind, flags, 0, 0, 0, 0, 0, 0];
+        object.extend_from_slice(&(16 + body.len() as u64).to_le_bytes());
+        object.extend_from_slice(body);
+        object.resize((object.len() + 7) & !7, 0);
+        object
+    }
+
+    /// a journal with a data object for every field and an entry per message
+    fn journal(entries: &[(u64, &[&str])]) -> Vec<u8> {
+        let mut arena = Vec::new();
+        let mut data_offsets = std::collections::HashMap::new();
+        for (_, fields) in entries {
+            for field in fields.iter() {
+                if data_offsets.contains_key(field) {
+                    continue;
+                }
+                data_offsets.insert(*field, HEADER_SIZE + arena.len());
+                let mut body = vec![0; 48];
+                // compress the messages, like journald does for long ones
+                if field.starts_with("MESSAGE=") {
+                    body.extend_from_slice(&(field.len() as u64).to_le_bytes());
+                    body.extend(lz4_flex::block::compress(field.as_bytes()));
+                    arena.extend(object(OBJECT_DATA, OBJECT_COMPRESSED_LZ4, &body));
+                } else {
+                    body.extend_from_slice(field.as_bytes());
+                    arena.extend(object(OBJECT_DATA, 0, &body));
+                }
+            }
+        }
+        for (seqnum, (realtime, fields)) in entries.iter().enumerate() {
+            let mut body = (seqnum as u64 + 1).to_le_bytes().to_vec();
+            body.extend_from_slice(&realtime.to_le_bytes());
+            body.resize(48, 0);
+            for field in fields.iter() {
+                body.extend_from_slice(&(data_offsets[field] as u64).to_le_bytes());
+                body.extend_from_slice(&[0; 8]);
+            }
+            arena.extend(object(OBJECT_ENTRY, 0, &body));
+        }
+        let mut header = b"LPKSHHRH".to_vec();
+        header.resize(88, 0);
+        header.extend_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+        header.extend_from_slice(&(arena.len() as u64).to_le_bytes());
+        header.resize(HEADER_SIZE, 0);
+        header.extend(arena);
+        header
+    }
+
+    #[test]
+    fn journal_entries() -> Result<()> {
+        let data = journal(&[
+            (
+                1_600_000_000_123_456,
+                &[
+                    "_HOSTNAME=web1",
+                    "_SYSTEMD_UNIT=nginx.service",
+                    "SYSLOG_IDENTIFIER=nginx",
+                    "MESSAGE=bind() to 0.0.0.0:80 failed",
+                    "ERRNO=98",
+                ],
+            ),
+            (
+                1_600_000_001_000_000,
+                &[
+                    "_HOSTNAME=web1",
+                    "SYSLOG_IDENTIFIER=kernel",
+                    "MESSAGE=Out of memory: Killed process 812\nsecond line",
+                ],
+            ),
+            (1_600_000_002_000_000, &["_HOSTNAME=web1", "CODE_LINE=12"]),
+        ]);
+        let (a, d) =
+            simple_adapt_info(Path::new("system@000.journal"), Box::new(Cursor::new(data)));
+        let mut r = JournalAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:2020-09-13T12:26:40.123456Z nginx.service: bind() to 0.0.0.0:80 failed\t_HOSTNAME=web1, ERRNO=98
+PREFIX:2020-09-13T12:26:41.000000Z kernel: Out of memory: Killed process 812\t_HOSTNAME=web1
+PREFIX:2020-09-13T12:26:41.000000Z kernel: second line
+"
+        );
+        Ok(())
+    }
+}
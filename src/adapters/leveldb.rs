@@ -0,0 +1,577 @@
+use super::executable::{ascii_strings, utf16_strings};
+use super::protobuf::{read_varint, take};
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["ldb", "sst"];
+/// the write-ahead log with the latest changes, e.g. `000003.log`
+static LOG_FILE_NAME: &str = "[0-9][0-9][0-9][0-9][0-9][0-9].log";
+
+const LEVELDB_MAGIC: u64 = 0xdb47_7524_8b80_fb57;
+const ROCKSDB_MAGIC: u64 = 0x88e2_41b7_85f4_cff7;
+/// the compression type and checksum after each block
+const BLOCK_TRAILER_SIZE: u64 = 5;
+const LOG_BLOCK_SIZE: usize = 32768;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "leveldb".to_owned(),
+        version: 1,
+        description: "Writes the keys and values in the table files (.ldb, .sst) and write-ahead logs of LevelDB and RocksDB databases, as used by Chrome (local storage, IndexedDB) and many other applications. Binary values are reduced to their strings".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .chain(std::iter::once(FastMatcher::FileName(LOG_FILE_NAME.to_string())))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct LevelDbAdapter;
+
+impl LevelDbAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(LevelDbAdapter))
+    }
+}
+impl GetMetadata for LevelDbAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+#[derive(Clone, Copy)]
+struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+fn read_handle(data: &mut &[u8]) -> Option<BlockHandle> {
+    Some(BlockHandle {
+        offset: read_varint(data)?,
+        size: read_varint(data)?,
+    })
+}
+
+fn length_prefixed<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = read_varint(data)?;
+    take(data, len.try_into().ok()?)
+}
+
+/// blocks end with the offsets of their restart points and their count. rocksdb can add a hash
+/// index in front of the count, which is then marked by the highest bit
+fn entries_end(block: &[u8]) -> Option<usize> {
+    let packed = u32::from_le_bytes(block.get(block.len().checked_sub(4)?..)?.try_into().ok()?);
+    let restarts = (packed & 0x7fff_ffff) as usize * 4;
+    let mut end = block.len() - 4;
+    if packed & 0x8000_0000 != 0 {
+        let buckets = block.get(end.checked_sub(2)?..end)?;
+        end -= 2 + u16::from_le_bytes([buckets[0], buckets[1]]) as usize;
+    }
+    end.checked_sub(restarts)
+}
+
+/// the keys are prefix compressed: each entry only stores what differs from the key before it
+fn block_entries(block: &[u8]) -> Option<Vec<(Vec<u8>, &[u8])>> {
+    let mut data = block.get(..entries_end(block)?)?;
+    let mut entries = Vec::new();
+    let mut key: Vec<u8> = Vec::new();
+    while !data.is_empty() {
+        let shared = read_varint(&mut data)? as usize;
+        let non_shared = read_varint(&mut data)?;
+        let value_len = read_varint(&mut data)?;
+        key.truncate(shared);
+        key.extend(take(&mut data, non_shared.try_into().ok()?)?);
+        entries.push((key.clone(), take(&mut data, value_len.try_into().ok()?)?));
+    }
+    Some(entries)
+}
+
+/// newer rocksdb versions leave out the value length in index blocks and only store the
+/// difference in size to the block before, except at restart points
+fn delta_encoded_handles(block: &[u8], with_first_key: bool) -> Option<Vec<BlockHandle>> {
+    let mut data = block.get(..entries_end(block)?)?;
+    let mut handles: Vec<BlockHandle> = Vec::new();
+    while !data.is_empty() {
+        let shared = read_varint(&mut data)?;
+        let non_shared = read_varint(&mut data)?;
+        take(&mut data, non_shared.try_into().ok()?)?;
+        let handle = match handles.last() {
+            Some(prev) if shared > 0 => {
+                let zigzag = read_varint(&mut data)?;
+                let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+                BlockHandle {
+                    offset: prev.offset + prev.size + BLOCK_TRAILER_SIZE,
+                    size: (prev.size as i64 + delta) as u64,
+                }
+            }
+            _ => read_handle(&mut data)?,
+        };
+        if with_first_key {
+            length_prefixed(&mut data)?;
+        }
+        handles.push(handle);
+    }
+    Some(handles)
+}
+
+fn decompress(compression: u8, mut block: &[u8], size_prefixed: bool) -> Result<Vec<u8>> {
+    // from format version 2 on, rocksdb writes the decompressed size in front of everything but snappy
+    let size = if size_prefixed && compression > 1 {
+        Some(read_varint(&mut block).ok_or_else(|| format_err!("invalid block"))? as usize)
+    } else {
+        None
+    };
+    Ok(match compression {
+        0 => block.to_vec(),
+        1 => snap::raw::Decoder::new().decompress_vec(block)?,
+        // rocksdb writes raw deflate streams for zlib
+        2 => {
+            let mut out = Vec::new();
+            flate2::read::DeflateDecoder::new(block).read_to_end(&mut out)?;
+            out
+        }
+        3 => {
+            let mut out = Vec::new();
+            bzip2::read::BzDecoder::new(block).read_to_end(&mut out)?;
+            out
+        }
+        4 | 5 => {
+            let size =
+                size.ok_or_else(|| format_err!("lz4 blocks without size are not supported"))?;
+            lz4_flex::block::decompress(block, size)?
+        }
+        7 | 0x80 => zstd::stream::decode_all(block)?,
+        _ => bail!("unsupported block compression {}", compression),
+    })
+}
+
+struct Table<'a> {
+    data: &'a [u8],
+    format_version: u32,
+}
+
+impl<'a> Table<'a> {
+    fn block(&self, handle: BlockHandle) -> Result<Vec<u8>> {
+        let start = handle.offset as usize;
+        let end = start.saturating_add(handle.size as usize);
+        let block = self
+            .data
+            .get(start..end)
+            .ok_or_else(|| format_err!("block out of range"))?;
+        let compression = *self
+            .data
+            .get(end)
+            .ok_or_else(|| format_err!("block trailer out of range"))?;
+        decompress(compression, block, self.format_version >= 2)
+    }
+
+    fn entries(&self, handle: BlockHandle) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let block = self.block(handle)?;
+        Ok(block_entries(&block)
+            .ok_or_else(|| format_err!("invalid block"))?
+            .into_iter()
+            .map(|(key, value)| (key, value.to_vec()))
+            .collect())
+    }
+}
+
+/// reads the footer, then the index and the data blocks it points to. returns the user keys and values
+fn table_entries(data: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let magic = data
+        .len()
+        .checked_sub(8)
+        .map(|start| u64::from_le_bytes(data[start..].try_into().unwrap()));
+    let (format_version, part2) = match magic {
+        Some(LEVELDB_MAGIC) if data.len() >= 48 => (0, &data[data.len() - 48..]),
+        Some(ROCKSDB_MAGIC) if data.len() >= 53 => {
+            let version = &data[data.len() - 12..data.len() - 8];
+            (
+                u32::from_le_bytes(version.try_into()?),
+                // after the checksum type
+                &data[data.len() - 52..],
+            )
+        }
+        _ => bail!("not a leveldb or rocksdb table (plain and cuckoo tables are not supported)"),
+    };
+    let table = Table {
+        data,
+        format_version,
+    };
+    let (metaindex, index) = if format_version >= 6 {
+        // the metaindex is right in front of the footer, and the index is one of its entries
+        let size = u32::from_le_bytes(part2[12..16].try_into()?) as u64;
+        let offset = (data.len() as u64 - 53)
+            .checked_sub(size + BLOCK_TRAILER_SIZE)
+            .ok_or_else(|| format_err!("invalid metaindex size"))?;
+        (BlockHandle { offset, size }, None)
+    } else {
+        let mut footer = part2;
+        let metaindex = read_handle(&mut footer).ok_or_else(|| format_err!("invalid footer"))?;
+        let index = read_handle(&mut footer).ok_or_else(|| format_err!("invalid footer"))?;
+        (metaindex, Some(index))
+    };
+    let meta: HashMap<_, _> = table.entries(metaindex)?.into_iter().collect();
+    let meta_handle = |name: &str| {
+        meta.get(name.as_bytes())
+            .and_then(|v| read_handle(&mut &v[..]))
+    };
+    let index = index
+        .or_else(|| meta_handle("rocksdb.index"))
+        .ok_or_else(|| format_err!("table without index"))?;
+    let properties: HashMap<_, _> = match meta_handle("rocksdb.properties") {
+        Some(handle) => table.entries(handle)?.into_iter().collect(),
+        None => HashMap::new(),
+    };
+    let property = |name: &str| {
+        properties
+            .get(name.as_bytes())
+            .and_then(|v| read_varint(&mut &v[..]))
+            .unwrap_or(0)
+    };
+    let delta_encoded = property("rocksdb.index.value.is.delta.encoded") == 1;
+    let index_type = property("rocksdb.block.based.table.index.type");
+    let index_handles = |block: &[u8]| -> Result<Vec<BlockHandle>> {
+        let handles = if delta_encoded {
+            delta_encoded_handles(block, index_type == 3)
+        } else {
+            block_entries(block).and_then(|entries| {
+                entries
+                    .into_iter()
+                    .map(|(_, mut value)| read_handle(&mut value))
+                    .collect()
+            })
+        };
+        handles.ok_or_else(|| format_err!("invalid index block"))
+    };
+    let mut data_blocks = index_handles(&table.block(index)?)?;
+    // partitioned index: the top level points to the index blocks
+    if index_type == 2 {
+        let mut partitions = Vec::new();
+        for handle in data_blocks {
+            partitions.extend(index_handles(&table.block(handle)?)?);
+        }
+        data_blocks = partitions;
+    }
+    let mut entries = Vec::new();
+    for handle in data_blocks {
+        for (key, value) in table.entries(handle)? {
+            // the internal key ends with the sequence number and the type, only values and merge operands are interesting
+            let kind = key.len().checked_sub(8).map(|i| key[i]);
+            if let Some(1) | Some(2) = kind {
+                entries.push((key[..key.len() - 8].to_vec(), value));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// the log consists of 32k blocks of records, which can be split over several blocks.
+/// returns None if the data is not a log
+fn log_records(data: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut records = Vec::new();
+    let mut current = Vec::new();
+    for mut block in data.chunks(LOG_BLOCK_SIZE) {
+        // the rest of a block is padded with zeroes if it is too short for a header
+        while block.len() >= 7 {
+            let header = take(&mut block, 7)?;
+            let len = u16::from_le_bytes([header[4], header[5]]) as usize;
+            let payload = take(&mut block, len)?;
+            match header[6] {
+                // preallocated space at the end of the file
+                0 if len == 0 => break,
+                1 => records.push(payload.to_vec()),
+                2 => current = payload.to_vec(),
+                3 => current.extend(payload),
+                4 => {
+                    current.extend(payload);
+                    records.push(std::mem::take(&mut current));
+                }
+                _ => return None,
+            }
+        }
+    }
+    Some(records)
+}
+
+/// a write batch starts with the sequence number and count, followed by the operations
+fn batch_entries(mut record: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut entries = Vec::new();
+    let mut parse = || -> Option<()> {
+        take(&mut record, 12)?;
+        while !record.is_empty() {
+            let tag = take(&mut record, 1)?[0];
+            // the column family variants of the operations start with its id
+            if let 4 | 5 | 6 | 8 = tag {
+                read_varint(&mut record)?;
+            }
+            match tag {
+                1 | 2 | 5 | 6 => {
+                    let key = length_prefixed(&mut record)?;
+                    entries.push((key, length_prefixed(&mut record)?));
+                }
+                // deletions and log data
+                0 | 3 | 4 | 7 | 8 => {
+                    length_prefixed(&mut record)?;
+                }
+                // rocksdb transaction markers etc.
+                _ => return None,
+            }
+        }
+        Some(())
+    };
+    parse();
+    entries
+}
+
+/// values are often binary (serialized objects, utf-16 strings in chrome's local storage), only their strings are written then
+fn value_text(value: &[u8]) -> String {
+    match std::str::from_utf8(value) {
+        std::result::Result::Ok(s) if !value.contains(&0) => s.to_string(),
+        _ => ascii_strings(value)
+            .into_iter()
+            .chain(utf16_strings(value))
+            .chain(utf16_strings(value.get(1..).unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+fn without_control_chars(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_control() && c != '\n' { ' ' } else { c })
+        .collect()
+}
+
+fn write_entry(line_prefix: &str, key: &[u8], value: &[u8], oup: &mut dyn Write) -> Result<()> {
+    let key = without_control_chars(&String::from_utf8_lossy(key));
+    let value = without_control_chars(&value_text(value));
+    let mut lines = value.lines().filter(|l| !l.trim().is_empty()).peekable();
+    if lines.peek().is_none() {
+        writeln!(oup, "{}{}", line_prefix, key)?;
+    }
+    for line in lines {
+        writeln!(oup, "{}{}: {}", line_prefix, key, line)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for LevelDbAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        if let SlowMatcher::Fast(FastMatcher::FileName(_)) = detection_reason {
+            // other programs also name their logs like this
+            match log_records(&data) {
+                Some(records) => {
+                    for record in &records {
+                        for (key, value) in batch_entries(record) {
+                            write_entry(&ai.line_prefix, key, value, oup)?;
+                        }
+                    }
+                }
+                None => oup.write_all(&data)?,
+            }
+            return Ok(());
+        }
+        for (key, value) in table_entries(&data)? {
+            write_entry(&ai.line_prefix, &key, &value, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn varint(mut v: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        while v >= 0x80 {
+            out.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+        out
+    }
+
+    fn internal_key(key: &str, sequence: u64, kind: u8) -> Vec<u8> {
+        let mut out = key.as_bytes().to_vec();
+        out.extend(((sequence << 8) | kind as u64).to_le_bytes().iter());
+        out
+    }
+
+    /// a block with a single restart point and without prefix compression
+    fn block(entries: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (key, value) in entries {
+            out.extend(varint(0));
+            out.extend(varint(key.len() as u64));
+            out.extend(varint(value.len() as u64));
+            out.extend(key);
+            out.extend(value);
+        }
+        out.extend(&[0, 0, 0, 0, 1, 0, 0, 0]);
+        out
+    }
+
+    /// appends a block with its trailer, returns the encoded handle
+    fn add_block(table: &mut Vec<u8>, block: &[u8], compression: u8) -> Vec<u8> {
+        let handle = [varint(table.len() as u64), varint(block.len() as u64)].concat();
+        table.extend(block);
+        table.extend(&[compression, 0, 0, 0, 0]);
+        handle
+    }
+
+    fn adapt(filename: &str, data: Vec<u8>, matcher: FastMatcher) -> Result<String> {
+        let (mut a, _) =
+            simple_adapt_info(Path::new("placeholder.ldb"), Box::new(Cursor::new(data)));
+        a.filepath_hint = PathBuf::from(filename);
+        let mut r = LevelDbAdapter::new().adapt(a, &matcher.into())?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn leveldb_table() -> Result<()> {
+        let mut table = Vec::new();
+        let first = add_block(
+            &mut table,
+            &block(&[
+                (
+                    internal_key("config", 3, 1),
+                    b"theme = dark\nlang = en".to_vec(),
+                ),
+                (internal_key("session", 5, 0), vec![]),
+            ]),
+            0,
+        );
+        let compressed = snap::raw::Encoder::new().compress_vec(&block(&[(
+            internal_key("_https://example.com\0\x01user", 4, 1),
+            b"\0J\0a\0n\0e\0 \0D\0o\0e\0".to_vec(),
+        )]))?;
+        let second = add_block(&mut table, &compressed, 1);
+        let metaindex = add_block(&mut table, &block(&[]), 0);
+        let index = add_block(
+            &mut table,
+            &block(&[(b"d".to_vec(), first), (b"z".to_vec(), second)]),
+            0,
+        );
+        let mut footer = [metaindex, index].concat();
+        footer.resize(40, 0);
+        table.extend(footer);
+        table.extend(LEVELDB_MAGIC.to_le_bytes().iter());
+
+        assert_eq!(
+            adapt(
+                "000005.ldb",
+                table,
+                FastMatcher::FileExtension("ldb".to_string())
+            )?,
+            "PREFIX:config: theme = dark
+PREFIX:config: lang = en
+PREFIX:_https://example.com  user: Jane Doe
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rocksdb_table() -> Result<()> {
+        let mut table = Vec::new();
+        let mut sizes = Vec::new();
+        for (key, value) in &[("key1", "first value"), ("key2", "second value")] {
+            let data = block(&[(internal_key(key, 1, 1), value.as_bytes().to_vec())]);
+            let compressed = zstd::stream::encode_all(&data[..], 0)?;
+            let compressed = [varint(data.len() as u64), compressed].concat();
+            sizes.push(compressed.len() as i64);
+            add_block(&mut table, &compressed, 7);
+        }
+        let properties = add_block(
+            &mut table,
+            &block(&[(b"rocksdb.index.value.is.delta.encoded".to_vec(), varint(1))]),
+            0,
+        );
+        let metaindex = add_block(
+            &mut table,
+            &block(&[(b"rocksdb.properties".to_vec(), properties)]),
+            0,
+        );
+        // the second entry shares the "key" prefix and only stores the size difference
+        let mut index = [
+            varint(0),
+            varint(4),
+            b"key1".to_vec(),
+            varint(0),
+            varint(sizes[0] as u64),
+            varint(3),
+            varint(1),
+            b"2".to_vec(),
+        ]
+        .concat();
+        let delta = sizes[1] - sizes[0];
+        index.extend(varint(((delta << 1) ^ (delta >> 63)) as u64));
+        index.extend(&[0, 0, 0, 0, 1, 0, 0, 0]);
+        let index = add_block(&mut table, &index, 0);
+        let mut footer = [vec![1], metaindex, index].concat();
+        footer.resize(41, 0);
+        table.extend(footer);
+        table.extend(5u32.to_le_bytes().iter());
+        table.extend(ROCKSDB_MAGIC.to_le_bytes().iter());
+
+        assert_eq!(
+            adapt(
+                "000012.sst",
+                table,
+                FastMatcher::FileExtension("sst".to_string())
+            )?,
+            "PREFIX:key1: first value\nPREFIX:key2: second value\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn log() -> Result<()> {
+        let mut batch = vec![0u8; 12];
+        for (tag, key, value) in &[(1u8, "name", "Jane"), (0, "old", ""), (1, "city", "Berlin")] {
+            batch.push(*tag);
+            batch.extend(varint(key.len() as u64));
+            batch.extend(key.as_bytes());
+            if *tag == 1 {
+                batch.extend(varint(value.len() as u64));
+                batch.extend(value.as_bytes());
+            }
+        }
+        let mut log = vec![0, 0, 0, 0];
+        log.extend((batch.len() as u16).to_le_bytes().iter());
+        log.push(1);
+        log.extend(batch);
+        let matcher = FastMatcher::FileName(LOG_FILE_NAME.to_string());
+        assert_eq!(
+            adapt("000003.log", log, matcher.clone())?,
+            "PREFIX:name: Jane\nPREFIX:city: Berlin\n"
+        );
+        // not every log with such a name belongs to a database
+        assert_eq!(
+            adapt("000001.log", b"server started\n".to_vec(), matcher)?,
+            "server started\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use super::*;
+use ::lmdb::{Cursor, Environment, EnvironmentFlags, Transaction};
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+/// lmdb creates `data.mdb` and `lock.mdb` in the database directory
+static FILE_NAMES: &[&str] = &["data.mdb"];
+
+/// each named database that is opened needs a slot
+const MAX_DATABASES: u32 = 4096;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "lmdb".to_owned(),
+        version: 1,
+        description: "Writes the keys and values of all databases in LMDB data files, as `database: key: value`. Binary keys and values are written as hex".to_owned(),
+        recurses: false,
+        fast_matchers: FILE_NAMES
+            .iter()
+            .map(|s| FastMatcher::FileName(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct LmdbAdapter;
+
+impl LmdbAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(LmdbAdapter))
+    }
+}
+impl GetMetadata for LmdbAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// text as is (each line separately), everything else as hex
+fn printable(data: &[u8]) -> Vec<String> {
+    match std::str::from_utf8(data) {
+        std::result::Result::Ok(s)
+            if !s.chars().any(|c| c.is_control() && c != '\n' && c != '\t') =>
+        {
+            s.lines().map(str::to_string).collect()
+        }
+        _ => vec![hex(data)],
+    }
+}
+
+fn write_entries(
+    line_prefix: &str,
+    entries: &[(Vec<u8>, Vec<u8>)],
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for (key, value) in entries {
+        let key = printable(key).join(" ");
+        for line in printable(value) {
+            writeln!(oup, "{}{}: {}", line_prefix, key, line)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_entries(env: &Environment, name: Option<&str>) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let db = env.open_db(name)?;
+    let txn = env.begin_ro_txn()?;
+    let mut cursor = txn.open_ro_cursor(db)?;
+    let mut entries = Vec::new();
+    for entry in cursor.iter_start() {
+        let (key, value) = entry?;
+        entries.push((key.to_vec(), value.to_vec()));
+    }
+    Ok(entries)
+}
+
+impl WritingFileAdapterTrait for LmdbAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // lmdb maps the file into memory
+        let _tmp_file;
+        let path = if ai.is_real_file {
+            ai.filepath_hint.clone()
+        } else {
+            let mut tmp = tempfile::Builder::new().prefix("rga-lmdb-").tempfile()?;
+            std::io::copy(&mut ai.inp, &mut tmp)?;
+            let path = tmp.path().to_owned();
+            _tmp_file = tmp;
+            path
+        };
+        // no lock file, the database might be in a read only location
+        let env = Environment::new()
+            .set_flags(
+                EnvironmentFlags::NO_SUB_DIR
+                    | EnvironmentFlags::READ_ONLY
+                    | EnvironmentFlags::NO_LOCK,
+            )
+            .set_max_dbs(MAX_DATABASES)
+            .open(&path)?;
+        let main = read_entries(&env, None)?;
+        // the names of the other databases are keys in the main database, but not every key is a database
+        let mut plain = Vec::new();
+        let mut databases = Vec::new();
+        for (key, value) in main {
+            let name = std::str::from_utf8(&key)
+                .ok()
+                .filter(|name| !name.contains('\0'));
+            match name.map(|name| (name, read_entries(&env, Some(name)))) {
+                Some((name, std::result::Result::Ok(entries))) => {
+                    debug!("{}|{}", ai.filepath_hint.display(), name);
+                    databases.push((name.to_string(), entries));
+                }
+                _ => plain.push((key, value)),
+            }
+        }
+        write_entries(&ai.line_prefix, &plain, oup)?;
+        for (name, entries) in databases {
+            write_entries(&format!("{}{}: ", ai.line_prefix, name), &entries, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use ::lmdb::{DatabaseFlags, WriteFlags};
+    use std::fs::File;
+
+    #[test]
+    fn lmdb() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.mdb");
+        let env = Environment::new()
+            .set_flags(EnvironmentFlags::NO_SUB_DIR)
+            .set_max_dbs(2)
+            .open(&path)?;
+        let main = env.open_db(None)?;
+        let users = env.create_db(Some("users"), DatabaseFlags::empty())?;
+        let mut txn = env.begin_rw_txn()?;
+        txn.put(main, b"version", b"3", WriteFlags::empty())?;
+        txn.put(users, b"jane", b"Jane Doe\nBerlin", WriteFlags::empty())?;
+        txn.put(users, b"\x00\x01", b"\xff\xfe", WriteFlags::empty())?;
+        txn.commit()?;
+        drop(env);
+
+        let (a, d) = simple_adapt_info(&path, Box::new(File::open(&path)?));
+        let mut r = LmdbAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:version: 3
+PREFIX:users: 0001: fffe
+PREFIX:users: jane: Jane Doe
+PREFIX:users: jane: Berlin
+"
+        );
+        Ok(())
+    }
+}
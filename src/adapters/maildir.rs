@@ -0,0 +1,118 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::ffi::OsStr;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+/// maildir messages have no extension. delivered messages are named `time.unique.host`,
+/// and get the flags appended (`:2,FS`, or `!2,FS` where `:` is not allowed) once they are seen
+static FILE_NAMES: &[&str] = &[
+    "*:2,*",
+    "*!2,*",
+    "[0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9][0-9].*.*",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "maildir".to_owned(),
+        version: 1,
+        description: "Reads the messages in Maildir folders (cur/ and new/, as used by Dovecot, offlineimap and mbsync) like the mail adapter, prefixed with the folder name. Files with these names outside of a maildir are passed through".to_owned(),
+        recurses: true,
+        fast_matchers: FILE_NAMES
+            .iter()
+            .map(|s| FastMatcher::FileName(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct MaildirAdapter;
+
+impl MaildirAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(MaildirAdapter))
+    }
+}
+impl GetMetadata for MaildirAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the folder of a message in `folder/cur/` or `folder/new/`. Maildir++ subfolders
+/// are hidden directories next to cur/, with dots separating the levels (`.Work.Projects`)
+fn folder_name(path: &Path) -> Option<String> {
+    let dir = path.parent()?;
+    if !matches!(
+        dir.file_name().and_then(OsStr::to_str),
+        Some("cur") | Some("new") | Some("tmp")
+    ) {
+        return None;
+    }
+    let folder = dir.parent()?.file_name()?.to_string_lossy();
+    Some(match folder.strip_prefix('.') {
+        Some(subfolder) => subfolder.replace('.', "/"),
+        None => folder.into_owned(),
+    })
+}
+
+impl WritingFileAdapterTrait for MaildirAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let folder = match folder_name(&ai.filepath_hint) {
+            Some(folder) => folder,
+            None => {
+                std::io::copy(&mut ai.inp, oup)?;
+                return Ok(());
+            }
+        };
+        let mut message = Vec::new();
+        ai.inp.read_to_end(&mut message)?;
+        ai.line_prefix = format!("{}{}: ", ai.line_prefix, folder);
+        eml::write_mail(&message, &ai, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    static MESSAGE: &str = "From: Alice <alice@example.com>\r\nTo: bob@example.com\r\nSubject: lunch\r\nMessage-ID: <1@example.com>\r\n\r\nnoon at the usual place?\r\n";
+
+    fn adapt(path: &str) -> Result<String> {
+        let (a, d) = simple_adapt_info(Path::new(path), Box::new(Cursor::new(MESSAGE)));
+        let mut r = MaildirAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn maildir() -> Result<()> {
+        assert_eq!(
+            adapt("Maildir/.Work.Projects/cur/1700000000.M20P31.mail.example.com:2,S")?,
+            "PREFIX:Work/Projects: From: Alice <alice@example.com>
+PREFIX:Work/Projects: To: bob@example.com
+PREFIX:Work/Projects: Subject: lunch
+PREFIX:Work/Projects: noon at the usual place?
+"
+        );
+        assert!(adapt("Mail/INBOX/new/1700000000.M20P31.mail")?.starts_with("PREFIX:INBOX: From: "));
+        Ok(())
+    }
+
+    #[test]
+    fn not_maildir() -> Result<()> {
+        assert_eq!(adapt("backups/1700000000.db.bak")?, MESSAGE);
+        Ok(())
+    }
+}
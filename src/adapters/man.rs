@@ -0,0 +1,475 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::io::{BufRead, BufReader};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+// compressed man pages (ls.1.gz) are decompressed first and then matched by the inner extension
+static EXTENSIONS: &[&str] = &[
+    "1", "2", "3", "4", "5", "6", "7", "8", "9", "man", "1p", "3p", "3pm", "1ssl", "3ssl",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "man".to_owned(),
+        version: 1,
+        description: "Renders man pages (troff with the man or mdoc macros) to plain text by removing the formatting requests and escapes. Files with these extensions that are not troff (e.g. rotated logs like syslog.1) are passed through".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType("text/troff".to_owned())]),
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct ManAdapter;
+
+impl ManAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(ManAdapter))
+    }
+}
+impl GetMetadata for ManAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the special characters that are common in man pages, as in `\(em` or `\[em]`
+fn glyph(name: &str) -> &'static str {
+    match name {
+        "em" => "—",
+        "en" => "–",
+        "hy" | "mi" | "-" => "-",
+        "bu" => "•",
+        "aq" | "cq" | "oq" => "'",
+        "dq" | "lq" | "rq" | "Lq" | "Rq" => "\"",
+        "co" => "©",
+        "rg" => "®",
+        "tm" => "™",
+        "de" => "°",
+        "mu" => "×",
+        "<=" => "≤",
+        ">=" => "≥",
+        "->" => "→",
+        "<-" => "←",
+        "ti" | "a~" => "~",
+        "ha" | "a^" => "^",
+        "rs" | "e" => "\\",
+        "ga" => "`",
+        "ba" | "or" => "|",
+        _ => "",
+    }
+}
+
+/// the argument of an escape like `\f(BI`, `\f[BI]` or `\fB`
+fn escape_argument(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    match chars.next() {
+        Some('(') => chars.take(2).collect(),
+        Some('[') => chars.take_while(|c| *c != ']').collect(),
+        Some(c) => c.to_string(),
+        None => String::new(),
+    }
+}
+
+/// removes font changes and other escapes from a line of text
+fn unescape(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // the rest of the line is a comment
+            Some('"') | Some('#') => break,
+            Some('f') | Some('*') | Some('n') | Some('F') | Some('m') | Some('g') => {
+                escape_argument(&mut chars);
+            }
+            Some('(') => {
+                let name: String = chars.by_ref().take(2).collect();
+                out.push_str(glyph(&name));
+            }
+            Some('[') => {
+                let name: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                out.push_str(glyph(&name));
+            }
+            // size changes: \s+2, \s-1, \s0, \s(12
+            Some('s') => {
+                if matches!(chars.peek(), Some('+') | Some('-')) {
+                    chars.next();
+                }
+                if chars.peek() == Some(&'(') {
+                    chars.next();
+                    chars.next();
+                    chars.next();
+                } else {
+                    chars.next();
+                }
+            }
+            // movements and other escapes with a quoted argument, e.g. \h'2n' or \w'text'
+            Some('h') | Some('v') | Some('w') | Some('l') | Some('L') | Some('o') | Some('X') => {
+                if chars.peek() == Some(&'\'') {
+                    chars.next();
+                    for c in chars.by_ref() {
+                        if c == '\'' {
+                            break;
+                        }
+                    }
+                }
+            }
+            Some('-') => out.push('-'),
+            Some('e') | Some('\\') => out.push('\\'),
+            Some(' ') | Some('~') | Some('0') => out.push(' '),
+            Some('t') => out.push('\t'),
+            // zero width characters, hyphenation points and line continuation
+            Some('&') | Some('|') | Some('^') | Some('%') | Some('c') | Some(',') | Some('/')
+            | Some(')') | Some(':') => {}
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+/// the arguments of a macro line, with double quotes for arguments that contain spaces
+fn arguments(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek() == Some(&' ') || chars.peek() == Some(&'\t') {
+            chars.next();
+        }
+        let mut arg = String::new();
+        match chars.peek() {
+            None => break,
+            Some('"') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        // "" is a quote inside a quoted argument
+                        '"' if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            arg.push('"');
+                        }
+                        '"' => break,
+                        c => arg.push(c),
+                    }
+                }
+            }
+            Some(_) => {
+                while let Some(c) = chars.peek() {
+                    if *c == ' ' || *c == '\t' {
+                        break;
+                    }
+                    arg.push(*c);
+                    chars.next();
+                }
+            }
+        }
+        args.push(arg);
+    }
+    args
+}
+
+/// mdoc macros that can appear in the arguments of other macros, like `.Op Fl v Ar file`
+static MDOC_CALLABLE: &[&str] = &[
+    "Ac", "Ad", "An", "Ao", "Ap", "Aq", "Ar", "At", "Bc", "Bo", "Bq", "Brc", "Bro", "Brq", "Bsx",
+    "Bx", "Cd", "Cm", "Dc", "Do", "Dq", "Dv", "Ec", "Em", "En", "Eo", "Er", "Ev", "Fa", "Fc", "Fl",
+    "Fn", "Fo", "Fr", "Ft", "Fx", "Ic", "Li", "Lk", "Ms", "Mt", "Nm", "No", "Ns", "Nx", "Oc", "Oo",
+    "Op", "Ot", "Ox", "Pa", "Pc", "Pf", "Po", "Pq", "Qc", "Ql", "Qo", "Qq", "Sc", "So", "Sq", "Sx",
+    "Sy", "Ta", "Tn", "Ux", "Va", "Vt", "Xc", "Xo", "Xr",
+];
+
+#[derive(Default)]
+struct Renderer {
+    /// the name of an mdoc page, which `.Nm` without arguments refers to
+    name: String,
+    /// inside `.de`/`.ig` blocks, which end with `..`
+    skipping: bool,
+}
+
+impl Renderer {
+    /// the text of an mdoc macro line, e.g. `.Op Fl v Ar file` is `[-v file]`
+    fn mdoc(&mut self, args: &[String]) -> String {
+        // words, and whether there is no space before and after them
+        let mut words: Vec<(String, bool, bool)> = Vec::new();
+        let mut closing = Vec::new();
+        let mut macro_name = "";
+        // `.Nm` and `.Ar` without an argument stand for the name of the page and `file ...`
+        let mut default_written = false;
+        for arg in args {
+            let arg = arg.as_str();
+            if MDOC_CALLABLE.contains(&arg) {
+                macro_name = arg;
+                default_written = false;
+                let enclosure = match arg {
+                    "Op" => Some(("[", "]")),
+                    "Dq" | "Qq" => Some(("\"", "\"")),
+                    "Sq" | "Ql" => Some(("'", "'")),
+                    "Pq" => Some(("(", ")")),
+                    "Aq" => Some(("<", ">")),
+                    _ => None,
+                };
+                if let Some((open, close)) = enclosure {
+                    words.push((open.to_string(), false, true));
+                    closing.push(close);
+                }
+                match arg {
+                    "Oo" => words.push(("[".to_string(), false, true)),
+                    "Oc" => words.push(("]".to_string(), true, false)),
+                    "Nm" | "Ar" => {
+                        let default = if arg == "Nm" {
+                            self.name.clone()
+                        } else {
+                            "file ...".to_string()
+                        };
+                        words.push((default, false, false));
+                        default_written = true;
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if default_written {
+                words.pop();
+                default_written = false;
+            }
+            let word = unescape(arg);
+            match macro_name {
+                "Nm" if self.name.is_empty() => self.name = word.clone(),
+                _ => {}
+            }
+            let punctuation = matches!(word.as_str(), "," | "." | ";" | ":" | ")" | "]");
+            let word = if macro_name == "Fl" {
+                format!("-{}", word)
+            } else {
+                word
+            };
+            words.push((word, punctuation, false));
+        }
+        while let Some(close) = closing.pop() {
+            words.push((close.to_string(), true, false));
+        }
+        let mut text = String::new();
+        let mut glue = true;
+        for (word, glue_before, glue_after) in words {
+            if !glue && !glue_before {
+                text.push(' ');
+            }
+            text.push_str(&word);
+            glue = glue_after;
+        }
+        text
+    }
+
+    /// the text of a line, None for lines without text
+    fn render(&mut self, line: &str) -> Option<String> {
+        if self.skipping {
+            if line.trim_end() == ".." {
+                self.skipping = false;
+            }
+            return None;
+        }
+        let control = match line.strip_prefix('.').or_else(|| line.strip_prefix('\'')) {
+            Some(control) => control.trim_start(),
+            None => return Some(unescape(line)),
+        };
+        if control.starts_with("\\\"") || control.is_empty() {
+            return None;
+        }
+        let (name, rest) = control.split_once([' ', '\t']).unwrap_or((control, ""));
+        let args = arguments(rest);
+        let text = match name {
+            "de" | "de1" | "am" | "ig" => {
+                self.skipping = true;
+                return None;
+            }
+            // the title, as `LS(1)`
+            "TH" => format!(
+                "{}({})",
+                args.first().map(String::as_str).unwrap_or_default(),
+                args.get(1).map(String::as_str).unwrap_or_default()
+            ),
+            "Dt" => format!(
+                "{}({})",
+                args.first().map(String::as_str).unwrap_or_default(),
+                args.get(1).map(String::as_str).unwrap_or_default()
+            ),
+            // headings, font changes and tagged paragraphs
+            "SH" | "SS" | "B" | "I" | "SM" | "SB" | "IP" | "TQ" | "UR" | "MT" => match name {
+                // the second argument of .IP is the indentation
+                "IP" => args.first().map(|a| unescape(a)).unwrap_or_default(),
+                _ => unescape(&args.join(" ")),
+            },
+            // alternating fonts, without spaces in between
+            "BR" | "BI" | "IB" | "IR" | "RB" | "RI" => {
+                args.iter().map(|a| unescape(a)).collect::<String>()
+            }
+            "Nd" => format!("- {}", unescape(&args.join(" "))),
+            _ if name.len() >= 2
+                && name.chars().next().is_some_and(|c| c.is_ascii_uppercase())
+                && name.chars().nth(1).is_some_and(|c| c.is_ascii_lowercase()) =>
+            {
+                let mut all = vec![name.to_string()];
+                all.extend(args);
+                match name {
+                    // sections and display blocks: only the arguments
+                    "Sh" | "Ss" => unescape(&all[1..].join(" ")),
+                    "Bl" | "El" | "Bd" | "Ed" | "It" if all.len() == 1 => return None,
+                    "Bl" | "El" | "Bd" | "Ed" | "Pp" | "Lp" | "Os" | "Dd" | "Sm" | "Bk" | "Ek" => {
+                        return None
+                    }
+                    "It" => self.mdoc(&all[1..]),
+                    _ => self.mdoc(&all),
+                }
+            }
+            // everything else (.PP, .TP, .br, .sp, .RS, .if, ...) only affects the layout
+            _ => return None,
+        };
+        Some(text)
+    }
+}
+
+/// roff source starts with a comment or a request
+fn is_roff(start: &[u8]) -> bool {
+    let first = start
+        .split(|b| *b == b'\n')
+        .map(|l| l.trim_ascii())
+        .find(|l| !l.is_empty());
+    match first {
+        Some(line) => line.starts_with(b".") || line.starts_with(b"'\\\""),
+        None => false,
+    }
+}
+
+impl WritingFileAdapterTrait for ManAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut inp = BufReader::new(ai.inp);
+        if !is_roff(inp.fill_buf()?) {
+            std::io::copy(&mut inp, oup)?;
+            return Ok(());
+        }
+        let mut renderer = Renderer::default();
+        let mut line = Vec::new();
+        while inp.read_until(b'\n', &mut line)? > 0 {
+            let text = String::from_utf8_lossy(&line);
+            if let Some(text) = renderer.render(text.trim_end_matches(['\n', '\r'])) {
+                writeln!(oup, "{}{}", ai.line_prefix, text)?;
+            }
+            line.clear();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn adapt(filename: &str, page: &str) -> Result<String> {
+        let (a, d) = simple_adapt_info(
+            Path::new(filename),
+            Box::new(Cursor::new(page.as_bytes().to_vec())),
+        );
+        let mut r = ManAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn man() -> Result<()> {
+        let page = r#".\" Copyright (c) 2020
+.de XX
+.ft B
+..
+.TH GREP 1 "2020-01-01" "GNU grep 3.4" "User Commands"
+.SH NAME
+grep \- print lines that match patterns
+.SH SYNOPSIS
+.B grep
+.RI [ OPTION... ]
+.I PATTERNS
+.SH OPTIONS
+.TP
+.BR \-i ", " \-\^\-ignore\-case
+Ignore case distinctions in \fIpatterns\fP and input data,
+so that characters that differ only in case match each other\(em\s-1see\s0 below.
+.PP
+"#;
+        assert_eq!(
+            adapt("grep.1", page)?,
+            "PREFIX:GREP(1)
+PREFIX:NAME
+PREFIX:grep - print lines that match patterns
+PREFIX:SYNOPSIS
+PREFIX:grep
+PREFIX:[OPTION...]
+PREFIX:PATTERNS
+PREFIX:OPTIONS
+PREFIX:-i, --ignore-case
+PREFIX:Ignore case distinctions in patterns and input data,
+PREFIX:so that characters that differ only in case match each other—see below.
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn mdoc() -> Result<()> {
+        let page = r#".Dd March 1, 2020
+.Dt LS 1
+.Os
+.Sh NAME
+.Nm ls
+.Nd list directory contents
+.Sh SYNOPSIS
+.Nm
+.Op Fl al
+.Op Ar
+.Sh DESCRIPTION
+For each operand that names a
+.Ar file ,
+.Nm
+displays its name.
+"#;
+        assert_eq!(
+            adapt("ls.1", page)?,
+            "PREFIX:LS(1)
+PREFIX:NAME
+PREFIX:ls
+PREFIX:- list directory contents
+PREFIX:SYNOPSIS
+PREFIX:ls
+PREFIX:[-al]
+PREFIX:[file ...]
+PREFIX:DESCRIPTION
+PREFIX:For each operand that names a
+PREFIX:file,
+PREFIX:ls
+PREFIX:displays its name.
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn not_roff() -> Result<()> {
+        let log = "Jan  1 00:00:00 host kernel: started\n";
+        assert_eq!(adapt("syslog.1", log)?, log);
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use super::spawning::map_exe_error;
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["mdb", "accdb"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "mdb".to_owned(),
+        version: 1,
+        description: "Uses mdbtools to dump the tables of Microsoft Access databases in the same format as the sqlite adapter. Make sure you have mdbtools installed.".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![
+            SlowMatcher::MimeType("application/x-msaccess".to_owned()),
+            SlowMatcher::MimeType("application/vnd.ms-access".to_owned())
+        ]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct MdbAdapter;
+
+impl MdbAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(MdbAdapter))
+    }
+}
+impl GetMetadata for MdbAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Field {
+    /// mdb-export quotes text, memo and date columns
+    Quoted(String),
+    /// numbers, booleans and (if empty) NULL
+    Bare(String),
+}
+
+impl Field {
+    fn format(&self) -> String {
+        match self {
+            // every row has to stay on one line so it keeps the line prefix
+            Field::Quoted(s) => format!(
+                "'{}'",
+                s.lines().collect::<Vec<_>>().join(" ").replace("'", "''")
+            ),
+            Field::Bare(s) if s.is_empty() => "NULL".to_owned(),
+            Field::Bare(s) => s.clone(),
+        }
+    }
+}
+
+/// reads one csv record, quoted fields may contain newlines
+fn read_record(inp: &mut impl BufRead) -> Result<Option<Vec<Field>>> {
+    let mut line = String::new();
+    if inp.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    while line.matches('"').count() % 2 == 1 {
+        if inp.read_line(&mut line)? == 0 {
+            bail!("unterminated quoted field");
+        }
+    }
+    let mut fields = Vec::new();
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut chars = line.trim_end_matches(['\r', '\n']).chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c != '"' {
+                value.push(c);
+            } else if chars.peek() == Some(&'"') {
+                chars.next();
+                value.push('"');
+            } else {
+                in_quotes = false;
+            }
+        } else if c == '"' && value.is_empty() && !quoted {
+            quoted = true;
+            in_quotes = true;
+        } else if c == ',' {
+            let value = std::mem::take(&mut value);
+            fields.push(if quoted {
+                Field::Quoted(value)
+            } else {
+                Field::Bare(value)
+            });
+            quoted = false;
+        } else {
+            value.push(c);
+        }
+    }
+    fields.push(if quoted {
+        Field::Quoted(value)
+    } else {
+        Field::Bare(value)
+    });
+    Ok(Some(fields))
+}
+
+/// converts the csv output of mdb-export into lines like `table: col='text', col2=123`
+fn write_rows(
+    line_prefix: &str,
+    table: &str,
+    mut inp: impl BufRead,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let columns: Vec<String> = match read_record(&mut inp)? {
+        Some(header) => header
+            .into_iter()
+            .map(|f| match f {
+                Field::Quoted(s) | Field::Bare(s) => s,
+            })
+            .collect(),
+        None => return Ok(()),
+    };
+    while let Some(row) = read_record(&mut inp)? {
+        writeln!(
+            oup,
+            "{}{}: {}",
+            line_prefix,
+            table,
+            columns
+                .iter()
+                .zip(row.iter())
+                .map(|(c, v)| format!("{}={}", c, v.format()))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+fn spawn(exe: &str, args: &[&OsStr]) -> Result<std::process::Child> {
+    Command::new(exe)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| map_exe_error(e, exe, "Make sure you have mdbtools installed."))
+}
+
+fn list_tables(db: &Path) -> Result<Vec<String>> {
+    let output = spawn("mdb-tables", &["-1".as_ref(), db.as_ref()])?.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format_err!("mdb-tables failed: {:?}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_owned())
+        .collect())
+}
+
+impl WritingFileAdapterTrait for MdbAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let AdaptInfo {
+            filepath_hint,
+            is_real_file,
+            mut inp,
+            line_prefix,
+            ..
+        } = ai;
+        // mdbtools needs to seek, so a database within an archive has to be written to disk first
+        let _tmp_file;
+        let db_path = if is_real_file {
+            filepath_hint
+        } else {
+            let mut tmp = tempfile::Builder::new()
+                .prefix("rga-mdb-")
+                .suffix(".mdb")
+                .tempfile()?;
+            std::io::copy(&mut inp, &mut tmp)?;
+            let path = tmp.path().to_owned();
+            _tmp_file = tmp;
+            path
+        };
+        let tables = list_tables(&db_path)?;
+        debug!("db has {} tables", tables.len());
+        for table in tables {
+            // -b strip: leave out binary columns (OLE objects)
+            let mut cmd = spawn(
+                "mdb-export",
+                &[
+                    "-b".as_ref(),
+                    "strip".as_ref(),
+                    db_path.as_ref(),
+                    table.as_ref(),
+                ],
+            )?;
+            write_rows(
+                &line_prefix,
+                &table,
+                BufReader::new(cmd.stdout.as_mut().expect("is piped")),
+                oup,
+            )?;
+            let status = cmd.wait()?;
+            if !status.success() {
+                return Err(format_err!("mdb-export failed: {:?}", status));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export() -> Result<()> {
+        let csv = "ID,Name,Notes,Price,Added
+1,\"Widget\",\"says \"\"hi\"\"\",9.5,\"2020-01-02 00:00:00\"
+2,\"Gadget\",\"line one
+line two\",,
+3,\"O'Brien\",,12,\"2021-03-04 00:00:00\"
+";
+        let mut o = Vec::new();
+        write_rows("PREFIX:", "Products", csv.as_bytes(), &mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Products: ID=1, Name='Widget', Notes='says \"hi\"', Price=9.5, Added='2020-01-02 00:00:00'
+PREFIX:Products: ID=2, Name='Gadget', Notes='line one line two', Price=NULL, Added=NULL
+PREFIX:Products: ID=3, Name='O''Brien', Notes=NULL, Price=12, Added='2021-03-04 00:00:00'
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use cfb::CompoundFile;
+use lazy_static::lazy_static;
+use log::*;
+use mailparse::MailHeaderMap;
+use std::io::{Cursor, Seek};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["msg"];
+
+// MAPI property ids, see [MS-OXPROPS]
+const PR_SUBJECT: u16 = 0x0037;
+const PR_TRANSPORT_MESSAGE_HEADERS: u16 = 0x007D;
+const PR_SENDER_NAME: u16 = 0x0C1A;
+const PR_SENDER_EMAIL_ADDRESS: u16 = 0x0C1F;
+const PR_DISPLAY_CC: u16 = 0x0E03;
+const PR_DISPLAY_TO: u16 = 0x0E04;
+const PR_BODY: u16 = 0x1000;
+const PR_HTML: u16 = 0x1013;
+const PR_DISPLAY_NAME: u16 = 0x3001;
+const PR_ATTACH_DATA: u16 = 0x3701;
+const PR_ATTACH_FILENAME: u16 = 0x3704;
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+
+// property types
+const PT_STRING8: u16 = 0x001E;
+const PT_UNICODE: u16 = 0x001F;
+const PT_BINARY: u16 = 0x0102;
+const PT_OBJECT: u16 = 0x000D;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "msg".to_owned(),
+        version: 1,
+        description: "Reads Outlook .msg files. Outputs sender, recipients, subject and body and recurses into attachments".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType("application/vnd.ms-outlook".to_owned())]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![AdapterOption::HtmlLinks]
+    };
+}
+#[derive(Default, Clone)]
+pub struct MsgAdapter;
+
+impl MsgAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(MsgAdapter))
+    }
+}
+impl GetMetadata for MsgAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn prop_path(storage: &Path, prop: u16, typ: u16) -> PathBuf {
+    storage.join(format!("__substg1.0_{:04X}{:04X}", prop, typ))
+}
+
+fn read_stream<F: Read + Seek>(cfb: &mut CompoundFile<F>, path: &Path) -> Result<Option<Vec<u8>>> {
+    if !cfb.is_stream(path) {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    cfb.open_stream(path)?.read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+fn read_binary<F: Read + Seek>(
+    cfb: &mut CompoundFile<F>,
+    storage: &Path,
+    prop: u16,
+) -> Result<Option<Vec<u8>>> {
+    read_stream(cfb, &prop_path(storage, prop, PT_BINARY))
+}
+
+fn read_string<F: Read + Seek>(
+    cfb: &mut CompoundFile<F>,
+    storage: &Path,
+    prop: u16,
+) -> Result<Option<String>> {
+    if let Some(data) = read_stream(cfb, &prop_path(storage, prop, PT_UNICODE))? {
+        let (text, _) = encoding_rs::UTF_16LE.decode_without_bom_handling(&data);
+        return Ok(Some(text.trim_end_matches('\0').to_string()));
+    }
+    if let Some(data) = read_stream(cfb, &prop_path(storage, prop, PT_STRING8))? {
+        // the code page is stored in a property stream, but it's nearly always windows-1252
+        let (text, _) = encoding_rs::WINDOWS_1252.decode_without_bom_handling(&data);
+        return Ok(Some(text.trim_end_matches('\0').to_string()));
+    }
+    Ok(None)
+}
+
+fn write_attachment<F: Read + Seek>(
+    cfb: &mut CompoundFile<F>,
+    storage: &Path,
+    index: usize,
+    ai: &AdaptInfo,
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let name = match read_string(cfb, storage, PR_ATTACH_LONG_FILENAME)? {
+        Some(name) => name,
+        None => read_string(cfb, storage, PR_ATTACH_FILENAME)?
+            .or(read_string(cfb, storage, PR_DISPLAY_NAME)?)
+            .unwrap_or_else(|| format!("attachment{}", index)),
+    };
+    let inner_prefix = format!("{}{}: ", line_prefix, name);
+    let embedded = prop_path(storage, PR_ATTACH_DATA, PT_OBJECT);
+    if cfb.is_storage(&embedded) {
+        // an attached message is stored as a nested storage of the same format
+        return write_message(cfb, &embedded, ai, &inner_prefix, oup);
+    }
+    let data = match read_binary(cfb, storage, PR_ATTACH_DATA)? {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+    debug!(
+        "{}|{}: {}",
+        ai.filepath_hint.display(),
+        name,
+        crate::print_bytes(data.len() as f64)
+    );
+    let mut inner = rga_preproc(AdaptInfo {
+        filepath_hint: PathBuf::from(&name),
+        is_real_file: false,
+        archive_recursion_depth: ai.archive_recursion_depth + 1,
+        inp: Box::new(Cursor::new(data)),
+        line_prefix: inner_prefix,
+        config: ai.config.clone(),
+    })?;
+    std::io::copy(&mut inner, oup)?;
+    Ok(())
+}
+
+fn write_message<F: Read + Seek>(
+    cfb: &mut CompoundFile<F>,
+    storage: &Path,
+    ai: &AdaptInfo,
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let from = match (
+        read_string(cfb, storage, PR_SENDER_NAME)?,
+        read_string(cfb, storage, PR_SENDER_EMAIL_ADDRESS)?,
+    ) {
+        (Some(name), Some(addr)) if name != addr => Some(format!("{} <{}>", name, addr)),
+        (name, addr) => name.or(addr),
+    };
+    // the date is only available as a binary timestamp, except in the original headers of received mails
+    let date = read_string(cfb, storage, PR_TRANSPORT_MESSAGE_HEADERS)?.and_then(|headers| {
+        mailparse::parse_headers(headers.as_bytes())
+            .ok()
+            .and_then(|(headers, _)| headers.get_first_value("Date"))
+    });
+    let headers = [
+        ("From", from),
+        ("To", read_string(cfb, storage, PR_DISPLAY_TO)?),
+        ("Cc", read_string(cfb, storage, PR_DISPLAY_CC)?),
+        ("Date", date),
+        ("Subject", read_string(cfb, storage, PR_SUBJECT)?),
+    ];
+    for (header, value) in headers.iter() {
+        if let Some(value) = value.as_ref().filter(|v| !v.is_empty()) {
+            writeln!(oup, "{}{}: {}", line_prefix, header, value)?;
+        }
+    }
+    if let Some(body) = read_string(cfb, storage, PR_BODY)? {
+        for line in body.trim_end().lines() {
+            writeln!(oup, "{}{}", line_prefix, line)?;
+        }
+    } else if let Some(html) = read_binary(cfb, storage, PR_HTML)? {
+        super::html::write_html_text(
+            line_prefix,
+            &String::from_utf8_lossy(&html),
+            ai.config.args.html_links,
+            oup,
+        )?;
+    }
+
+    let mut attachments = cfb
+        .read_storage(storage)?
+        .filter(|e| e.is_storage() && e.name().starts_with("__attach_version1.0_"))
+        .map(|e| e.path().to_owned())
+        .collect::<Vec<_>>();
+    attachments.sort();
+    for (i, attachment) in attachments.iter().enumerate() {
+        write_attachment(cfb, attachment, i + 1, ai, line_prefix, oup)
+            .with_context(|| format!("reading attachment {}", i + 1))?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for MsgAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut cfb = CompoundFile::open(Cursor::new(data))?;
+        write_message(&mut cfb, Path::new("/"), &ai, &ai.line_prefix, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    fn write_prop(
+        cfb: &mut CompoundFile<Cursor<Vec<u8>>>,
+        storage: &str,
+        prop: u16,
+        typ: u16,
+        data: &[u8],
+    ) -> Result<()> {
+        cfb.create_stream(prop_path(Path::new(storage), prop, typ))?
+            .write_all(data)?;
+        Ok(())
+    }
+
+    /// outlook can't be scripted in the test environment, so build a minimal msg file by hand
+    fn make_msg() -> Result<Vec<u8>> {
+        let mut cfb = CompoundFile::create(Cursor::new(Vec::new()))?;
+        write_prop(&mut cfb, "/", PR_SUBJECT, PT_UNICODE, &utf16("Grüße"))?;
+        write_prop(&mut cfb, "/", PR_SENDER_NAME, PT_UNICODE, &utf16("Alice"))?;
+        write_prop(
+            &mut cfb,
+            "/",
+            PR_SENDER_EMAIL_ADDRESS,
+            PT_UNICODE,
+            &utf16("alice@example.com"),
+        )?;
+        write_prop(&mut cfb, "/", PR_DISPLAY_TO, PT_STRING8, b"Bob")?;
+        write_prop(
+            &mut cfb,
+            "/",
+            PR_BODY,
+            PT_UNICODE,
+            &utf16("hello world\r\nthis is the body.\r\n"),
+        )?;
+
+        let docx = std::fs::read(test_data_dir().join("short.docx"))?;
+        let attach = "/__attach_version1.0_#00000000";
+        cfb.create_storage(attach)?;
+        write_prop(
+            &mut cfb,
+            attach,
+            PR_ATTACH_LONG_FILENAME,
+            PT_UNICODE,
+            &utf16("short.docx"),
+        )?;
+        write_prop(&mut cfb, attach, PR_ATTACH_DATA, PT_BINARY, &docx)?;
+
+        let attach = "/__attach_version1.0_#00000001";
+        cfb.create_storage(attach)?;
+        write_prop(
+            &mut cfb,
+            attach,
+            PR_DISPLAY_NAME,
+            PT_UNICODE,
+            &utf16("forwarded"),
+        )?;
+        let embedded = prop_path(Path::new(attach), PR_ATTACH_DATA, PT_OBJECT);
+        cfb.create_storage(&embedded)?;
+        let embedded = embedded.to_str().unwrap();
+        write_prop(&mut cfb, embedded, PR_SUBJECT, PT_UNICODE, &utf16("inner"))?;
+        write_prop(
+            &mut cfb,
+            embedded,
+            PR_HTML,
+            PT_BINARY,
+            b"<html><body><p>inner <i>html</i></p></body></html>",
+        )?;
+        cfb.flush()?;
+        Ok(cfb.into_inner().into_inner())
+    }
+
+    #[test]
+    fn simple() -> Result<()> {
+        let (a, d) = simple_adapt_info(Path::new("test.msg"), Box::new(Cursor::new(make_msg()?)));
+        let mut r = MsgAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:From: Alice <alice@example.com>\nPREFIX:To: Bob\nPREFIX:Subject: Grüße\nPREFIX:hello world\nPREFIX:this is the body.\nPREFIX:short.docx: hello world\nPREFIX:short.docx: this is just a\ttest.\nPREFIX:short.docx: \nPREFIX:short.docx: a footnote\nPREFIX:forwarded: Subject: inner\nPREFIX:forwarded: inner html\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["msi", "msm", "msp"];
+static MIME_TYPES: &[&str] = &["application/x-msi", "application/x-ole-storage"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "msi".to_owned(),
+        version: 1,
+        description: "Dumps the summary info and the database tables (Property, File, Registry, ...) of Windows Installer packages, and recurses into the embedded cabinets".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct MsiAdapter;
+
+impl MsiAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(MsiAdapter))
+    }
+}
+impl GetMetadata for MsiAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn write_summary(line_prefix: &str, info: &::msi::SummaryInfo, oup: &mut dyn Write) -> Result<()> {
+    let keywords = info.keywords().join(", ");
+    let fields = [
+        ("Title", info.title()),
+        ("Subject", info.subject()),
+        ("Author", info.author()),
+        ("Keywords", Some(keywords.as_str())),
+        ("Comments", info.comments()),
+        ("Creating application", info.creating_application()),
+    ];
+    for (label, value) in fields.iter() {
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            writeln!(oup, "{}{}: {}", line_prefix, label, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// one line per row like the sqlite adapter, `Property: Property=ProductName, Value=Example`
+fn write_table<F: Read + Seek>(
+    line_prefix: &str,
+    package: &mut ::msi::Package<F>,
+    table: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for row in package.select_rows(::msi::Select::table(table))? {
+        let cells: Vec<String> = row
+            .columns()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, column)| match &row[i] {
+                ::msi::Value::Str(s) => Some(format!("{}={}", column.name(), s)),
+                ::msi::Value::Int(n) => Some(format!("{}={}", column.name(), n)),
+                ::msi::Value::Null | ::msi::Value::Binary => None,
+            })
+            .collect();
+        if !cells.is_empty() {
+            writeln!(oup, "{}{}: {}", line_prefix, table, cells.join(", "))?;
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for MsiAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut package = ::msi::Package::open(Cursor::new(data))?;
+        write_summary(&ai.line_prefix, package.summary_info(), oup)?;
+        // the tables starting with an underscore describe the database itself
+        let tables: Vec<String> = package
+            .tables()
+            .map(|t| t.name().to_string())
+            .filter(|name| !name.starts_with('_'))
+            .collect();
+        for table in tables {
+            write_table(&ai.line_prefix, &mut package, &table, oup)?;
+        }
+        // the installed files are usually in a cabinet stream, the other streams are binaries
+        let streams: Vec<String> = package.streams().collect();
+        for name in streams {
+            let mut data = Vec::new();
+            package.read_stream(&name)?.read_to_end(&mut data)?;
+            if data.starts_with(b"MSCF") {
+                debug!("{}|{}", ai.filepath_hint.display(), name);
+                super::cab::adapt_cabinet(
+                    data,
+                    Path::new(&name),
+                    &format!("{}{}: ", ai.line_prefix, name),
+                    ai.archive_recursion_depth + 1,
+                    &ai.config,
+                    oup,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn test_package() -> Result<Vec<u8>> {
+        let mut package =
+            ::msi::Package::create(::msi::PackageType::Installer, Cursor::new(Vec::new()))?;
+        package.summary_info_mut().set_title("Example Installer");
+        package.summary_info_mut().set_author("Example Corp");
+        package.create_table(
+            "Property",
+            vec![
+                ::msi::Column::build("Property").primary_key().id_string(72),
+                ::msi::Column::build("Value").nullable().formatted_string(0),
+            ],
+        )?;
+        package.insert_rows(::msi::Insert::into("Property").rows(vec![
+            vec![
+                ::msi::Value::from("ProductName"),
+                ::msi::Value::from("Example"),
+            ],
+            vec![
+                ::msi::Value::from("UpdateURL"),
+                ::msi::Value::from("https://example.com"),
+            ],
+        ]))?;
+        package.create_table(
+            "Registry",
+            vec![
+                ::msi::Column::build("Registry").primary_key().id_string(72),
+                ::msi::Column::build("Root").int16(),
+                ::msi::Column::build("Key").text_string(255),
+                ::msi::Column::build("Name")
+                    .nullable()
+                    .formatted_string(255),
+            ],
+        )?;
+        package.insert_rows(::msi::Insert::into("Registry").row(vec![
+            ::msi::Value::from("reg1"),
+            ::msi::Value::from(2),
+            ::msi::Value::from("Software\\Example"),
+            ::msi::Value::Null,
+        ]))?;
+        package
+            .write_stream("media.cab")?
+            .write_all(&crate::adapters::cab::tests::test_cabinet()?)?;
+        package.write_stream("icon.ico")?.write_all(b"\0\0\x01\0")?;
+        Ok(package.into_inner()?.into_inner())
+    }
+
+    #[test]
+    fn msi() -> Result<()> {
+        let (mut a, d) = simple_adapt_info(
+            Path::new("setup.msi"),
+            Box::new(Cursor::new(test_package()?)),
+        );
+        // the html members show that the members are adapted
+        a.config.args.adapters = vec!["+html".to_string()];
+        let mut r = MsiAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Title: Example Installer
+PREFIX:Author: Example Corp
+PREFIX:Property: Property=ProductName, Value=Example
+PREFIX:Property: Property=UpdateURL, Value=https://example.com
+PREFIX:Registry: Registry=reg1, Root=2, Key=Software\\Example
+PREFIX:media.cab: docs/readme.html: Thanks for installing
+[Setup]
+Server=db.example.com
+"
+        );
+        Ok(())
+    }
+}
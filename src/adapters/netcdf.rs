@@ -0,0 +1,393 @@
+use super::*;
+use ::netcdf_reader::{NcAttrValue, NcAttribute, NcFile, NcGroup, NcType};
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["nc", "nc4", "netcdf"];
+
+/// numeric variables with more values are only declared, not dumped (coordinates are usually small)
+const MAX_DATA_VALUES: u64 = 100;
+/// text variables are what people search for, so allow more of them
+const MAX_STRING_VALUES: u64 = 1000;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "netcdf".to_owned(),
+        version: 1,
+        description: "Writes the header of NetCDF files (dimensions, variables and attributes) like ncdump -h, followed by the data of small variables. Supports the classic formats and NetCDF-4".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/x-netcdf".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct NetCdfAdapter;
+
+impl NetCdfAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(NetCdfAdapter))
+    }
+}
+impl GetMetadata for NetCdfAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the names ncdump uses in CDL
+fn type_name(dtype: &NcType) -> String {
+    match dtype {
+        NcType::Byte => "byte".to_string(),
+        NcType::Char => "char".to_string(),
+        NcType::Short => "short".to_string(),
+        NcType::Int => "int".to_string(),
+        NcType::Float => "float".to_string(),
+        NcType::Double => "double".to_string(),
+        NcType::UByte => "ubyte".to_string(),
+        NcType::UShort => "ushort".to_string(),
+        NcType::UInt => "uint".to_string(),
+        NcType::Int64 => "int64".to_string(),
+        NcType::UInt64 => "uint64".to_string(),
+        NcType::String => "string".to_string(),
+        NcType::Enum { base, .. } => format!("enum {}", type_name(base)),
+        NcType::Compound { .. } => "compound".to_string(),
+        NcType::Opaque { .. } => "opaque".to_string(),
+        NcType::Array { base, .. } => format!("{}(*)", type_name(base)),
+        NcType::VLen { base } => format!("{}(*)", type_name(base)),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\n', "\\n"))
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(T::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn attribute_value(value: &NcAttrValue) -> String {
+    match value {
+        NcAttrValue::Chars(s) => quote(s.trim_end_matches('\0')),
+        NcAttrValue::Strings(v) => v.iter().map(|s| quote(s)).collect::<Vec<_>>().join(", "),
+        NcAttrValue::Bytes(v) => join(v),
+        NcAttrValue::Shorts(v) => join(v),
+        NcAttrValue::Ints(v) => join(v),
+        NcAttrValue::Floats(v) => join(v),
+        NcAttrValue::Doubles(v) => join(v),
+        NcAttrValue::UBytes(v) => join(v),
+        NcAttrValue::UShorts(v) => join(v),
+        NcAttrValue::UInts(v) => join(v),
+        NcAttrValue::Int64s(v) => join(v),
+        NcAttrValue::UInt64s(v) => join(v),
+    }
+}
+
+fn write_attributes(
+    line_prefix: &str,
+    indent: &str,
+    variable: &str,
+    attributes: &[NcAttribute],
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for attribute in attributes {
+        writeln!(
+            oup,
+            "{}{}\t\t{}:{} = {} ;",
+            line_prefix,
+            indent,
+            variable,
+            attribute.name,
+            attribute_value(&attribute.value)
+        )?;
+    }
+    Ok(())
+}
+
+fn write_header(
+    line_prefix: &str,
+    indent: &str,
+    group: &NcGroup,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    if !group.dimensions.is_empty() {
+        writeln!(oup, "{}{}dimensions:", line_prefix, indent)?;
+    }
+    for dimension in &group.dimensions {
+        if dimension.is_unlimited {
+            writeln!(
+                oup,
+                "{}{}\t{} = UNLIMITED ; // ({} currently)",
+                line_prefix, indent, dimension.name, dimension.size
+            )?;
+        } else {
+            writeln!(
+                oup,
+                "{}{}\t{} = {} ;",
+                line_prefix, indent, dimension.name, dimension.size
+            )?;
+        }
+    }
+    if !group.variables.is_empty() {
+        writeln!(oup, "{}{}variables:", line_prefix, indent)?;
+    }
+    for variable in &group.variables {
+        let dimensions: Vec<&str> = variable
+            .dimensions
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        let dimensions = if dimensions.is_empty() {
+            String::new()
+        } else {
+            format!("({})", dimensions.join(", "))
+        };
+        writeln!(
+            oup,
+            "{}{}\t{} {}{} ;",
+            line_prefix,
+            indent,
+            type_name(&variable.dtype),
+            variable.name,
+            dimensions
+        )?;
+        write_attributes(
+            line_prefix,
+            indent,
+            &variable.name,
+            &variable.attributes,
+            oup,
+        )?;
+    }
+    if !group.attributes.is_empty() {
+        writeln!(oup, "{}", line_prefix)?;
+        writeln!(oup, "{}{}// global attributes:", line_prefix, indent)?;
+        write_attributes(line_prefix, indent, "", &group.attributes, oup)?;
+    }
+    Ok(())
+}
+
+/// string and char variables one string per line, numbers on a single line
+fn write_data(
+    file: &NcFile,
+    line_prefix: &str,
+    indent: &str,
+    path: &str,
+    group: &NcGroup,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let mut wrote_section = false;
+    for variable in &group.variables {
+        let count = variable.num_elements()?;
+        let is_text = matches!(variable.dtype, NcType::Char | NcType::String);
+        let limit = if is_text {
+            MAX_STRING_VALUES
+        } else {
+            MAX_DATA_VALUES
+        };
+        if count == 0 || count > limit {
+            continue;
+        }
+        let path = format!("{}{}", path, variable.name);
+        let line = if is_text {
+            file.read_variable_as_strings(&path).map(|strings| {
+                let strings: Vec<String> = strings
+                    .iter()
+                    .map(|s| format!("{}  {}", line_prefix, quote(s.trim_end_matches('\0'))))
+                    .collect();
+                format!("\n{} ;", strings.join(",\n"))
+            })
+        } else if variable.dtype.is_primitive() {
+            file.read_variable_as_f64(&path)
+                .map(|values| format!(" {} ;", join(&values.iter().collect::<Vec<_>>())))
+        } else {
+            continue;
+        };
+        match line {
+            std::result::Result::Ok(line) => {
+                if !wrote_section {
+                    writeln!(oup, "{}", line_prefix)?;
+                    writeln!(oup, "{}{}data:", line_prefix, indent)?;
+                    wrote_section = true;
+                }
+                writeln!(oup, "{}", line_prefix)?;
+                writeln!(oup, "{}{} {} ={}", line_prefix, indent, variable.name, line)?;
+            }
+            Err(e) => debug!("{}: {}", path, e),
+        }
+    }
+    Ok(())
+}
+
+/// netcdf-4 files can contain nested groups with their own dimensions and variables
+fn adapt_group(
+    file: &NcFile,
+    line_prefix: &str,
+    indent: &str,
+    path: &str,
+    group: &NcGroup,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    write_header(line_prefix, indent, group, oup)?;
+    write_data(file, line_prefix, indent, path, group, oup)?;
+    for child in &group.groups {
+        writeln!(oup, "{}", line_prefix)?;
+        writeln!(oup, "{}{}group: {} {{", line_prefix, indent, child.name)?;
+        adapt_group(
+            file,
+            line_prefix,
+            &format!("{}  ", indent),
+            &format!("{}{}/", path, child.name),
+            child,
+            oup,
+        )?;
+        writeln!(oup, "{}{}  }} // group {}", line_prefix, indent, child.name)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for NetCdfAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let file = if ai.is_real_file {
+            NcFile::open(&ai.filepath_hint)?
+        } else {
+            let mut data = Vec::new();
+            ai.inp.read_to_end(&mut data)?;
+            NcFile::from_bytes(&data)?
+        };
+        let name = ai
+            .filepath_hint
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        writeln!(oup, "{}netcdf {} {{", ai.line_prefix, name)?;
+        adapt_group(&file, &ai.line_prefix, "", "", file.root_group()?, oup)?;
+        writeln!(oup, "{}}}", ai.line_prefix)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    /// names and values in the classic format are padded to 4 bytes
+    fn padded(oup: &mut Vec<u8>, data: &[u8]) {
+        oup.extend_from_slice(data);
+        oup.resize(oup.len() + (4 - data.len() % 4) % 4, 0);
+    }
+
+    fn name(oup: &mut Vec<u8>, name: &str) {
+        oup.extend_from_slice(&(name.len() as u32).to_be_bytes());
+        padded(oup, name.as_bytes());
+    }
+
+    fn text_attribute(oup: &mut Vec<u8>, attribute: &str, value: &str) {
+        name(oup, attribute);
+        oup.extend_from_slice(&2u32.to_be_bytes());
+        oup.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        padded(oup, value.as_bytes());
+    }
+
+    /// a classic netcdf file with `station = 2`, `len = 8`, a double `temperature(station)`
+    /// and a char `name(station, len)`
+    fn make_netcdf() -> Vec<u8> {
+        let mut header = b"CDF\x01\0\0\0\0".to_vec();
+        header.extend_from_slice(&[0, 0, 0, 0x0a, 0, 0, 0, 2]);
+        name(&mut header, "station");
+        header.extend_from_slice(&2u32.to_be_bytes());
+        name(&mut header, "len");
+        header.extend_from_slice(&8u32.to_be_bytes());
+        header.extend_from_slice(&[0, 0, 0, 0x0c, 0, 0, 0, 1]);
+        text_attribute(&mut header, "title", "Baltic Sea buoys");
+        header.extend_from_slice(&[0, 0, 0, 0x0b, 0, 0, 0, 2]);
+        let mut begin_positions = [0usize; 2];
+        for (i, (variable, dims, nc_type, size)) in [
+            ("temperature", &[0u32][..], 6u32, 16u32),
+            ("name", &[0, 1][..], 2, 16),
+        ]
+        .iter()
+        .enumerate()
+        {
+            name(&mut header, variable);
+            header.extend_from_slice(&(dims.len() as u32).to_be_bytes());
+            for dim in *dims {
+                header.extend_from_slice(&dim.to_be_bytes());
+            }
+            if i == 0 {
+                header.extend_from_slice(&[0, 0, 0, 0x0c, 0, 0, 0, 1]);
+                text_attribute(&mut header, "units", "degC");
+            } else {
+                header.extend_from_slice(&[0; 8]);
+            }
+            header.extend_from_slice(&nc_type.to_be_bytes());
+            header.extend_from_slice(&size.to_be_bytes());
+            begin_positions[i] = header.len();
+            header.extend_from_slice(&[0; 4]);
+        }
+        let data_start = header.len();
+        for (i, position) in begin_positions.iter().enumerate() {
+            let begin = (data_start + i * 16) as u32;
+            header[*position..*position + 4].copy_from_slice(&begin.to_be_bytes());
+        }
+        header.extend_from_slice(&4.5f64.to_be_bytes());
+        header.extend_from_slice(&(-1.25f64).to_be_bytes());
+        header.extend_from_slice(b"Gotland\0Arkona\0\0");
+        header
+    }
+
+    #[test]
+    fn classic() -> Result<()> {
+        let (mut a, d) =
+            simple_adapt_info(Path::new("buoys.nc"), Box::new(Cursor::new(make_netcdf())));
+        a.is_real_file = false;
+        let mut r = NetCdfAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:netcdf buoys {
+PREFIX:dimensions:
+PREFIX:\tstation = 2 ;
+PREFIX:\tlen = 8 ;
+PREFIX:variables:
+PREFIX:\tdouble temperature(station) ;
+PREFIX:\t\ttemperature:units = \"degC\" ;
+PREFIX:\tchar name(station, len) ;
+PREFIX:
+PREFIX:// global attributes:
+PREFIX:\t\t:title = \"Baltic Sea buoys\" ;
+PREFIX:
+PREFIX:data:
+PREFIX:
+PREFIX: temperature = 4.5, -1.25 ;
+PREFIX:
+PREFIX: name =
+PREFIX:  \"Gotland\",
+PREFIX:  \"Arkona\" ;
+PREFIX:}
+"
+        );
+        Ok(())
+    }
+}
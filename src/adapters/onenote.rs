@@ -0,0 +1,198 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use onenote_parser::contents::{Content, Image, OutlineElement, OutlineItem};
+use onenote_parser::page::{Page, PageContent};
+use onenote_parser::Parser;
+use typed_path::TypedPath;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["one"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "onenote".to_owned(),
+        version: 1,
+        description:
+            "Extracts the text of OneNote section files (.one), prefixed with the page title"
+                .to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/onenote".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct OneNoteAdapter;
+
+impl OneNoteAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(OneNoteAdapter))
+    }
+}
+impl GetMetadata for OneNoteAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// paragraphs can contain soft line breaks (shift+enter)
+fn write_lines(prefix: &str, text: &str, oup: &mut dyn Write) -> Result<()> {
+    for line in text.split(['\r', '\n', '\u{b}']) {
+        let line = line.trim();
+        if !line.is_empty() {
+            writeln!(oup, "{}{}", prefix, line)?;
+        }
+    }
+    Ok(())
+}
+
+/// the text of a table cell, on a single line
+fn cell_text(elements: &[OutlineElement]) -> String {
+    let mut text = Vec::new();
+    for element in elements {
+        for content in element.contents() {
+            if let Content::RichText(t) = content {
+                text.extend(t.text().split_whitespace().map(|s| s.to_string()));
+            }
+        }
+    }
+    text.join(" ")
+}
+
+/// alt text and the text onenote recognized in the image
+fn write_image_text(prefix: &str, image: &Image, oup: &mut dyn Write) -> Result<()> {
+    for text in image.alt_text().iter().chain(image.text().iter()) {
+        write_lines(prefix, text, oup)?;
+    }
+    Ok(())
+}
+
+fn write_items(prefix: &str, items: &[OutlineItem], oup: &mut dyn Write) -> Result<()> {
+    for item in items {
+        match item {
+            OutlineItem::Group(group) => write_items(prefix, group.outlines(), oup)?,
+            OutlineItem::Element(element) => {
+                for content in element.contents() {
+                    match content {
+                        Content::RichText(t) => write_lines(prefix, t.text(), oup)?,
+                        Content::Table(table) => {
+                            for row in table.contents() {
+                                let cells: Vec<String> = row
+                                    .contents()
+                                    .iter()
+                                    .map(|cell| cell_text(cell.contents()))
+                                    .collect();
+                                if cells.iter().any(|c| !c.is_empty()) {
+                                    writeln!(oup, "{}{}", prefix, cells.join("\t"))?;
+                                }
+                            }
+                        }
+                        Content::Image(image) => write_image_text(prefix, image, oup)?,
+                        Content::EmbeddedFile(file) => {
+                            writeln!(oup, "{}[attachment: {}]", prefix, file.filename())?
+                        }
+                        Content::Ink(_) | Content::Unknown => {}
+                    }
+                }
+                write_items(prefix, element.children(), oup)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_page(prefix: &str, page: &Page, oup: &mut dyn Write) -> Result<()> {
+    for content in page.contents() {
+        match content {
+            PageContent::Outline(outline) => write_items(prefix, outline.items(), oup)?,
+            PageContent::Image(image) => write_image_text(prefix, image, oup)?,
+            PageContent::EmbeddedFile(file) => {
+                writeln!(oup, "{}[attachment: {}]", prefix, file.filename())?
+            }
+            PageContent::Ink(_) | PageContent::Unknown => {}
+        }
+    }
+    if let Some(ink) = page.ink_recognition() {
+        write_lines(prefix, &ink.text(), oup)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for OneNoteAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let file_name = ai.filepath_hint.to_string_lossy();
+        let section = Parser::new().parse_section_buffer(&data, TypedPath::derive(&*file_name))?;
+        writeln!(oup, "{}Section: {}", ai.line_prefix, section.display_name())?;
+        for series in section.page_series() {
+            // subpages have a higher level than their parent page
+            let mut titles: Vec<String> = Vec::new();
+            for page in series.pages() {
+                let level = page.level().max(0) as usize;
+                titles.truncate(level);
+                titles.push(
+                    page.title_text()
+                        .unwrap_or("Untitled page")
+                        .trim()
+                        .to_string(),
+                );
+                let title = titles.join(" / ");
+                writeln!(oup, "{}Page: {}", ai.line_prefix, title)?;
+                write_page(&format!("{}{}: ", ai.line_prefix, title), page, oup)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn lines() -> Result<()> {
+        let mut o = Vec::new();
+        write_lines(
+            "PREFIX:Meeting notes: ",
+            "agenda\u{b}budget review\r\n  \rnext steps ",
+            &mut o,
+        )?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Meeting notes: agenda
+PREFIX:Meeting notes: budget review
+PREFIX:Meeting notes: next steps
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn not_a_section() {
+        let (a, d) = simple_adapt_info(
+            Path::new("notes.one"),
+            Box::new(Cursor::new(b"not a onenote file".to_vec())),
+        );
+        let mut o = Vec::new();
+        let res = OneNoteAdapter::new()
+            .adapt(a, &d)
+            .and_then(|mut r| Ok(r.read_to_end(&mut o)?));
+        assert!(res.is_err());
+    }
+}
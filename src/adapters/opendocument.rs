@@ -0,0 +1,293 @@
+use super::xml::{text_of, xml_reader};
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use quick_xml::events::{BytesStart, Event};
+use std::io::{BufReader, Cursor};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["odt", "ods", "odp"];
+static MIME_TYPES: &[&str] = &[
+    "application/vnd.oasis.opendocument.text",
+    "application/vnd.oasis.opendocument.spreadsheet",
+    "application/vnd.oasis.opendocument.presentation",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "opendocument".to_owned(),
+        version: 1,
+        description: "Reads the text of OpenDocument text documents, spreadsheets and presentations directly from content.xml (does not need pandoc). Lines are prefixed with the sheet name or slide number".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct OpenDocumentAdapter;
+
+impl OpenDocumentAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(OpenDocumentAdapter))
+    }
+}
+impl GetMetadata for OpenDocumentAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    Text,
+    Spreadsheet,
+    Presentation,
+}
+
+struct Writer<'a> {
+    line_prefix: &'a str,
+    /// sheet name or slide number
+    section: Option<String>,
+    line: String,
+}
+
+impl Writer<'_> {
+    /// whitespace in paragraphs is collapsed, explicit spaces are written as text:s
+    fn push_text(&mut self, text: &str) {
+        for c in text.chars() {
+            if !c.is_whitespace() {
+                self.line.push(c);
+            } else if !self.line.ends_with(' ') {
+                self.line.push(' ');
+            }
+        }
+    }
+
+    fn flush(&mut self, oup: &mut dyn Write) -> Result<()> {
+        let text = self.line.trim_end();
+        if !text.trim_start().is_empty() {
+            match &self.section {
+                Some(section) => writeln!(oup, "{}{}: {}", self.line_prefix, section, text)?,
+                None => writeln!(oup, "{}{}", self.line_prefix, text)?,
+            }
+        }
+        self.line.clear();
+        Ok(())
+    }
+}
+
+fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+fn repeated(e: &BytesStart, name: &[u8]) -> usize {
+    attribute(e, name).and_then(|n| n.parse().ok()).unwrap_or(1)
+}
+
+/// write the text of content.xml, one paragraph or table row per line
+pub fn write_content(line_prefix: &str, inp: impl BufRead, oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(inp);
+    let mut buf = Vec::new();
+    let mut w = Writer {
+        line_prefix,
+        section: None,
+        line: String::new(),
+    };
+    let mut kind = Kind::Text;
+    let mut slide = 0;
+    let mut paragraph_depth = 0;
+    let mut cell_depth = 0;
+    let mut skip_depth = 0;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(_) if skip_depth > 0 => skip_depth += 1,
+            Event::End(_) if skip_depth > 0 => skip_depth -= 1,
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"spreadsheet" => kind = Kind::Spreadsheet,
+                b"presentation" => kind = Kind::Presentation,
+                // old versions of changed text, it's not part of the document
+                b"tracked-changes" => skip_depth = 1,
+                b"table" if kind == Kind::Spreadsheet => {
+                    w.flush(oup)?;
+                    w.section = attribute(&e, b"table:name");
+                }
+                b"page" if kind == Kind::Presentation => {
+                    w.flush(oup)?;
+                    slide += 1;
+                    w.section = Some(format!("Slide {}", slide));
+                }
+                b"p" | b"h" => paragraph_depth += 1,
+                b"table-cell" | b"covered-table-cell" => cell_depth += 1,
+                b"table-row" => w.flush(oup)?,
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"table" if kind == Kind::Spreadsheet => {
+                    w.flush(oup)?;
+                    w.section = None;
+                }
+                b"page" if kind == Kind::Presentation => {
+                    w.flush(oup)?;
+                    w.section = None;
+                }
+                b"p" | b"h" => {
+                    paragraph_depth -= 1;
+                    if cell_depth > 0 {
+                        // multiple paragraphs in one cell stay on the line of the row
+                        w.push_text(" ");
+                    } else {
+                        w.flush(oup)?;
+                    }
+                }
+                b"table-cell" | b"covered-table-cell" => {
+                    cell_depth -= 1;
+                    let len = w.line.trim_end_matches(' ').len();
+                    w.line.truncate(len);
+                    w.line.push('\t');
+                }
+                b"table-row" => {
+                    let len = w.line.trim_end_matches('\t').len();
+                    w.line.truncate(len);
+                    w.flush(oup)?;
+                }
+                _ => {}
+            },
+            Event::Empty(e) if skip_depth == 0 => match e.local_name().as_ref() {
+                b"tab" => w.line.push('\t'),
+                b"s" => {
+                    for _ in 0..repeated(&e, b"text:c") {
+                        w.line.push(' ');
+                    }
+                }
+                b"line-break" if cell_depth > 0 => w.push_text(" "),
+                b"line-break" => w.flush(oup)?,
+                // empty cells are usually written once with a repeat count
+                b"table-cell" | b"covered-table-cell" => {
+                    for _ in 0..repeated(&e, b"table:number-columns-repeated") {
+                        w.line.push('\t');
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(t) if skip_depth == 0 && paragraph_depth > 0 => w.push_text(&text_of(&t)),
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    w.flush(oup)
+}
+
+impl WritingFileAdapterTrait for OpenDocumentAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // zip needs to seek to the central directory, so read the whole file to memory
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+        let content = archive
+            .by_name("content.xml")
+            .context("no content.xml in OpenDocument file")?;
+        write_content(&ai.line_prefix, BufReader::new(content), oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+
+    fn convert(body: &str) -> Result<String> {
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0">
+<office:body>{}</office:body></office:document-content>"#,
+            body
+        );
+        let mut o = Vec::new();
+        write_content("PREFIX:", xml.as_bytes(), &mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn text() -> Result<()> {
+        let adapter: Box<dyn FileAdapter> = Box::new(OpenDocumentAdapter::new());
+        let fname = test_data_dir().join("short.odt");
+        let rd = File::open(&fname)?;
+        let (a, d) = simple_adapt_info(&fname, Box::new(rd));
+        let mut res = adapter.adapt(a, &d)?;
+
+        let mut buf = Vec::new();
+        res.read_to_end(&mut buf)?;
+
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "PREFIX:hello world\nPREFIX:this is just a\ttest.\nPREFIX:with  spaces and a\nPREFIX:line break\nPREFIX:a\tb\n",
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn spreadsheet() -> Result<()> {
+        assert_eq!(
+            convert(
+                r#"<office:spreadsheet>
+  <table:table table:name="Prices">
+    <table:table-row>
+      <table:table-cell><text:p>apple</text:p></table:table-cell>
+      <table:table-cell table:number-columns-repeated="2"/>
+      <table:table-cell office:value-type="float" office:value="1.5"><text:p>1.50</text:p></table:table-cell>
+      <table:table-cell table:number-columns-repeated="1020"/>
+    </table:table-row>
+    <table:table-row table:number-rows-repeated="1000"><table:table-cell table:number-columns-repeated="1024"/></table:table-row>
+  </table:table>
+  <table:table table:name="Notes">
+    <table:table-row><table:table-cell><text:p>two</text:p><text:p>paragraphs</text:p></table:table-cell></table:table-row>
+  </table:table>
+</office:spreadsheet>"#
+            )?,
+            "PREFIX:Prices: apple\t\t\t1.50\nPREFIX:Notes: two paragraphs\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn presentation() -> Result<()> {
+        assert_eq!(
+            convert(
+                r#"<office:presentation>
+  <draw:page draw:name="page1"><draw:frame><draw:text-box>
+    <text:p>Title slide</text:p>
+  </draw:text-box></draw:frame></draw:page>
+  <draw:page draw:name="page2"><draw:frame><draw:text-box>
+    <text:list><text:list-item><text:p>first point</text:p></text:list-item></text:list>
+    <text:p>second
+      point</text:p>
+  </draw:text-box></draw:frame></draw:page>
+</office:presentation>"#
+            )?,
+            "PREFIX:Slide 1: Title slide\nPREFIX:Slide 2: first point\nPREFIX:Slide 2: second point\n"
+        );
+        Ok(())
+    }
+}
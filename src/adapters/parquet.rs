@@ -0,0 +1,143 @@
+use super::*;
+use ::parquet::file::reader::{ChunkReader, FileReader, SerializedFileReader};
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::fs::File;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["parquet"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "parquet".to_owned(),
+        version: 1,
+        description: "Reads Apache Parquet files and outputs every row as a line of JSON, prefixed with the row group and row number".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct ParquetAdapter;
+
+impl ParquetAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(ParquetAdapter))
+    }
+}
+impl GetMetadata for ParquetAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn write_rows<R: ChunkReader + 'static>(
+    reader: SerializedFileReader<R>,
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for group in 0..reader.num_row_groups() {
+        let row_group = reader.get_row_group(group)?;
+        for (i, row) in row_group.get_row_iter(None)?.enumerate() {
+            writeln!(
+                oup,
+                "{}Row group {}, row {}: {}",
+                line_prefix,
+                group + 1,
+                i + 1,
+                row?.to_json_value()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for ParquetAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        if ai.is_real_file {
+            let reader = SerializedFileReader::new(File::open(&ai.filepath_hint)?)?;
+            write_rows(reader, &ai.line_prefix, oup)
+        } else {
+            // the metadata is at the end of the file, so it has to be in memory
+            let mut data = Vec::new();
+            ai.inp.read_to_end(&mut data)?;
+            let reader = SerializedFileReader::new(bytes::Bytes::from(data))?;
+            write_rows(reader, &ai.line_prefix, oup)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use ::parquet::basic::Compression;
+    use ::parquet::data_type::{ByteArray, ByteArrayType, Int32Type};
+    use ::parquet::file::properties::WriterProperties;
+    use ::parquet::file::writer::SerializedFileWriter;
+    use ::parquet::schema::parser::parse_message_type;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    fn make_parquet() -> Result<Vec<u8>> {
+        let schema = Arc::new(parse_message_type(
+            "message test { REQUIRED INT32 id; OPTIONAL BYTE_ARRAY name (UTF8); }",
+        )?);
+        let props = Arc::new(
+            WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build(),
+        );
+        let mut buf = Vec::new();
+        let mut writer = SerializedFileWriter::new(&mut buf, schema, props)?;
+        for (ids, names) in [(vec![1, 2], vec!["alice", "bob"]), (vec![3], vec![])] {
+            let mut group = writer.next_row_group()?;
+            let mut col = group.next_column()?.unwrap();
+            col.typed::<Int32Type>().write_batch(&ids, None, None)?;
+            col.close()?;
+            let mut col = group.next_column()?.unwrap();
+            let names = names.into_iter().map(ByteArray::from).collect::<Vec<_>>();
+            let def_levels = ids
+                .iter()
+                .map(|&i| if (i as usize) <= names.len() { 1 } else { 0 })
+                .collect::<Vec<_>>();
+            col.typed::<ByteArrayType>()
+                .write_batch(&names, Some(&def_levels), None)?;
+            col.close()?;
+            group.close()?;
+        }
+        writer.close()?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn rows() -> Result<()> {
+        let (mut a, d) = simple_adapt_info(
+            Path::new("test.parquet"),
+            Box::new(Cursor::new(make_parquet()?)),
+        );
+        a.is_real_file = false;
+        let mut r = ParquetAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            r#"PREFIX:Row group 1, row 1: {"id":1,"name":"alice"}
+PREFIX:Row group 1, row 2: {"id":2,"name":"bob"}
+PREFIX:Row group 2, row 1: {"id":3,"name":null}
+"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,365 @@
+use super::*;
+use anyhow::*;
+use etherparse::{EtherType, LaxNetSlice, LaxSlicedPacket, TransportSlice};
+use lazy_static::lazy_static;
+use pcap_parser::traits::PcapReaderIterator;
+use pcap_parser::{Block, LegacyPcapReader, Linktype, PcapBlockOwned, PcapError, PcapNGReader};
+use std::io::BufReader;
+use std::net::{IpAddr, SocketAddr};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["pcap", "pcapng", "cap"];
+static MIME_TYPES: &[&str] = &["application/vnd.tcpdump.pcap", "application/x-pcapng"];
+
+/// shorter runs of printable characters in payloads are usually binary noise
+const MIN_STRING_LEN: usize = 4;
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "pcap".to_owned(),
+        version: 1,
+        description: "Reads pcap and pcapng network captures. Outputs the printable strings of TCP/UDP payloads, prefixed with the timestamp, protocol, source and destination of the packet".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct PcapAdapter;
+
+impl PcapAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(PcapAdapter))
+    }
+}
+impl GetMetadata for PcapAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// how to decode the packets of one capture interface
+#[derive(Clone, Copy)]
+struct Interface {
+    linktype: Linktype,
+    /// timestamp units per second
+    resolution: u64,
+    offset: i64,
+}
+
+fn slice_packet(linktype: Linktype, data: &[u8]) -> Option<LaxSlicedPacket<'_>> {
+    match linktype {
+        Linktype::ETHERNET => LaxSlicedPacket::from_ethernet(data).ok(),
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => LaxSlicedPacket::from_ip(data).ok(),
+        // bsd loopback: 4 byte address family
+        Linktype::NULL | Linktype::LOOP => LaxSlicedPacket::from_ip(data.get(4..)?).ok(),
+        // linux "cooked" capture: 16 byte header ending with the ether type
+        Linktype::LINUX_SLL => {
+            let ether_type = u16::from_be_bytes([*data.get(14)?, *data.get(15)?]);
+            Some(LaxSlicedPacket::from_ether_type(
+                EtherType(ether_type),
+                data.get(16..)?,
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn timestamp(secs: i64, nanos: u32) -> String {
+    match chrono::DateTime::from_timestamp(secs, nanos) {
+        Some(t) => t.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string(),
+        None => secs.to_string(),
+    }
+}
+
+/// runs of printable ascii, like `strings`
+fn printable_strings(payload: &[u8]) -> impl Iterator<Item = &str> {
+    payload
+        .split(|&b| !(b == b'\t' || (0x20..0x7f).contains(&b)))
+        .filter(|s| s.len() >= MIN_STRING_LEN)
+        .map(|s| std::str::from_utf8(s).expect("ascii is utf8"))
+}
+
+fn write_packet(
+    line_prefix: &str,
+    interface: &Interface,
+    time: String,
+    data: &[u8],
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let packet = match slice_packet(interface.linktype, data) {
+        Some(packet) => packet,
+        None => return Ok(()),
+    };
+    let (src, dst): (IpAddr, IpAddr) = match &packet.net {
+        Some(LaxNetSlice::Ipv4(ip)) => (
+            ip.header().source_addr().into(),
+            ip.header().destination_addr().into(),
+        ),
+        Some(LaxNetSlice::Ipv6(ip)) => (
+            ip.header().source_addr().into(),
+            ip.header().destination_addr().into(),
+        ),
+        _ => return Ok(()),
+    };
+    let (protocol, ports, payload) = match &packet.transport {
+        Some(TransportSlice::Tcp(tcp)) => (
+            "TCP",
+            Some((tcp.source_port(), tcp.destination_port())),
+            tcp.payload(),
+        ),
+        Some(TransportSlice::Udp(udp)) => (
+            "UDP",
+            Some((udp.source_port(), udp.destination_port())),
+            udp.payload(),
+        ),
+        Some(TransportSlice::Icmpv4(icmp)) => ("ICMP", None, icmp.payload()),
+        Some(TransportSlice::Icmpv6(icmp)) => ("ICMPv6", None, icmp.payload()),
+        _ => return Ok(()),
+    };
+    let (src, dst) = match ports {
+        Some((src_port, dst_port)) => (
+            SocketAddr::new(src, src_port).to_string(),
+            SocketAddr::new(dst, dst_port).to_string(),
+        ),
+        None => (src.to_string(), dst.to_string()),
+    };
+    for s in printable_strings(payload) {
+        writeln!(
+            oup,
+            "{}{} {} {} → {}: {}",
+            line_prefix, time, protocol, src, dst, s
+        )?;
+    }
+    Ok(())
+}
+
+fn write_capture(
+    line_prefix: &str,
+    reader: &mut dyn PcapReaderIterator,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut legacy_nanos = false;
+    loop {
+        match reader.next() {
+            Result::Ok((offset, block)) => {
+                match block {
+                    PcapBlockOwned::LegacyHeader(header) => {
+                        legacy_nanos = header.is_nanosecond_precision();
+                        interfaces = vec![Interface {
+                            linktype: header.network,
+                            resolution: if legacy_nanos {
+                                1_000_000_000
+                            } else {
+                                1_000_000
+                            },
+                            offset: 0,
+                        }];
+                    }
+                    PcapBlockOwned::Legacy(packet) => {
+                        let nanos = if legacy_nanos {
+                            packet.ts_usec
+                        } else {
+                            packet.ts_usec.saturating_mul(1000)
+                        };
+                        let time = timestamp(packet.ts_sec as i64, nanos);
+                        if let Some(interface) = interfaces.first() {
+                            write_packet(line_prefix, interface, time, packet.data, oup)?;
+                        }
+                    }
+                    // interface ids are only valid within their section
+                    PcapBlockOwned::NG(Block::SectionHeader(_)) => interfaces.clear(),
+                    PcapBlockOwned::NG(Block::InterfaceDescription(idb)) => {
+                        interfaces.push(Interface {
+                            linktype: idb.linktype,
+                            resolution: idb.ts_resolution().unwrap_or(1_000_000),
+                            offset: idb.ts_offset(),
+                        })
+                    }
+                    PcapBlockOwned::NG(Block::EnhancedPacket(epb)) => {
+                        if let Some(interface) = interfaces.get(epb.if_id as usize) {
+                            let ts = ((epb.ts_high as u64) << 32) | epb.ts_low as u64;
+                            let secs = (ts / interface.resolution) as i64 + interface.offset;
+                            let frac = ts % interface.resolution;
+                            let nanos = (frac as u128 * 1_000_000_000
+                                / interface.resolution as u128)
+                                as u32;
+                            write_packet(
+                                line_prefix,
+                                interface,
+                                timestamp(secs, nanos),
+                                epb.data,
+                                oup,
+                            )?;
+                        }
+                    }
+                    // simple packets don't have a timestamp
+                    PcapBlockOwned::NG(Block::SimplePacket(spb)) => {
+                        if let Some(interface) = interfaces.first() {
+                            write_packet(line_prefix, interface, "-".to_string(), spb.data, oup)?;
+                        }
+                    }
+                    _ => {}
+                }
+                reader.consume(offset);
+            }
+            Err(PcapError::Eof) => break,
+            Err(PcapError::Incomplete(_)) => reader
+                .refill()
+                .map_err(|e| format_err!("could not read capture: {:?}", e))?,
+            Err(e) => return Err(format_err!("could not parse capture: {:?}", e)),
+        }
+    }
+    Ok(())
+}
+
+/// magic number of the section header block that starts every pcapng file
+const PCAPNG_MAGIC: &[u8] = &[0x0a, 0x0d, 0x0d, 0x0a];
+
+impl WritingFileAdapterTrait for PcapAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut inp = BufReader::new(ai.inp);
+        let is_ng = inp.fill_buf()?.starts_with(PCAPNG_MAGIC);
+        let mut reader: Box<dyn PcapReaderIterator> = if is_ng {
+            Box::new(
+                PcapNGReader::new(65536, inp)
+                    .map_err(|e| format_err!("not a pcapng file: {:?}", e))?,
+            )
+        } else {
+            Box::new(
+                LegacyPcapReader::new(65536, inp)
+                    .map_err(|e| format_err!("not a pcap file: {:?}", e))?,
+            )
+        };
+        write_capture(&ai.line_prefix, reader.as_mut(), oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use etherparse::PacketBuilder;
+    use std::io::Cursor;
+
+    const HTTP_REQUEST: &[u8] = b"GET /secret HTTP/1.1\r\nHost: example.com\r\n\r\n";
+
+    fn packets() -> Result<Vec<Vec<u8>>> {
+        let mut tcp = Vec::new();
+        PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [7, 8, 9, 10, 11, 12])
+            .ipv4([10, 0, 0, 1], [93, 184, 216, 34], 64)
+            .tcp(51234, 80, 1, 65535)
+            .write(&mut tcp, HTTP_REQUEST)?;
+        let mut udp = Vec::new();
+        PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [7, 8, 9, 10, 11, 12])
+            .ipv6([0xfe; 16], [0xfd; 16], 64)
+            .udp(5353, 53)
+            .write(&mut udp, b"\x00\x01\x07example\x03com\x00")?;
+        Ok(vec![tcp, udp])
+    }
+
+    fn adapt(capture: Vec<u8>) -> Result<String> {
+        let (a, d) = simple_adapt_info(Path::new("test.pcap"), Box::new(Cursor::new(capture)));
+        let mut r = PcapAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn legacy() -> Result<()> {
+        // microsecond timestamps, ethernet
+        let mut capture = Vec::new();
+        capture.extend(&0xa1b2c3d4u32.to_le_bytes());
+        capture.extend(&2u16.to_le_bytes());
+        capture.extend(&4u16.to_le_bytes());
+        capture.extend(&[0; 8]);
+        capture.extend(&65535u32.to_le_bytes());
+        capture.extend(&1u32.to_le_bytes());
+        for (i, packet) in packets()?.iter().enumerate() {
+            capture.extend(&(1_600_000_000 + i as u32).to_le_bytes());
+            capture.extend(&123_456u32.to_le_bytes());
+            capture.extend(&(packet.len() as u32).to_le_bytes());
+            capture.extend(&(packet.len() as u32).to_le_bytes());
+            capture.extend(packet);
+        }
+        assert_eq!(
+            adapt(capture)?,
+            "PREFIX:2020-09-13T12:26:40.123456Z TCP 10.0.0.1:51234 → 93.184.216.34:80: GET /secret HTTP/1.1
+PREFIX:2020-09-13T12:26:40.123456Z TCP 10.0.0.1:51234 → 93.184.216.34:80: Host: example.com
+PREFIX:2020-09-13T12:26:41.123456Z UDP [fefe:fefe:fefe:fefe:fefe:fefe:fefe:fefe]:5353 → [fdfd:fdfd:fdfd:fdfd:fdfd:fdfd:fdfd:fdfd]:53: example
+"
+        );
+        Ok(())
+    }
+
+    fn ng_block(typ: u32, body: &[u8]) -> Vec<u8> {
+        let mut body = body.to_vec();
+        while !body.len().is_multiple_of(4) {
+            body.push(0);
+        }
+        let len = (body.len() + 12) as u32;
+        let mut block = Vec::new();
+        block.extend(&typ.to_le_bytes());
+        block.extend(&len.to_le_bytes());
+        block.extend(&body);
+        block.extend(&len.to_le_bytes());
+        block
+    }
+
+    #[test]
+    fn pcapng() -> Result<()> {
+        let mut shb = Vec::new();
+        shb.extend(&0x1a2b3c4du32.to_le_bytes());
+        shb.extend(&1u16.to_le_bytes());
+        shb.extend(&0u16.to_le_bytes());
+        shb.extend(&(-1i64).to_le_bytes());
+        let mut capture = ng_block(0x0a0d0d0a, &shb);
+
+        // a linux cooked capture interface with the default microsecond resolution
+        let mut idb = Vec::new();
+        idb.extend(&113u16.to_le_bytes());
+        idb.extend(&0u16.to_le_bytes());
+        idb.extend(&65535u32.to_le_bytes());
+        capture.extend(ng_block(1, &idb));
+
+        let tcp = &packets()?[0];
+        let mut sll = vec![0, 0, 0, 1, 0, 6, 1, 2, 3, 4, 5, 6, 0, 0, 0x08, 0x00];
+        sll.extend(&tcp[14..]);
+        // truncated by the snap length
+        let caplen = sll.len() - 10;
+        let ts = 1_600_000_000_000_000u64;
+        let mut epb = Vec::new();
+        epb.extend(&0u32.to_le_bytes());
+        epb.extend(&((ts >> 32) as u32).to_le_bytes());
+        epb.extend(&(ts as u32).to_le_bytes());
+        epb.extend(&(caplen as u32).to_le_bytes());
+        epb.extend(&(sll.len() as u32).to_le_bytes());
+        epb.extend(&sll[..caplen]);
+        capture.extend(ng_block(6, &epb));
+        assert_eq!(
+            adapt(capture)?,
+            "PREFIX:2020-09-13T12:26:40.000000Z TCP 10.0.0.1:51234 → 93.184.216.34:80: GET /secret HTTP/1.1
+PREFIX:2020-09-13T12:26:40.000000Z TCP 10.0.0.1:51234 → 93.184.216.34:80: Host: examp
+"
+        );
+        Ok(())
+    }
+}
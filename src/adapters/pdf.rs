@@ -0,0 +1,648 @@
+//! the parts of pdf files that pdftotext doesn't output, read with lopdf
+use super::spawning::pipe_output;
+use super::xml::{text_of, xml_reader};
+use anyhow::*;
+use log::*;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use quick_xml::events::Event;
+use std::collections::HashSet;
+use std::io::{Cursor, Read, Write};
+use std::process::Command;
+
+/// annotations without text of their own: links, form fields and the popups that show another annotation's contents
+static SKIPPED_ANNOTATIONS: &[&[u8]] = &[b"Link", b"Widget", b"Popup"];
+
+/// markup on text in the page, which is covered by the quadrilaterals in QuadPoints
+static TEXT_MARKUP_ANNOTATIONS: &[&[u8]] = &[b"Highlight", b"Underline", b"StrikeOut", b"Squiggly"];
+
+/// text strings are PDFDocEncoding or UTF-16 with a byte order mark
+pub fn text_string(object: &Object) -> Option<String> {
+    let bytes = object.as_str().ok()?;
+    let decode = |bytes: &[u8]| match lopdf::decode_text_string(&Object::string_literal(bytes)) {
+        std::result::Result::Ok(s) => s,
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    if bytes.starts_with(b"\xfe\xff") || bytes.starts_with(b"\xef\xbb\xbf") {
+        return Some(decode(bytes));
+    }
+    // lopdf drops the line breaks in PDFDocEncoding, which are common in the contents of notes
+    let lines: Vec<String> = bytes
+        .split(|b| *b == b'\r' || *b == b'\n')
+        .map(decode)
+        .collect();
+    Some(lines.join("\n"))
+}
+
+fn numbers(object: &Object) -> Vec<f32> {
+    object
+        .as_array()
+        .map(|a| a.iter().filter_map(|n| n.as_float().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// the visible area of the page, which can be inherited from the page tree
+fn page_box(doc: &Document, page: &Dictionary) -> Option<[f32; 4]> {
+    let mut node = page;
+    for _ in 0..32 {
+        for key in &[b"CropBox".as_ref(), b"MediaBox"] {
+            if let std::result::Result::Ok(object) = node.get_deref(key, doc) {
+                if let [x1, y1, x2, y2] = numbers(object)[..] {
+                    return Some([x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)]);
+                }
+            }
+        }
+        node = node.get_deref(b"Parent", doc).ok()?.as_dict().ok()?;
+    }
+    None
+}
+
+/// converts a quadrilateral (x1 y1 ... x4 y4, in pdf coordinates with the origin at the bottom)
+/// to the `x y width height` of `pdftotext -x -y -W -H`, which counts from the top left of the page
+pub fn crop_area(quad: &[f32], page_box: [f32; 4]) -> [i64; 4] {
+    let xs = quad.iter().step_by(2);
+    let ys = quad.iter().skip(1).step_by(2);
+    let left = xs.clone().cloned().fold(f32::INFINITY, f32::min);
+    let right = xs.cloned().fold(f32::NEG_INFINITY, f32::max);
+    let bottom = ys.clone().cloned().fold(f32::INFINITY, f32::min);
+    let top = ys.cloned().fold(f32::NEG_INFINITY, f32::max);
+    [
+        (left - page_box[0]).floor() as i64,
+        (page_box[3] - top).floor() as i64,
+        (right - left).ceil() as i64,
+        (top - bottom).ceil() as i64,
+    ]
+}
+
+/// the text under a highlight. pdftotext includes words that overlap the area, so
+/// this is a bit more than what was highlighted if it starts or ends in the middle of a word
+fn marked_text(pdf: &[u8], page: u32, areas: &[[i64; 4]]) -> Result<String> {
+    let mut words = Vec::new();
+    for [x, y, w, h] in areas {
+        let mut cmd = Command::new("pdftotext");
+        cmd.arg("-f").arg(page.to_string());
+        cmd.arg("-l").arg(page.to_string());
+        for (flag, value) in &[("-x", x), ("-y", y), ("-W", w), ("-H", h)] {
+            cmd.arg(flag).arg(value.to_string());
+        }
+        cmd.arg("-").arg("-");
+        let mut text = String::new();
+        pipe_output(
+            "",
+            cmd,
+            &mut Cursor::new(pdf),
+            "pdftotext",
+            "Make sure you have poppler-utils installed.",
+        )?
+        .read_to_string(&mut text)?;
+        words.extend(text.split_whitespace().map(str::to_string));
+    }
+    Ok(words.join(" "))
+}
+
+fn write_annotation(
+    doc: &Document,
+    pdf: &[u8],
+    page_number: u32,
+    page_box: Option<[f32; 4]>,
+    annotation: &Dictionary,
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let subtype = annotation
+        .get(b"Subtype")
+        .and_then(Object::as_name)
+        .unwrap_or_default();
+    if SKIPPED_ANNOTATIONS.contains(&subtype) {
+        return Ok(());
+    }
+    // acrobat calls them sticky notes
+    let kind = match subtype {
+        b"Text" => "Note".to_string(),
+        other => String::from_utf8_lossy(other).into_owned(),
+    };
+    let get_text = |key: &[u8]| annotation.get_deref(key, doc).ok().and_then(text_string);
+    if let (true, Some(page_box)) = (TEXT_MARKUP_ANNOTATIONS.contains(&subtype), page_box) {
+        let quads = annotation
+            .get_deref(b"QuadPoints", doc)
+            .map(numbers)
+            .unwrap_or_default();
+        let areas: Vec<[i64; 4]> = quads
+            .chunks_exact(8)
+            .map(|quad| crop_area(quad, page_box))
+            .collect();
+        match marked_text(pdf, page_number, &areas) {
+            std::result::Result::Ok(text) if !text.is_empty() => writeln!(
+                oup,
+                "{}Page {}: {}: \"{}\"",
+                line_prefix, page_number, kind, text
+            )?,
+            std::result::Result::Ok(_) => {}
+            Err(e) => debug!("could not get the text of a {}: {}", kind, e),
+        }
+    }
+    let contents = get_text(b"Contents").unwrap_or_default();
+    let author = match get_text(b"T") {
+        Some(author) if !author.trim().is_empty() => format!(" ({})", author.trim()),
+        _ => String::new(),
+    };
+    for line in contents.lines() {
+        let line = line.trim();
+        if !line.is_empty() {
+            writeln!(
+                oup,
+                "{}Page {}: {}{}: {}",
+                line_prefix, page_number, kind, author, line
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// notes, highlights (with the highlighted text), free text and the other comments on each page
+pub fn write_annotations(
+    doc: &Document,
+    pdf: &[u8],
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for (page_number, page_id) in doc.get_pages() {
+        let page = doc.get_dictionary(page_id)?;
+        let page_box = page_box(doc, page);
+        for annotation in doc.get_page_annotations(page_id)? {
+            write_annotation(
+                doc,
+                pdf,
+                page_number,
+                page_box,
+                annotation,
+                line_prefix,
+                oup,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// the value of a form field: text, the export value of a checked box, or the selected options of a list
+fn field_value(doc: &Document, value: &Object) -> Option<String> {
+    let value = doc.dereference(value).ok()?.1;
+    match value {
+        Object::Name(name) if name == b"Off" => None,
+        Object::Name(name) => Some(String::from_utf8_lossy(name).into_owned()),
+        Object::Array(options) => {
+            let options: Vec<String> = options.iter().filter_map(|o| field_value(doc, o)).collect();
+            Some(options.join(", "))
+        }
+        Object::Stream(stream) => stream
+            .get_plain_content()
+            .ok()
+            .map(|text| String::from_utf8_lossy(&text).into_owned()),
+        other => text_string(other),
+    }
+    .filter(|v| !v.trim().is_empty())
+}
+
+/// fields are a tree, the full name of a field is the names of its ancestors joined with `.`.
+/// the leaves without a name are the widgets that show the field on a page
+fn write_field(
+    doc: &Document,
+    field: &Object,
+    parent_name: &str,
+    visited: &mut HashSet<ObjectId>,
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<bool> {
+    if let Object::Reference(id) = field {
+        if !visited.insert(*id) {
+            return Ok(false);
+        }
+    }
+    let field = match doc.dereference(field)?.1.as_dict() {
+        std::result::Result::Ok(field) => field,
+        Err(_) => return Ok(false),
+    };
+    let name = match field.get_deref(b"T", doc).ok().and_then(text_string) {
+        Some(name) if parent_name.is_empty() => name,
+        Some(name) => format!("{}.{}", parent_name, name),
+        None => return Ok(false),
+    };
+    let mut written = false;
+    let value = field
+        .get(b"V")
+        .ok()
+        .and_then(|value| field_value(doc, value));
+    if let Some(value) = value {
+        // the alternate name is the label shown to the user, the names are often just `f1_01`
+        let label = match field.get_deref(b"TU", doc).ok().and_then(text_string) {
+            Some(label) if !label.trim().is_empty() => format!(" ({})", label.trim()),
+            _ => String::new(),
+        };
+        for line in value.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            writeln!(oup, "{}Form: {}{}: {}", line_prefix, name, label, line)?;
+        }
+        written = true;
+    }
+    if let std::result::Result::Ok(kids) = field.get_deref(b"Kids", doc).and_then(Object::as_array)
+    {
+        for kid in kids {
+            written |= write_field(doc, kid, &name, visited, line_prefix, oup)?;
+        }
+    }
+    Ok(written)
+}
+
+/// the filled in values of XFA forms are in the `datasets` packet, as xml below `xfa:data`
+fn write_xfa_data(xdp: &[u8], line_prefix: &str, oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(xdp);
+    let mut buf = Vec::new();
+    // the elements below `xfa:data`, with their text and whether they have child elements
+    let mut path: Vec<(String, String, bool)> = Vec::new();
+    let mut in_data = false;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                if in_data {
+                    if let Some(parent) = path.last_mut() {
+                        parent.2 = true;
+                    }
+                    path.push((name, String::new(), false));
+                } else if name == "data" {
+                    in_data = true;
+                }
+            }
+            Event::Text(t) => {
+                if let Some((_, text, _)) = path.last_mut() {
+                    text.push_str(&text_of(&t));
+                }
+            }
+            Event::CData(t) => {
+                if let Some((_, text, _)) = path.last_mut() {
+                    text.push_str(&String::from_utf8_lossy(&t));
+                }
+            }
+            Event::End(_) if in_data => match path.pop() {
+                Some((name, text, false)) => {
+                    let mut names: Vec<&str> = path.iter().map(|(n, _, _)| n.as_str()).collect();
+                    names.push(&name);
+                    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                        writeln!(oup, "{}Form: {}: {}", line_prefix, names.join("."), line)?;
+                    }
+                }
+                Some(_) => {}
+                None => in_data = false,
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// the names and values of the fields of AcroForm forms, or of XFA forms if there are no AcroForm fields
+pub fn write_form_fields(doc: &Document, line_prefix: &str, oup: &mut dyn Write) -> Result<()> {
+    let form = match doc
+        .catalog()?
+        .get_deref(b"AcroForm", doc)
+        .and_then(Object::as_dict)
+    {
+        std::result::Result::Ok(form) => form,
+        Err(_) => return Ok(()),
+    };
+    let mut visited = HashSet::new();
+    let mut written = false;
+    if let std::result::Result::Ok(fields) =
+        form.get_deref(b"Fields", doc).and_then(Object::as_array)
+    {
+        for field in fields {
+            written |= write_field(doc, field, "", &mut visited, line_prefix, oup)?;
+        }
+    }
+    if written {
+        return Ok(());
+    }
+    // the xdp document is either a single stream or split into packets: [name stream name stream ...]
+    let xdp = match form.get_deref(b"XFA", doc) {
+        std::result::Result::Ok(Object::Stream(stream)) => stream.get_plain_content()?,
+        std::result::Result::Ok(Object::Array(packets)) => {
+            let mut xdp = Vec::new();
+            for packet in packets {
+                if let std::result::Result::Ok(stream) =
+                    doc.dereference(packet).and_then(|(_, p)| p.as_stream())
+                {
+                    xdp.extend(stream.get_plain_content()?);
+                }
+            }
+            xdp
+        }
+        _ => return Ok(()),
+    };
+    write_xfa_data(&xdp, line_prefix, oup)
+}
+
+/// a file specification: the name of the file and the stream with its content
+fn embedded_file(doc: &Document, filespec: &Object) -> Option<(String, Vec<u8>)> {
+    let filespec = doc.dereference(filespec).ok()?.1.as_dict().ok()?;
+    let name = [b"UF".as_ref(), b"F"]
+        .iter()
+        .find_map(|key| filespec.get_deref(key, doc).ok().and_then(text_string))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "attachment".to_string());
+    let streams = filespec.get_deref(b"EF", doc).ok()?.as_dict().ok()?;
+    let stream = [b"UF".as_ref(), b"F"]
+        .iter()
+        .find_map(|key| streams.get_deref(key, doc).ok()?.as_stream().ok())?;
+    match stream.get_plain_content() {
+        std::result::Result::Ok(content) => Some((name, content)),
+        Err(e) => {
+            debug!("could not decode attachment {}: {}", name, e);
+            None
+        }
+    }
+}
+
+/// name trees are sorted arrays of `[key value key value ...]` in the leaves below /Kids
+fn name_tree_values<'a>(
+    doc: &'a Document,
+    node: &'a Dictionary,
+    depth: usize,
+    values: &mut Vec<&'a Object>,
+) {
+    if depth > 32 {
+        return;
+    }
+    if let std::result::Result::Ok(names) = node.get_deref(b"Names", doc).and_then(Object::as_array)
+    {
+        values.extend(names.iter().skip(1).step_by(2));
+    }
+    if let std::result::Result::Ok(kids) = node.get_deref(b"Kids", doc).and_then(Object::as_array) {
+        for kid in kids {
+            if let std::result::Result::Ok((_, Object::Dictionary(kid))) = doc.dereference(kid) {
+                name_tree_values(doc, kid, depth + 1, values);
+            }
+        }
+    }
+}
+
+/// the files attached to the document, and to file attachment annotations on the pages
+pub fn attachments(doc: &Document) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut filespecs = Vec::new();
+    if let std::result::Result::Ok(tree) = doc
+        .catalog()?
+        .get_deref(b"Names", doc)
+        .and_then(Object::as_dict)
+        .and_then(|names| names.get_deref(b"EmbeddedFiles", doc))
+        .and_then(Object::as_dict)
+    {
+        name_tree_values(doc, tree, 0, &mut filespecs);
+    }
+    for (_, page_id) in doc.get_pages() {
+        for annotation in doc.get_page_annotations(page_id)? {
+            if annotation.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"FileAttachment")
+            {
+                if let std::result::Result::Ok(filespec) = annotation.get(b"FS") {
+                    filespecs.push(filespec);
+                }
+            }
+        }
+    }
+    Ok(filespecs
+        .into_iter()
+        .filter_map(|filespec| embedded_file(doc, filespec))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream, StringFormat};
+
+    fn make_pdf() -> Result<Vec<u8>> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let note = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Rect" => vec![10.into(), 10.into(), 30.into(), 30.into()],
+            "Contents" => Object::string_literal("Please check\nthe totals"),
+            "T" => Object::String(b"\xfe\xff\x00J\x00\xf6\x00r\x00g".to_vec(), StringFormat::Hexadecimal),
+        });
+        let popup = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Popup",
+            "Parent" => note,
+            "Contents" => Object::string_literal("Please check\nthe totals"),
+        });
+        let free_text = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "FreeText",
+            "Rect" => vec![50.into(), 700.into(), 200.into(), 720.into()],
+            "Contents" => Object::string_literal("DRAFT"),
+        };
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Annots" => vec![note.into(), popup.into(), free_text.into()],
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        let mut data = Vec::new();
+        doc.save_to(&mut data)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn annotations() -> Result<()> {
+        let pdf = make_pdf()?;
+        let doc = Document::load_mem(&pdf)?;
+        let mut o = Vec::new();
+        write_annotations(&doc, &pdf, "PREFIX:", &mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Page 1: Note (Jörg): Please check
+PREFIX:Page 1: Note (Jörg): the totals
+PREFIX:Page 1: FreeText: DRAFT
+"
+        );
+        Ok(())
+    }
+
+    fn form_document(form: Dictionary, objects: Vec<(ObjectId, Object)>) -> Document {
+        let mut doc = Document::with_version("1.5");
+        doc.objects.extend(objects);
+        let form_id = doc.add_object(form);
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "AcroForm" => form_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        doc
+    }
+
+    #[test]
+    fn acroform() -> Result<()> {
+        let widget = dictionary! { "Type" => "Annot", "Subtype" => "Widget" };
+        let street = dictionary! {
+            "T" => Object::string_literal("street"),
+            "V" => Object::string_literal("Hauptstr. 1"),
+            "Kids" => vec![widget.clone().into()],
+        };
+        let address_id = (10, 0);
+        let address = dictionary! {
+            "T" => Object::string_literal("address"),
+            // a broken file where the field is its own child
+            "Kids" => vec![street.into(), address_id.into()],
+        };
+        let fields: Vec<Object> = vec![
+            dictionary! {
+                "FT" => "Tx",
+                "T" => Object::string_literal("f1_01"),
+                "TU" => Object::string_literal("Your name"),
+                "V" => Object::String(b"\xfe\xff\x00J\x00\xf6\x00r\x00g".to_vec(), StringFormat::Hexadecimal),
+            }
+            .into(),
+            address_id.into(),
+            dictionary! { "FT" => "Btn", "T" => Object::string_literal("married"), "V" => "Yes" }.into(),
+            dictionary! { "FT" => "Btn", "T" => Object::string_literal("children"), "V" => "Off" }.into(),
+            dictionary! {
+                "FT" => "Ch",
+                "T" => Object::string_literal("languages"),
+                "V" => vec![Object::string_literal("German"), Object::string_literal("English")],
+            }
+            .into(),
+            dictionary! { "FT" => "Tx", "T" => Object::string_literal("empty"), "V" => Object::string_literal("") }.into(),
+        ];
+        let doc = form_document(
+            dictionary! { "Fields" => fields },
+            vec![(address_id, address.into())],
+        );
+        let mut o = Vec::new();
+        write_form_fields(&doc, "PREFIX:", &mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Form: f1_01 (Your name): Jörg
+PREFIX:Form: address.street: Hauptstr. 1
+PREFIX:Form: married: Yes
+PREFIX:Form: languages: German, English
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn xfa() -> Result<()> {
+        let datasets = br#"<xfa:datasets xmlns:xfa="http://www.xfa.org/schema/xfa-data/1.0/">
+<xfa:data><form1><Page1><Name>Jane Doe</Name><Amount>1234.50</Amount><Notes/></Page1></form1></xfa:data>
+</xfa:datasets>"#;
+        let template_id = (10, 0);
+        let datasets_id = (11, 0);
+        let doc = form_document(
+            dictionary! {
+                "Fields" => Vec::<Object>::new(),
+                "XFA" => vec![
+                    Object::string_literal("template"),
+                    template_id.into(),
+                    Object::string_literal("datasets"),
+                    datasets_id.into(),
+                ],
+            },
+            vec![
+                (
+                    template_id,
+                    Stream::new(
+                        dictionary! {},
+                        b"<template><subform name=\"form1\"/></template>".to_vec(),
+                    )
+                    .into(),
+                ),
+                (
+                    datasets_id,
+                    Stream::new(dictionary! {}, datasets.to_vec()).into(),
+                ),
+            ],
+        );
+        let mut o = Vec::new();
+        write_form_fields(&doc, "PREFIX:", &mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Form: form1.Page1.Name: Jane Doe
+PREFIX:Form: form1.Page1.Amount: 1234.50
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn embedded_files() -> Result<()> {
+        let mut doc = Document::with_version("1.7");
+        let mut invoice = Stream::new(
+            dictionary! { "Type" => "EmbeddedFile" },
+            b"<Invoice/>".to_vec(),
+        );
+        invoice.compress()?;
+        let invoice = doc.add_object(invoice);
+        let filespec = doc.add_object(dictionary! {
+            "Type" => "Filespec",
+            "F" => Object::string_literal("factur-x.xml"),
+            "UF" => Object::string_literal("factur-x.xml"),
+            "EF" => dictionary! { "F" => invoice, "UF" => invoice },
+        });
+        let leaf = doc.add_object(dictionary! {
+            "Names" => vec![Object::string_literal("factur-x.xml"), filespec.into()],
+        });
+        let note = doc.add_object(Stream::new(dictionary! {}, b"see attached".to_vec()));
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Annots" => vec![dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "FileAttachment",
+                "FS" => dictionary! { "F" => Object::string_literal("note.txt"), "EF" => dictionary! { "F" => note } },
+            }
+            .into()],
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(
+                dictionary! { "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1 },
+            ),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Names" => dictionary! { "EmbeddedFiles" => dictionary! { "Kids" => vec![leaf.into()] } },
+        });
+        doc.trailer.set("Root", catalog_id);
+        assert_eq!(
+            attachments(&doc)?,
+            vec![
+                ("factur-x.xml".to_string(), b"<Invoice/>".to_vec()),
+                ("note.txt".to_string(), b"see attached".to_vec()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn highlight_area() {
+        let quad = [72.0, 720.5, 300.0, 720.5, 72.0, 708.0, 300.0, 708.0];
+        assert_eq!(
+            crop_area(&quad, [0.0, 0.0, 612.0, 792.0]),
+            [72, 71, 228, 13]
+        );
+    }
+}
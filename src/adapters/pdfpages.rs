@@ -23,7 +23,9 @@ lazy_static! {
         slow_matchers: Some(vec![SlowMatcher::MimeType(
             "application/pdf".to_owned()
         )]),
-        disabled_by_default: true
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![]
     };
 }
 #[derive(Default)]
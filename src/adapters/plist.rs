@@ -0,0 +1,304 @@
+use super::serialized::{bytes_to_json, float_to_json, key_to_string};
+use super::*;
+use ::plist::{Dictionary, Value};
+use anyhow::*;
+use lazy_static::lazy_static;
+use serde_json::{Map, Value as Json};
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["plist", "bplist"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "plist".to_owned(),
+        version: 1,
+        description: "Renders Apple property lists (binary, XML and ASCII) as indented JSON. NSKeyedArchiver object graphs are resolved to plain values".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct PlistAdapter;
+
+impl PlistAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(PlistAdapter))
+    }
+}
+impl GetMetadata for PlistAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// NSDate counts seconds since 2001-01-01
+const APPLE_EPOCH: i64 = 978_307_200;
+
+/// archives can reference the same objects many times, stop before it explodes
+const MAX_DEPTH: usize = 64;
+
+fn is_keyed_archive(dict: &Dictionary) -> bool {
+    dict.get("$archiver").and_then(Value::as_string) == Some("NSKeyedArchiver")
+        && dict.get("$objects").and_then(Value::as_array).is_some()
+        && dict.get("$top").and_then(Value::as_dictionary).is_some()
+}
+
+struct Converter<'a> {
+    /// the `$objects` table when converting an NSKeyedArchiver payload
+    objects: Option<&'a [Value]>,
+}
+
+impl<'a> Converter<'a> {
+    fn convert(&self, value: &Value, depth: usize) -> Json {
+        if depth > MAX_DEPTH {
+            return Json::String("<too deeply nested>".to_owned());
+        }
+        match value {
+            Value::Array(a) => Json::Array(a.iter().map(|v| self.convert(v, depth + 1)).collect()),
+            Value::Dictionary(d) => self.convert_dict(d, depth),
+            Value::Boolean(b) => Json::Bool(*b),
+            Value::Data(d) => data_to_json(d),
+            Value::Date(d) => Json::String(d.to_xml_format()),
+            Value::Real(f) => float_to_json(*f),
+            Value::Integer(i) => match (i.as_unsigned(), i.as_signed()) {
+                (Some(u), _) => Json::from(u),
+                (None, Some(i)) => Json::from(i),
+                (None, None) => Json::Null,
+            },
+            Value::String(s) => Json::String(s.clone()),
+            Value::Uid(uid) => match self
+                .objects
+                .and_then(|objects| objects.get(uid.get() as usize))
+            {
+                Some(Value::String(s)) if s == "$null" => Json::Null,
+                Some(object) => self.convert(object, depth + 1),
+                None => Json::from(uid.get()),
+            },
+            _ => Json::Null,
+        }
+    }
+
+    fn class_name(&self, dict: &Dictionary) -> Option<&'a str> {
+        let uid = dict.get("$class")?.as_uid()?;
+        self.objects?
+            .get(uid.get() as usize)?
+            .as_dictionary()?
+            .get("$classname")?
+            .as_string()
+    }
+
+    fn convert_dict(&self, dict: &Dictionary, depth: usize) -> Json {
+        if self.objects.is_none() && is_keyed_archive(dict) {
+            return convert_keyed_archive(dict, depth);
+        }
+        let class_name = match self.class_name(dict) {
+            Some(class_name) => class_name,
+            None => {
+                return Json::Object(
+                    dict.iter()
+                        .map(|(k, v)| (k.clone(), self.convert(v, depth + 1)))
+                        .collect::<Map<_, _>>(),
+                )
+            }
+        };
+        let field = |name: &str| dict.get(name).map(|v| self.convert(v, depth + 1));
+        // the foundation classes are unwrapped, everything else keeps its fields and class name
+        match (dict.get("NS.keys"), dict.get("NS.objects")) {
+            (Some(Value::Array(keys)), Some(Value::Array(objects))) => {
+                return Json::Object(
+                    keys.iter()
+                        .zip(objects)
+                        .map(|(k, v)| {
+                            (
+                                key_to_string(self.convert(k, depth + 1)),
+                                self.convert(v, depth + 1),
+                            )
+                        })
+                        .collect::<Map<_, _>>(),
+                )
+            }
+            (None, Some(objects @ Value::Array(_))) => return self.convert(objects, depth),
+            _ => {}
+        }
+        if let Some(time) = dict.get("NS.time").and_then(Value::as_real) {
+            let secs = time.floor();
+            let nanos = ((time - secs) * 1e9) as u32;
+            if let Some(date) = chrono::DateTime::from_timestamp(APPLE_EPOCH + secs as i64, nanos) {
+                return Json::String(date.to_rfc3339());
+            }
+        }
+        for name in &["NS.string", "NS.bytes", "NS.data", "NS.relative"] {
+            if let Some(value) = field(name) {
+                return value;
+            }
+        }
+        let mut object = Map::new();
+        object.insert("$class".to_owned(), Json::String(class_name.to_owned()));
+        for (k, v) in dict.iter().filter(|(k, _)| *k != "$class") {
+            object.insert(k.clone(), self.convert(v, depth + 1));
+        }
+        Json::Object(object)
+    }
+}
+
+/// replaces the `$top` references of an archive with the objects they point to
+fn convert_keyed_archive(archive: &Dictionary, depth: usize) -> Json {
+    let objects = archive
+        .get("$objects")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice);
+    let converter = Converter { objects };
+    let top = match archive.get("$top").and_then(Value::as_dictionary) {
+        Some(top) => top,
+        None => return Json::Null,
+    };
+    match top.get("root") {
+        Some(root) if top.len() == 1 => converter.convert(root, depth + 1),
+        _ => Json::Object(
+            top.iter()
+                .map(|(k, v)| (k.clone(), converter.convert(v, depth + 1)))
+                .collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+/// plists are often nested as data blobs (e.g. archived objects in preferences)
+fn data_to_json(data: &[u8]) -> Json {
+    if data.starts_with(b"bplist00") {
+        if let Result::Ok(value) = Value::from_reader(Cursor::new(data)) {
+            return plist_to_json(&value);
+        }
+    }
+    bytes_to_json(data.to_vec())
+}
+
+pub fn plist_to_json(value: &Value) -> Json {
+    Converter { objects: None }.convert(value, 0)
+}
+
+impl WritingFileAdapterTrait for PlistAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let value = Value::from_reader(Cursor::new(data))?;
+        for line in serde_json::to_string_pretty(&plist_to_json(&value))?.lines() {
+            writeln!(oup, "{}{}", ai.line_prefix, line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use ::plist::Uid;
+
+    fn adapt(value: &Value) -> Result<String> {
+        let mut data = Vec::new();
+        value.to_writer_binary(&mut data)?;
+        let (a, d) = simple_adapt_info(Path::new("test.plist"), Box::new(Cursor::new(data)));
+        let mut r = PlistAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    fn dict(entries: Vec<(&str, Value)>) -> Value {
+        Value::Dictionary(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), v))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn binary() -> Result<()> {
+        let value = dict(vec![
+            ("CFBundleName", Value::from("Test")),
+            ("Count", Value::from(3)),
+            (
+                "Items",
+                Value::Array(vec![Value::Boolean(true), Value::Data(b"hello".to_vec())]),
+            ),
+        ]);
+        assert_eq!(
+            adapt(&value)?,
+            "PREFIX:{
+PREFIX:  \"CFBundleName\": \"Test\",
+PREFIX:  \"Count\": 3,
+PREFIX:  \"Items\": [
+PREFIX:    true,
+PREFIX:    \"hello\"
+PREFIX:  ]
+PREFIX:}
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keyed_archive() -> Result<()> {
+        let uid = |i| Value::Uid(Uid::new(i));
+        let class = |name: &str| {
+            dict(vec![
+                ("$classname", Value::from(name)),
+                (
+                    "$classes",
+                    Value::Array(vec![Value::from(name), Value::from("NSObject")]),
+                ),
+            ])
+        };
+        let archive = dict(vec![
+            ("$version", Value::from(100000)),
+            ("$archiver", Value::from("NSKeyedArchiver")),
+            ("$top", dict(vec![("root", uid(1))])),
+            (
+                "$objects",
+                Value::Array(vec![
+                    Value::from("$null"),
+                    dict(vec![
+                        ("NS.keys", Value::Array(vec![uid(2), uid(3), uid(4)])),
+                        ("NS.objects", Value::Array(vec![uid(5), uid(6), uid(0)])),
+                        ("$class", uid(9)),
+                    ]),
+                    Value::from("title"),
+                    Value::from("created"),
+                    Value::from("missing"),
+                    dict(vec![
+                        ("NS.string", Value::from("Meeting notes")),
+                        ("$class", uid(8)),
+                    ]),
+                    dict(vec![("NS.time", Value::Real(0.5)), ("$class", uid(7))]),
+                    class("NSDate"),
+                    class("NSMutableString"),
+                    class("NSDictionary"),
+                ]),
+            ),
+        ]);
+        assert_eq!(
+            adapt(&archive)?,
+            "PREFIX:{
+PREFIX:  \"title\": \"Meeting notes\",
+PREFIX:  \"created\": \"2001-01-01T00:00:00.500+00:00\",
+PREFIX:  \"missing\": null
+PREFIX:}
+"
+        );
+        Ok(())
+    }
+}
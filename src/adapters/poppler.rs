@@ -1,29 +1,351 @@
+use super::spawning::pipe_output;
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor};
+use std::process::Command;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
 
+static EXTENSIONS: &[&str] = &["pdf"];
 
+/// if pdftotext outputs fewer (non-whitespace) characters than this, the pdf is assumed to be scanned
+const OCR_MIN_TEXT_LEN: usize = 10;
 
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "poppler".to_owned(),
+        version: 3,
+        description: "Uses pdftotext (from poppler-utils) to extract plain text from PDF files, as `Page n: text`. Comments (notes, highlights with the highlighted text, free text) are added as `Page n: Note: text`. With --rga-pdf-forms, the values of form fields are added too. Recurses into embedded files. With --rga-pdf-ocr, pages of PDFs without a text layer are run through tesseract".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/pdf".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![AdapterOption::PdfOcr, AdapterOption::PdfForms]
+    };
+}
 
+#[derive(Default, Clone)]
+pub struct PopplerAdapter;
 
-/*
-static EXTENSIONS: &[&str] = &["pdf"];
+impl PopplerAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(PopplerAdapter))
+    }
+}
+impl GetMetadata for PopplerAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
 
+fn pdftotext(inp: &mut dyn Read) -> Result<ReadBox> {
+    let mut cmd = Command::new("pdftotext");
+    cmd.arg("-").arg("-");
+    pipe_output(
+        "",
+        cmd,
+        inp,
+        "pdftotext",
+        "Make sure you have poppler-utils installed.",
+    )
+}
 
-    postproc: "add_lines"
-    fn postproc(line_prefix: &str, inp: &mut dyn Read, oup: &mut dyn Write) -> Result<()> {
-        // prepend Page X to each line
-        let mut page = 1;
-        for line in BufReader::new(inp).lines() {
-            let mut line = line?;
-            if line.contains('\x0c') {
-                // page break
-                line = line.replace('\x0c', "");
+/// pdftotext ends every page with a form feed. the lines are prefixed with their page like the
+/// ocr output and the annotations, so that matches show (and the cache records) where they are
+fn write_pages(line_prefix: &str, text: impl Read, oup: &mut dyn Write) -> Result<()> {
+    let mut page = 1;
+    for line in BufReader::new(text).split(b'\n') {
+        let line = line?;
+        for (i, part) in line.split(|c| *c == b'\x0c').enumerate() {
+            if i > 0 {
                 page += 1;
-                if line.is_empty() {
-                    continue;
+            }
+            // blank lines are left out, like the form feed after the last page (which is no line of the next one)
+            if !part.is_empty() {
+                write!(oup, "{}Page {}: ", line_prefix, page)?;
+                oup.write_all(part)?;
+                oup.write_all(b"\n")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn has_text_layer(text: &[u8]) -> bool {
+    text.iter().filter(|c| !c.is_ascii_whitespace()).count() >= OCR_MIN_TEXT_LEN
+}
+
+/// render every page to a png and run them through the ocr adapter
+fn ocr_pages(pdf: &[u8], ai: &AdaptInfo, oup: &mut dyn Write) -> Result<()> {
+    let out_dir = tempfile::Builder::new().prefix("rga-pdfocr-").tempdir()?;
+    let out_prefix = out_dir.path().join("page");
+    debug!("rendering pdf pages to {}", out_dir.path().display());
+    let exe_name = "pdftoppm";
+    let mut cmd = Command::new(exe_name);
+    cmd.arg("-r")
+        .arg("300")
+        .arg("-png")
+        .arg("-")
+        .arg(&out_prefix);
+    let mut render = pipe_output(
+        "",
+        cmd,
+        &mut Cursor::new(pdf),
+        exe_name,
+        "Make sure you have poppler-utils installed.",
+    )?;
+    std::io::copy(&mut render, &mut std::io::sink())?;
+
+    // pdftoppm zero-pads the page numbers, so lexical order is page order
+    let mut pages = glob::glob(
+        out_dir
+            .path()
+            .join("page*.png")
+            .to_str()
+            .expect("temp path has invalid encoding"),
+    )?
+    .collect::<std::result::Result<Vec<_>, _>>()?;
+    pages.sort();
+    let ocr = tesseract::TesseractAdapter::new();
+    let detection_reason = SlowMatcher::Fast(FastMatcher::FileExtension("png".to_owned()));
+    for (i, page) in pages.iter().enumerate() {
+        let mut text = ocr
+            .adapt(
+                AdaptInfo {
+                    filepath_hint: PathBuf::from(format!("Page {}.png", i + 1)),
+                    is_real_file: false,
+                    archive_recursion_depth: ai.archive_recursion_depth + 1,
+                    inp: Box::new(File::open(page)?),
+                    line_prefix: ai.line_prefix.clone(),
+                    config: ai.config.clone(),
+                },
+                &detection_reason,
+            )
+            .with_context(|| format!("running ocr on page {}", i + 1))?;
+        let mut buf = Vec::new();
+        text.read_to_end(&mut buf)?;
+        for line in String::from_utf8_lossy(&buf).lines() {
+            writeln!(oup, "{}Page {}: {}", ai.line_prefix, i + 1, line)?;
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for PopplerAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // we need the pdf again for the annotations and attachments (and for ocr if there is no text layer), so keep it in memory
+        let mut pdf = Vec::new();
+        ai.inp.read_to_end(&mut pdf)?;
+        if !ai.config.args.pdf_ocr {
+            write_pages(&ai.line_prefix, pdftotext(&mut Cursor::new(&pdf))?, oup)?;
+        } else {
+            let mut text = Vec::new();
+            pdftotext(&mut Cursor::new(&pdf))?.read_to_end(&mut text)?;
+            if has_text_layer(&text) {
+                write_pages(&ai.line_prefix, &text[..], oup)?;
+            } else {
+                debug!(
+                    "{}: no text layer found, running ocr",
+                    ai.filepath_hint.display()
+                );
+                ocr_pages(&pdf, &ai, oup)?;
+            }
+        }
+        // pdftotext already succeeded, so a pdf that lopdf can't read isn't an error
+        match lopdf::Document::load_mem(&pdf) {
+            std::result::Result::Ok(doc) => {
+                pdf::write_annotations(&doc, &pdf, &ai.line_prefix, oup)?;
+                if ai.config.args.pdf_forms {
+                    pdf::write_form_fields(&doc, &ai.line_prefix, oup)?;
+                }
+                // e.g. the xml invoice in ZUGFeRD / Factur-X pdfs
+                for (name, data) in pdf::attachments(&doc)? {
+                    debug!(
+                        "{}|{}: {}",
+                        ai.filepath_hint.display(),
+                        name,
+                        crate::print_bytes(data.len() as f64)
+                    );
+                    let mut inner = rga_preproc(AdaptInfo {
+                        line_prefix: format!("{}{}: ", ai.line_prefix, name),
+                        filepath_hint: PathBuf::from(name),
+                        is_real_file: false,
+                        archive_recursion_depth: ai.archive_recursion_depth + 1,
+                        inp: Box::new(Cursor::new(data)),
+                        config: ai.config.clone(),
+                    })?;
+                    std::io::copy(&mut inner, oup)?;
                 }
             }
-            oup.write_all(format!("{}Page {}: {}\n", line_prefix, page, line).as_bytes())?;
+            Err(e) => debug!(
+                "{}: could not read annotations and attachments: {}",
+                ai.filepath_hint.display(),
+                e
+            ),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn poppler() -> Result<()> {
+        let adapter = PopplerAdapter::new();
+
+        let filepath = test_data_dir().join("short.pdf");
+
+        let (a, d) = simple_adapt_info(&filepath, Box::new(File::open(&filepath)?));
+        let mut r = adapter.adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Page 1: hello world
+PREFIX:Page 1: this is just a test.
+PREFIX:Page 1: 1
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pages() -> Result<()> {
+        let mut o = Vec::new();
+        write_pages(
+            "a.pdf: ",
+            &b"one\n\n\x0ctwo\n\x0c\x0cfour\n\x0c"[..],
+            &mut o,
+        )?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "a.pdf: Page 1: one\na.pdf: Page 2: two\na.pdf: Page 4: four\n"
+        );
+        Ok(())
+    }
+
+    /// a pdf with a page for each of the texts
+    fn make_pdf(texts: &[&str]) -> Result<Vec<u8>> {
+        use lopdf::content::{Content, Operation};
+        use lopdf::{dictionary, Document, Object, Stream};
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let resources_id = doc.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+        let mut pages = Vec::new();
+        for text in texts {
+            let content = Content {
+                operations: vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 24.into()]),
+                    Operation::new("Td", vec![100.into(), 600.into()]),
+                    Operation::new("Tj", vec![Object::string_literal(*text)]),
+                    Operation::new("ET", vec![]),
+                ],
+            };
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode()?));
+            pages.push(Object::from(doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+            })));
+        }
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Count" => pages.len() as i64,
+                "Kids" => pages,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        let mut data = Vec::new();
+        doc.save_to(&mut data)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn page_sections() -> Result<()> {
+        use crate::preproc::tests::{adapter_entry, preproc};
+        use std::io::Write;
+        let dir = tempfile::tempdir()?;
+        let args = crate::args::RgaConfig::default();
+        let cache = crate::preproc_cache::open_in(dir.path(), &args)?.unwrap();
+        let pdf = make_pdf(&["first page", "second page"])?;
+        let pdf_file = dir.path().join("two.pdf");
+        std::fs::write(&pdf_file, &pdf)?;
+        let mut zip = ::zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("docs/two.pdf", ::zip::write::FileOptions::default())?;
+        zip.write_all(&pdf)?;
+        let zip_file = dir.path().join("docs.zip");
+        std::fs::write(&zip_file, zip.finish()?.into_inner())?;
+
+        for (adapter, file, member) in &[
+            ("poppler", &pdf_file, ""),
+            ("zip", &zip_file, "docs/two.pdf: "),
+        ] {
+            let output = String::from_utf8(preproc(file, &cache, &args)?)?;
+            let entry = adapter_entry(adapter, file, &cache, &args)?.unwrap();
+            let sections = crate::sections::read(&entry)?;
+            let pages: Vec<_> = sections
+                .iter()
+                .map(|s| {
+                    (
+                        &s.location[..],
+                        output[s.start as usize..s.end as usize].lines().next(),
+                    )
+                })
+                .collect();
+            let locations = [format!("{}Page 1", member), format!("{}Page 2", member)];
+            let lines = [
+                format!("{}Page 1: first page", member),
+                format!("{}Page 2: second page", member),
+            ];
+            assert_eq!(
+                pages,
+                [
+                    (&locations[0][..], Some(&lines[0][..])),
+                    (&locations[1][..], Some(&lines[1][..]))
+                ]
+            );
+            assert_eq!(sections[1].end as usize, output.len());
         }
         Ok(())
     }
+
+    #[test]
+    fn text_layer() {
+        assert!(!has_text_layer(b"\x0c\n \x0c"));
+        assert!(!has_text_layer(b"  12 \x0c"));
+        assert!(has_text_layer(b"hello world\nthis is just a test.\x0c"));
+    }
 }
-*/
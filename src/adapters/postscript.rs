@@ -0,0 +1,130 @@
+use super::spawning::map_exe_error;
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::process::{Command, Stdio};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["ps", "eps", "epsf", "epsi"];
+static MIME_TYPES: &[&str] = &["application/postscript", "image/x-eps"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "postscript".to_owned(),
+        version: 1,
+        description: "Uses ghostscript (txtwrite device) to extract plain text from PostScript and EPS files. Lines are prefixed with the page number".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct PostScriptAdapter;
+
+impl PostScriptAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(PostScriptAdapter))
+    }
+}
+impl GetMetadata for PostScriptAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// ghostscript writes one file per page, zero-padded so lexical order is page order
+fn write_pages(line_prefix: &str, out_dir: &Path, oup: &mut dyn Write) -> Result<()> {
+    let mut pages = std::fs::read_dir(out_dir)?
+        .map(|e| Ok(e?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    pages.sort();
+    for (i, page) in pages.iter().enumerate() {
+        let text = std::fs::read(page)?;
+        for line in String::from_utf8_lossy(&text)
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+        {
+            // txtwrite positions the text with spaces
+            writeln!(oup, "{}Page {}: {}", line_prefix, i + 1, line.trim())?;
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for PostScriptAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let tmp_dir = tempfile::Builder::new().prefix("rga-ps-").tempdir()?;
+        // eps files with a binary (DOS) header need to be seekable, so don't use stdin
+        let input = if ai.is_real_file {
+            ai.filepath_hint.clone()
+        } else {
+            let input = tmp_dir.path().join("input.ps");
+            std::io::copy(&mut ai.inp, &mut std::fs::File::create(&input)?)?;
+            input
+        };
+        let out_dir = tmp_dir.path().join("pages");
+        std::fs::create_dir(&out_dir)?;
+        debug!("writing postscript pages to {}", out_dir.display());
+        let out = Command::new("gs")
+            .arg("-q")
+            .arg("-dSAFER")
+            .arg("-dBATCH")
+            .arg("-dNOPAUSE")
+            .arg("-sDEVICE=txtwrite")
+            .arg(format!(
+                "-sOutputFile={}",
+                out_dir.join("page%05d.txt").display()
+            ))
+            .arg(&input)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| map_exe_error(e, "gs", "Make sure you have ghostscript installed."))?;
+        if !out.status.success() {
+            return Err(format_err!(
+                "gs failed: {:?}: {}",
+                out.status,
+                String::from_utf8_lossy(&out.stderr)
+            ));
+        }
+        write_pages(&ai.line_prefix, &out_dir, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("page00002.txt"), "\n      second   page\n")?;
+        std::fs::write(
+            dir.path().join("page00001.txt"),
+            "  Invoice No. 1983\n\n  Total: $ 12.00  \n",
+        )?;
+        let mut o = Vec::new();
+        write_pages("PREFIX:", dir.path(), &mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Page 1: Invoice No. 1983\nPREFIX:Page 1: Total: $ 12.00\nPREFIX:Page 2: second   page\n"
+        );
+        Ok(())
+    }
+}
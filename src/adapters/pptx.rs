@@ -0,0 +1,298 @@
+use super::xml::{text_of, xml_reader};
+use super::xps::{attribute, for_each_element, read_part, resolve};
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use quick_xml::events::{BytesStart, Event};
+use std::io::{Cursor, Seek};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["pptx", "pptm", "ppsx", "ppsm", "potx", "potm"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "pptx".to_owned(),
+        version: 1,
+        description: "Reads the slide titles, text and speaker notes of PowerPoint presentations directly from the OOXML container, as `slide N: title: text`".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        // like docx, presentations are usually detected as plain zip files
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct PptxAdapter;
+
+impl PptxAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(PptxAdapter))
+    }
+}
+impl GetMetadata for PptxAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+static PRESENTATION_PART: &str = "ppt/presentation.xml";
+
+/// the relationships of a part, as (id, type, target part)
+fn relationships<R: Read + Seek>(
+    archive: &mut ::zip::ZipArchive<R>,
+    part: &str,
+) -> Result<Vec<(String, String, String)>> {
+    let (dir, name) = part.rsplit_once('/').unwrap_or(("", part));
+    let mut relationships = Vec::new();
+    if let Some(rels) = read_part(archive, &format!("{}/_rels/{}.rels", dir, name))? {
+        for_each_element(&rels, b"Relationship", |e| {
+            if let (Some(id), Some(target)) = (attribute(e, b"Id"), attribute(e, b"Target")) {
+                let kind = attribute(e, b"Type").unwrap_or_default();
+                relationships.push((id, kind, resolve(part, &target)));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(relationships)
+}
+
+/// `r:id`, which is not the same as the `id` of the slide
+fn relationship_id(e: &BytesStart) -> Option<String> {
+    e.attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.prefix().is_some() && a.key.local_name().as_ref() == b"id")
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+/// the slide parts, in the order of the presentation
+fn slides<R: Read + Seek>(archive: &mut ::zip::ZipArchive<R>) -> Result<Vec<String>> {
+    let targets = relationships(archive, PRESENTATION_PART)?;
+    let presentation = read_part(archive, PRESENTATION_PART)?
+        .context("not a powerpoint presentation: no ppt/presentation.xml")?;
+    let mut slides = Vec::new();
+    for_each_element(&presentation, b"sldId", |e| {
+        if let Some(id) = relationship_id(e) {
+            if let Some((_, _, target)) = targets.iter().find(|(i, _, _)| *i == id) {
+                slides.push(target.clone());
+            }
+        }
+        Ok(())
+    })?;
+    Ok(slides)
+}
+
+/// the paragraphs of a slide, with the type of the placeholder they are in (`title`, `body`, ...).
+/// text that is not in a placeholder (text boxes, tables) has no type
+fn paragraphs(xml: &[u8]) -> Result<Vec<(Option<String>, String)>> {
+    let mut reader = xml_reader(xml);
+    let mut buf = Vec::new();
+    let mut paragraphs = Vec::new();
+    let mut placeholder: Option<String> = None;
+    let mut line = String::new();
+    let mut in_text = false;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"sp" | b"graphicFrame" => placeholder = None,
+                // placeholders without a type are content (`obj`) placeholders
+                b"ph" => {
+                    placeholder = Some(attribute(&e, b"type").unwrap_or_else(|| "obj".to_string()))
+                }
+                b"t" => in_text = true,
+                b"br" => {
+                    paragraphs.push((placeholder.clone(), std::mem::take(&mut line)));
+                }
+                _ => {}
+            },
+            Event::Text(t) if in_text => line.push_str(&text_of(&t)),
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => paragraphs.push((placeholder.clone(), std::mem::take(&mut line))),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    paragraphs.retain(|(_, text)| !text.trim().is_empty());
+    Ok(paragraphs)
+}
+
+impl WritingFileAdapterTrait for PptxAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // zip needs to seek to the central directory, so read the whole file to memory
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+        for (i, slide) in slides(&mut archive)?.iter().enumerate() {
+            let prefix = format!("{}slide {}: ", ai.line_prefix, i + 1);
+            let xml = match read_part(&mut archive, slide)? {
+                Some(xml) => xml,
+                None => continue,
+            };
+            for (placeholder, text) in paragraphs(&xml)? {
+                match placeholder.as_deref() {
+                    Some("title") | Some("ctrTitle") => writeln!(oup, "{}title: {}", prefix, text)?,
+                    _ => writeln!(oup, "{}{}", prefix, text)?,
+                }
+            }
+            // the notes page also has placeholders for the slide image and the slide number
+            let notes = relationships(&mut archive, slide)?
+                .into_iter()
+                .find(|(_, kind, _)| kind.ends_with("/notesSlide"));
+            if let Some(xml) = match notes {
+                Some((_, _, notes)) => read_part(&mut archive, &notes)?,
+                None => None,
+            } {
+                for (placeholder, text) in paragraphs(&xml)? {
+                    if placeholder.as_deref() == Some("body") {
+                        writeln!(oup, "{}notes: {}", prefix, text)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    static NS: &str = r#"xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main""#;
+
+    fn shape(placeholder: Option<&str>, paragraphs: &[&str]) -> String {
+        let ph = match placeholder {
+            Some(kind) => format!(r#"<p:ph type="{}"/>"#, kind),
+            None => String::new(),
+        };
+        let paragraphs: String = paragraphs
+            .iter()
+            .map(|p| format!("<a:p><a:r><a:t>{}</a:t></a:r></a:p>", p))
+            .collect();
+        format!(
+            "<p:sp><p:nvSpPr><p:cNvPr id=\"2\" name=\"x\"/><p:cNvSpPr/><p:nvPr>{}</p:nvPr></p:nvSpPr><p:txBody><a:bodyPr/>{}</p:txBody></p:sp>",
+            ph, paragraphs
+        )
+    }
+
+    fn slide(root: &str, shapes: &[String]) -> String {
+        format!(
+            "<p:{} {}><p:cSld><p:spTree>{}</p:spTree></p:cSld></p:{}>",
+            root,
+            NS,
+            shapes.concat(),
+            root
+        )
+    }
+
+    fn rels(relationships: &[(&str, &str, &str)]) -> String {
+        let relationships: String = relationships
+            .iter()
+            .map(|(id, kind, target)| {
+                format!(
+                    r#"<Relationship Id="{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/{}" Target="{}"/>"#,
+                    id, kind, target
+                )
+            })
+            .collect();
+        format!(
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">{}</Relationships>"#,
+            relationships
+        )
+    }
+
+    #[test]
+    fn pptx() -> Result<()> {
+        let parts = vec![
+            (
+                "ppt/presentation.xml".to_string(),
+                format!(
+                    r#"<p:presentation {}><p:sldIdLst><p:sldId id="256" r:id="rId3"/><p:sldId id="257" r:id="rId2"/></p:sldIdLst></p:presentation>"#,
+                    NS
+                ),
+            ),
+            (
+                "ppt/_rels/presentation.xml.rels".to_string(),
+                rels(&[
+                    ("rId2", "slide", "slides/slide2.xml"),
+                    ("rId3", "slide", "slides/slide1.xml"),
+                ]),
+            ),
+            (
+                "ppt/slides/slide1.xml".to_string(),
+                slide(
+                    "sld",
+                    &[
+                        shape(Some("ctrTitle"), &["Quarterly results"]),
+                        shape(Some("subTitle"), &["Q3 &amp; Q4"]),
+                    ],
+                ),
+            ),
+            (
+                "ppt/slides/_rels/slide1.xml.rels".to_string(),
+                rels(&[
+                    ("rId1", "slideLayout", "../slideLayouts/slideLayout1.xml"),
+                    ("rId2", "notesSlide", "../notesSlides/notesSlide1.xml"),
+                ]),
+            ),
+            (
+                "ppt/notesSlides/notesSlide1.xml".to_string(),
+                slide(
+                    "notes",
+                    &[
+                        shape(Some("sldImg"), &[]),
+                        shape(Some("body"), &["Mention the new office", "Thank the team"]),
+                        shape(Some("sldNum"), &["1"]),
+                    ],
+                ),
+            ),
+            (
+                "ppt/slides/slide2.xml".to_string(),
+                slide(
+                    "sld",
+                    &[
+                        shape(Some("title"), &["Revenue"]),
+                        shape(None, &["up 10%", ""]),
+                    ],
+                ),
+            ),
+        ];
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        for (name, content) in parts {
+            zip.start_file(name, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        let data = zip.finish()?.into_inner();
+
+        let (a, d) = simple_adapt_info(Path::new("results.pptx"), Box::new(Cursor::new(data)));
+        let mut r = PptxAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:slide 1: title: Quarterly results
+PREFIX:slide 1: Q3 & Q4
+PREFIX:slide 1: notes: Mention the new office
+PREFIX:slide 1: notes: Thank the team
+PREFIX:slide 2: title: Revenue
+PREFIX:slide 2: up 10%
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+use std::convert::TryInto;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["pb", "binpb", "protobuf"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "protobuf".to_owned(),
+        version: 1,
+        description: "Decodes (length-delimited) protobuf messages. Without a schema, field numbers and values are dumped. With --rga-proto-descriptor, messages are output as JSON with field names".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![AdapterOption::ProtoDescriptor, AdapterOption::ProtoMessage]
+    };
+}
+#[derive(Default, Clone)]
+pub struct ProtobufAdapter;
+
+impl ProtobufAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(ProtobufAdapter))
+    }
+}
+impl GetMetadata for ProtobufAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+pub fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&b, rest) = data.split_first()?;
+        *data = rest;
+        value |= u64::from(b & 0x7f) << shift;
+        if b & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+pub fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if data.len() < len {
+        return None;
+    }
+    let (value, rest) = data.split_at(len);
+    *data = rest;
+    Some(value)
+}
+
+/// parse the wire format without a schema. returns None if the data is not a valid message
+fn parse_message(mut data: &[u8]) -> Option<Vec<(u64, WireValue<'_>)>> {
+    let mut fields = Vec::new();
+    while !data.is_empty() {
+        let key = read_varint(&mut data)?;
+        let field = key >> 3;
+        if field == 0 {
+            return None;
+        }
+        let value = match key & 7 {
+            0 => WireValue::Varint(read_varint(&mut data)?),
+            1 => WireValue::Fixed64(u64::from_le_bytes(take(&mut data, 8)?.try_into().ok()?)),
+            2 => {
+                let len = read_varint(&mut data)?;
+                WireValue::Bytes(take(&mut data, len.try_into().ok()?)?)
+            }
+            5 => WireValue::Fixed32(u32::from_le_bytes(take(&mut data, 4)?.try_into().ok()?)),
+            // groups are deprecated and seldom used, so treat them as invalid
+            _ => return None,
+        };
+        fields.push((field, value));
+    }
+    Some(fields)
+}
+
+/// split a stream of messages that are each prefixed with their length
+fn split_delimited(mut data: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut messages = Vec::new();
+    while !data.is_empty() {
+        let len = read_varint(&mut data)?;
+        messages.push(take(&mut data, len.try_into().ok()?)?);
+    }
+    Some(messages)
+}
+
+fn as_text(data: &[u8]) -> Option<&str> {
+    std::str::from_utf8(data)
+        .ok()
+        .filter(|s| s.chars().all(|c| !c.is_control() || c.is_whitespace()))
+}
+
+/// one line per field, prefixed with the path of field numbers. the wire format can't tell
+/// strings from nested messages, so anything that looks like text is assumed to be a string
+fn write_fields(
+    line_prefix: &str,
+    path: &str,
+    fields: Vec<(u64, WireValue<'_>)>,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for (field, value) in fields {
+        let path = if path.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", path, field)
+        };
+        match value {
+            WireValue::Varint(v) | WireValue::Fixed64(v) => {
+                writeln!(oup, "{}{}: {}", line_prefix, path, v)?
+            }
+            WireValue::Fixed32(v) => writeln!(oup, "{}{}: {}", line_prefix, path, v)?,
+            WireValue::Bytes(b) => {
+                if let Some(text) = as_text(b) {
+                    writeln!(oup, "{}{}: {:?}", line_prefix, path, text)?;
+                } else if let Some(inner) = parse_message(b) {
+                    write_fields(line_prefix, &path, inner, oup)?;
+                } else {
+                    writeln!(oup, "{}{}: <{} bytes>", line_prefix, path, b.len())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn load_descriptor(path: &str, message: Option<&str>) -> Result<MessageDescriptor> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("reading protobuf descriptor {}", path))?;
+    let pool = DescriptorPool::decode(&bytes[..])
+        .with_context(|| format!("parsing protobuf descriptor {}", path))?;
+    match message {
+        Some(name) => pool
+            .get_message_by_name(name)
+            .ok_or_else(|| format_err!("message type {} not found in {}", name, path)),
+        None => pool
+            .files()
+            .last()
+            .and_then(|f| f.messages().next())
+            .ok_or_else(|| format_err!("no message types found in {}", path)),
+    }
+}
+
+impl WritingFileAdapterTrait for ProtobufAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let args = &ai.config.args;
+        let descriptor = match &args.proto_descriptor {
+            Some(path) => Some(load_descriptor(path, args.proto_message.as_deref())?),
+            None => None,
+        };
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        // if the file can't be split into length-delimited messages, it's probably a single message
+        let (messages, delimited) = match split_delimited(&data) {
+            Some(messages) => (messages, true),
+            None => (vec![&data[..]], false),
+        };
+        debug!(
+            "{}: {} protobuf messages",
+            ai.filepath_hint.display(),
+            messages.len()
+        );
+        for (i, message) in messages.into_iter().enumerate() {
+            let line_prefix = if delimited {
+                format!("{}Message {}: ", ai.line_prefix, i + 1)
+            } else {
+                ai.line_prefix.clone()
+            };
+            match &descriptor {
+                Some(descriptor) => {
+                    let message = DynamicMessage::decode(descriptor.clone(), message)?;
+                    writeln!(oup, "{}{}", line_prefix, serde_json::to_string(&message)?)?;
+                }
+                None => match parse_message(message) {
+                    Some(fields) => write_fields(&line_prefix, "", fields, oup)?,
+                    None => writeln!(oup, "{}[rga: invalid protobuf message]", line_prefix)?,
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::RgaConfig;
+    use crate::test_utils::*;
+    use prost_reflect::prost::Message;
+    use prost_reflect::prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+    };
+    use std::io::Cursor;
+
+    /// two messages of `message Person { string name = 1; int32 id = 2; Address address = 3 }`
+    /// and `message Address { string city = 1 }`
+    static DELIMITED: &[u8] = b"\x0f\x0a\x05alice\x10\x01\x1a\x04\x0a\x02NY\x07\x0a\x03bob\x10\x02";
+
+    fn adapt(data: &[u8], config: RgaConfig) -> Result<String> {
+        let (mut a, d) =
+            simple_adapt_info(Path::new("test.pb"), Box::new(Cursor::new(data.to_vec())));
+        a.config.args = config;
+        let mut r = ProtobufAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    fn field(name: &str, number: i32, typ: Type, type_name: Option<&str>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(typ as i32),
+            type_name: type_name.map(|t| t.to_string()),
+            json_name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn without_schema() -> Result<()> {
+        assert_eq!(
+            adapt(DELIMITED, RgaConfig::default())?,
+            "PREFIX:Message 1: 1: \"alice\"\nPREFIX:Message 1: 2: 1\nPREFIX:Message 1: 3.1: \"NY\"\nPREFIX:Message 2: 1: \"bob\"\nPREFIX:Message 2: 2: 2\n"
+        );
+        // not length delimited
+        assert_eq!(
+            adapt(&DELIMITED[1..16], RgaConfig::default())?,
+            "PREFIX:1: \"alice\"\nPREFIX:2: 1\nPREFIX:3.1: \"NY\"\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn with_descriptor() -> Result<()> {
+        let file = FileDescriptorProto {
+            name: Some("person.proto".to_string()),
+            package: Some("test".to_string()),
+            syntax: Some("proto3".to_string()),
+            message_type: vec![
+                DescriptorProto {
+                    name: Some("Person".to_string()),
+                    field: vec![
+                        field("name", 1, Type::String, None),
+                        field("id", 2, Type::Int32, None),
+                        field("address", 3, Type::Message, Some(".test.Address")),
+                    ],
+                    ..Default::default()
+                },
+                DescriptorProto {
+                    name: Some("Address".to_string()),
+                    field: vec![field("city", 1, Type::String, None)],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut desc = tempfile::NamedTempFile::new()?;
+        desc.write_all(&FileDescriptorSet { file: vec![file] }.encode_to_vec())?;
+        let config = RgaConfig {
+            proto_descriptor: Some(desc.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            adapt(DELIMITED, config)?,
+            "PREFIX:Message 1: {\"name\":\"alice\",\"id\":1,\"address\":{\"city\":\"NY\"}}\nPREFIX:Message 2: {\"name\":\"bob\",\"id\":2}\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::convert::TryInto;
+use std::io::BufReader;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["psd", "psb"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "psd".to_owned(),
+        version: 1,
+        description:
+            "Lists the layer names and the contents of text layers of Photoshop .psd/.psb files"
+                .to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "image/vnd.adobe.photoshop".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct PsdAdapter;
+
+impl PsdAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(PsdAdapter))
+    }
+}
+impl GetMetadata for PsdAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// additional layer info keys with an 8 byte length in .psb files
+static PSB_LONG_KEYS: &[&[u8; 4]] = &[
+    b"LMsk", b"Lr16", b"Lr32", b"Layr", b"Mt16", b"Mt32", b"Mtrn", b"Alph", b"FMsk", b"lnk2",
+    b"FEid", b"FXid", b"PxSD",
+];
+
+/// layer records can't be larger than this, everything big is in the channel image data
+const MAX_BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+struct PsdReader<R: Read> {
+    inp: R,
+    /// .psb ("large document format") files use 8 byte lengths in a few places
+    large: bool,
+}
+
+impl<R: Read> PsdReader<R> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.inp.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes()?))
+    }
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes()?))
+    }
+    /// a section length that is 4 bytes in .psd and 8 bytes in .psb
+    fn length(&mut self) -> Result<u64> {
+        if self.large {
+            Ok(u64::from_be_bytes(self.bytes()?))
+        } else {
+            Ok(self.u32()? as u64)
+        }
+    }
+    fn skip(&mut self, len: u64) -> Result<()> {
+        let copied = std::io::copy(&mut (&mut self.inp).take(len), &mut std::io::sink())?;
+        if copied != len {
+            bail!("unexpected end of file");
+        }
+        Ok(())
+    }
+    fn block(&mut self, len: u64) -> Result<Vec<u8>> {
+        if len > MAX_BLOCK_SIZE {
+            bail!("layer record too large ({} bytes)", len);
+        }
+        let mut buf = vec![0u8; len as usize];
+        self.inp.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Layer {
+    name: String,
+    text: Option<String>,
+    /// the hidden layer closing a group
+    group_end: bool,
+}
+
+fn utf16_be(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// a descriptor unicode string: character count followed by UTF-16BE
+fn unicode_string(data: &[u8]) -> Option<String> {
+    let count = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    Some(utf16_be(data.get(4..4 + count.checked_mul(2)?)?))
+}
+
+/// the text of a type tool object (TySh) is the "Txt " item of its descriptor
+fn text_of_type_tool(data: &[u8]) -> Option<String> {
+    let pos = memchr::memmem::find(data, b"Txt TEXT")?;
+    unicode_string(&data[pos + 8..])
+}
+
+/// parses the additional layer info blocks at the end of a layer record
+fn parse_layer_info(mut data: &[u8], large: bool, layer: &mut Layer) {
+    while data.len() >= 12 {
+        let (sig, key) = (&data[0..4], &data[4..8]);
+        if sig != b"8BIM" && sig != b"8B64" {
+            break;
+        }
+        let long = large && PSB_LONG_KEYS.iter().any(|k| &k[..] == key);
+        let (len, header) = if long {
+            if data.len() < 16 {
+                break;
+            }
+            (u64::from_be_bytes(data[8..16].try_into().unwrap()), 16)
+        } else {
+            (
+                u32::from_be_bytes(data[8..12].try_into().unwrap()) as u64,
+                12,
+            )
+        };
+        let body = match data.get(header..).and_then(|d| d.get(..len as usize)) {
+            Some(body) => body,
+            None => break,
+        };
+        match key {
+            b"luni" => {
+                if let Some(name) = unicode_string(body) {
+                    layer.name = name;
+                }
+            }
+            b"TySh" => layer.text = text_of_type_tool(body),
+            b"lsct" => {
+                layer.group_end = body.get(..4) == Some(&[0, 0, 0, 3]);
+            }
+            _ => {}
+        }
+        data = &data[header + len as usize..];
+    }
+}
+
+fn parse_layers(inp: impl Read) -> Result<Vec<Layer>> {
+    let mut r = PsdReader { inp, large: false };
+    if &r.bytes::<4>()? != b"8BPS" {
+        bail!("not a photoshop file");
+    }
+    r.large = match r.u16()? {
+        1 => false,
+        2 => true,
+        v => bail!("unsupported photoshop file version {}", v),
+    };
+    // reserved, channels, height, width, depth, color mode
+    r.skip(6 + 2 + 4 + 4 + 2 + 2)?;
+    // color mode data and image resources
+    for _ in 0..2 {
+        let len = r.u32()?;
+        r.skip(len as u64)?;
+    }
+    let layer_and_mask_len = r.length()?;
+    if layer_and_mask_len == 0 {
+        return Ok(Vec::new());
+    }
+    let layer_info_len = r.length()?;
+    if layer_info_len == 0 {
+        return Ok(Vec::new());
+    }
+    // negative if the first alpha channel is the merged transparency
+    let count = (r.u16()? as i16).unsigned_abs();
+    let mut layers = Vec::new();
+    for _ in 0..count {
+        // bounding rectangle
+        r.skip(16)?;
+        let channels = r.u16()?;
+        r.skip(channels as u64 * if r.large { 10 } else { 6 })?;
+        // blend mode signature and key, opacity, clipping, flags, filler
+        r.skip(12)?;
+        let extra_len = r.u32()?;
+        let extra = r.block(extra_len as u64)?;
+        let mut pos = 0;
+        // layer mask and blending ranges
+        for _ in 0..2 {
+            let len = extra
+                .get(pos..pos + 4)
+                .map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize)
+                .ok_or_else(|| format_err!("truncated layer record"))?;
+            pos += 4 + len;
+        }
+        let name_len = *extra
+            .get(pos)
+            .ok_or_else(|| format_err!("truncated layer record"))? as usize;
+        let mut layer = Layer {
+            // pascal strings are in the system encoding, luni has the real name
+            name: extra
+                .get(pos + 1..pos + 1 + name_len)
+                .map(|n| String::from_utf8_lossy(n).into_owned())
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        // the name is padded to a multiple of 4 bytes
+        pos += (1 + name_len + 3) & !3;
+        if let Some(info) = extra.get(pos..) {
+            parse_layer_info(info, r.large, &mut layer);
+        }
+        layers.push(layer);
+    }
+    Ok(layers)
+}
+
+impl WritingFileAdapterTrait for PsdAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let layers = parse_layers(BufReader::new(ai.inp))?;
+        // layers are stored bottom to top, so list them like the layers panel does
+        for layer in layers.iter().rev().filter(|l| !l.group_end) {
+            writeln!(oup, "{}Layer: {}", ai.line_prefix, layer.name)?;
+            if let Some(text) = &layer.text {
+                // photoshop uses \r as line separator
+                for line in text.split(['\r', '\n']).filter(|l| !l.trim().is_empty()) {
+                    writeln!(oup, "{}Text: {}", ai.line_prefix, line.trim())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn utf16(s: &str) -> Vec<u8> {
+        let mut out = (s.encode_utf16().count() as u32).to_be_bytes().to_vec();
+        out.extend(s.encode_utf16().flat_map(u16::to_be_bytes));
+        out
+    }
+
+    fn info_block(key: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut out = b"8BIM".to_vec();
+        out.extend(key);
+        out.extend(&(data.len() as u32).to_be_bytes());
+        out.extend(data);
+        out
+    }
+
+    fn layer_record(name: &str, info: &[u8]) -> Vec<u8> {
+        let mut out = vec![0u8; 16];
+        // one channel without data
+        out.extend(&1u16.to_be_bytes());
+        out.extend(&[0, 0, 0, 0, 0, 0]);
+        out.extend(b"8BIMnorm");
+        out.extend(&[255, 0, 0, 0]);
+        let mut extra = vec![0u8; 8];
+        extra.push(name.len() as u8);
+        extra.extend(name.as_bytes());
+        while !extra.len().is_multiple_of(4) {
+            extra.push(0);
+        }
+        extra.extend(info);
+        out.extend(&(extra.len() as u32).to_be_bytes());
+        out.extend(extra);
+        out
+    }
+
+    /// just enough of a type tool object for the text to be found
+    fn type_tool(text: &str) -> Vec<u8> {
+        let mut out = vec![0, 1];
+        out.extend(&[0u8; 48]);
+        out.extend(&[0, 50, 0, 0, 0, 16]);
+        out.extend(utf16(""));
+        out.extend(&[0, 0, 0, 0]);
+        out.extend(b"TxLr");
+        out.extend(&1u32.to_be_bytes());
+        out.extend(&[0, 0, 0, 0]);
+        out.extend(b"Txt TEXT");
+        out.extend(utf16(&format!("{}\0", text)));
+        out
+    }
+
+    fn test_psd() -> Vec<u8> {
+        let mut layers = 3u16.to_be_bytes().to_vec();
+        layers.extend(layer_record("Background", &[]));
+        layers.extend(layer_record(
+            "Headline",
+            &[
+                info_block(b"luni", &utf16("Überschrift")),
+                info_block(b"TySh", &type_tool("Summer Sale\rup to 50% off")),
+            ]
+            .concat(),
+        ));
+        layers.extend(layer_record(
+            "</Layer group>",
+            &info_block(b"lsct", &[0, 0, 0, 3]),
+        ));
+        let mut psd = b"8BPS".to_vec();
+        psd.extend(&1u16.to_be_bytes());
+        psd.extend(&[0u8; 6]);
+        psd.extend(&[0, 3, 0, 0, 0, 10, 0, 0, 0, 10, 0, 8, 0, 3]);
+        // empty color mode data and image resources
+        psd.extend(&[0u8; 8]);
+        psd.extend(&(layers.len() as u32 + 4).to_be_bytes());
+        psd.extend(&(layers.len() as u32).to_be_bytes());
+        psd.extend(layers);
+        psd
+    }
+
+    #[test]
+    fn psd() -> Result<()> {
+        let (a, d) = simple_adapt_info(Path::new("banner.psd"), Box::new(Cursor::new(test_psd())));
+        let mut r = PsdAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Layer: Überschrift
+PREFIX:Text: Summer Sale
+PREFIX:Text: up to 50% off
+PREFIX:Layer: Background
+"
+        );
+        Ok(())
+    }
+}
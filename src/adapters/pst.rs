@@ -0,0 +1,177 @@
+use super::spawning::map_exe_error;
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use mailparse::MailHeaderMap;
+use std::fs::File;
+use std::process::{Command, Stdio};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["pst", "ost"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "pst".to_owned(),
+        version: 1,
+        description: "Uses readpst (from libpst) to read Outlook PST/OST stores. Lines are prefixed with the folder and subject of each message, attachments are recursed into".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct PstAdapter;
+
+impl PstAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(PstAdapter))
+    }
+}
+impl GetMetadata for PstAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// readpst writes every item to `<folder>/<n>.eml`, so the folder is the parent directory of the item
+fn item_prefix(line_prefix: &str, item: &Path, subject: Option<&str>) -> String {
+    let folder = item
+        .parent()
+        .map(|p| p.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_default();
+    let name = match subject {
+        Some(subject) if !subject.trim().is_empty() => subject.trim().to_string(),
+        _ => item
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    if folder.is_empty() {
+        format!("{}{}: ", line_prefix, name)
+    } else {
+        format!("{}{}/{}: ", line_prefix, folder, name)
+    }
+}
+
+impl WritingFileAdapterTrait for PstAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let AdaptInfo {
+            filepath_hint,
+            is_real_file,
+            mut inp,
+            line_prefix,
+            archive_recursion_depth,
+            config,
+        } = ai;
+        // readpst can't read from stdin, so a pst within an archive has to be written to disk first
+        let _tmp_file;
+        let store_path = if is_real_file {
+            filepath_hint.clone()
+        } else {
+            let mut tmp = tempfile::Builder::new()
+                .prefix("rga-pst-")
+                .suffix(".pst")
+                .tempfile()?;
+            std::io::copy(&mut inp, &mut tmp)?;
+            let path = tmp.path().to_owned();
+            _tmp_file = tmp;
+            path
+        };
+        let out_dir = tempfile::Builder::new().prefix("rga-pst-").tempdir()?;
+        debug!(
+            "extracting {} to {}",
+            filepath_hint.display(),
+            out_dir.path().display()
+        );
+        // -e: one file per item, with mime attachments and an extension. -8: utf8
+        let extract = Command::new("readpst")
+            .args(["-e", "-8", "-q", "-o"])
+            .arg(out_dir.path())
+            .arg(&store_path)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| map_exe_error(e, "readpst", "Make sure you have libpst installed."))?;
+        if !extract.status.success() {
+            return Err(format_err!(
+                "readpst failed: {:?}: {}",
+                extract.status,
+                String::from_utf8_lossy(&extract.stderr)
+            ));
+        }
+        let mut items = glob::glob(
+            out_dir
+                .path()
+                .join("**/*")
+                .to_str()
+                .expect("temp path has invalid encoding"),
+        )?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+        items.retain(|p| p.is_file());
+        items.sort();
+        for item in items {
+            let relative = item.strip_prefix(out_dir.path())?;
+            let mut data = Vec::new();
+            File::open(&item)?.read_to_end(&mut data)?;
+            let item_ai = |line_prefix: String, inp: ReadBox| AdaptInfo {
+                filepath_hint: relative.to_owned(),
+                is_real_file: false,
+                archive_recursion_depth: archive_recursion_depth + 1,
+                inp,
+                line_prefix,
+                config: config.clone(),
+            };
+            if relative.extension().is_some_and(|e| e == "eml") {
+                let subject = mailparse::parse_headers(&data)
+                    .ok()
+                    .and_then(|(headers, _)| headers.get_first_value("Subject"));
+                let prefix = item_prefix(&line_prefix, relative, subject.as_deref());
+                eml::write_mail(&data, &item_ai(prefix, Box::new(std::io::empty())), oup)
+                    .with_context(|| format!("reading message {}", relative.display()))?;
+            } else {
+                // contacts (.vcf), appointments (.ics) and so on
+                let prefix = item_prefix(&line_prefix, relative, None);
+                let mut inner = rga_preproc(item_ai(prefix, Box::new(std::io::Cursor::new(data))))?;
+                std::io::copy(&mut inner, oup)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(
+            item_prefix(
+                "PREFIX:",
+                Path::new("Personal Folders/Inbox/12.eml"),
+                Some(" Re: lunch ")
+            ),
+            "PREFIX:Personal Folders/Inbox/Re: lunch: "
+        );
+        assert_eq!(
+            item_prefix("", Path::new("Inbox/3.eml"), Some("")),
+            "Inbox/3.eml: "
+        );
+        assert_eq!(
+            item_prefix("", Path::new("contacts.vcf"), None),
+            "contacts.vcf: "
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::convert::TryInto;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["pyc", "pyo"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "pyc".to_owned(),
+        version: 1,
+        description: "Reads compiled Python files (.pyc, Python 2.7 and 3.x). Outputs the string constants and the names used by each function, as `module.Class.function: text`".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/x-python-code".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct PycAdapter;
+
+impl PycAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(PycAdapter))
+    }
+}
+impl GetMetadata for PycAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the magic numbers of python 2 are larger than those of python 3 (62211 for 2.7)
+const MAGIC_PY2: u16 = 20000;
+/// 3.8 added the number of positional-only arguments to code objects
+const MAGIC_POSONLY: u16 = 3410;
+/// 3.11 replaced the variable names with `co_localsplusnames` and added the qualified name
+const MAGIC_LOCALSPLUS: u16 = 3450;
+
+/// objects that are stored by reference once and then referred to by index
+const FLAG_REF: u8 = 0x80;
+
+#[derive(Clone, Debug)]
+enum Value {
+    Str(String),
+    Bytes(Vec<u8>),
+    Tuple(Vec<Value>),
+    Code(Box<Code>),
+    Other,
+}
+
+#[derive(Clone, Debug)]
+struct Code {
+    name: String,
+    consts: Vec<Value>,
+    names: Vec<String>,
+}
+
+/// reads the marshal format that pyc files are written in
+struct Unmarshaller<'a> {
+    data: &'a [u8],
+    pos: usize,
+    magic: u16,
+    refs: Vec<Value>,
+    /// interned strings of python 2, referred to with `R`
+    interned: Vec<String>,
+}
+
+impl<'a> Unmarshaller<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(n))
+            .context("truncated pyc file")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn string(&mut self, len: usize) -> Result<String> {
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn is_py3(&self) -> bool {
+        self.magic < MAGIC_PY2
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        let code = self.u8()?;
+        let (kind, is_ref) = (code & !FLAG_REF, code & FLAG_REF != 0);
+        // containers are referenced before their contents are read
+        let index = self.refs.len();
+        if is_ref {
+            self.refs.push(Value::Other);
+        }
+        let value = match kind {
+            b'0' | b'N' | b'F' | b'T' | b'S' | b'.' => Value::Other,
+            b'i' => {
+                self.bytes(4)?;
+                Value::Other
+            }
+            b'I' | b'g' => {
+                self.bytes(8)?;
+                Value::Other
+            }
+            b'y' => {
+                self.bytes(16)?;
+                Value::Other
+            }
+            b'l' => {
+                let digits = self.u32()? as i32;
+                self.bytes(digits.unsigned_abs() as usize * 2)?;
+                Value::Other
+            }
+            b'f' => {
+                let len = self.u8()? as usize;
+                self.bytes(len)?;
+                Value::Other
+            }
+            b'x' => {
+                for _ in 0..2 {
+                    let len = self.u8()? as usize;
+                    self.bytes(len)?;
+                }
+                Value::Other
+            }
+            // python 2 str, or python 3 bytes
+            b's' => {
+                let len = self.u32()? as usize;
+                let bytes = self.bytes(len)?.to_vec();
+                if self.is_py3() {
+                    Value::Bytes(bytes)
+                } else {
+                    Value::Str(String::from_utf8_lossy(&bytes).into_owned())
+                }
+            }
+            b't' => {
+                let len = self.u32()? as usize;
+                let s = self.string(len)?;
+                self.interned.push(s.clone());
+                Value::Str(s)
+            }
+            b'R' => {
+                let index = self.u32()? as usize;
+                Value::Str(
+                    self.interned
+                        .get(index)
+                        .context("invalid string reference")?
+                        .clone(),
+                )
+            }
+            b'u' | b'a' | b'A' => {
+                let len = self.u32()? as usize;
+                Value::Str(self.string(len)?)
+            }
+            b'z' | b'Z' => {
+                let len = self.u8()? as usize;
+                Value::Str(self.string(len)?)
+            }
+            b'(' | b'[' | b'<' | b'>' => {
+                let len = self.u32()? as usize;
+                self.sequence(len)?
+            }
+            b')' => {
+                let len = self.u8()? as usize;
+                self.sequence(len)?
+            }
+            b'{' => {
+                let mut items = Vec::new();
+                while self.data.get(self.pos) != Some(&b'0') {
+                    items.push(self.object()?);
+                    items.push(self.object()?);
+                }
+                self.pos += 1;
+                Value::Tuple(items)
+            }
+            // slices, only in the constants of 3.14
+            b':' => self.sequence(3)?,
+            b'r' => {
+                let index = self.u32()? as usize;
+                self.refs.get(index).context("invalid reference")?.clone()
+            }
+            b'c' => Value::Code(Box::new(self.code()?)),
+            _ => bail!(
+                "unknown marshal type {:?} at {}",
+                kind as char,
+                self.pos - 1
+            ),
+        };
+        if is_ref {
+            self.refs[index] = value.clone();
+        }
+        Ok(value)
+    }
+
+    fn sequence(&mut self, len: usize) -> Result<Value> {
+        let mut items = Vec::new();
+        for _ in 0..len {
+            items.push(self.object()?);
+        }
+        Ok(Value::Tuple(items))
+    }
+
+    fn names(&mut self) -> Result<Vec<String>> {
+        Ok(match self.object()? {
+            Value::Tuple(items) => items
+                .into_iter()
+                .filter_map(|item| match item {
+                    Value::Str(s) => Some(s),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    fn code(&mut self) -> Result<Code> {
+        // argcount, [posonlyargcount], [kwonlyargcount], [nlocals], stacksize, flags
+        let int_fields = if !self.is_py3() {
+            4
+        } else if self.magic >= MAGIC_POSONLY && self.magic < MAGIC_LOCALSPLUS {
+            6
+        } else {
+            5
+        };
+        self.bytes(int_fields * 4)?;
+        let _bytecode = self.object()?;
+        let consts = match self.object()? {
+            Value::Tuple(items) => items,
+            _ => Vec::new(),
+        };
+        let names = self.names()?;
+        if self.magic >= MAGIC_LOCALSPLUS {
+            // localsplusnames, localspluskinds
+            self.object()?;
+            self.object()?;
+        } else {
+            // varnames, freevars, cellvars
+            self.object()?;
+            self.object()?;
+            self.object()?;
+        }
+        let _filename = self.object()?;
+        let name = match self.object()? {
+            Value::Str(name) => name,
+            _ => String::new(),
+        };
+        if self.magic >= MAGIC_LOCALSPLUS {
+            let _qualname = self.object()?;
+        }
+        let _firstlineno = self.u32()?;
+        // the line number table, and the exception table in 3.11
+        self.object()?;
+        if self.magic >= MAGIC_LOCALSPLUS {
+            self.object()?;
+        }
+        Ok(Code {
+            name,
+            consts,
+            names,
+        })
+    }
+}
+
+/// writes the strings in a constant, e.g. the docstring or a tuple of keyword names
+fn write_const(prefix: &str, value: &Value, oup: &mut dyn Write) -> Result<()> {
+    match value {
+        Value::Str(s) => {
+            for line in s.lines().filter(|l| !l.trim().is_empty()) {
+                writeln!(oup, "{}{}", prefix, line)?;
+            }
+        }
+        // only bytes that are text, not random binary data
+        Value::Bytes(b) => {
+            if let Result::Ok(s) = std::str::from_utf8(b) {
+                write_const(prefix, &Value::Str(s.to_string()), oup)?;
+            }
+        }
+        Value::Tuple(items) => {
+            for item in items {
+                write_const(prefix, item, oup)?;
+            }
+        }
+        Value::Code(_) | Value::Other => {}
+    }
+    Ok(())
+}
+
+fn write_code(line_prefix: &str, path: &str, code: &Code, oup: &mut dyn Write) -> Result<()> {
+    let prefix = format!("{}{}: ", line_prefix, path);
+    for value in &code.consts {
+        write_const(&prefix, value, oup)?;
+    }
+    if !code.names.is_empty() {
+        writeln!(oup, "{}names: {}", prefix, code.names.join(", "))?;
+    }
+    for value in &code.consts {
+        if let Value::Code(inner) = value {
+            write_code(line_prefix, &format!("{}.{}", path, inner.name), inner, oup)?;
+        }
+    }
+    Ok(())
+}
+
+/// the size of the header before the code object: the magic number, then the
+/// flags (3.7 and later), modification time and source size (3.3 and later)
+fn header_size(magic: u16) -> usize {
+    match magic {
+        0..=3189 => 8,
+        3190..=3389 => 12,
+        MAGIC_PY2.. => 8,
+        _ => 16,
+    }
+}
+
+impl WritingFileAdapterTrait for PycAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        if data.len() < 8 || &data[2..4] != b"\r\n" {
+            bail!("not a compiled python file");
+        }
+        let magic = u16::from_le_bytes([data[0], data[1]]);
+        let mut unmarshaller = Unmarshaller {
+            data: &data,
+            pos: header_size(magic),
+            magic,
+            refs: Vec::new(),
+            interned: Vec::new(),
+        };
+        let module = match unmarshaller.object()? {
+            Value::Code(code) => code,
+            _ => bail!("pyc file does not contain a code object"),
+        };
+        // the name of the module code object is always `<module>`, so use the file name instead
+        let name = ai
+            .filepath_hint
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        // python 3 puts them in __pycache__/name.cpython-311.pyc
+        let name = name.split('.').next().unwrap_or_default();
+        write_code(&ai.line_prefix, name, &module, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn short_str(s: &str) -> Vec<u8> {
+        let mut out = vec![b'z', s.len() as u8];
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    fn tuple(items: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = vec![b')', items.len() as u8];
+        out.extend(items.concat());
+        out
+    }
+
+    /// a python 3.11 code object
+    fn code(name: &str, consts: &[Vec<u8>], names: &[&str]) -> Vec<u8> {
+        let mut out = vec![b'c' | FLAG_REF];
+        out.extend_from_slice(&[0; 20]);
+        out.extend(b"s\x02\x00\x00\x00\x97\x00");
+        out.extend(tuple(consts));
+        out.extend(tuple(
+            &names.iter().map(|n| short_str(n)).collect::<Vec<_>>(),
+        ));
+        out.extend(tuple(&[]));
+        out.extend(b"s\x00\x00\x00\x00");
+        // the file name is stored once and then referenced
+        let mut filename = short_str("app.py");
+        filename[0] |= FLAG_REF;
+        out.extend(filename);
+        out.extend(short_str(name));
+        out.extend(short_str(name));
+        out.extend_from_slice(&1u32.to_le_bytes());
+        out.extend(b"s\x00\x00\x00\x00");
+        out.extend(b"s\x00\x00\x00\x00");
+        out
+    }
+
+    #[test]
+    fn pyc() -> Result<()> {
+        let connect = code(
+            "connect",
+            &[
+                b"N".to_vec(),
+                short_str("https://api.example.com/v1"),
+                b"i\x1e\x00\x00\x00".to_vec(),
+            ],
+            &["requests", "get"],
+        );
+        let module = code(
+            "<module>",
+            &[
+                short_str("Client for the example api.\n\nNeeds a token."),
+                connect,
+                // the file name of connect
+                vec![b'r', 2, 0, 0, 0],
+                b"N".to_vec(),
+            ],
+            &["__doc__", "requests", "connect"],
+        );
+        let mut data = 3495u16.to_le_bytes().to_vec();
+        data.extend_from_slice(b"\r\n");
+        data.extend_from_slice(&[0; 12]);
+        data.extend(module);
+
+        let (a, d) = simple_adapt_info(
+            Path::new("__pycache__/client.cpython-311.pyc"),
+            Box::new(Cursor::new(data)),
+        );
+        let mut r = PycAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:client: Client for the example api.
+PREFIX:client: Needs a token.
+PREFIX:client: app.py
+PREFIX:client: names: __doc__, requests, connect
+PREFIX:client.connect: https://api.example.com/v1
+PREFIX:client.connect: names: requests, get
+"
+        );
+        Ok(())
+    }
+}
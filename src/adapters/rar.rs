@@ -0,0 +1,163 @@
+use super::spawning::map_exe_error;
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::process::{Command, Stdio};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["rar"];
+static MIME_TYPES: &[&str] = &["application/vnd.rar", "application/x-rar"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "rar".to_owned(),
+        version: 1,
+        description: "Uses unrar to read rar archives and recurses down into their contents"
+            .to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct RarAdapter;
+
+impl RarAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(RarAdapter))
+    }
+}
+impl GetMetadata for RarAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+pub fn spawn_fail(e: std::io::Error) -> Error {
+    map_exe_error(e, "unrar", "Make sure you have unrar installed.")
+}
+
+/// `unrar lb` also lists directories. drop every entry that is the parent of some other entry
+fn files_only(names: Vec<String>) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| {
+            !names.iter().any(|other| {
+                other.len() > name.len()
+                    && other.starts_with(name.as_str())
+                    && other[name.len()..].starts_with(['/', '\\'])
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+/// the files in a rar archive, in archive order
+pub fn list_files(archive_path: &Path) -> Result<Vec<String>> {
+    // -p- prevents unrar from asking for a password
+    let list = Command::new("unrar")
+        .args(["lb", "-p-", "--"])
+        .arg(archive_path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(spawn_fail)?;
+    if !list.status.success() {
+        return Err(format_err!(
+            "unrar failed: {:?}: {}",
+            list.status,
+            String::from_utf8_lossy(&list.stderr)
+        ));
+    }
+    let names = String::from_utf8_lossy(&list.stdout)
+        .lines()
+        .map(|s| s.to_string())
+        .collect();
+    Ok(files_only(names))
+}
+
+impl WritingFileAdapterTrait for RarAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let AdaptInfo {
+            filepath_hint,
+            is_real_file,
+            mut inp,
+            line_prefix,
+            archive_recursion_depth,
+            config,
+        } = ai;
+        // unrar can't read from stdin, so a rar within an archive has to be written to disk first
+        let _tmp_file;
+        let archive_path = if is_real_file {
+            filepath_hint.clone()
+        } else {
+            let mut tmp = tempfile::Builder::new()
+                .prefix("rga-rar-")
+                .suffix(".rar")
+                .tempfile()?;
+            std::io::copy(&mut inp, &mut tmp)?;
+            let path = tmp.path().to_owned();
+            _tmp_file = tmp;
+            path
+        };
+        for name in list_files(&archive_path)? {
+            debug!("{}|{}", filepath_hint.display(), name);
+            let mut cmd = Command::new("unrar")
+                .args(["p", "-inul", "-p-", "--"])
+                .arg(&archive_path)
+                .arg(&name)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .spawn()
+                .map_err(spawn_fail)?;
+            let stdo = cmd.stdout.take().expect("is piped");
+            let mut member = rga_preproc(AdaptInfo {
+                filepath_hint: PathBuf::from(&name),
+                is_real_file: false,
+                archive_recursion_depth: archive_recursion_depth + 1,
+                inp: Box::new(stdo),
+                line_prefix: format!("{}{}: ", line_prefix, name),
+                config: config.clone(),
+            })?;
+            std::io::copy(&mut member, oup)?;
+            drop(member);
+            // fails with a broken pipe if the inner adapter did not read the whole file, which is fine
+            let status = cmd.wait()?;
+            if !status.success() {
+                debug!("unrar p {} exited with {:?}", name, status);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_dirs() {
+        let names = ["a", "a/b.txt", "a/c", "a/c/d.pdf", "ab.txt", "e"];
+        assert_eq!(
+            files_only(names.iter().map(|s| s.to_string()).collect()),
+            vec!["a/b.txt", "a/c/d.pdf", "ab.txt", "e"]
+        );
+    }
+}
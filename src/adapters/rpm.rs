@@ -0,0 +1,367 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["rpm", "srpm"];
+static MIME_TYPES: &[&str] = &["application/x-rpm"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "rpm".to_owned(),
+        version: 1,
+        description: "Reads RPM packages. Writes the header tags (name, description, dependencies, scriptlets, changelog), and recurses into the files of the cpio payload".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct RpmAdapter;
+
+impl RpmAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(RpmAdapter))
+    }
+}
+impl GetMetadata for RpmAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+static LEAD_MAGIC: &[u8] = b"\xed\xab\xee\xdb";
+static HEADER_MAGIC: &[u8] = b"\x8e\xad\xe8\x01";
+/// headers of real packages are a few hundred kilobytes
+static MAX_HEADER_SIZE: usize = 256 << 20;
+
+/// single valued tags, in output order
+static FIELDS: &[(u32, &str)] = &[
+    (1000, "Name"),
+    (1001, "Version"),
+    (1002, "Release"),
+    (1004, "Summary"),
+    (1005, "Description"),
+    (1014, "License"),
+    (1016, "Group"),
+    (1020, "URL"),
+    (1011, "Vendor"),
+    (1015, "Packager"),
+    (1044, "Source RPM"),
+];
+
+/// (label, name, version, flags) tags of the dependencies
+static DEPENDENCIES: &[(&str, u32, u32, u32)] = &[
+    ("Provides", 1047, 1113, 1112),
+    ("Requires", 1049, 1050, 1048),
+    ("Conflicts", 1054, 1055, 1053),
+    ("Obsoletes", 1090, 1115, 1114),
+];
+
+static SCRIPTLETS: &[(u32, &str)] = &[
+    (1023, "%pre"),
+    (1024, "%post"),
+    (1025, "%preun"),
+    (1026, "%postun"),
+];
+
+static CHANGELOG_TIME: u32 = 1080;
+static CHANGELOG_NAME: u32 = 1081;
+static CHANGELOG_TEXT: u32 = 1082;
+
+enum Value {
+    Int(Vec<u64>),
+    Str(Vec<String>),
+    Bin,
+}
+
+struct Header(HashMap<u32, Value>);
+
+impl Header {
+    fn strings(&self, tag: u32) -> &[String] {
+        match self.0.get(&tag) {
+            Some(Value::Str(s)) => s,
+            _ => &[],
+        }
+    }
+
+    fn ints(&self, tag: u32) -> &[u64] {
+        match self.0.get(&tag) {
+            Some(Value::Int(i)) => i,
+            _ => &[],
+        }
+    }
+}
+
+fn be_u32(data: &[u8], pos: usize) -> Result<u32> {
+    Ok(u32::from_be_bytes(
+        data.get(pos..pos + 4)
+            .context("truncated rpm header")?
+            .try_into()?,
+    ))
+}
+
+/// reads a header structure (used for both the signature and the main header)
+fn read_header(inp: &mut dyn Read) -> Result<(Header, usize)> {
+    let mut intro = [0u8; 16];
+    inp.read_exact(&mut intro)?;
+    if &intro[..4] != HEADER_MAGIC {
+        bail!("invalid rpm header magic");
+    }
+    let count = be_u32(&intro, 8)? as usize;
+    let store_size = be_u32(&intro, 12)? as usize;
+    let size = count * 16 + store_size;
+    if size > MAX_HEADER_SIZE {
+        bail!("rpm header too large ({} bytes)", size);
+    }
+    let mut data = vec![0u8; size];
+    inp.read_exact(&mut data)?;
+    let (index, store) = data.split_at(count * 16);
+    let mut tags = HashMap::new();
+    for entry in index.chunks(16) {
+        let tag = be_u32(entry, 0)?;
+        let typ = be_u32(entry, 4)?;
+        let offset = be_u32(entry, 8)? as usize;
+        let n = be_u32(entry, 12)? as usize;
+        let rest = store.get(offset..).context("invalid rpm tag offset")?;
+        let ints = |width: usize| -> Result<Value> {
+            let bytes = rest.get(..n * width).context("truncated rpm tag")?;
+            Ok(Value::Int(
+                bytes
+                    .chunks(width)
+                    .map(|c| c.iter().fold(0u64, |acc, &b| acc << 8 | b as u64))
+                    .collect(),
+            ))
+        };
+        let value = match typ {
+            1 | 2 => ints(1)?,
+            3 => ints(2)?,
+            4 => ints(4)?,
+            5 => ints(8)?,
+            // string, string array, i18n string (the first one is the untranslated text)
+            6 | 8 | 9 => Value::Str(
+                rest.split(|&b| b == 0)
+                    .take(if typ == 6 { 1 } else { n })
+                    .map(|s| String::from_utf8_lossy(s).into_owned())
+                    .collect(),
+            ),
+            _ => Value::Bin,
+        };
+        tags.insert(tag, value);
+    }
+    Ok((Header(tags), 16 + size))
+}
+
+/// `libc.so.6 >= 2.34`
+fn dependency(name: &str, version: Option<&String>, flags: Option<&u64>) -> String {
+    let version = match version {
+        Some(v) if !v.is_empty() => v,
+        _ => return name.to_string(),
+    };
+    let flags = flags.copied().unwrap_or(0);
+    let op = match (flags & 0x02 != 0, flags & 0x04 != 0, flags & 0x08 != 0) {
+        (true, false, true) => "<=",
+        (true, false, false) => "<",
+        (false, true, true) => ">=",
+        (false, true, false) => ">",
+        _ => "=",
+    };
+    format!("{} {} {}", name, op, version)
+}
+
+fn write_header(line_prefix: &str, header: &Header, oup: &mut dyn Write) -> Result<()> {
+    let mut write_lines = |label: &str, text: &str| -> Result<()> {
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            writeln!(oup, "{}{}: {}", line_prefix, label, line)?;
+        }
+        Ok(())
+    };
+    for (tag, label) in FIELDS {
+        if let Some(value) = header.strings(*tag).first() {
+            write_lines(label, value)?;
+        }
+    }
+    for (label, names, versions, flags) in DEPENDENCIES {
+        let (versions, flags) = (header.strings(*versions), header.ints(*flags));
+        for (i, name) in header.strings(*names).iter().enumerate() {
+            // internal dependencies on rpm features
+            if !name.starts_with("rpmlib(") {
+                write_lines(label, &dependency(name, versions.get(i), flags.get(i)))?;
+            }
+        }
+    }
+    for (tag, label) in SCRIPTLETS {
+        if let Some(script) = header.strings(*tag).first() {
+            write_lines(label, script)?;
+        }
+    }
+    let times = header.ints(CHANGELOG_TIME);
+    let names = header.strings(CHANGELOG_NAME);
+    for (i, text) in header.strings(CHANGELOG_TEXT).iter().enumerate() {
+        let date = times
+            .get(i)
+            .and_then(|t| chrono::DateTime::from_timestamp(*t as i64, 0))
+            .map(|d| d.format("%a %b %d %Y").to_string())
+            .unwrap_or_default();
+        let name = names.get(i).map(String::as_str).unwrap_or_default();
+        write_lines("Changelog", &format!("* {} {}", date, name))?;
+        write_lines("Changelog", text)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for RpmAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut lead = [0u8; 96];
+        ai.inp.read_exact(&mut lead)?;
+        if &lead[..4] != LEAD_MAGIC {
+            bail!("not an rpm package");
+        }
+        // the signature header is padded to a multiple of 8 bytes
+        let (_signature, size) = read_header(&mut ai.inp)?;
+        let padding = (8 - size % 8) % 8;
+        ai.inp.read_exact(&mut vec![0u8; padding])?;
+        let (header, _) = read_header(&mut ai.inp)?;
+        write_header(&ai.line_prefix, &header, oup)?;
+        // the payload compressor is stored in a tag, but the cpio adapter checks the magic bytes anyway
+        cpio::adapt_entries(
+            ai.inp,
+            &ai.filepath_hint,
+            &ai.line_prefix,
+            ai.archive_recursion_depth,
+            &ai.config,
+            oup,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    enum Tag<'a> {
+        Str(&'a str),
+        Array(&'a [&'a str]),
+        Int32(&'a [u32]),
+    }
+
+    fn header(tags: &[(u32, Tag)]) -> Vec<u8> {
+        let mut index: Vec<u8> = Vec::new();
+        let mut store = Vec::new();
+        for (tag, value) in tags {
+            let (typ, count, data) = match value {
+                Tag::Str(s) => (6u32, 1, format!("{}\0", s).into_bytes()),
+                Tag::Array(a) => (
+                    8,
+                    a.len(),
+                    a.iter()
+                        .flat_map(|s| format!("{}\0", s).into_bytes())
+                        .collect(),
+                ),
+                Tag::Int32(i) => {
+                    // ints are aligned in the store
+                    while store.len() % 4 != 0 {
+                        store.push(0);
+                    }
+                    (4, i.len(), i.iter().flat_map(|i| i.to_be_bytes()).collect())
+                }
+            };
+            for v in &[*tag, typ, store.len() as u32, count as u32] {
+                index.extend(&v.to_be_bytes());
+            }
+            store.extend(data);
+        }
+        let mut out = HEADER_MAGIC.to_vec();
+        out.extend(&[0; 4]);
+        out.extend(&(tags.len() as u32).to_be_bytes());
+        out.extend(&(store.len() as u32).to_be_bytes());
+        out.extend(index);
+        out.extend(store);
+        out
+    }
+
+    fn payload() -> Result<Vec<u8>> {
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let content = b"hello, world\n";
+        let mut file = ::cpio::NewcBuilder::new("./usr/share/doc/hello/README")
+            .mode(0o100644)
+            .write(gz, content.len() as u32);
+        file.write_all(content)?;
+        let gz = ::cpio::NewcBuilder::new("./usr/share/doc/hello")
+            .mode(0o040755)
+            .write(file.finish()?, 0)
+            .finish()?;
+        Ok(::cpio::newc::trailer(gz)?.finish()?)
+    }
+
+    #[test]
+    fn rpm() -> Result<()> {
+        let mut rpm = LEAD_MAGIC.to_vec();
+        rpm.resize(96, 0);
+        rpm.extend(header(&[(1000, Tag::Str("ab"))]));
+        // pad the signature to 8 bytes
+        rpm.resize(rpm.len() + 5, 0);
+        rpm.extend(header(&[
+            (1000, Tag::Str("hello")),
+            (1001, Tag::Str("2.12")),
+            (1004, Tag::Str("Prints a familiar, friendly greeting")),
+            (
+                1049,
+                Tag::Array(&["rpmlib(CompressedFileNames)", "libc.so.6", "glibc"]),
+            ),
+            (1050, Tag::Array(&["3.0.4-1", "", "2.34"])),
+            (1048, Tag::Int32(&[0x100_0008 | 0x02, 0, 0x08 | 0x04])),
+            (
+                1024,
+                Tag::Str("/sbin/install-info /usr/share/info/hello.info"),
+            ),
+            (1080, Tag::Int32(&[1_700_000_000])),
+            (1081, Tag::Array(&["Jane Doe <jane@example.com> - 2.12-1"])),
+            (1082, Tag::Array(&["- update to 2.12\n- fix build"])),
+        ]));
+        rpm.extend(payload()?);
+
+        let (a, d) = simple_adapt_info(
+            Path::new("hello-2.12-1.x86_64.rpm"),
+            Box::new(Cursor::new(rpm)),
+        );
+        let mut r = RpmAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Name: hello
+PREFIX:Version: 2.12
+PREFIX:Summary: Prints a familiar, friendly greeting
+PREFIX:Requires: libc.so.6
+PREFIX:Requires: glibc >= 2.34
+PREFIX:%post: /sbin/install-info /usr/share/info/hello.info
+PREFIX:Changelog: * Tue Nov 14 2023 Jane Doe <jane@example.com> - 2.12-1
+PREFIX:Changelog: - update to 2.12
+PREFIX:Changelog: - fix build
+hello, world
+"
+        );
+        Ok(())
+    }
+}
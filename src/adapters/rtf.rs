@@ -0,0 +1,285 @@
+use super::*;
+use anyhow::*;
+use encoding_rs::Encoding;
+use lazy_static::lazy_static;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["rtf"];
+static MIME_TYPES: &[&str] = &["application/rtf", "text/rtf"];
+
+/// groups that don't contain document text
+static SKIP_DESTINATIONS: &[&str] = &[
+    "colortbl",
+    "datastore",
+    "filetbl",
+    "fonttbl",
+    "generator",
+    "info",
+    "latentstyles",
+    "listoverridetable",
+    "listtable",
+    "object",
+    "pict",
+    "revtbl",
+    "rsidtbl",
+    "stylesheet",
+    "themedata",
+    "xmlnstbl",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "rtf".to_owned(),
+        version: 1,
+        description: "Extracts plain text from RTF documents".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct RtfAdapter;
+
+impl RtfAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(RtfAdapter))
+    }
+}
+impl GetMetadata for RtfAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Group {
+    skip: bool,
+    /// number of fallback characters after a \u escape
+    uc: usize,
+}
+
+struct Converter {
+    text: String,
+    /// \'hh escapes, decoded together since they can form multi-byte characters
+    pending: Vec<u8>,
+    encoding: &'static Encoding,
+    group: Group,
+    stack: Vec<Group>,
+    /// fallback characters still to skip after a \u escape
+    skip_chars: usize,
+}
+
+impl Converter {
+    fn flush_pending(&mut self) {
+        if !self.pending.is_empty() {
+            let (text, _) = self.encoding.decode_without_bom_handling(&self.pending);
+            self.text.push_str(&text);
+            self.pending.clear();
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        self.flush_pending();
+        if !self.group.skip {
+            self.text.push(c);
+        }
+    }
+
+    /// returns true for characters that replace a preceding \u escape
+    fn skip_fallback(&mut self) -> bool {
+        if self.skip_chars > 0 {
+            self.skip_chars -= 1;
+            return true;
+        }
+        false
+    }
+
+    fn control_word(&mut self, word: &str, param: Option<i32>) {
+        match word {
+            "par" | "line" | "row" | "sect" | "page" => self.push('\n'),
+            "tab" | "cell" => self.push('\t'),
+            "emdash" => self.push('—'),
+            "endash" => self.push('–'),
+            "bullet" => self.push('•'),
+            "lquote" => self.push('‘'),
+            "rquote" => self.push('’'),
+            "ldblquote" => self.push('“'),
+            "rdblquote" => self.push('”'),
+            "uc" => self.group.uc = param.unwrap_or(1).max(0) as usize,
+            "u" => {
+                // negative values are used for code points above 32767
+                let code = param.unwrap_or(0) as i64;
+                let code = if code < 0 { code + 65536 } else { code };
+                self.push(std::char::from_u32(code as u32).unwrap_or('\u{fffd}'));
+                self.skip_chars = self.group.uc;
+            }
+            "ansicpg" => {
+                let label = match param {
+                    Some(65001) => "utf-8".to_string(),
+                    Some(cp) => format!("windows-{}", cp),
+                    None => return,
+                };
+                if let Some(encoding) = Encoding::for_label(label.as_bytes()) {
+                    self.encoding = encoding;
+                }
+            }
+            w if SKIP_DESTINATIONS.contains(&w) => self.group.skip = true,
+            _ => {}
+        }
+    }
+}
+
+/// a small rtf to text converter, ignoring all formatting
+pub fn rtf_to_text(rtf: &[u8]) -> String {
+    let mut c = Converter {
+        text: String::new(),
+        pending: Vec::new(),
+        encoding: encoding_rs::WINDOWS_1252,
+        group: Group { skip: false, uc: 1 },
+        stack: Vec::new(),
+        skip_chars: 0,
+    };
+    let mut i = 0;
+    while i < rtf.len() {
+        let b = rtf[i];
+        i += 1;
+        match b {
+            b'{' => {
+                c.flush_pending();
+                c.stack.push(c.group);
+                c.skip_chars = 0;
+            }
+            b'}' => {
+                c.flush_pending();
+                if let Some(group) = c.stack.pop() {
+                    c.group = group;
+                }
+                c.skip_chars = 0;
+            }
+            b'\\' if i < rtf.len() => {
+                let next = rtf[i];
+                i += 1;
+                match next {
+                    b'\'' => {
+                        let hex = rtf.get(i..i + 2).and_then(|h| std::str::from_utf8(h).ok());
+                        i += 2;
+                        if c.skip_fallback() {
+                            continue;
+                        }
+                        if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                            if !c.group.skip {
+                                c.pending.push(byte);
+                            }
+                        }
+                    }
+                    // "ignorable destination": everything in the group is metadata we don't know
+                    b'*' => c.group.skip = true,
+                    b'~' => c.push(' '),
+                    b'_' => c.push('-'),
+                    b'-' => {}
+                    b'\n' | b'\r' => c.push('\n'),
+                    b if b.is_ascii_alphabetic() => {
+                        let start = i - 1;
+                        while i < rtf.len() && rtf[i].is_ascii_alphabetic() {
+                            i += 1;
+                        }
+                        let word = String::from_utf8_lossy(&rtf[start..i]).into_owned();
+                        let param_start = i;
+                        if i < rtf.len() && rtf[i] == b'-' {
+                            i += 1;
+                        }
+                        while i < rtf.len() && rtf[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                        let param = std::str::from_utf8(&rtf[param_start..i])
+                            .ok()
+                            .and_then(|p| p.parse().ok());
+                        // a single space delimits the control word and is not part of the text
+                        if i < rtf.len() && rtf[i] == b' ' {
+                            i += 1;
+                        }
+                        if word == "bin" {
+                            // raw binary data follows
+                            i += param.unwrap_or(0).max(0) as usize;
+                        } else {
+                            c.control_word(&word, param);
+                        }
+                    }
+                    // escaped \ { } and unknown control symbols
+                    other => {
+                        if !c.skip_fallback() {
+                            c.push(other as char)
+                        }
+                    }
+                }
+            }
+            b'\r' | b'\n' => {}
+            // 8-bit text is not allowed by the spec, but some writers use it anyway
+            b if b >= 0x80 => {
+                if !c.skip_fallback() && !c.group.skip {
+                    c.pending.push(b)
+                }
+            }
+            b => {
+                if !c.skip_fallback() {
+                    c.push(b as char)
+                }
+            }
+        }
+    }
+    c.flush_pending();
+    c.text
+}
+
+impl WritingFileAdapterTrait for RtfAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut rtf = Vec::new();
+        ai.inp.read_to_end(&mut rtf)?;
+        for line in rtf_to_text(&rtf).trim_end().lines() {
+            writeln!(oup, "{}{}", ai.line_prefix, line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple() {
+        let rtf = br#"{\rtf1\ansi\ansicpg1252\deff0{\fonttbl{\f0\fswiss Helvetica;}}{\colortbl;\red0\green0\blue0;}
+{\*\generator Riched20 10.0;}{\info{\title secret title}}
+\pard\plain\f0\fs24 hello \b world\b0\par
+this is just a\tab test with \'e4 and \u8364?\~euro\par
+{\*\themedata 0123abcd}escaped \{braces\} and \\backslash\par
+}"#;
+        assert_eq!(
+            rtf_to_text(rtf),
+            "hello world\nthis is just a\ttest with ä and € euro\nescaped {braces} and \\backslash\n"
+        );
+    }
+
+    #[test]
+    fn codepage() {
+        // cp1251 (cyrillic) and utf-16 escapes with two fallback characters
+        let rtf = br"{\rtf1\ansi\ansicpg1251 \'cf\'f0\'e8\'e2\'e5\'f2 {\uc2\u-3913\'3f\'3f}!}";
+        assert_eq!(rtf_to_text(rtf), "Привет \u{f0b7}!");
+    }
+}
@@ -0,0 +1,238 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use serde_json::{Map, Value as Json};
+use std::convert::TryFrom;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["msgpack", "mpk", "cbor", "bson"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "serialized".to_owned(),
+        version: 1,
+        description: "Renders MessagePack, CBOR and BSON files as indented JSON".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType("application/cbor".to_owned())]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct SerializedAdapter;
+
+impl SerializedAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(SerializedAdapter))
+    }
+}
+impl GetMetadata for SerializedAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// binary blobs are kept if they are text, otherwise there's nothing useful to search in
+pub fn bytes_to_json(bytes: Vec<u8>) -> Json {
+    match String::from_utf8(bytes) {
+        Result::Ok(text) => Json::String(text),
+        Err(e) => Json::String(format!("<{} bytes>", e.as_bytes().len())),
+    }
+}
+
+/// json only allows string keys
+pub fn key_to_string(key: Json) -> String {
+    match key {
+        Json::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+pub fn float_to_json(f: f64) -> Json {
+    serde_json::Number::from_f64(f)
+        .map(Json::Number)
+        .unwrap_or_else(|| Json::String(f.to_string()))
+}
+
+fn msgpack_to_json(value: rmpv::Value) -> Json {
+    use rmpv::Value;
+    match value {
+        Value::Nil => Json::Null,
+        Value::Boolean(b) => Json::Bool(b),
+        Value::Integer(i) => match (i.as_u64(), i.as_i64()) {
+            (Some(u), _) => Json::from(u),
+            (None, Some(i)) => Json::from(i),
+            (None, None) => Json::Null,
+        },
+        Value::F32(f) => float_to_json(f64::from(f)),
+        Value::F64(f) => float_to_json(f),
+        Value::String(s) => bytes_to_json(s.into_bytes()),
+        Value::Binary(b) => bytes_to_json(b),
+        Value::Array(a) => Json::Array(a.into_iter().map(msgpack_to_json).collect()),
+        Value::Map(m) => Json::Object(
+            m.into_iter()
+                .map(|(k, v)| (key_to_string(msgpack_to_json(k)), msgpack_to_json(v)))
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Ext(typ, data) => Json::String(format!("<ext {}: {} bytes>", typ, data.len())),
+    }
+}
+
+fn cbor_to_json(value: ciborium::value::Value) -> Json {
+    use ciborium::value::Value;
+    match value {
+        Value::Null => Json::Null,
+        Value::Bool(b) => Json::Bool(b),
+        Value::Integer(i) => {
+            let i = i128::from(i);
+            match (u64::try_from(i), i64::try_from(i)) {
+                (Result::Ok(u), _) => Json::from(u),
+                (_, Result::Ok(i)) => Json::from(i),
+                _ => Json::String(i.to_string()),
+            }
+        }
+        Value::Float(f) => float_to_json(f),
+        Value::Text(s) => Json::String(s),
+        Value::Bytes(b) => bytes_to_json(b),
+        // tags are type hints like "this is a date", the content is what we want
+        Value::Tag(_, v) => cbor_to_json(*v),
+        Value::Array(a) => Json::Array(a.into_iter().map(cbor_to_json).collect()),
+        Value::Map(m) => Json::Object(
+            m.into_iter()
+                .map(|(k, v)| (key_to_string(cbor_to_json(k)), cbor_to_json(v)))
+                .collect::<Map<_, _>>(),
+        ),
+        _ => Json::Null,
+    }
+}
+
+/// files often contain multiple concatenated values (e.g. a mongodump collection), so read until the end
+fn read_all(
+    data: Vec<u8>,
+    read_one: impl Fn(&mut Cursor<Vec<u8>>) -> Result<Json>,
+) -> Result<Vec<Json>> {
+    let len = data.len() as u64;
+    let mut cursor = Cursor::new(data);
+    let mut values = Vec::new();
+    while cursor.position() < len {
+        values.push(read_one(&mut cursor)?);
+    }
+    Ok(values)
+}
+
+fn decode(extension: &str, data: Vec<u8>) -> Result<Vec<Json>> {
+    match extension {
+        "msgpack" | "mpk" => read_all(data, |r| Ok(msgpack_to_json(rmpv::decode::read_value(r)?))),
+        "bson" => read_all(data, |r| {
+            Ok(bson::Bson::Document(bson::Document::from_reader(r)?).into_relaxed_extjson())
+        }),
+        _ => read_all(data, |r| {
+            Ok(cbor_to_json(ciborium::de::from_reader::<
+                ciborium::value::Value,
+                _,
+            >(r)?))
+        }),
+    }
+}
+
+impl WritingFileAdapterTrait for SerializedAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let extension = match detection_reason {
+            SlowMatcher::Fast(FastMatcher::FileExtension(ext)) => ext.as_str(),
+            // only cbor has a mime type
+            _ => "cbor",
+        };
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let values = decode(extension, data)?;
+        let multiple = values.len() > 1;
+        for (i, value) in values.iter().enumerate() {
+            let line_prefix = if multiple {
+                format!("{}Record {}: ", ai.line_prefix, i + 1)
+            } else {
+                ai.line_prefix.clone()
+            };
+            for line in serde_json::to_string_pretty(value)?.lines() {
+                writeln!(oup, "{}{}", line_prefix, line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn adapt(fname: &str, data: Vec<u8>) -> Result<String> {
+        let filepath = PathBuf::from(fname);
+        let (a, _) = simple_adapt_info(&filepath, Box::new(Cursor::new(data)));
+        let ext = filepath.extension().unwrap().to_string_lossy().to_string();
+        let mut r = SerializedAdapter::new()
+            .adapt(a, &SlowMatcher::Fast(FastMatcher::FileExtension(ext)))?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    static EXPECTED: &str = "PREFIX:{\nPREFIX:  \"name\": \"alice\",\nPREFIX:  \"tags\": [\nPREFIX:    \"admin\",\nPREFIX:    1\nPREFIX:  ]\nPREFIX:}\n";
+
+    #[test]
+    fn msgpack() -> Result<()> {
+        use rmpv::Value;
+        let value = Value::Map(vec![
+            (Value::from("name"), Value::Binary(b"alice".to_vec())),
+            (
+                Value::from("tags"),
+                Value::Array(vec![Value::from("admin"), Value::from(1)]),
+            ),
+        ]);
+        let mut data = Vec::new();
+        rmpv::encode::write_value(&mut data, &value)?;
+        assert_eq!(adapt("test.msgpack", data)?, EXPECTED);
+        Ok(())
+    }
+
+    #[test]
+    fn cbor() -> Result<()> {
+        use ciborium::value::Value;
+        let value = Value::Map(vec![
+            (Value::from("name"), Value::from("alice")),
+            (
+                Value::from("tags"),
+                Value::Array(vec![
+                    Value::Tag(32, Box::new(Value::from("admin"))),
+                    Value::from(1),
+                ]),
+            ),
+        ]);
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(&value, &mut data)?;
+        assert_eq!(adapt("test.cbor", data)?, EXPECTED);
+        Ok(())
+    }
+
+    #[test]
+    fn bson_sequence() -> Result<()> {
+        let mut data = Vec::new();
+        bson::doc! { "name": "alice" }.to_writer(&mut data)?;
+        bson::doc! { "name": "bob", "n": 2.5 }.to_writer(&mut data)?;
+        assert_eq!(
+            adapt("test.bson", data)?,
+            "PREFIX:Record 1: {\nPREFIX:Record 1:   \"name\": \"alice\"\nPREFIX:Record 1: }\nPREFIX:Record 2: {\nPREFIX:Record 2:   \"name\": \"bob\",\nPREFIX:Record 2:   \"n\": 2.5\nPREFIX:Record 2: }\n"
+        );
+        Ok(())
+    }
+}
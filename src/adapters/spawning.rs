@@ -97,18 +97,27 @@ pub fn map_exe_error(err: std::io::Error, exe_name: &str, help: &str) -> Error {
 
 struct ProcWaitReader {
     proce: Child,
+    /// the thread that writes the input to stdin, see pipe_output_in_thread
+    writer: Option<std::thread::JoinHandle<std::io::Result<u64>>>,
 }
 impl Read for ProcWaitReader {
     fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
         let status = self.proce.wait()?;
-        if status.success() {
-            Ok(0)
-        } else {
-            Err(std::io::Error::new(
+        if !status.success() {
+            return Err(std::io::Error::new(
                 std::io::ErrorKind::Other,
                 format_err!("subprocess failed: {:?}", status),
-            ))
+            ));
         }
+        if let Some(writer) = self.writer.take() {
+            match writer.join().expect("stdin writer panicked") {
+                // the process does not have to read all of its input
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+                Err(e) => return Err(e),
+                Ok(_) => {}
+            }
+        }
+        Ok(0)
     }
 }
 pub fn pipe_output(
@@ -134,7 +143,33 @@ pub fn pipe_output(
         Ok(())
     })
     .unwrap()?;
-    Ok(Box::new(stdo.chain(ProcWaitReader { proce: cmd })))
+    Ok(Box::new(stdo.chain(ProcWaitReader {
+        proce: cmd,
+        writer: None,
+    })))
+}
+
+/// like pipe_output, but the input is written to stdin from a thread while the output is read.
+/// needed for commands that write output before they read all of their input,
+/// since they block once the stdout pipe is full
+pub fn pipe_output_in_thread(
+    mut cmd: Command,
+    mut inp: ReadBox,
+    exe_name: &str,
+    help: &str,
+) -> Result<ReadBox> {
+    let mut cmd = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| map_exe_error(e, exe_name, help))?;
+    let mut stdi = cmd.stdin.take().expect("is piped");
+    let stdo = cmd.stdout.take().expect("is piped");
+    let writer = std::thread::spawn(move || std::io::copy(&mut inp, &mut stdi));
+    Ok(Box::new(stdo.chain(ProcWaitReader {
+        proce: cmd,
+        writer: Some(writer),
+    })))
 }
 
 impl FileAdapter for SpawningFileAdapter {
@@ -24,7 +24,9 @@ lazy_static! {
         slow_matchers: Some(vec![SlowMatcher::MimeType(
             "application/x-sqlite3".to_owned()
         )]),
-        disabled_by_default: false
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
     };
 }
 
@@ -59,6 +61,45 @@ fn format_blob(b: ValueRef) -> String {
     }
 }
 
+/// writes each row as `table: column=value, ...`
+pub fn write_table(
+    conn: &Connection,
+    table: &str,
+    line_prefix: &str,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    // can't use query param at that position
+    let mut sel = conn.prepare(&format!(
+        "select * from {}",
+        rusqlite::vtab::escape_double_quote(table)
+    ))?;
+    let mut z = sel.query(NO_PARAMS)?;
+    let col_names: Vec<String> = z
+        .column_names()
+        .ok_or_else(|| format_err!("no column names"))?
+        .into_iter()
+        .map(|e| e.to_owned())
+        .collect();
+    // writeln!(oup, "{}: {}", table, cols.join(", "))?;
+
+    // kind of shitty (lossy) output. maybe output real csv or something?
+    while let Some(row) = z.next()? {
+        writeln!(
+            oup,
+            "{}{}: {}",
+            line_prefix,
+            table,
+            col_names
+                .iter()
+                .enumerate()
+                .map(|(i, e)| format!("{}={}", e, format_blob(row.get_raw(i))))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )?;
+    }
+    Ok(())
+}
+
 impl WritingFileAdapterTrait for SqliteAdapter {
     fn adapt_write(
         &self,
@@ -88,35 +129,7 @@ impl WritingFileAdapterTrait for SqliteAdapter {
             .collect();
         debug!("db has {} tables", tables.len());
         for table in tables {
-            // can't use query param at that position
-            let mut sel = conn.prepare(&format!(
-                "select * from {}",
-                rusqlite::vtab::escape_double_quote(&table)
-            ))?;
-            let mut z = sel.query(NO_PARAMS)?;
-            let col_names: Vec<String> = z
-                .column_names()
-                .ok_or_else(|| format_err!("no column names"))?
-                .into_iter()
-                .map(|e| e.to_owned())
-                .collect();
-            // writeln!(oup, "{}: {}", table, cols.join(", "))?;
-
-            // kind of shitty (lossy) output. maybe output real csv or something?
-            while let Some(row) = z.next()? {
-                writeln!(
-                    oup,
-                    "{}{}: {}",
-                    line_prefix,
-                    table,
-                    col_names
-                        .iter()
-                        .enumerate()
-                        .map(|(i, e)| format!("{}={}", e, format_blob(row.get_raw(i))))
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                )?;
-            }
+            write_table(&conn, &table, &line_prefix, oup)?;
         }
         Ok(())
     }
@@ -125,8 +138,8 @@ impl WritingFileAdapterTrait for SqliteAdapter {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{test_utils::*};
-    use std::{fs::File};
+    use crate::test_utils::*;
+    use std::fs::File;
 
     #[test]
     fn simple() -> Result<()> {
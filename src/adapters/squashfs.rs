@@ -0,0 +1,192 @@
+use super::*;
+use crate::preproc::rga_preproc;
+use ::backhand::compression::{CompressionAction, Compressor, DefaultCompressor};
+use ::backhand::kind::{Kind, BE_V4_0, LE_V4_0};
+use ::backhand::{BackhandError, BufReadSeek, FilesystemCompressor, FilesystemReader, InnerNode};
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["squashfs", "sqsh", "snap"];
+static MIME_TYPES: &[&str] = &["application/vnd.squashfs"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "squashfs".to_owned(),
+        version: 1,
+        description: "Reads SquashFS images (firmware, snap packages) and recurses down into the files of the file system"
+            .to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct SquashfsAdapter;
+
+impl SquashfsAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(SquashfsAdapter))
+    }
+}
+impl GetMetadata for SquashfsAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// backhand brings its own liblzma and zstd, which can not be linked next to the ones of xz2 and zstd.
+/// so its xz and zstd features are disabled and those blocks are decoded here instead
+struct BlockDecompressor;
+
+static BLOCK_DECOMPRESSOR: BlockDecompressor = BlockDecompressor;
+
+impl CompressionAction for BlockDecompressor {
+    type Error = BackhandError;
+    type Compressor = Compressor;
+    type FilesystemCompressor = FilesystemCompressor;
+    type SuperBlock = ::backhand::SuperBlock;
+
+    fn decompress(
+        &self,
+        bytes: &[u8],
+        out: &mut Vec<u8>,
+        compressor: Compressor,
+    ) -> Result<(), BackhandError> {
+        match compressor {
+            Compressor::Xz => {
+                xz2::read::XzDecoder::new(bytes).read_to_end(out)?;
+            }
+            Compressor::Zstd => {
+                zstd::stream::read::Decoder::new(bytes)?.read_to_end(out)?;
+            }
+            _ => DefaultCompressor.decompress(bytes, out, compressor)?,
+        }
+        Ok(())
+    }
+
+    fn compress(
+        &self,
+        bytes: &[u8],
+        fc: FilesystemCompressor,
+        block_size: u32,
+    ) -> Result<Vec<u8>, BackhandError> {
+        DefaultCompressor.compress(bytes, fc, block_size)
+    }
+}
+
+fn adapt_filesystem(
+    filesystem: &FilesystemReader,
+    ai: &AdaptInfo,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for node in filesystem.files() {
+        let file = match &node.inner {
+            InnerNode::File(file) => file,
+            _ => continue,
+        };
+        let path = node.fullpath.strip_prefix("/").unwrap_or(&node.fullpath);
+        debug!("{}|{}", ai.filepath_hint.display(), path.display());
+        // the file reader borrows the filesystem, so the inner adapter can not read from it directly
+        let mut data = Vec::new();
+        filesystem.file(file).reader().read_to_end(&mut data)?;
+        let mut inner = rga_preproc(AdaptInfo {
+            filepath_hint: path.to_path_buf(),
+            is_real_file: false,
+            inp: Box::new(Cursor::new(data)),
+            line_prefix: format!("{}{}: ", ai.line_prefix, path.display()),
+            archive_recursion_depth: ai.archive_recursion_depth + 1,
+            config: ai.config.clone(),
+        })?;
+        std::io::copy(&mut inner, oup)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for SquashfsAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // backhand needs to seek, so only an image within an archive is read into memory
+        let mut reader: Box<dyn BufReadSeek> = if ai.is_real_file {
+            Box::new(BufReader::new(File::open(&ai.filepath_hint)?))
+        } else {
+            let mut data = Vec::new();
+            ai.inp.read_to_end(&mut data)?;
+            Box::new(Cursor::new(data))
+        };
+        // big endian images (some older firmware) start with `sqsh` instead of `hsqs`
+        let kind = if reader.fill_buf()?.starts_with(b"sqsh") {
+            Kind::new_v4_with_const(&BLOCK_DECOMPRESSOR, BE_V4_0)
+        } else {
+            Kind::new_v4_with_const(&BLOCK_DECOMPRESSOR, LE_V4_0)
+        };
+        let filesystem = FilesystemReader::from_reader_with_offset_and_kind(reader, 0, kind)?;
+        adapt_filesystem(&filesystem, &ai, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use ::backhand::{FilesystemWriter, NodeHeader};
+
+    #[test]
+    fn squashfs() -> Result<()> {
+        let mut writer = FilesystemWriter::default();
+        writer.set_compressor(FilesystemCompressor::new(Compressor::Gzip, None)?);
+        let header = NodeHeader::default();
+        writer.push_dir("etc", header)?;
+        writer.push_file(
+            &b"root:x:0:0:root:/root:/bin/sh\n"[..],
+            "etc/passwd",
+            header,
+        )?;
+        writer.push_dir("www", header)?;
+        writer.push_file(
+            &b"<p>Firmware <b>v2.1</b></p>"[..],
+            "www/index.html",
+            header,
+        )?;
+        writer.push_symlink("etc/passwd", "passwd", header)?;
+        let mut image = Cursor::new(Vec::new());
+        writer.write(&mut image)?;
+
+        let (mut a, d) = simple_adapt_info(
+            Path::new("rootfs.squashfs"),
+            Box::new(Cursor::new(image.into_inner())),
+        );
+        // the html members show that the members are adapted
+        a.config.args.adapters = vec!["+html".to_string()];
+        // the image only exists in memory
+        a.is_real_file = false;
+        let mut r = SquashfsAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "root:x:0:0:root:/root:/bin/sh
+PREFIX:www/index.html: Firmware v2.1
+"
+        );
+        Ok(())
+    }
+}
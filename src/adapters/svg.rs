@@ -0,0 +1,222 @@
+use super::epub::BLOCK_ELEMENTS;
+use super::xml::{text_of, xml_reader};
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use quick_xml::events::{BytesStart, Event};
+use std::io::{BufRead, BufReader};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["svg", "svgz"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "svg".to_owned(),
+        version: 1,
+        description: "Extracts the text labels and title/description of SVG images (including .svgz and draw.io diagrams)".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType("image/svg+xml".to_owned())]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct SvgAdapter;
+
+impl SvgAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(SvgAdapter))
+    }
+}
+impl GetMetadata for SvgAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// elements whose text content is shown (or meant to be read)
+static TEXT_ELEMENTS: &[&[u8]] = &[b"text", b"title", b"desc", b"foreignObject"];
+
+/// inkscape writes each line of a multi-line text as a tspan with this role
+fn is_line_tspan(e: &BytesStart) -> bool {
+    e.local_name().as_ref() == b"tspan"
+        && e.attributes()
+            .with_checks(false)
+            .flatten()
+            .any(|a| a.key.as_ref() == b"sodipodi:role" && a.value.as_ref() == b"line")
+}
+
+#[derive(Default)]
+struct Switch {
+    depth: usize,
+    child_seen: bool,
+}
+
+pub fn write_svg_text(line_prefix: &str, inp: impl BufRead, oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(inp);
+    let mut buf = Vec::new();
+    let mut line = String::new();
+    let mut depth = 0;
+    // depth of the innermost element whose text is shown
+    let mut text_depth: Option<usize> = None;
+    // depth of an element we don't want anything from
+    let mut skip_depth: Option<usize> = None;
+    let mut switches: Vec<Switch> = Vec::new();
+    let flush = |line: &mut String, oup: &mut dyn Write| -> Result<()> {
+        let text = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            writeln!(oup, "{}{}", line_prefix, text)?;
+        }
+        line.clear();
+        Ok(())
+    };
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(e) | Event::Empty(e) if skip_depth.is_none() => {
+                let is_start = matches!(event, Event::Start(_));
+                depth += 1;
+                // only the first child of a <switch> is rendered, the others are fallbacks
+                // (draw.io labels are a foreignObject followed by a plain <text> copy)
+                if let Some(switch) = switches.last_mut() {
+                    if switch.depth + 1 == depth {
+                        if switch.child_seen {
+                            skip_depth = Some(depth);
+                        }
+                        switch.child_seen = true;
+                    }
+                }
+                let name = e.local_name();
+                if skip_depth.is_none() {
+                    if name.as_ref() == b"switch" && is_start {
+                        switches.push(Switch {
+                            depth,
+                            child_seen: false,
+                        });
+                    } else if TEXT_ELEMENTS.contains(&name.as_ref()) {
+                        flush(&mut line, oup)?;
+                        if is_start && text_depth.is_none() {
+                            text_depth = Some(depth);
+                        }
+                    } else if text_depth.is_some()
+                        && (BLOCK_ELEMENTS.contains(&name.as_ref()) || is_line_tspan(e))
+                    {
+                        flush(&mut line, oup)?;
+                    }
+                }
+                if !is_start {
+                    if skip_depth == Some(depth) {
+                        skip_depth = None;
+                    }
+                    depth -= 1;
+                }
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(e) => {
+                if skip_depth == Some(depth) {
+                    skip_depth = None;
+                } else if skip_depth.is_none() {
+                    let name = e.local_name();
+                    if text_depth == Some(depth) {
+                        flush(&mut line, oup)?;
+                        text_depth = None;
+                    } else if text_depth.is_some() && BLOCK_ELEMENTS.contains(&name.as_ref()) {
+                        flush(&mut line, oup)?;
+                    }
+                    if switches.last().map(|s| s.depth) == Some(depth) {
+                        switches.pop();
+                    }
+                }
+                depth -= 1;
+            }
+            Event::Text(t) if skip_depth.is_none() && text_depth.is_some() => {
+                line.push_str(&text_of(t));
+            }
+            Event::CData(t) if skip_depth.is_none() && text_depth.is_some() => {
+                line.push_str(&String::from_utf8_lossy(t));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    flush(&mut line, oup)
+}
+
+impl WritingFileAdapterTrait for SvgAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut inp = BufReader::new(ai.inp);
+        // .svgz is just gzipped svg
+        if inp.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+            let inp = BufReader::new(flate2::read::MultiGzDecoder::new(inp));
+            write_svg_text(&ai.line_prefix, inp, oup)
+        } else {
+            write_svg_text(&ai.line_prefix, inp, oup)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use flate2::write::GzEncoder;
+    use std::io::Cursor;
+
+    static SVG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:sodipodi="http://sodipodi.sourceforge.net/DTD/sodipodi-0.dtd" width="200" height="100">
+  <title>Network overview</title>
+  <desc>Draft &amp; unreviewed</desc>
+  <style>.label { font: 12px sans-serif; }</style>
+  <rect x="0" y="0" width="50" height="20"/>
+  <text x="10" y="20" class="label">Load <tspan font-weight="bold">balancer</tspan></text>
+  <text x="10" y="60"><tspan sodipodi:role="line">first line</tspan><tspan sodipodi:role="line">second line</tspan></text>
+  <switch>
+    <foreignObject width="100" height="40"><div xmlns="http://www.w3.org/1999/xhtml"><div>Database<br/>primary</div></div></foreignObject>
+    <text x="10" y="80">Database primary</text>
+  </switch>
+  <switch><g/><a><text>Text is not SVG - cannot display</text></a></switch>
+</svg>
+"#;
+
+    static EXPECTED: &str = "PREFIX:Network overview
+PREFIX:Draft & unreviewed
+PREFIX:Load balancer
+PREFIX:first line
+PREFIX:second line
+PREFIX:Database
+PREFIX:primary
+";
+
+    fn adapt(filename: &str, data: Vec<u8>) -> Result<String> {
+        let (a, d) = simple_adapt_info(Path::new(filename), Box::new(Cursor::new(data)));
+        let mut r = SvgAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn svg() -> Result<()> {
+        assert_eq!(adapt("test.svg", SVG.as_bytes().to_vec())?, EXPECTED);
+        Ok(())
+    }
+
+    #[test]
+    fn svgz() -> Result<()> {
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(SVG.as_bytes())?;
+        assert_eq!(adapt("test.svgz", gz.finish()?)?, EXPECTED);
+        Ok(())
+    }
+}
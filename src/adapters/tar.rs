@@ -4,7 +4,9 @@ use ::tar::EntryType::Regular;
 use anyhow::*;
 use lazy_static::lazy_static;
 use log::*;
-use std::path::PathBuf;
+use std::io::Cursor;
+use wheel::write_package_metadata;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
 
 static EXTENSIONS: &[&str] = &["tar"];
 
@@ -19,15 +21,17 @@ lazy_static! {
             .map(|s| FastMatcher::FileExtension(s.to_string()))
             .collect(),
         slow_matchers: None,
-        disabled_by_default: false
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
     };
 }
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct TarAdapter;
 
 impl TarAdapter {
-    pub fn new() -> TarAdapter {
-        TarAdapter
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(TarAdapter))
     }
 }
 impl GetMetadata for TarAdapter {
@@ -36,41 +40,133 @@ impl GetMetadata for TarAdapter {
     }
 }
 
-impl FileAdapter for TarAdapter {
-    fn adapt(&self, ai: AdaptInfo, _detection_reason: &SlowMatcher) -> Result<()> {
+/// recurse into every regular file of a tar stream, prefixed with its path
+pub fn adapt_entries(
+    inp: impl Read,
+    filepath_hint: &Path,
+    line_prefix: &str,
+    archive_recursion_depth: i32,
+    config: &PreprocConfig,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let mut archive = ::tar::Archive::new(inp);
+    for entry in archive.entries()? {
+        let mut file = entry?;
+        if Regular != file.header().entry_type() {
+            continue;
+        }
+        let path = file.path()?.into_owned();
+        debug!(
+            "{}|{}: {}",
+            filepath_hint.display(),
+            path.display(),
+            print_bytes(file.header().size()? as f64),
+        );
+        // the entry borrows the archive, so the inner adapter can not read from it directly
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let name = path.to_string_lossy();
+        // the PKG-INFO of python sdists
+        if wheel::is_package_metadata(&name) {
+            write_package_metadata(line_prefix, &name, &data, oup)?;
+            continue;
+        }
+        let mut inner = rga_preproc(AdaptInfo {
+            line_prefix: format!("{}{}: ", line_prefix, path.display()),
+            filepath_hint: path,
+            is_real_file: false,
+            archive_recursion_depth: archive_recursion_depth + 1,
+            inp: Box::new(Cursor::new(data)),
+            config: config.clone(),
+        })?;
+        std::io::copy(&mut inner, oup)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for TarAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
         let AdaptInfo {
             filepath_hint,
-            mut inp,
-            oup,
+            inp,
             line_prefix,
             archive_recursion_depth,
             config,
             ..
         } = ai;
-        let mut archive = ::tar::Archive::new(&mut inp);
-        for entry in archive.entries()? {
-            let mut file = entry?;
-            if Regular == file.header().entry_type() {
-                let path = PathBuf::from(file.path()?.to_owned());
-                debug!(
-                    "{}|{}: {}",
-                    filepath_hint.display(),
-                    path.display(),
-                    print_bytes(file.header().size()? as f64),
-                );
-                let line_prefix = &format!("{}{}: ", line_prefix, path.display());
-                let ai2: AdaptInfo = AdaptInfo {
-                    filepath_hint: &path,
-                    is_real_file: false,
-                    archive_recursion_depth: archive_recursion_depth + 1,
-                    inp: &mut file,
-                    oup,
-                    line_prefix,
-                    config: config.clone(),
-                };
-                rga_preproc(ai2)?;
-            }
-        }
+        adapt_entries(
+            inp,
+            &filepath_hint,
+            &line_prefix,
+            archive_recursion_depth,
+            &config,
+            oup,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn tar() -> Result<()> {
+        let mut builder = ::tar::Builder::new(Vec::new());
+        let mut header = ::tar::Header::new_gnu();
+        let content = b"hello from a tarball\n";
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "dir/hello.txt", &content[..])?;
+        let html = b"<p>some <b>markup</b></p>";
+        header.set_size(html.len() as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, "dir/page.html", &html[..])?;
+        let data = builder.into_inner()?;
+
+        let (mut a, d) = simple_adapt_info(Path::new("test.tar"), Box::new(Cursor::new(data)));
+        // the html members show that the members are adapted
+        a.config.args.adapters = vec!["+html".to_string()];
+        let mut r = TarAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "hello from a tarball\nPREFIX:dir/page.html: some markup\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sdist() -> Result<()> {
+        let mut builder = ::tar::Builder::new(Vec::new());
+        let mut header = ::tar::Header::new_gnu();
+        let pkg_info =
+            b"Metadata-Version: 2.1\nName: example\nRequires-Dist: requests\n\nDoes things.\n";
+        header.set_size(pkg_info.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "example-1.0/PKG-INFO", &pkg_info[..])?;
+        let data = builder.into_inner()?;
+
+        let (a, d) = simple_adapt_info(Path::new("example-1.0.tar"), Box::new(Cursor::new(data)));
+        let mut r = TarAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Metadata-Version: 2.1
+PREFIX:Name: example
+PREFIX:Requires-Dist: requests
+PREFIX:example-1.0/PKG-INFO: Does things.
+"
+        );
         Ok(())
     }
 }
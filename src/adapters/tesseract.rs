@@ -3,11 +3,12 @@ use lazy_static::lazy_static;
 use spawning::{SpawningFileAdapter, SpawningFileAdapterTrait};
 use std::process::Command;
 
-static EXTENSIONS: &[&str] = &["jpg", "png"];
+pub static EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tif", "tiff", "webp"];
+static MIME_TYPES: &[&str] = &["image/jpeg", "image/png", "image/tiff", "image/webp"];
 
 lazy_static! {
     static ref METADATA: AdapterMeta = AdapterMeta {
-        name: "tesseract".to_owned(),
+        name: "ocr".to_owned(),
         version: 1,
         description: "Uses tesseract to run OCR on images to make them searchable. May need -j1 to prevent overloading the system. Make sure you have tesseract installed.".to_owned(),
         recurses: false,
@@ -15,16 +16,23 @@ lazy_static! {
             .iter()
             .map(|s| FastMatcher::FileExtension(s.to_string()))
             .collect(),
-        slow_matchers: None,
-        disabled_by_default: true
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![]
     };
 }
 #[derive(Default)]
 pub struct TesseractAdapter {}
 
 impl TesseractAdapter {
-    pub fn new() -> TesseractAdapter {
-        TesseractAdapter {}
+    pub fn new() -> SpawningFileAdapter {
+        SpawningFileAdapter::new(Box::new(TesseractAdapter {}))
     }
 }
 
@@ -37,9 +45,9 @@ impl SpawningFileAdapterTrait for TesseractAdapter {
     fn get_exe(&self) -> &str {
         "tesseract"
     }
-    fn command(&self, _filepath_hint: &Path, mut cmd: Command) -> Command {
+    fn command(&self, _filepath_hint: &Path, mut cmd: Command) -> Result<Command> {
         // rg already does threading
         cmd.env("OMP_THREAD_LIMIT", "1").arg("-").arg("-");
-        cmd
+        Ok(cmd)
     }
 }
@@ -0,0 +1,317 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["torrent"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "torrent".to_owned(),
+        version: 1,
+        description: "Lists the files, trackers and comment of BitTorrent .torrent files"
+            .to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/x-bittorrent".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct TorrentAdapter;
+
+impl TorrentAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(TorrentAdapter))
+    }
+}
+impl GetMetadata for TorrentAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Bencode {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dict(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode {
+    fn get(&self, key: &str) -> Option<&Bencode> {
+        match self {
+            Bencode::Dict(d) => d.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+    fn as_str(&self) -> Option<String> {
+        match self {
+            Bencode::Bytes(b) => Some(String::from_utf8_lossy(b).into_owned()),
+            _ => None,
+        }
+    }
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Bencode::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+    fn as_list(&self) -> &[Bencode] {
+        match self {
+            Bencode::List(l) => l,
+            _ => &[],
+        }
+    }
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn take_until(&mut self, end: u8) -> Result<&'a str> {
+        let len = self.data[self.pos..]
+            .iter()
+            .position(|&b| b == end)
+            .ok_or_else(|| format_err!("unterminated value at {}", self.pos))?;
+        let s = std::str::from_utf8(&self.data[self.pos..self.pos + len])?;
+        self.pos += len + 1;
+        Ok(s)
+    }
+
+    fn parse(&mut self) -> Result<Bencode> {
+        let c = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| format_err!("unexpected end of file"))?;
+        Ok(match c {
+            b'i' => {
+                self.pos += 1;
+                Bencode::Int(self.take_until(b'e')?.parse()?)
+            }
+            b'l' | b'd' => {
+                self.pos += 1;
+                self.depth += 1;
+                if self.depth > 64 {
+                    bail!("bencode nested too deeply");
+                }
+                let mut items = Vec::new();
+                while self.data.get(self.pos) != Some(&b'e') {
+                    items.push(self.parse()?);
+                }
+                self.pos += 1;
+                self.depth -= 1;
+                if c == b'l' {
+                    Bencode::List(items)
+                } else {
+                    let mut dict = BTreeMap::new();
+                    let mut items = items.into_iter();
+                    while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                        match k {
+                            Bencode::Bytes(k) => dict.insert(k, v),
+                            _ => bail!("dictionary key is not a string"),
+                        };
+                    }
+                    Bencode::Dict(dict)
+                }
+            }
+            b'0'..=b'9' => {
+                let len: usize = self.take_until(b':')?.parse()?;
+                let bytes = self
+                    .data
+                    .get(self.pos..self.pos + len)
+                    .ok_or_else(|| format_err!("unexpected end of file"))?;
+                self.pos += len;
+                Bencode::Bytes(bytes.to_vec())
+            }
+            c => bail!("invalid bencode type {:?} at {}", c as char, self.pos),
+        })
+    }
+}
+
+/// v2 torrents describe the files as a nested dictionary, each file has a "" entry with its length
+fn collect_file_tree(path: &str, tree: &Bencode, files: &mut Vec<(String, i64)>) {
+    if let Bencode::Dict(entries) = tree {
+        for (name, child) in entries {
+            if name.is_empty() {
+                let length = child.get("length").and_then(Bencode::as_int).unwrap_or(0);
+                files.push((path.to_string(), length));
+            } else {
+                let name = String::from_utf8_lossy(name);
+                collect_file_tree(&format!("{}/{}", path, name), child, files);
+            }
+        }
+    }
+}
+
+fn list_files(info: &Bencode) -> Vec<(String, i64)> {
+    let name = info
+        .get("name.utf-8")
+        .or_else(|| info.get("name"))
+        .and_then(Bencode::as_str)
+        .unwrap_or_default();
+    let mut files = Vec::new();
+    if let Some(tree) = info.get("file tree") {
+        collect_file_tree(&name, tree, &mut files);
+    } else if let Some(Bencode::List(entries)) = info.get("files") {
+        for entry in entries {
+            // padding files only fill up pieces
+            if entry.get("attr").and_then(Bencode::as_str).as_deref() == Some("p") {
+                continue;
+            }
+            let path = entry
+                .get("path.utf-8")
+                .or_else(|| entry.get("path"))
+                .map(Bencode::as_list)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(Bencode::as_str)
+                .collect::<Vec<_>>()
+                .join("/");
+            let length = entry.get("length").and_then(Bencode::as_int).unwrap_or(0);
+            files.push((format!("{}/{}", name, path), length));
+        }
+    } else {
+        let length = info.get("length").and_then(Bencode::as_int).unwrap_or(0);
+        files.push((name, length));
+    }
+    files
+}
+
+fn write_torrent(line_prefix: &str, torrent: &Bencode, oup: &mut dyn Write) -> Result<()> {
+    let info = torrent
+        .get("info")
+        .ok_or_else(|| format_err!("not a torrent file: no info dictionary"))?;
+    if let Some(name) = info.get("name").and_then(Bencode::as_str) {
+        writeln!(oup, "{}Name: {}", line_prefix, name)?;
+    }
+    for (key, label) in &[("comment", "Comment"), ("created by", "Created by")] {
+        if let Some(value) = torrent.get(key).and_then(Bencode::as_str) {
+            writeln!(oup, "{}{}: {}", line_prefix, label, value)?;
+        }
+    }
+    if let Some(date) = torrent
+        .get("creation date")
+        .and_then(Bencode::as_int)
+        .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+    {
+        writeln!(
+            oup,
+            "{}Creation date: {}",
+            line_prefix,
+            date.format("%Y-%m-%dT%H:%M:%SZ")
+        )?;
+    }
+    let mut trackers = Vec::new();
+    trackers.extend(torrent.get("announce").and_then(Bencode::as_str));
+    for tier in torrent
+        .get("announce-list")
+        .map(Bencode::as_list)
+        .unwrap_or_default()
+    {
+        trackers.extend(tier.as_list().iter().filter_map(Bencode::as_str));
+    }
+    let mut seen = std::collections::HashSet::new();
+    for tracker in trackers.iter().filter(|t| seen.insert(t.as_str())) {
+        writeln!(oup, "{}Tracker: {}", line_prefix, tracker)?;
+    }
+    // url-list is either a single url or a list of them
+    match torrent.get("url-list") {
+        Some(Bencode::List(urls)) => {
+            for url in urls.iter().filter_map(Bencode::as_str) {
+                writeln!(oup, "{}Web seed: {}", line_prefix, url)?;
+            }
+        }
+        Some(url) => {
+            if let Some(url) = url.as_str() {
+                writeln!(oup, "{}Web seed: {}", line_prefix, url)?;
+            }
+        }
+        None => {}
+    }
+    for (path, length) in list_files(info) {
+        writeln!(oup, "{}File: {} ({} bytes)", line_prefix, path, length)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for TorrentAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let torrent = Parser {
+            data: &data,
+            pos: 0,
+            depth: 0,
+        }
+        .parse()?;
+        write_torrent(&ai.line_prefix, &torrent, oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn adapt(torrent: &[u8]) -> Result<String> {
+        let (a, d) = simple_adapt_info(
+            Path::new("test.torrent"),
+            Box::new(Cursor::new(torrent.to_vec())),
+        );
+        let mut r = TorrentAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn multi_file() -> Result<()> {
+        let torrent = b"d8:announce26:http://tracker.example/ann13:announce-listll26:http://tracker.example/annel21:udp://backup.example/ee7:comment12:test release10:created by8:rga test13:creation datei1600000000e4:infod5:filesld6:lengthi1024e4:pathl4:docs10:readme.txteed4:attr1:p6:lengthi7e4:pathl4:.pad1:7eed6:lengthi2048e4:pathl8:data.csveee4:name7:dataset12:piece lengthi16384e6:pieces0:e8:url-list23:https://mirror.example/e";
+        assert_eq!(
+            adapt(torrent)?,
+            "PREFIX:Name: dataset
+PREFIX:Comment: test release
+PREFIX:Created by: rga test
+PREFIX:Creation date: 2020-09-13T12:26:40Z
+PREFIX:Tracker: http://tracker.example/ann
+PREFIX:Tracker: udp://backup.example/
+PREFIX:Web seed: https://mirror.example/
+PREFIX:File: dataset/docs/readme.txt (1024 bytes)
+PREFIX:File: dataset/data.csv (2048 bytes)
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn file_tree() -> Result<()> {
+        let torrent = b"d4:infod9:file treed5:b.txtd0:d6:lengthi6eee3:subd5:a.txtd0:d6:lengthi5eeeee12:meta versioni2e4:name4:rootee";
+        assert_eq!(
+            adapt(torrent)?,
+            "PREFIX:Name: root
+PREFIX:File: root/b.txt (6 bytes)
+PREFIX:File: root/sub/a.txt (5 bytes)
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::path::Path;
+use tempfile::NamedTempFile;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+// 7z opens the partition table of the disk by itself and lists the partitions as `0.ntfs`, `1.fat` etc.
+// those get PARTITION_EXTENSION appended to recurse into this adapter again, so that other .ntfs, .fat
+// and .ext files are not claimed
+static EXTENSIONS: &[&str] = &[
+    "qcow",
+    "qcow2",
+    "vmdk",
+    "vhd",
+    "vhdx",
+    "vdi",
+    PARTITION_EXTENSION,
+];
+const PARTITION_EXTENSION: &str = "vmpart";
+static FILE_SYSTEMS: &[&str] = &["ntfs", "fat", "ext"];
+static MIME_TYPES: &[&str] = &[
+    "application/x-qemu-disk",
+    "application/x-vmdk-disk",
+    "application/x-vhd-disk",
+    "application/x-vhdx-disk",
+    "application/x-virtualbox-vdi",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "vmdisk".to_owned(),
+        version: 1,
+        description: "Uses 7z to read the partitions of virtual machine disk images (qcow2, monolithic VMDK, VHD, VHDX, VDI) and recurses down into the files of their NTFS, FAT and ext file systems. Disabled by default since disk images are large, enable with --rga-adapters=+vmdisk".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct VmDiskAdapter;
+
+impl VmDiskAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(VmDiskAdapter))
+    }
+}
+impl GetMetadata for VmDiskAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the name the adapter of a member of the image is chosen by
+fn member_hint(name: &str) -> PathBuf {
+    let is_partition = !name.contains('/')
+        && Path::new(name)
+            .extension()
+            .is_some_and(|e| FILE_SYSTEMS.iter().any(|fs| e == *fs));
+    if is_partition {
+        PathBuf::from(format!("{}.{}", name, PARTITION_EXTENSION))
+    } else {
+        PathBuf::from(name)
+    }
+}
+
+/// the path of the image for 7z, and the temp file it was written to if it is not a real file.
+/// 7z looks at the extension, so the temp file gets the one of the image or partition
+fn image_file(ai: &mut AdaptInfo) -> Result<(PathBuf, Option<NamedTempFile>)> {
+    if ai.is_real_file {
+        return Ok((ai.filepath_hint.clone(), None));
+    }
+    let mut name = ai.filepath_hint.as_path();
+    if name.extension().is_some_and(|e| e == PARTITION_EXTENSION) {
+        name = Path::new(name.file_stem().unwrap_or_default());
+    }
+    let extension = name
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    let mut tmp = tempfile::Builder::new()
+        .prefix("rga-vmdisk-")
+        .suffix(&extension)
+        .tempfile()?;
+    std::io::copy(&mut ai.inp, &mut tmp)?;
+    Ok((tmp.path().to_owned(), Some(tmp)))
+}
+
+impl WritingFileAdapterTrait for VmDiskAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // the partitions are always extracted from the image, so they have to be written to disk to be read by 7z again
+        let (image_path, _tmp_file) = image_file(&mut ai)?;
+        dmg::adapt_with_7z(
+            &image_path,
+            &ai.line_prefix,
+            ai.archive_recursion_depth,
+            &ai.config,
+            member_hint,
+            oup,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::{adapter_matcher, FileMeta};
+    use crate::test_utils::simple_adapt_info;
+    use std::rc::Rc;
+
+    #[test]
+    fn metadata() {
+        let meta = VmDiskAdapter.metadata();
+        assert_eq!(meta.name, "vmdisk");
+        assert!(meta.recurses);
+        assert!(meta.disabled_by_default);
+    }
+
+    #[test]
+    fn matches_images_and_partitions() -> Result<()> {
+        let adapters: Vec<Rc<dyn FileAdapter>> = vec![Rc::new(VmDiskAdapter::new())];
+        let matcher = adapter_matcher(&adapters, false)?;
+        let matches = |name: &str| {
+            matcher(FileMeta {
+                lossy_filename: name.to_string(),
+                mimetype: None,
+            })
+            .is_some()
+        };
+        assert!(matches("disk.qcow2"));
+        assert!(matches("Windows.vmdk"));
+        assert!(matches(&member_hint("0.ntfs").to_string_lossy()));
+        // only the partitions listed by 7z
+        assert!(!matches("backup.ntfs"));
+        assert!(!matches("image.fat"));
+        assert!(!matches("notes.ext"));
+        Ok(())
+    }
+
+    #[test]
+    fn partition_hints() {
+        assert_eq!(member_hint("0.ntfs"), PathBuf::from("0.ntfs.vmpart"));
+        assert_eq!(member_hint("1.fat"), PathBuf::from("1.fat.vmpart"));
+        assert_eq!(member_hint("2.ext"), PathBuf::from("2.ext.vmpart"));
+        assert_eq!(
+            member_hint("Users/a/notes.ext"),
+            PathBuf::from("Users/a/notes.ext")
+        );
+        assert_eq!(member_hint("readme.txt"), PathBuf::from("readme.txt"));
+    }
+
+    #[test]
+    fn temp_file() -> Result<()> {
+        let (mut ai, _) = simple_adapt_info(
+            Path::new("disk.qcow2/0.ntfs.vmpart"),
+            Box::new(std::io::Cursor::new(b"partition".to_vec())),
+        );
+        ai.is_real_file = false;
+        let (path, tmp) = image_file(&mut ai)?;
+        assert!(tmp.is_some());
+        assert_eq!(path.extension().unwrap(), "ntfs");
+        assert_eq!(std::fs::read(&path)?, b"partition");
+
+        let (mut ai, _) = simple_adapt_info(
+            Path::new("images/disk.vhdx"),
+            Box::new(std::io::Cursor::new(b"disk".to_vec())),
+        );
+        ai.is_real_file = false;
+        let (path, _tmp) = image_file(&mut ai)?;
+        assert_eq!(path.extension().unwrap(), "vhdx");
+
+        // real files are read by 7z directly
+        let (mut ai, _) = simple_adapt_info(Path::new("/vm/disk.vdi"), Box::new(std::io::empty()));
+        let (path, tmp) = image_file(&mut ai)?;
+        assert!(tmp.is_none());
+        assert_eq!(path, Path::new("/vm/disk.vdi"));
+        Ok(())
+    }
+}
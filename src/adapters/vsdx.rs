@@ -0,0 +1,271 @@
+use super::xml::{text_of, xml_reader};
+use super::xps::{attribute, for_each_element, read_part, resolve};
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use quick_xml::events::Event;
+use std::collections::HashMap;
+use std::io::{Cursor, Seek};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["vsdx", "vsdm", "vstx", "vstm"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "vsdx".to_owned(),
+        version: 1,
+        description: "Extracts the shape text and shape data of Visio drawings (.vsdx). Lines are prefixed with the page and shape name".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/vnd.ms-visio.drawing.main+xml".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct VsdxAdapter;
+
+impl VsdxAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(VsdxAdapter))
+    }
+}
+impl GetMetadata for VsdxAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+static PAGES_PART: &str = "visio/pages/pages.xml";
+
+/// the name and part of every page, in the order of the page tabs
+fn pages<R: Read + Seek>(archive: &mut ::zip::ZipArchive<R>) -> Result<Vec<(String, String)>> {
+    let mut targets = HashMap::new();
+    if let Some(rels) = read_part(archive, "visio/pages/_rels/pages.xml.rels")? {
+        for_each_element(&rels, b"Relationship", |e| {
+            if let (Some(id), Some(target)) = (attribute(e, b"Id"), attribute(e, b"Target")) {
+                targets.insert(id, resolve(PAGES_PART, &target));
+            }
+            Ok(())
+        })?;
+    }
+    let pages_xml = read_part(archive, PAGES_PART)?.context("not a visio drawing: no pages")?;
+    let mut pages = Vec::new();
+    let mut reader = xml_reader(&pages_xml[..]);
+    let mut buf = Vec::new();
+    let mut name = String::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"Page" => {
+                    name = attribute(&e, b"Name")
+                        .or_else(|| attribute(&e, b"NameU"))
+                        .unwrap_or_else(|| format!("Page {}", pages.len() + 1));
+                }
+                b"Rel" => {
+                    if let Some(target) = attribute(&e, b"id").and_then(|id| targets.get(&id)) {
+                        pages.push((name.clone(), target.clone()));
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(pages)
+}
+
+struct Shape {
+    name: String,
+    text: String,
+}
+
+/// writes the text and the shape data ("Property" section) of every shape on a page
+fn write_page(line_prefix: &str, page: &str, xml: &[u8], oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(xml);
+    let mut buf = Vec::new();
+    let mut shapes: Vec<Shape> = Vec::new();
+    let mut in_text = false;
+    let mut in_properties = false;
+    // label and value of the current shape data row
+    let mut row: Option<(String, Option<String>, Option<String>)> = None;
+    loop {
+        let event = reader.read_event_into(&mut buf)?;
+        match &event {
+            Event::Start(e) | Event::Empty(e) if !in_text => {
+                // empty shapes, texts and rows have nothing to show
+                let is_start = matches!(event, Event::Start(_));
+                match e.local_name().as_ref() {
+                    b"Shape" if is_start => {
+                        let name = attribute(e, b"Name")
+                            .or_else(|| attribute(e, b"NameU"))
+                            .or_else(|| attribute(e, b"ID").map(|id| format!("Shape {}", id)))
+                            .unwrap_or_else(|| "Shape".to_string());
+                        shapes.push(Shape {
+                            name,
+                            text: String::new(),
+                        });
+                    }
+                    b"Text" => in_text = is_start,
+                    b"Section" => {
+                        in_properties =
+                            is_start && attribute(e, b"N").as_deref() == Some("Property")
+                    }
+                    b"Row" if in_properties && is_start => {
+                        row = Some((attribute(e, b"N").unwrap_or_default(), None, None));
+                    }
+                    b"Cell" => {
+                        if let Some((_, label, value)) = &mut row {
+                            match attribute(e, b"N").as_deref() {
+                                Some("Label") => *label = attribute(e, b"V"),
+                                Some("Value") => *value = attribute(e, b"V"),
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(t) if in_text => {
+                if let Some(shape) = shapes.last_mut() {
+                    shape.text.push_str(&text_of(t));
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"Text" => in_text = false,
+                b"Section" => in_properties = false,
+                b"Row" => {
+                    if let (Some((name, label, Some(value))), Some(shape)) =
+                        (row.take(), shapes.last())
+                    {
+                        if !value.trim().is_empty() {
+                            writeln!(
+                                oup,
+                                "{}{}/{}: {}: {}",
+                                line_prefix,
+                                page,
+                                shape.name,
+                                label.filter(|l| !l.is_empty()).unwrap_or(name),
+                                value.trim()
+                            )?;
+                        }
+                    }
+                }
+                b"Shape" => {
+                    if let Some(shape) = shapes.pop() {
+                        for line in shape.text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                            writeln!(oup, "{}{}/{}: {}", line_prefix, page, shape.name, line)?;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for VsdxAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+        for (name, part) in pages(&mut archive)? {
+            let xml = read_part(&mut archive, &part)?
+                .with_context(|| format!("missing page {}", part))?;
+            write_page(&ai.line_prefix, &name, &xml, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    static PAGES: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Pages xmlns="http://schemas.microsoft.com/office/visio/2012/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+  <Page ID="0" NameU="Page-1" Name="Network"><PageSheet/><Rel r:id="rId2"/></Page>
+  <Page ID="4" NameU="Page-2" Name="Racks"><Rel r:id="rId1"/></Page>
+</Pages>"#;
+
+    static RELS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Id="rId1" Type="http://schemas.microsoft.com/visio/2010/relationships/page" Target="page2.xml"/>
+  <Relationship Id="rId2" Type="http://schemas.microsoft.com/visio/2010/relationships/page" Target="page1.xml"/>
+</Relationships>"#;
+
+    static PAGE1: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<PageContents xmlns="http://schemas.microsoft.com/office/visio/2012/main">
+  <Shapes>
+    <Shape ID="1" NameU="Server" Name="web01" Type="Shape">
+      <Cell N="PinX" V="1.5"/>
+      <Section N="Property">
+        <Row N="IPAddress"><Cell N="Label" V="IP address"/><Cell N="Value" V="10.0.0.12" U="STR"/></Row>
+        <Row N="Rack"><Cell N="Value" V="R4"/></Row>
+        <Row N="Empty"><Cell N="Value" V=""/></Row>
+      </Section>
+      <Text><cp IX="0"/>web01.example.com<pp IX="0"/>
+frontend</Text>
+    </Shape>
+    <Shape ID="2" Type="Group">
+      <Shapes>
+        <Shape ID="3" NameU="Dynamic connector" Name="Dynamic connector"><Text>HTTPS &amp; SSH <fld IX="0">443</fld></Text></Shape>
+      </Shapes>
+    </Shape>
+  </Shapes>
+</PageContents>"#;
+
+    static PAGE2: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<PageContents xmlns="http://schemas.microsoft.com/office/visio/2012/main">
+  <Shapes><Shape ID="7" Name="Rack R4"><Text>Row B</Text></Shape></Shapes>
+</PageContents>"#;
+
+    #[test]
+    fn vsdx() -> Result<()> {
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        for (name, content) in &[
+            ("visio/pages/pages.xml", PAGES),
+            ("visio/pages/_rels/pages.xml.rels", RELS),
+            ("visio/pages/page1.xml", PAGE1),
+            ("visio/pages/page2.xml", PAGE2),
+        ] {
+            zip.start_file(*name, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        let data = zip.finish()?.into_inner();
+        let (a, d) = simple_adapt_info(Path::new("network.vsdx"), Box::new(Cursor::new(data)));
+        let mut r = VsdxAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Network/web01: IP address: 10.0.0.12
+PREFIX:Network/web01: Rack: R4
+PREFIX:Network/web01: web01.example.com
+PREFIX:Network/web01: frontend
+PREFIX:Network/Dynamic connector: HTTPS & SSH 443
+PREFIX:Racks/Rack R4: Row B
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+use super::html::write_html_text;
+use super::*;
+use crate::preproc::rga_preproc;
+use anyhow::*;
+use encoding_rs::Encoding;
+use lazy_static::lazy_static;
+use mailparse::{MailHeader, MailHeaderMap};
+use std::io::{BufReader, Cursor};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["warc"];
+/// payloads that are written as text instead of recursing into them
+static TEXT_MIME_TYPES: &[&str] = &[
+    "application/json",
+    "application/javascript",
+    "application/xml",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "warc".to_owned(),
+        version: 1,
+        description: "Reads WARC web archives. HTTP responses are decoded and converted to text (HTML) or recursed into (other formats). Lines are prefixed with the URL of the record. .warc.gz is handled by the decompress adapter".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType("application/warc".to_owned())]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![AdapterOption::HtmlLinks]
+    };
+}
+#[derive(Default, Clone)]
+pub struct WarcAdapter;
+
+impl WarcAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(WarcAdapter))
+    }
+}
+impl GetMetadata for WarcAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// read a header block up to (and including) the empty line that ends it
+fn read_header_block(inp: &mut dyn BufRead) -> Result<Vec<u8>> {
+    let mut block = Vec::new();
+    loop {
+        let len = block.len();
+        if inp.read_until(b'\n', &mut block)? == 0 {
+            return Ok(block);
+        }
+        let line = &block[len..];
+        if line == b"\r\n" || line == b"\n" {
+            return Ok(block);
+        }
+    }
+}
+
+fn parse_headers(block: &[u8]) -> Result<Vec<MailHeader<'_>>> {
+    Ok(mailparse::parse_headers(block)?.0)
+}
+
+/// undo the http transfer encoding
+fn dechunk(body: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut rest = body;
+    loop {
+        let line_end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .context("truncated chunked body")?;
+        let size_str = String::from_utf8_lossy(&rest[..line_end]);
+        // chunk extensions start with ;
+        let size_str = size_str.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_str, 16)
+            .with_context(|| format!("invalid chunk size {:?}", size_str))?;
+        rest = &rest[line_end + 1..];
+        if size == 0 {
+            return Ok(out);
+        }
+        let chunk = rest.get(..size).context("truncated chunked body")?;
+        out.extend_from_slice(chunk);
+        rest = &rest[size..];
+        rest = rest.strip_prefix(b"\r").unwrap_or(rest);
+        rest = rest.strip_prefix(b"\n").unwrap_or(rest);
+    }
+}
+
+fn decode_content(encoding: &str, body: Vec<u8>) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match encoding.trim().to_ascii_lowercase().as_str() {
+        "" | "identity" => return Ok(body),
+        "gzip" | "x-gzip" => {
+            flate2::read::MultiGzDecoder::new(&body[..]).read_to_end(&mut out)?;
+        }
+        "deflate" => {
+            // usually zlib wrapped, but some servers send raw deflate streams
+            if flate2::read::ZlibDecoder::new(&body[..])
+                .read_to_end(&mut out)
+                .is_err()
+            {
+                out.clear();
+                flate2::read::DeflateDecoder::new(&body[..]).read_to_end(&mut out)?;
+            }
+        }
+        "br" => {
+            brotli::Decompressor::new(&body[..], 4096).read_to_end(&mut out)?;
+        }
+        other => return Err(format_err!("unknown content encoding {}", other)),
+    }
+    Ok(out)
+}
+
+/// split an http message into its headers and decoded body
+fn parse_http_response(block: &[u8]) -> Result<(Option<String>, Vec<u8>)> {
+    let mut inp = block;
+    // status line
+    let mut status = Vec::new();
+    inp.read_until(b'\n', &mut status)?;
+    let header_block = read_header_block(&mut inp)?;
+    let headers = parse_headers(&header_block)?;
+    let mut body = inp.to_vec();
+    if headers
+        .get_first_value("Transfer-Encoding")
+        .is_some_and(|e| e.to_ascii_lowercase().contains("chunked"))
+    {
+        body = dechunk(&body)?;
+    }
+    // content encodings are applied in order, so they have to be undone in reverse
+    if let Some(encodings) = headers.get_first_value("Content-Encoding") {
+        for encoding in encodings.rsplit(',') {
+            body = decode_content(encoding, body)?;
+        }
+    }
+    Ok((headers.get_first_value("Content-Type"), body))
+}
+
+/// write a downloaded resource as text according to its content type, recursing into other formats
+pub fn write_payload(
+    ai: &AdaptInfo,
+    line_prefix: &str,
+    uri: &str,
+    content_type: Option<&str>,
+    body: Vec<u8>,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    if body.is_empty() {
+        return Ok(());
+    }
+    let content_type = content_type.unwrap_or("").to_ascii_lowercase();
+    let mut params = content_type.split(';').map(str::trim);
+    let mime = params.next().unwrap_or("");
+    let charset = params.find_map(|p| p.strip_prefix("charset="));
+    let text = || {
+        let encoding = charset
+            .and_then(|c| Encoding::for_label(c.trim_matches('"').as_bytes()))
+            .unwrap_or(encoding_rs::UTF_8);
+        encoding.decode_without_bom_handling(&body).0.into_owned()
+    };
+    if mime == "text/html" || mime == "application/xhtml+xml" {
+        write_html_text(line_prefix, &text(), ai.config.args.html_links, oup)
+    } else if mime.starts_with("text/") || TEXT_MIME_TYPES.contains(&mime) {
+        for line in text().lines() {
+            writeln!(oup, "{}{}", line_prefix, line)?;
+        }
+        Ok(())
+    } else {
+        // use the last part of the url as file name so the right adapter is found
+        let name = uri
+            .split(['?', '#'])
+            .next()
+            .and_then(|u| u.rsplit('/').next())
+            .filter(|n| !n.is_empty())
+            .unwrap_or("index");
+        let mut inner = rga_preproc(AdaptInfo {
+            filepath_hint: PathBuf::from(name),
+            is_real_file: false,
+            archive_recursion_depth: ai.archive_recursion_depth + 1,
+            inp: Box::new(Cursor::new(body)),
+            line_prefix: line_prefix.to_string(),
+            config: ai.config.clone(),
+        })?;
+        let mut text = Vec::new();
+        inner.read_to_end(&mut text)?;
+        // there is no adapter for images etc., don't pass them through since rg stops at binary data
+        if text.contains(&0) {
+            writeln!(oup, "{}[rga: binary data]", line_prefix)?;
+        } else {
+            oup.write_all(&text)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_record(
+    ai: &AdaptInfo,
+    headers: &[MailHeader],
+    block: Vec<u8>,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let warc_type = headers
+        .get_first_value("WARC-Type")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    // warc 1.0 drafts wrapped the uri in angle brackets
+    let uri = headers
+        .get_first_value("WARC-Target-URI")
+        .map(|u| u.trim_start_matches('<').trim_end_matches('>').to_string())
+        .unwrap_or_default();
+    let content_type = headers.get_first_value("Content-Type");
+    let line_prefix = format!("{}{}: ", ai.line_prefix, uri);
+    match warc_type.as_str() {
+        "response"
+            if content_type
+                .as_deref()
+                .is_some_and(|t| t.starts_with("application/http")) =>
+        {
+            let (content_type, body) = parse_http_response(&block)?;
+            write_payload(ai, &line_prefix, &uri, content_type.as_deref(), body, oup)
+        }
+        "resource" | "conversion" => {
+            write_payload(ai, &line_prefix, &uri, content_type.as_deref(), block, oup)
+        }
+        // requests, metadata, warcinfo etc. don't contain page content
+        _ => Ok(()),
+    }
+}
+
+impl WritingFileAdapterTrait for WarcAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut inp = BufReader::new(std::mem::replace(&mut ai.inp, Box::new(std::io::empty())));
+        let mut n = 0;
+        loop {
+            // records are separated by empty lines
+            let mut version = String::new();
+            while version.trim().is_empty() {
+                version.clear();
+                if inp.read_line(&mut version)? == 0 {
+                    return Ok(());
+                }
+            }
+            n += 1;
+            if !version.starts_with("WARC/") {
+                return Err(format_err!("record {}: not a warc record", n));
+            }
+            let header_block = read_header_block(&mut inp)?;
+            let headers = parse_headers(&header_block)?;
+            let len: u64 = headers
+                .get_first_value("Content-Length")
+                .and_then(|l| l.trim().parse().ok())
+                .with_context(|| format!("record {}: missing Content-Length", n))?;
+            let mut block = Vec::new();
+            (&mut inp).take(len).read_to_end(&mut block)?;
+            write_record(&ai, &headers, block, oup).with_context(|| format!("record {}", n))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use flate2::write::GzEncoder;
+
+    fn record(warc_type: &str, uri: &str, content_type: &str, block: &[u8]) -> Vec<u8> {
+        let mut r = format!(
+            "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Target-URI: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            warc_type,
+            uri,
+            content_type,
+            block.len()
+        )
+        .into_bytes();
+        r.extend_from_slice(block);
+        r.extend_from_slice(b"\r\n\r\n");
+        r
+    }
+
+    fn make_warc() -> Result<Vec<u8>> {
+        let mut warc = record(
+            "warcinfo",
+            "",
+            "application/warc-fields",
+            b"software: test\r\n",
+        );
+        warc.extend(record(
+            "request",
+            "https://example.com/",
+            "application/http; msgtype=request",
+            b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n",
+        ));
+
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(
+            b"<html><title>Example</title><p>hello <b>world</b></p><script>no()</script></html>",
+        )?;
+        let gz = gz.finish()?;
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n",
+            10
+        )
+        .into_bytes();
+        response.extend_from_slice(&gz[..10]);
+        response.extend(format!("\r\n{:x}\r\n", gz.len() - 10).into_bytes());
+        response.extend_from_slice(&gz[10..]);
+        response.extend_from_slice(b"\r\n0\r\n\r\n");
+        warc.extend(record(
+            "response",
+            "https://example.com/",
+            "application/http; msgtype=response",
+            &response,
+        ));
+
+        let mut br = Vec::new();
+        brotli::CompressorWriter::new(&mut br, 4096, 5, 22)
+            .write_all(b"{\"api\": \"secret token\"}\n")?;
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Encoding: br\r\n\r\n"
+                .to_vec();
+        response.extend(br);
+        warc.extend(record(
+            "response",
+            "https://example.com/api?x=1",
+            "application/http;msgtype=response",
+            &response,
+        ));
+
+        warc.extend(record(
+            "resource",
+            "file:///notes.txt",
+            "text/plain",
+            b"a resource\nwith two lines",
+        ));
+        Ok(warc)
+    }
+
+    #[test]
+    fn records() -> Result<()> {
+        let (a, d) = simple_adapt_info(Path::new("test.warc"), Box::new(Cursor::new(make_warc()?)));
+        let mut r = WarcAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:https://example.com/: Example
+PREFIX:https://example.com/: hello world
+PREFIX:https://example.com/api?x=1: {\"api\": \"secret token\"}
+PREFIX:file:///notes.txt: a resource
+PREFIX:file:///notes.txt: with two lines
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+use super::executable::ascii_strings;
+use super::*;
+use ::wasmparser::{KnownCustom, Name, Parser, Payload};
+use anyhow::*;
+use lazy_static::lazy_static;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["wasm"];
+static MIME_TYPES: &[&str] = &["application/wasm"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "wasm".to_owned(),
+        version: 1,
+        description: "Writes the imports, exports, function names (from the name section), producers and the strings in the data segments of WebAssembly modules".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct WasmAdapter;
+
+impl WasmAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(WasmAdapter))
+    }
+}
+impl GetMetadata for WasmAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn write_custom(
+    line_prefix: &str,
+    section: &::wasmparser::CustomSectionReader,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    match section.as_known() {
+        KnownCustom::Name(names) => {
+            for name in names {
+                match name? {
+                    Name::Module { name, .. } => writeln!(oup, "{}Module: {}", line_prefix, name)?,
+                    Name::Function(map) => {
+                        for naming in map {
+                            writeln!(oup, "{}Function: {}", line_prefix, naming?.name)?;
+                        }
+                    }
+                    // locals, labels, types etc. are only interesting for debugging
+                    _ => {}
+                }
+            }
+        }
+        // `processed-by: rustc 1.70.0`
+        KnownCustom::Producers(producers) => {
+            for field in producers {
+                let field = field?;
+                for value in field.values {
+                    let value = value?;
+                    writeln!(
+                        oup,
+                        "{}Producer: {}: {} {}",
+                        line_prefix, field.name, value.name, value.version
+                    )?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for WasmAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let line_prefix = &ai.line_prefix;
+        for payload in Parser::new(0).parse_all(&data) {
+            match payload? {
+                Payload::ImportSection(imports) => {
+                    for import in imports.into_imports() {
+                        let import = import?;
+                        writeln!(
+                            oup,
+                            "{}Import: {}.{}",
+                            line_prefix, import.module, import.name
+                        )?;
+                    }
+                }
+                Payload::ExportSection(exports) => {
+                    for export in exports {
+                        writeln!(oup, "{}Export: {}", line_prefix, export?.name)?;
+                    }
+                }
+                Payload::DataSection(segments) => {
+                    for segment in segments {
+                        for s in ascii_strings(segment?.data) {
+                            writeln!(oup, "{}Data: {}", line_prefix, s)?;
+                        }
+                    }
+                }
+                Payload::CustomSection(section) => write_custom(line_prefix, &section, oup)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut out = vec![s.len() as u8];
+        out.extend(s.as_bytes());
+        out
+    }
+
+    /// all sizes in the test module are below 128, so a single byte is enough for their leb128 encoding
+    fn section(id: u8, content: Vec<u8>) -> Vec<u8> {
+        let mut out = vec![id, content.len() as u8];
+        out.extend(content);
+        out
+    }
+
+    fn module() -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // one type: () -> ()
+        wasm.extend(section(1, vec![1, 0x60, 0, 0]));
+        wasm.extend(section(
+            2,
+            [vec![1], string("env"), string("log"), vec![0, 0]].concat(),
+        ));
+        wasm.extend(section(3, vec![1, 0]));
+        wasm.extend(section(5, vec![1, 0, 1]));
+        wasm.extend(section(
+            7,
+            [
+                vec![2],
+                string("main"),
+                vec![0, 1],
+                string("memory"),
+                vec![2, 0],
+            ]
+            .concat(),
+        ));
+        wasm.extend(section(10, vec![1, 2, 0, 0x0b]));
+        let strings = b"hello from wasm\0\x01\x02api_key\0";
+        wasm.extend(section(
+            11,
+            [
+                vec![1, 0, 0x41, 0x10, 0x0b, strings.len() as u8],
+                strings.to_vec(),
+            ]
+            .concat(),
+        ));
+        let functions = [vec![2, 0], string("log"), vec![1], string("run_app")].concat();
+        wasm.extend(section(
+            0,
+            [string("name"), vec![1, functions.len() as u8], functions].concat(),
+        ));
+        wasm
+    }
+
+    #[test]
+    fn wasm() -> Result<()> {
+        let (a, d) = simple_adapt_info(Path::new("app.wasm"), Box::new(Cursor::new(module())));
+        let mut r = WasmAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Import: env.log
+PREFIX:Export: main
+PREFIX:Export: memory
+PREFIX:Data: hello from wasm
+PREFIX:Data: api_key
+PREFIX:Function: log
+PREFIX:Function: run_app
+"
+        );
+        Ok(())
+    }
+}
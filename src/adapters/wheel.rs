@@ -0,0 +1,169 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["whl"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "wheel".to_owned(),
+        version: 1,
+        description: "Reads Python wheels. Writes the package metadata (`Requires-Dist: ...`) first and recurses into the other files. The PKG-INFO of sdists is handled the same way by the tar adapter".to_owned(),
+        recurses: true,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct WheelAdapter;
+
+impl WheelAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(WheelAdapter))
+    }
+}
+impl GetMetadata for WheelAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// `requests-2.31.0.dist-info/METADATA` in a wheel, `requests-2.31.0/PKG-INFO` in an sdist
+pub fn is_package_metadata(path: &str) -> bool {
+    let parts: Vec<&str> = path.trim_start_matches("./").split('/').collect();
+    match parts.as_slice() {
+        [dir, "METADATA"] => dir.ends_with(".dist-info"),
+        [_, "PKG-INFO"] => true,
+        _ => false,
+    }
+}
+
+/// writes the header fields without the file name so they can be searched for directly,
+/// the long description that follows the headers is prefixed with the file name
+pub fn write_package_metadata(
+    line_prefix: &str,
+    path: &str,
+    data: &[u8],
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let text = String::from_utf8_lossy(data);
+    let mut lines = text.lines();
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+        // continuation lines of multi-line fields are indented
+        let line = line.trim();
+        if line != "|" {
+            writeln!(oup, "{}{}", line_prefix, line.trim_start_matches("| "))?;
+        }
+    }
+    for line in lines.filter(|l| !l.trim().is_empty()) {
+        writeln!(oup, "{}{}: {}", line_prefix, path, line)?;
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for WheelAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+        let metadata = archive
+            .file_names()
+            .find(|n| is_package_metadata(n))
+            .map(|n| n.to_string());
+        if let Some(name) = &metadata {
+            let mut data = Vec::new();
+            archive.by_name(name)?.read_to_end(&mut data)?;
+            write_package_metadata(&ai.line_prefix, name, &data, oup)?;
+        }
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            if file.is_dir() || Some(file.name()) == metadata.as_deref() {
+                continue;
+            }
+            zip::adapt_member(&mut file, &ai, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    static PKG_INFO: &str = "Metadata-Version: 2.1
+Name: example
+Version: 1.0
+License: MIT License
+        |
+        | Copyright (c) 2024
+Requires-Dist: requests (>=2.0)
+Requires-Dist: pytest ; extra == 'test'
+
+# example
+
+Does things.
+";
+
+    #[test]
+    fn wheel() -> Result<()> {
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        for (name, content) in &[
+            ("example/__init__.py", "API_URL = 'https://example.com'\n"),
+            ("example-1.0.dist-info/METADATA", PKG_INFO),
+        ] {
+            zip.start_file(*name, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        let data = zip.finish()?.into_inner();
+        let (a, d) = simple_adapt_info(
+            Path::new("example-1.0-py3-none-any.whl"),
+            Box::new(Cursor::new(data)),
+        );
+        let mut r = WheelAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Metadata-Version: 2.1
+PREFIX:Name: example
+PREFIX:Version: 1.0
+PREFIX:License: MIT License
+PREFIX:Copyright (c) 2024
+PREFIX:Requires-Dist: requests (>=2.0)
+PREFIX:Requires-Dist: pytest ; extra == 'test'
+PREFIX:example-1.0.dist-info/METADATA: # example
+PREFIX:example-1.0.dist-info/METADATA: Does things.
+API_URL = 'https://example.com'
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_paths() {
+        assert!(is_package_metadata("requests-2.31.0.dist-info/METADATA"));
+        assert!(is_package_metadata("./requests-2.31.0/PKG-INFO"));
+        assert!(!is_package_metadata(
+            "requests-2.31.0/src/requests.egg-info/PKG-INFO"
+        ));
+        assert!(!is_package_metadata("METADATA"));
+    }
+}
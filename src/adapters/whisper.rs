@@ -0,0 +1,147 @@
+use super::spawning::map_exe_error;
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &[
+    "mp3", "wav", "m4a", "ogg", "opus", "flac", "mkv", "mp4", "avi", "webm", "mov",
+];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "whisper".to_owned(),
+        version: 1,
+        description: "Uses ffmpeg and whisper.cpp to transcribe audio tracks, with timestamps. Very slow, needs --rga-whisper-model. Make sure you have ffmpeg and whisper-cli installed.".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: None,
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![AdapterOption::WhisperModel]
+    };
+}
+
+#[derive(Default, Clone)]
+pub struct WhisperAdapter;
+
+impl WhisperAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(WhisperAdapter))
+    }
+}
+impl GetMetadata for WhisperAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// rewrites `[00:00:00.000 --> 00:00:04.000]   text` to the format used for subtitles by the ffmpeg adapter
+fn write_transcript(line_prefix: &str, inp: impl BufRead, oup: &mut dyn Write) -> Result<()> {
+    let segment_re = Regex::new(r"^\[([\d:.]+) --> ([\d:.]+)\]\s*(.*)$").unwrap();
+    for line in inp.lines() {
+        let line = line?;
+        match segment_re.captures(line.trim()) {
+            Some(c) => {
+                let text = c[3].trim();
+                if !text.is_empty() {
+                    writeln!(oup, "{}{} --> {}: {}", line_prefix, &c[1], &c[2], text)?;
+                }
+            }
+            None => {
+                if !line.trim().is_empty() {
+                    writeln!(oup, "{}{}", line_prefix, line.trim())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for WhisperAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let model = ai.config.args.whisper_model.clone().ok_or_else(|| {
+            format_err!("the whisper adapter needs a model, see --rga-whisper-model")
+        })?;
+        let tmp_dir = tempfile::Builder::new().prefix("rga-whisper-").tempdir()?;
+        let input = if ai.is_real_file {
+            ai.filepath_hint.clone()
+        } else {
+            // mp4 and friends can't be demuxed from a pipe if the index is at the end
+            let input = tmp_dir.path().join("input");
+            std::io::copy(&mut ai.inp, &mut std::fs::File::create(&input)?)?;
+            input
+        };
+        // whisper.cpp only reads 16kHz mono wav
+        let wav = tmp_dir.path().join("audio.wav");
+        let status = Command::new("ffmpeg")
+            .args(["-hide_banner", "-loglevel", "error", "-nostdin", "-i"])
+            .arg(&input)
+            .args(["-vn", "-ar", "16000", "-ac", "1", "-c:a", "pcm_s16le"])
+            .arg(&wav)
+            .status()
+            .map_err(|e| map_exe_error(e, "ffmpeg", "Make sure you have ffmpeg installed."))?;
+        if !status.success() {
+            return Err(format_err!("ffmpeg failed: {:?}", status));
+        }
+        let mut cmd = Command::new("whisper-cli")
+            .arg("--model")
+            .arg(&model)
+            .args(["--language", "auto", "--no-prints", "--file"])
+            .arg(&wav)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                map_exe_error(
+                    e,
+                    "whisper-cli",
+                    "Make sure you have whisper.cpp installed.",
+                )
+            })?;
+        write_transcript(
+            &ai.line_prefix,
+            BufReader::new(cmd.stdout.as_mut().expect("is piped")),
+            oup,
+        )?;
+        let status = cmd.wait()?;
+        if !status.success() {
+            return Err(format_err!("whisper-cli failed: {:?}", status));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcript() -> Result<()> {
+        let out = "
+[00:00:00.000 --> 00:00:04.200]   Welcome to the weekly sync.
+[00:00:04.200 --> 00:00:09.000]   First item is the release.
+[00:00:09.000 --> 00:00:10.000]
+";
+        let mut o = Vec::new();
+        write_transcript("PREFIX:", out.as_bytes(), &mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:00:00:00.000 --> 00:00:04.200: Welcome to the weekly sync.
+PREFIX:00:00:04.200 --> 00:00:09.000: First item is the release.
+"
+        );
+        Ok(())
+    }
+}
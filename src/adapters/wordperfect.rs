@@ -0,0 +1,275 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use std::convert::TryInto;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["wpd", "wp", "wp5", "wp6"];
+static MIME_TYPES: &[&str] = &["application/vnd.wordperfect", "application/wordperfect"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "wordperfect".to_owned(),
+        version: 1,
+        description: "Extracts the text of WordPerfect 5 and later documents. Formatting codes are skipped, characters outside of ASCII are only partially supported".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct WordPerfectAdapter;
+
+impl WordPerfectAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(WordPerfectAdapter))
+    }
+}
+impl GetMetadata for WordPerfectAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// the size of the fixed length function codes 0xc0 - 0xcf (5.x) and 0xf0 - 0xff (6.x and later),
+/// which start and end with the code
+static WP5_FIXED_SIZES: [usize; 16] = [4, 9, 11, 3, 3, 5, 6, 7, 4, 5, 3, 5, 3, 4, 3, 3];
+static WP6_FIXED_SIZES: [usize; 16] = [4, 5, 3, 3, 3, 3, 4, 4, 4, 5, 5, 6, 6, 8, 8, 0];
+
+/// the end of a function code that starts at `i` with `size` bytes, if it looks valid
+fn fixed_end(body: &[u8], i: usize, size: usize) -> Option<usize> {
+    match body.get(i + size.max(1) - 1) {
+        Some(last) if size > 1 && *last == body[i] => Some(i + size),
+        _ => None,
+    }
+}
+
+/// variable length codes are `code subcode size:u16`, followed by `size` bytes.
+/// in 5.x the size does not include these four bytes, in 6.x it does
+fn variable_end(body: &[u8], i: usize, includes_header: bool) -> Option<usize> {
+    let size = u16::from_le_bytes(body.get(i + 2..i + 4)?.try_into().ok()?) as usize;
+    let end = if includes_header {
+        i + size
+    } else {
+        i + 4 + size
+    };
+    if end > i + 4 && end <= body.len() && body[end - 1] == body[i] {
+        Some(end)
+    } else {
+        None
+    }
+}
+
+/// the text of the document area. returns lines, with soft returns joined
+fn wp5_text(body: &[u8]) -> String {
+    let mut text = String::new();
+    let mut i = 0;
+    while i < body.len() {
+        let b = body[i];
+        let next = match b {
+            0x20..=0x7e => {
+                text.push(b as char);
+                i + 1
+            }
+            // hard return, soft and hard page break
+            0x0a..=0x0c => {
+                text.push('\n');
+                i + 1
+            }
+            // soft return, hard space
+            0x0d | 0xa0 => {
+                text.push(' ');
+                i + 1
+            }
+            0xa9..=0xab => {
+                text.push('-');
+                i + 1
+            }
+            0xc0..=0xcf => match fixed_end(body, i, WP5_FIXED_SIZES[(b - 0xc0) as usize]) {
+                Some(end) => {
+                    match b {
+                        // extended character: `c0 char charset c0`
+                        0xc0 => text.push(extended_char(body[i + 2], body[i + 1])),
+                        // tab and indents
+                        0xc1 | 0xc2 => text.push('\t'),
+                        _ => {}
+                    }
+                    end
+                }
+                None => i + 1,
+            },
+            0xd0..=0xff => variable_end(body, i, false).unwrap_or(i + 1),
+            _ => i + 1,
+        };
+        i = next;
+    }
+    text
+}
+
+fn wp6_text(body: &[u8]) -> String {
+    let mut text = String::new();
+    let mut i = 0;
+    while i < body.len() {
+        let b = body[i];
+        let next = match b {
+            0x20..=0x7e => {
+                text.push(b as char);
+                i + 1
+            }
+            // soft and hard space
+            0x80 | 0x81 => {
+                text.push(' ');
+                i + 1
+            }
+            0x84..=0x86 => {
+                text.push('-');
+                i + 1
+            }
+            // hard end of line, page and column
+            0xcc | 0xc7 | 0xc9 => {
+                text.push('\n');
+                i + 1
+            }
+            0xd0..=0xef => match variable_end(body, i, true) {
+                Some(end) => {
+                    // the end of line group: soft returns and hard returns in their various forms
+                    if b == 0xd0 {
+                        text.push(if body[i + 1] <= 0x01 { ' ' } else { '\n' });
+                    } else if b == 0xe0 {
+                        text.push('\t');
+                    }
+                    end
+                }
+                None => i + 1,
+            },
+            0xf0..=0xff => match fixed_end(body, i, WP6_FIXED_SIZES[(b - 0xf0) as usize]) {
+                Some(end) => {
+                    // extended character: `f0 char charset f0`
+                    if b == 0xf0 {
+                        text.push(extended_char(body[i + 2], body[i + 1]));
+                    }
+                    end
+                }
+                None => i + 1,
+            },
+            _ => i + 1,
+        };
+        i = next;
+    }
+    text
+}
+
+/// characters from the WordPerfect character sets. only ascii and the common typographic symbols are mapped
+fn extended_char(charset: u8, c: u8) -> char {
+    match (charset, c) {
+        // ascii
+        (0, 0x20..=0x7e) => c as char,
+        // typographic symbols: bullets, quotes and dashes
+        (4, 0) | (4, 3) => '•',
+        (4, 28) | (4, 29) => '\'',
+        (4, 30) | (4, 31) => '"',
+        (4, 32) | (4, 33) => '"',
+        (4, 34) => '–',
+        (4, 35) => '—',
+        _ => '\u{fffd}',
+    }
+}
+
+impl WritingFileAdapterTrait for WordPerfectAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        // versions before 5.0 don't have this header
+        if data.len() < 16 || !data.starts_with(b"\xffWPC") {
+            bail!("not a WordPerfect 5 or later document");
+        }
+        let document_offset = u32::from_le_bytes(data[4..8].try_into()?) as usize;
+        let major_version = data[10];
+        if u16::from_le_bytes(data[12..14].try_into()?) != 0 {
+            writeln!(
+                oup,
+                "{}[rga: skipping encrypted WordPerfect document]",
+                ai.line_prefix
+            )?;
+            return Ok(());
+        }
+        let body = data.get(document_offset..).unwrap_or_default();
+        let text = if major_version == 0 {
+            wp5_text(body)
+        } else {
+            wp6_text(body)
+        };
+        for line in text.lines() {
+            writeln!(oup, "{}{}", ai.line_prefix, line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::io::Cursor;
+
+    fn document(major_version: u8, body: &[u8]) -> Vec<u8> {
+        let mut data = b"\xffWPC".to_vec();
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&[1, 0x0a, major_version, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn adapt(data: Vec<u8>) -> Result<String> {
+        let (a, d) = simple_adapt_info(Path::new("letter.wpd"), Box::new(Cursor::new(data)));
+        let mut r = WordPerfectAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn wp5() -> Result<()> {
+        let mut body = Vec::new();
+        // font change, with the font name in it
+        body.extend_from_slice(b"\xd1\x01\x0b\x00Courier\x0b\x00\x01\xd1");
+        body.extend_from_slice(b"Dear Mr. \xc3\x0c\xc3Smith\xc4\x0c\xc4,\x0a\x0a");
+        body.extend_from_slice(b"\xc1\x00\x00\x00\x00\x00\x00\x00\xc1the contract is\x0dattached \xc0\x22\x04\xc0 see p.\xa02\x0a");
+        assert_eq!(
+            adapt(document(0, &body))?,
+            "PREFIX:Dear Mr. Smith,
+PREFIX:
+PREFIX:\tthe contract is attached – see p. 2
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wp6() -> Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(b"Invoice\xf2\x0c\xf2 2019\xd0\x04\x06\x00\x00\xd0");
+        body.extend_from_slice(b"paid in\x80full\xcc");
+        assert_eq!(
+            adapt(document(2, &body))?,
+            "PREFIX:Invoice 2019\nPREFIX:paid in full\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use log::*;
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader};
+use std::net::IpAddr;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::certification_request::X509CertificationRequest;
+use x509_parser::extensions::{GeneralName, ParsedExtension};
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::prelude::FromDer;
+use x509_parser::public_key::PublicKey;
+use x509_parser::time::ASN1Time;
+use x509_parser::x509::{AlgorithmIdentifier, SubjectPublicKeyInfo};
+
+static EXTENSIONS: &[&str] = &["pem", "crt", "cer", "der", "csr", "p7b", "p7c"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "x509".to_owned(),
+        version: 1,
+        description: "Prints the subject, issuer, alternative names and validity of X.509 certificates and certificate requests (PEM, DER and PKCS#7 bundles)".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![
+            SlowMatcher::MimeType("application/x-x509-ca-cert".to_owned()),
+            SlowMatcher::MimeType("application/pkcs10".to_owned()),
+            SlowMatcher::MimeType("application/pkcs7-mime".to_owned())
+        ]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct X509Adapter;
+
+impl X509Adapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(X509Adapter))
+    }
+}
+impl GetMetadata for X509Adapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+fn format_time(time: &ASN1Time) -> String {
+    match chrono::DateTime::from_timestamp(time.timestamp(), 0) {
+        Some(t) => t.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        None => time.to_string(),
+    }
+}
+
+fn algorithm_name(algorithm: &AlgorithmIdentifier) -> String {
+    oid2sn(&algorithm.algorithm, oid_registry())
+        .map(str::to_owned)
+        .unwrap_or_else(|_| algorithm.algorithm.to_id_string())
+}
+
+/// same notation as `openssl x509 -text`
+fn format_general_name(name: &GeneralName) -> String {
+    match name {
+        GeneralName::DNSName(s) => format!("DNS:{}", s),
+        GeneralName::RFC822Name(s) => format!("email:{}", s),
+        GeneralName::URI(s) => format!("URI:{}", s),
+        GeneralName::DirectoryName(n) => format!("DirName:{}", n),
+        GeneralName::IPAddress(b) => {
+            let ip = match b.len() {
+                4 => <[u8; 4]>::try_from(*b).ok().map(IpAddr::from),
+                16 => <[u8; 16]>::try_from(*b).ok().map(IpAddr::from),
+                _ => None,
+            };
+            match ip {
+                Some(ip) => format!("IP:{}", ip),
+                None => name.to_string(),
+            }
+        }
+        other => other.to_string(),
+    }
+}
+
+fn format_public_key(spki: &SubjectPublicKeyInfo) -> String {
+    let name = match spki.parsed() {
+        Result::Ok(PublicKey::RSA(_)) => "RSA".to_owned(),
+        Result::Ok(PublicKey::EC(_)) => "EC".to_owned(),
+        Result::Ok(PublicKey::DSA(_)) => "DSA".to_owned(),
+        _ => algorithm_name(&spki.algorithm),
+    };
+    match spki.parsed().map(|k| k.key_size()) {
+        Result::Ok(size) if size > 0 => format!("{} {} bits", name, size),
+        _ => name,
+    }
+}
+
+fn write_names<'a>(
+    line_prefix: &str,
+    extensions: impl Iterator<Item = &'a ParsedExtension<'a>>,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let names: Vec<String> = extensions
+        .filter_map(|ext| match ext {
+            ParsedExtension::SubjectAlternativeName(san) => Some(&san.general_names),
+            _ => None,
+        })
+        .flatten()
+        .map(format_general_name)
+        .collect();
+    if !names.is_empty() {
+        writeln!(
+            oup,
+            "{}Subject alternative names: {}",
+            line_prefix,
+            names.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+fn write_certificate(line_prefix: &str, cert: &X509Certificate, oup: &mut dyn Write) -> Result<()> {
+    writeln!(oup, "{}Subject: {}", line_prefix, cert.subject())?;
+    writeln!(oup, "{}Issuer: {}", line_prefix, cert.issuer())?;
+    writeln!(
+        oup,
+        "{}Serial: {}",
+        line_prefix,
+        cert.raw_serial_as_string()
+    )?;
+    let validity = cert.validity();
+    writeln!(
+        oup,
+        "{}Not before: {}",
+        line_prefix,
+        format_time(&validity.not_before)
+    )?;
+    writeln!(
+        oup,
+        "{}Not after: {}",
+        line_prefix,
+        format_time(&validity.not_after)
+    )?;
+    write_names(
+        line_prefix,
+        cert.extensions().iter().map(|ext| ext.parsed_extension()),
+        oup,
+    )?;
+    writeln!(
+        oup,
+        "{}Public key: {}",
+        line_prefix,
+        format_public_key(cert.public_key())
+    )?;
+    writeln!(
+        oup,
+        "{}Signature algorithm: {}",
+        line_prefix,
+        algorithm_name(&cert.signature_algorithm)
+    )?;
+    Ok(())
+}
+
+fn write_request(
+    line_prefix: &str,
+    req: &X509CertificationRequest,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let info = &req.certification_request_info;
+    writeln!(
+        oup,
+        "{}Certificate request subject: {}",
+        line_prefix, info.subject
+    )?;
+    if let Some(extensions) = req.requested_extensions() {
+        write_names(line_prefix, extensions, oup)?;
+    }
+    writeln!(
+        oup,
+        "{}Public key: {}",
+        line_prefix,
+        format_public_key(&info.subject_pki)
+    )?;
+    Ok(())
+}
+
+/// PKCS#7 bundles are a SignedData structure with the certificates somewhere inside,
+/// so just pick out everything that parses as a certificate
+fn write_embedded_certificates(
+    line_prefix: &str,
+    der: &[u8],
+    oup: &mut dyn Write,
+) -> Result<usize> {
+    let mut count = 0;
+    let mut pos = 0;
+    while pos < der.len() {
+        if der[pos] == 0x30 {
+            if let Result::Ok((rest, cert)) = X509Certificate::from_der(&der[pos..]) {
+                write_certificate(line_prefix, &cert, oup)?;
+                count += 1;
+                pos = der.len() - rest.len();
+                continue;
+            }
+        }
+        pos += 1;
+    }
+    Ok(count)
+}
+
+fn write_der(line_prefix: &str, der: &[u8], oup: &mut dyn Write) -> Result<()> {
+    if let Result::Ok((_, cert)) = X509Certificate::from_der(der) {
+        return write_certificate(line_prefix, &cert, oup);
+    }
+    if let Result::Ok((_, req)) = X509CertificationRequest::from_der(der) {
+        return write_request(line_prefix, &req, oup);
+    }
+    if write_embedded_certificates(line_prefix, der, oup)? == 0 {
+        bail!("no certificate or certificate request found");
+    }
+    Ok(())
+}
+
+/// writes the parsed contents of a PEM block
+fn write_pem_block(line_prefix: &str, label: &str, der: &[u8], oup: &mut dyn Write) -> Result<()> {
+    match label {
+        "CERTIFICATE" | "X509 CERTIFICATE" | "TRUSTED CERTIFICATE" => {
+            let (_, cert) = X509Certificate::from_der(der)?;
+            write_certificate(line_prefix, &cert, oup)
+        }
+        "CERTIFICATE REQUEST" | "NEW CERTIFICATE REQUEST" => {
+            let (_, req) = X509CertificationRequest::from_der(der)?;
+            write_request(line_prefix, &req, oup)
+        }
+        "PKCS7" => write_embedded_certificates(line_prefix, der, oup).map(|_| ()),
+        "PUBLIC KEY" => {
+            let (_, spki) = SubjectPublicKeyInfo::from_der(der)?;
+            writeln!(
+                oup,
+                "{}Public key: {}",
+                line_prefix,
+                format_public_key(&spki)
+            )?;
+            Ok(())
+        }
+        // private keys and parameters: the label is all we want to show
+        _ => {
+            writeln!(oup, "{}{}", line_prefix, label)?;
+            Ok(())
+        }
+    }
+}
+
+/// text around the PEM blocks (e.g. comments in CA bundles) is kept as is
+fn write_pem(ai: &AdaptInfo, inp: impl BufRead, oup: &mut dyn Write) -> Result<()> {
+    let mut block: Option<(String, String)> = None;
+    for line in inp.lines() {
+        let line = line?;
+        let trimmed = line.trim();
+        match &mut block {
+            None => {
+                if let Some(label) = trimmed
+                    .strip_prefix("-----BEGIN ")
+                    .and_then(|l| l.strip_suffix("-----"))
+                {
+                    block = Some((label.to_owned(), String::new()));
+                } else {
+                    writeln!(oup, "{}{}", ai.line_prefix, line)?;
+                }
+            }
+            Some((label, data)) => {
+                if trimmed.starts_with("-----END ") {
+                    let der = base64::decode(&data)
+                        .with_context(|| format!("invalid base64 in PEM block {}", label))?;
+                    if let Err(e) = write_pem_block(&ai.line_prefix, label, &der, oup) {
+                        warn!(
+                            "{}: could not parse {}: {}",
+                            ai.filepath_hint.display(),
+                            label,
+                            e
+                        );
+                        writeln!(oup, "{}{}", ai.line_prefix, label)?;
+                    }
+                    block = None;
+                } else if !trimmed.contains(':') {
+                    // skip headers of encrypted keys
+                    data.push_str(trimmed);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for X509Adapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let inp = std::mem::replace(&mut ai.inp, Box::new(std::io::empty()));
+        let mut inp = BufReader::new(inp);
+        // .crt, .cer and .p7b files can be either PEM or DER encoded
+        let is_der = inp.fill_buf()?.first() == Some(&0x30);
+        if is_der {
+            let mut der = Vec::new();
+            inp.read_to_end(&mut der)?;
+            write_der(&ai.line_prefix, &der, oup)
+        } else {
+            write_pem(&ai, inp, oup)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+    use std::io::Cursor;
+
+    fn adapt(filepath: &Path, inp: ReadBox) -> Result<String> {
+        let (a, d) = simple_adapt_info(filepath, inp);
+        let mut r = X509Adapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    static CERTIFICATE: &str = "PREFIX:Subject: C=DE, O=rga test, CN=example.com
+PREFIX:Issuer: C=DE, O=rga test, CN=example.com
+PREFIX:Serial: 12:34
+PREFIX:Not before: 2026-10-14T12:43:37Z
+PREFIX:Not after: 2036-10-11T12:43:37Z
+PREFIX:Subject alternative names: DNS:example.com, DNS:www.example.com, IP:192.0.2.1, email:admin@example.com
+PREFIX:Public key: EC 256 bits
+PREFIX:Signature algorithm: ecdsa-with-SHA256
+";
+
+    #[test]
+    fn pem_bundle() -> Result<()> {
+        let cert = std::fs::read_to_string(test_data_dir().join("short.pem"))?;
+        let csr = std::fs::read_to_string(test_data_dir().join("short.csr"))?;
+        let bundle = format!("# test bundle\n{}\n{}", cert, csr);
+        let o = adapt(
+            Path::new("bundle.pem"),
+            Box::new(Cursor::new(bundle.into_bytes())),
+        )?;
+        assert_eq!(
+            o,
+            format!(
+                "PREFIX:# test bundle\n{}PREFIX:\nPREFIX:Certificate request subject: O=rga test, CN=csr.example.com
+PREFIX:Subject alternative names: DNS:csr.example.com
+PREFIX:Public key: EC 256 bits
+",
+                CERTIFICATE
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn der() -> Result<()> {
+        let cert = std::fs::read_to_string(test_data_dir().join("short.pem"))?;
+        let base64: String = cert.lines().filter(|l| !l.starts_with("-----")).collect();
+        let o = adapt(
+            Path::new("short.der"),
+            Box::new(Cursor::new(base64::decode(&base64)?)),
+        )?;
+        assert_eq!(o, CERTIFICATE);
+        Ok(())
+    }
+
+    #[test]
+    fn pkcs7() -> Result<()> {
+        let filepath = test_data_dir().join("short.p7b");
+        let o = adapt(&filepath, Box::new(File::open(&filepath)?))?;
+        assert_eq!(o, CERTIFICATE);
+        Ok(())
+    }
+}
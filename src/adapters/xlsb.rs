@@ -0,0 +1,245 @@
+use super::*;
+use anyhow::*;
+use calamine::{Cell, Data, DataRef, Reader, Xlsb};
+use lazy_static::lazy_static;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["xlsb"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "xlsb".to_owned(),
+        version: 1,
+        description: "Uses calamine to read the cell values of binary Excel workbooks (.xlsb), one line per row".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(vec![SlowMatcher::MimeType(
+            "application/vnd.ms-excel.sheet.binary.macroEnabled.12".to_owned()
+        )]),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct XlsbAdapter;
+
+impl XlsbAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(XlsbAdapter))
+    }
+}
+impl GetMetadata for XlsbAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// 0 -> A, 25 -> Z, 26 -> AA
+pub fn column_name(mut col: u32) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (col % 26) as u8);
+        if col < 26 {
+            break;
+        }
+        col = col / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).expect("is ascii")
+}
+
+pub fn format_cell(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(d) if d.is_datetime() => match d.as_datetime() {
+            Some(dt) if dt.time() == chrono::NaiveTime::MIN => dt.format("%Y-%m-%d").to_string(),
+            Some(dt) => dt.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => d.to_string(),
+        },
+        // cells are tab separated and every row has to stay on one line
+        Data::String(s) => s.replace(['\t', '\r', '\n'], " "),
+        other => other.to_string(),
+    }
+}
+
+/// writes a row as `Sheet!A5: value<tab>value`, referencing the first non-empty cell
+pub fn write_row(
+    line_prefix: &str,
+    sheet: &str,
+    row: u32,
+    cells: &[(u32, String)],
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let first = match cells.first() {
+        Some((col, _)) => *col,
+        None => return Ok(()),
+    };
+    write!(
+        oup,
+        "{}{}!{}{}: ",
+        line_prefix,
+        sheet,
+        column_name(first),
+        row + 1
+    )?;
+    let mut next_col = first;
+    for (col, value) in cells {
+        // keep the columns aligned if there are empty cells in between
+        for _ in next_col..*col {
+            write!(oup, "\t")?;
+        }
+        if *col != first {
+            write!(oup, "\t")?;
+        }
+        write!(oup, "{}", value)?;
+        next_col = col + 1;
+    }
+    writeln!(oup)?;
+    Ok(())
+}
+
+/// writes the cells of a sheet, one line per row. cells come row by row,
+/// so large sheets don't have to be read into memory at once
+pub fn write_sheet<'a>(
+    line_prefix: &str,
+    sheet: &str,
+    mut next_cell: impl FnMut() -> Result<Option<Cell<DataRef<'a>>>>,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let mut row = 0;
+    let mut cells = Vec::new();
+    while let Some(cell) = next_cell()? {
+        if matches!(cell.get_value(), DataRef::Empty) {
+            continue;
+        }
+        let (r, c) = cell.get_position();
+        if r != row {
+            write_row(line_prefix, sheet, row, &cells, oup)?;
+            cells.clear();
+            row = r;
+        }
+        cells.push((c, format_cell(&cell.get_value().clone().into())));
+    }
+    write_row(line_prefix, sheet, row, &cells, oup)
+}
+
+impl WritingFileAdapterTrait for XlsbAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut workbook = Xlsb::new(Cursor::new(data))?;
+        for sheet in workbook.sheet_names() {
+            let mut reader = workbook.worksheet_cells_reader(&sheet)?;
+            write_sheet(&ai.line_prefix, &sheet, || Ok(reader.next_cell()?), oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn record(typ: u16, data: &[u8]) -> Vec<u8> {
+        let mut out = if typ < 0x80 {
+            vec![typ as u8]
+        } else {
+            vec![(typ & 0x7f) as u8 | 0x80, (typ >> 7) as u8]
+        };
+        // all test records are shorter than 128 bytes
+        out.push(data.len() as u8);
+        out.extend(data);
+        out
+    }
+
+    fn wide_str(s: &str) -> Vec<u8> {
+        let mut out = (s.encode_utf16().count() as u32).to_le_bytes().to_vec();
+        out.extend(s.encode_utf16().flat_map(u16::to_le_bytes));
+        out
+    }
+
+    fn cell(col: u32, value: &[u8]) -> Vec<u8> {
+        let mut out = col.to_le_bytes().to_vec();
+        // style
+        out.extend(&[0, 0, 0, 0]);
+        out.extend(value);
+        out
+    }
+
+    fn test_xlsb() -> Result<Vec<u8>> {
+        let mut workbook = Vec::new();
+        workbook.extend(record(
+            0x9c,
+            &[
+                &[0, 0, 0, 0, 1, 0, 0, 0][..],
+                &wide_str("rId1"),
+                &wide_str("Q3 Sales"),
+            ]
+            .concat(),
+        ));
+        workbook.extend(record(0x90, &[]));
+        workbook.extend(record(0x9d, &[]));
+
+        let mut sheet = Vec::new();
+        sheet.extend(record(0x94, &[0u8; 16]));
+        sheet.extend(record(0x91, &[]));
+        sheet.extend(record(0x00, &0u32.to_le_bytes()));
+        sheet.extend(record(0x06, &cell(0, &wide_str("Region"))));
+        sheet.extend(record(0x06, &cell(1, &wide_str("Revenue"))));
+        sheet.extend(record(0x00, &1u32.to_le_bytes()));
+        sheet.extend(record(0x06, &cell(0, &wide_str("North\tEast"))));
+        sheet.extend(record(0x02, &cell(1, &((1200 << 2) | 2u32).to_le_bytes())));
+        sheet.extend(record(0x00, &4u32.to_le_bytes()));
+        sheet.extend(record(0x05, &cell(2, &2.5f64.to_le_bytes())));
+        sheet.extend(record(0x06, &cell(4, &wide_str("note"))));
+        sheet.extend(record(0x92, &[]));
+
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        zip.start_file("xl/_rels/workbook.bin.rels", options)?;
+        zip.write_all(br#"<?xml version="1.0" encoding="UTF-8"?><Relationships><Relationship Id="rId1" Target="worksheets/sheet1.bin"/></Relationships>"#)?;
+        zip.start_file("xl/workbook.bin", options)?;
+        zip.write_all(&workbook)?;
+        zip.start_file("xl/worksheets/sheet1.bin", options)?;
+        zip.write_all(&sheet)?;
+        Ok(zip.finish()?.into_inner())
+    }
+
+    #[test]
+    fn columns() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(701), "ZZ");
+        assert_eq!(column_name(702), "AAA");
+    }
+
+    #[test]
+    fn xlsb() -> Result<()> {
+        let (a, d) = simple_adapt_info(
+            Path::new("report.xlsb"),
+            Box::new(Cursor::new(test_xlsb()?)),
+        );
+        let mut r = XlsbAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Q3 Sales!A1: Region\tRevenue
+PREFIX:Q3 Sales!A2: North East\t1200
+PREFIX:Q3 Sales!C5: 2.5\t\tnote
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use super::xlsb::write_sheet;
+use super::*;
+use anyhow::*;
+use calamine::{Reader, Xlsx};
+use lazy_static::lazy_static;
+use std::io::Cursor;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["xlsx", "xlsm", "xltx", "xltm"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "xlsx".to_owned(),
+        version: 1,
+        description: "Uses calamine to read the cell values of Excel workbooks (.xlsx), one line per row, as `Sheet!A5: value<tab>value`".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        // like docx, workbooks are usually detected as plain zip files
+        slow_matchers: None,
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct XlsxAdapter;
+
+impl XlsxAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(XlsxAdapter))
+    }
+}
+impl GetMetadata for XlsxAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+impl WritingFileAdapterTrait for XlsxAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // the zip container needs to be in memory, but the sheets are read cell by cell
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut workbook = Xlsx::new(Cursor::new(data))?;
+        for sheet in workbook.sheet_names() {
+            let mut reader = workbook.worksheet_cells_reader(&sheet)?;
+            write_sheet(&ai.line_prefix, &sheet, || Ok(reader.next_cell()?), oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    static NS: &str = r#"xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships""#;
+
+    fn test_xlsx() -> Result<Vec<u8>> {
+        let parts = vec![
+            (
+                "_rels/.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#.to_string(),
+            ),
+            (
+                "xl/workbook.xml",
+                format!(
+                    r#"<workbook {}><sheets><sheet name="Q3 Sales" sheetId="1" r:id="rId1"/><sheet name="Notes" sheetId="2" r:id="rId2"/></sheets></workbook>"#,
+                    NS
+                ),
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet2.xml"/><Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/sharedStrings" Target="sharedStrings.xml"/></Relationships>"#.to_string(),
+            ),
+            (
+                "xl/sharedStrings.xml",
+                format!(
+                    r#"<sst {} count="3" uniqueCount="3"><si><t>Region</t></si><si><t>Revenue</t></si><si><t>North &amp; East</t></si></sst>"#,
+                    NS
+                ),
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                format!(
+                    r#"<worksheet {}><sheetData>
+<row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v>1</v></c></row>
+<row r="2"><c r="A2" t="s"><v>2</v></c><c r="B2"><v>1200</v></c></row>
+<row r="5"><c r="C5"><v>2.5</v></c><c r="E5" t="inlineStr"><is><t>see
+notes</t></is></c></row>
+</sheetData></worksheet>"#,
+                    NS
+                ),
+            ),
+            (
+                "xl/worksheets/sheet2.xml",
+                format!(
+                    r#"<worksheet {}><sheetData><row r="1"><c r="A1" t="str"><v>B2*2</v></c></row></sheetData></worksheet>"#,
+                    NS
+                ),
+            ),
+        ];
+        let mut zip = ::zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = ::zip::write::FileOptions::default();
+        for (name, content) in parts {
+            zip.start_file(name, options)?;
+            zip.write_all(content.as_bytes())?;
+        }
+        Ok(zip.finish()?.into_inner())
+    }
+
+    #[test]
+    fn xlsx() -> Result<()> {
+        let (a, d) = simple_adapt_info(
+            Path::new("report.xlsx"),
+            Box::new(Cursor::new(test_xlsx()?)),
+        );
+        let mut r = XlsxAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Q3 Sales!A1: Region\tRevenue
+PREFIX:Q3 Sales!A2: North & East\t1200
+PREFIX:Q3 Sales!C5: 2.5\t\tsee notes
+PREFIX:Notes!A1: B2*2
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use quick_xml::events::{BytesStart, BytesText, Event};
+use std::collections::HashMap;
+use std::io::BufReader;
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["xml"];
+static MIME_TYPES: &[&str] = &["application/xml", "text/xml"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "xml".to_owned(),
+        version: 1,
+        description: "Flattens XML documents into one line per text node or attribute, prefixed with an XPath-like location (`/root/item[3]/name: value`)".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: true,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct XmlAdapter;
+
+impl XmlAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(XmlAdapter))
+    }
+}
+impl GetMetadata for XmlAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+/// a reader that does not give up on documents that are not well-formed
+pub fn xml_reader<R: BufRead>(inp: R) -> quick_xml::Reader<R> {
+    let mut reader = quick_xml::Reader::from_reader(inp);
+    // lots of xml in the wild is not well-formed
+    reader.check_end_names(false);
+    reader
+}
+
+/// unescape text, keeping it as-is if it contains (html) entities unknown to xml
+pub fn text_of(t: &BytesText) -> String {
+    match t.unescape() {
+        Result::Ok(s) => s.into_owned(),
+        Err(_) => String::from_utf8_lossy(t).into_owned(),
+    }
+}
+
+#[derive(Default)]
+struct Element {
+    path: String,
+    /// how often each child element name has been seen so far
+    children: HashMap<Vec<u8>, usize>,
+}
+
+/// the path of a child element. like in xpath, the index is the position among the
+/// siblings with the same name, but it's omitted for the first one since we don't know yet
+/// whether there will be more
+fn child_path(parent: &mut Element, e: &BytesStart) -> String {
+    let name = e.name();
+    let count = parent.children.entry(name.as_ref().to_vec()).or_insert(0);
+    *count += 1;
+    let name = String::from_utf8_lossy(name.as_ref());
+    if *count == 1 {
+        format!("{}/{}", parent.path, name)
+    } else {
+        format!("{}/{}[{}]", parent.path, name, count)
+    }
+}
+
+fn write_attributes(
+    line_prefix: &str,
+    path: &str,
+    e: &BytesStart,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    for a in e.attributes().with_checks(false).flatten() {
+        let value = match a.unescape_value() {
+            Result::Ok(v) => v.into_owned(),
+            Err(_) => String::from_utf8_lossy(&a.value).into_owned(),
+        };
+        let key = String::from_utf8_lossy(a.key.as_ref());
+        writeln!(oup, "{}{}/@{}: {}", line_prefix, path, key, value)?;
+    }
+    Ok(())
+}
+
+fn write_text(line_prefix: &str, path: &str, text: &str, oup: &mut dyn Write) -> Result<()> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !text.is_empty() {
+        writeln!(oup, "{}{}: {}", line_prefix, path, text)?;
+    }
+    Ok(())
+}
+
+pub fn write_flat_xml(line_prefix: &str, inp: impl BufRead, oup: &mut dyn Write) -> Result<()> {
+    let mut reader = xml_reader(inp);
+    let mut buf = Vec::new();
+    let mut stack = vec![Element::default()];
+    loop {
+        let parent = stack.last_mut().expect("root is never popped");
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                let path = child_path(parent, &e);
+                write_attributes(line_prefix, &path, &e, oup)?;
+                stack.push(Element {
+                    path,
+                    children: HashMap::new(),
+                });
+            }
+            Event::Empty(e) => {
+                let path = child_path(parent, &e);
+                write_attributes(line_prefix, &path, &e, oup)?;
+            }
+            Event::End(_) => {
+                if stack.len() > 1 {
+                    stack.pop();
+                }
+            }
+            Event::Text(t) => write_text(line_prefix, &parent.path, &text_of(&t), oup)?,
+            Event::CData(t) => {
+                write_text(line_prefix, &parent.path, &String::from_utf8_lossy(&t), oup)?
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for XmlAdapter {
+    fn adapt_write(
+        &self,
+        ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        write_flat_xml(&ai.line_prefix, BufReader::new(ai.inp), oup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flatten() -> Result<()> {
+        let xml = br#"<?xml version="1.0"?>
+<root><item id="1"><name>first</name></item><item/><item id="3">
+  <name>third &amp; last</name><name><![CDATA[<raw>]]></name>
+</item><other>tail</other></root>"#;
+        let mut o = Vec::new();
+        write_flat_xml("PREFIX:", &xml[..], &mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:/root/item/@id: 1
+PREFIX:/root/item/name: first
+PREFIX:/root/item[3]/@id: 3
+PREFIX:/root/item[3]/name: third & last
+PREFIX:/root/item[3]/name[2]: <raw>
+PREFIX:/root/other: tail
+"
+        );
+        Ok(())
+    }
+}
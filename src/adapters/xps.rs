@@ -0,0 +1,251 @@
+use super::xml::xml_reader;
+use super::*;
+use anyhow::*;
+use lazy_static::lazy_static;
+use quick_xml::events::{BytesStart, Event};
+use std::io::{Cursor, Seek};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
+
+static EXTENSIONS: &[&str] = &["xps", "oxps"];
+static MIME_TYPES: &[&str] = &["application/vnd.ms-xpsdocument", "application/oxps"];
+
+lazy_static! {
+    static ref METADATA: AdapterMeta = AdapterMeta {
+        name: "xps".to_owned(),
+        version: 1,
+        description: "Extracts the text of XPS / OpenXPS fixed documents. Lines are prefixed with the page number".to_owned(),
+        recurses: false,
+        fast_matchers: EXTENSIONS
+            .iter()
+            .map(|s| FastMatcher::FileExtension(s.to_string()))
+            .collect(),
+        slow_matchers: Some(
+            MIME_TYPES
+                .iter()
+                .map(|s| SlowMatcher::MimeType(s.to_string()))
+                .collect()
+        ),
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![]
+    };
+}
+#[derive(Default, Clone)]
+pub struct XpsAdapter;
+
+impl XpsAdapter {
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(XpsAdapter))
+    }
+}
+impl GetMetadata for XpsAdapter {
+    fn metadata(&self) -> &AdapterMeta {
+        &METADATA
+    }
+}
+
+pub fn read_part<R: Read + Seek>(
+    archive: &mut ::zip::ZipArchive<R>,
+    name: &str,
+) -> Result<Option<Vec<u8>>> {
+    let mut file = match archive.by_name(name.trim_start_matches('/')) {
+        Result::Ok(file) => file,
+        Err(::zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+/// resolve a part reference relative to the part that contains it
+pub fn resolve(base: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = if target.starts_with('/') {
+        Vec::new()
+    } else {
+        let mut dir: Vec<&str> = base.trim_start_matches('/').split('/').collect();
+        dir.pop();
+        dir
+    };
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            s => parts.push(s),
+        }
+    }
+    parts.join("/")
+}
+
+pub fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .with_checks(false)
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name)
+        .map(|a| match a.unescape_value() {
+            Result::Ok(v) => v.into_owned(),
+            Err(_) => String::from_utf8_lossy(&a.value).into_owned(),
+        })
+}
+
+/// call `f` for every element with the given (local) name
+pub fn for_each_element(
+    xml: &[u8],
+    element: &[u8],
+    mut f: impl FnMut(&BytesStart) -> Result<()>,
+) -> Result<()> {
+    let mut reader = xml_reader(xml);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == element => f(&e)?,
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// the `Source` of all children, resolved relative to the part
+fn sources(part: &str, xml: &[u8], element: &[u8]) -> Result<Vec<String>> {
+    let mut sources = Vec::new();
+    for_each_element(xml, element, |e| {
+        if let Some(source) = attribute(e, b"Source") {
+            sources.push(resolve(part, &source));
+        }
+        Ok(())
+    })?;
+    Ok(sources)
+}
+
+/// the pages of all documents, in reading order
+fn page_names<R: Read + Seek>(archive: &mut ::zip::ZipArchive<R>) -> Result<Vec<String>> {
+    let mut sequence = None;
+    if let Some(rels) = read_part(archive, "_rels/.rels")? {
+        for_each_element(&rels, b"Relationship", |e| {
+            let is_fixed =
+                attribute(e, b"Type").is_some_and(|t| t.ends_with("/fixedrepresentation"));
+            if is_fixed && sequence.is_none() {
+                sequence = attribute(e, b"Target").map(|t| resolve("", &t));
+            }
+            Ok(())
+        })?;
+    }
+    let sequence = match sequence {
+        Some(sequence) => sequence,
+        None => archive
+            .file_names()
+            .find(|n| n.ends_with(".fdseq"))
+            .map(|n| n.to_string())
+            .context("no fixed document sequence found")?,
+    };
+    let sequence_xml =
+        read_part(archive, &sequence)?.with_context(|| format!("missing part {}", sequence))?;
+    let mut pages = Vec::new();
+    for document in sources(&sequence, &sequence_xml, b"DocumentReference")? {
+        let document_xml =
+            read_part(archive, &document)?.with_context(|| format!("missing part {}", document))?;
+        pages.extend(sources(&document, &document_xml, b"PageContent")?);
+    }
+    Ok(pages)
+}
+
+/// write the glyph runs of a page, joining runs on the same baseline into one line
+fn write_page(line_prefix: &str, xml: &[u8], oup: &mut dyn Write) -> Result<()> {
+    let mut lines: Vec<(f64, Vec<(f64, String)>)> = Vec::new();
+    for_each_element(xml, b"Glyphs", |e| {
+        let text = match attribute(e, b"UnicodeString") {
+            // "{}" escapes strings that start with a brace
+            Some(text) => text.strip_prefix("{}").map(str::to_string).unwrap_or(text),
+            None => return Ok(()),
+        };
+        let coord = |name| {
+            attribute(e, name)
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .unwrap_or(0.0)
+        };
+        let (x, y) = (coord(b"OriginX"), coord(b"OriginY"));
+        match lines
+            .iter_mut()
+            .find(|(line_y, _)| (line_y - y).abs() < 1.0)
+        {
+            Some((_, runs)) => runs.push((x, text)),
+            None => lines.push((y, vec![(x, text)])),
+        }
+        Ok(())
+    })?;
+    for (_, mut runs) in lines {
+        runs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        let line = runs
+            .iter()
+            .map(|(_, text)| text.trim())
+            .filter(|t| !t.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !line.is_empty() {
+            writeln!(oup, "{}{}", line_prefix, line)?;
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for XpsAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        // zip needs to seek to the central directory, so read the whole file to memory
+        let mut data = Vec::new();
+        ai.inp.read_to_end(&mut data)?;
+        let mut archive = ::zip::ZipArchive::new(Cursor::new(data))?;
+        for (i, page) in page_names(&mut archive)?.iter().enumerate() {
+            let xml =
+                read_part(&mut archive, page)?.with_context(|| format!("missing page {}", page))?;
+            let prefix = format!("{}Page {}: ", ai.line_prefix, i + 1);
+            write_page(&prefix, &xml, oup)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs::File;
+
+    #[test]
+    fn pages() -> Result<()> {
+        let fname = test_data_dir().join("short.xps");
+        let (a, d) = simple_adapt_info(&fname, Box::new(File::open(&fname)?));
+        let mut r = XpsAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        assert_eq!(
+            String::from_utf8(o)?,
+            "PREFIX:Page 1: hello world\nPREFIX:Page 1: this is just a test.\nPREFIX:Page 2: second page\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn part_names() {
+        assert_eq!(
+            resolve("Documents/1/FixedDoc.fdoc", "Pages/1.fpage"),
+            "Documents/1/Pages/1.fpage"
+        );
+        assert_eq!(
+            resolve("Documents/1/FixedDoc.fdoc", "/Pages/1.fpage"),
+            "Pages/1.fpage"
+        );
+        assert_eq!(
+            resolve("Documents/1/FixedDoc.fdoc", "../2/x.fpage"),
+            "Documents/2/x.fpage"
+        );
+    }
+}
@@ -1,35 +1,39 @@
 use super::*;
 use crate::{preproc::rga_preproc, print_bytes};
-use ::zip::read::ZipFile;
+use ::zip::{read::ZipFile, result::ZipError, ZipArchive};
 use anyhow::*;
 use lazy_static::lazy_static;
 use log::*;
+use std::fs::File;
+use std::io::{Cursor, Seek};
+use writing::{WritingFileAdapter, WritingFileAdapterTrait};
 
-// todo:
-// maybe todo: read list of extensions from
-//ffmpeg -demuxers | tail -n+5 | awk '{print $2}' | while read demuxer; do echo MUX=$demuxer; ffmpeg -h demuxer=$demuxer | grep 'Common extensions'; done 2>/dev/null
-static EXTENSIONS: &[&str] = &["zip"];
+static EXTENSIONS: &[&str] = &["zip", "jar", "war", "ear"];
+/// the members are read into memory, with at most this much allocated up front
+const MAX_PREALLOCATE: usize = 64 << 20;
 
 lazy_static! {
     static ref METADATA: AdapterMeta = AdapterMeta {
         name: "zip".to_owned(),
         version: 1,
-        description: "Reads a zip file as a stream and recurses down into its contents".to_owned(),
+        description: "Reads a zip file and recurses down into its contents. Encrypted entries are decrypted if --rga-archive-password is given".to_owned(),
         recurses: true,
         fast_matchers: EXTENSIONS
             .iter()
             .map(|s| FastMatcher::FileExtension(s.to_string()))
             .collect(),
         slow_matchers: Some(vec![SlowMatcher::MimeType("application/zip".to_owned())]),
-        disabled_by_default: false
+        disabled_by_default: false,
+        skip_cache: false,
+        options: vec![AdapterOption::ArchivePassword]
     };
 }
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ZipAdapter;
 
 impl ZipAdapter {
-    pub fn new() -> ZipAdapter {
-        ZipAdapter
+    pub fn new() -> WritingFileAdapter {
+        WritingFileAdapter::new(Box::new(ZipAdapter))
     }
 }
 impl GetMetadata for ZipAdapter {
@@ -38,55 +42,137 @@ impl GetMetadata for ZipAdapter {
     }
 }
 
-// https://github.com/mvdnes/zip-rs/commit/b9af51e654793931af39f221f143b9dea524f349
-fn is_dir(f: &ZipFile) -> bool {
-    f.name()
-        .chars()
-        .rev()
-        .next()
-        .map_or(false, |c| c == '/' || c == '\\')
+pub fn adapt_member(file: &mut ZipFile, ai: &AdaptInfo, oup: &mut dyn Write) -> Result<()> {
+    debug!(
+        "{}{}|{}: {} ({} packed)",
+        ai.line_prefix,
+        ai.filepath_hint.to_string_lossy(),
+        file.name(),
+        print_bytes(file.size() as f64),
+        print_bytes(file.compressed_size() as f64)
+    );
+    // the zip file borrows the archive, so the inner adapter can not read from it directly.
+    // the size is only what the header claims, a crafted one must not allocate gigabytes
+    let mut data = Vec::with_capacity((file.size() as usize).min(MAX_PREALLOCATE));
+    file.read_to_end(&mut data)?;
+    let mut inner = rga_preproc(AdaptInfo {
+        // only a hint for the inner adapters, so fall back to the raw name for unsafe paths
+        filepath_hint: file
+            .enclosed_name()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(file.name())),
+        is_real_file: false,
+        inp: Box::new(Cursor::new(data)),
+        line_prefix: format!("{}{}: ", ai.line_prefix, file.name()),
+        archive_recursion_depth: ai.archive_recursion_depth + 1,
+        config: ai.config.clone(),
+    })?;
+    std::io::copy(&mut inner, oup)?;
+    Ok(())
 }
 
-impl FileAdapter for ZipAdapter {
-    fn adapt(&self, ai: AdaptInfo, _detection_reason: &SlowMatcher) -> Result<()> {
-        let AdaptInfo {
-            filepath_hint,
-            mut inp,
-            oup,
-            line_prefix,
-            archive_recursion_depth,
-            config,
-            ..
-        } = ai;
-        loop {
-            match ::zip::read::read_zipfile_from_stream(&mut inp) {
-                Ok(None) => break,
-                Ok(Some(mut file)) => {
-                    if is_dir(&file) {
-                        continue;
-                    }
-                    debug!(
-                        "{}{}|{}: {} ({} packed)",
-                        line_prefix,
-                        filepath_hint.to_string_lossy(),
-                        file.name(),
-                        print_bytes(file.size() as f64),
-                        print_bytes(file.compressed_size() as f64)
-                    );
-                    let line_prefix = &format!("{}{}: ", line_prefix, file.name());
-                    rga_preproc(AdaptInfo {
-                        filepath_hint: &file.sanitized_name(),
-                        is_real_file: false,
-                        inp: &mut file,
+fn adapt_archive<R: Read + Seek>(
+    mut archive: ZipArchive<R>,
+    ai: &AdaptInfo,
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let password = ai.config.args.archive_password.as_ref();
+    for i in 0..archive.len() {
+        let name = {
+            let raw = archive.by_index_raw(i)?;
+            if raw.is_dir() {
+                continue;
+            }
+            raw.name().to_string()
+        };
+        let file = match password {
+            Some(password) => archive.by_index_decrypt(i, password.as_bytes())?,
+            None => match archive.by_index(i) {
+                Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                    writeln!(
                         oup,
-                        line_prefix,
-                        archive_recursion_depth: archive_recursion_depth + 1,
-                        config: config.clone(),
-                    })?;
+                        "{}{}: [rga: encrypted, use --rga-archive-password to search in it]",
+                        ai.line_prefix, name
+                    )?;
+                    continue;
                 }
-                Err(e) => return Err(e.into()),
-            }
+                r => Result::Ok(r?),
+            },
+        };
+        match file {
+            Result::Ok(mut file) => adapt_member(&mut file, ai, oup)?,
+            Err(_invalid_password) => writeln!(
+                oup,
+                "{}{}: [rga: wrong archive password]",
+                ai.line_prefix, name
+            )?,
+        }
+    }
+    Ok(())
+}
+
+impl WritingFileAdapterTrait for ZipAdapter {
+    fn adapt_write(
+        &self,
+        mut ai: AdaptInfo,
+        _detection_reason: &SlowMatcher,
+        oup: &mut dyn Write,
+    ) -> Result<()> {
+        if ai.is_real_file {
+            let archive = ZipArchive::new(File::open(&ai.filepath_hint)?)?;
+            adapt_archive(archive, &ai, oup)
+        } else {
+            // zip needs to seek to the central directory
+            let mut data = Vec::new();
+            ai.inp.read_to_end(&mut data)?;
+            let archive = ZipArchive::new(Cursor::new(data))?;
+            adapt_archive(archive, &ai, oup)
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::RgaConfig;
+    use crate::test_utils::*;
+
+    fn adapt(fname: &str, archive_password: Option<&str>) -> Result<String> {
+        let filepath = test_data_dir().join(fname);
+        let (mut a, d) = simple_adapt_info(&filepath, Box::new(File::open(&filepath)?));
+        a.config.args = RgaConfig {
+            archive_password: archive_password.map(|p| p.to_string()),
+            ..Default::default()
+        };
+        let mut r = ZipAdapter::new().adapt(a, &d)?;
+        let mut o = Vec::new();
+        r.read_to_end(&mut o)?;
+        Ok(String::from_utf8(o)?)
+    }
+
+    #[test]
+    fn recurse() -> Result<()> {
+        assert_eq!(
+            adapt("short.zip", None)?,
+            "hello world\nPREFIX:dir/short.docx: hello world\nPREFIX:dir/short.docx: this is just a\ttest.\nPREFIX:dir/short.docx: \nPREFIX:dir/short.docx: a footnote\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted() -> Result<()> {
+        assert_eq!(
+            adapt("encrypted.zip", None)?,
+            "PREFIX:secret.txt: [rga: encrypted, use --rga-archive-password to search in it]\n"
+        );
+        assert_eq!(
+            adapt("encrypted.zip", Some("wrong"))?,
+            "PREFIX:secret.txt: [rga: wrong archive password]\n"
+        );
+        assert_eq!(
+            adapt("encrypted.zip", Some("hunter2"))?,
+            "the treasure is buried under the tree\n"
+        );
         Ok(())
     }
 }
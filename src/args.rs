@@ -14,9 +14,24 @@ struct ReadableBytesCount(i64);
 fn is_default<T: Default + PartialEq>(t: &T) -> bool {
     t == &T::default()
 }
-#[derive(JsonSchema, Debug, Serialize, Deserialize, Copy, Clone, PartialEq, FromStr)]
+#[derive(JsonSchema, Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub struct CacheCompressionLevel(pub i32);
 
+impl FromStr for CacheCompressionLevel {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level = i32::from_str(s).context("Could not parse int")?;
+        // zstd would silently use the closest level it supports
+        if level < zstd_safe::min_c_level() || level > zstd_safe::max_c_level() {
+            return Err(format_err!(
+                "compression level must be between 1 and {} (or negative for faster compression)",
+                zstd_safe::max_c_level()
+            ));
+        }
+        Ok(CacheCompressionLevel(level))
+    }
+}
+
 impl ToString for CacheCompressionLevel {
     fn to_string(&self) -> String {
         self.0.to_string()
@@ -55,26 +70,122 @@ impl Default for CacheMaxBlobLen {
     }
 }
 
+/// a byte count with an optional k, M or G suffix
+fn parse_readable_bytes(s: &str) -> Result<usize> {
+    let suffix = s.chars().last();
+    if let Some(suffix) = suffix {
+        match suffix {
+            'k' | 'M' | 'G' => usize::from_str(s.trim_end_matches(suffix))
+                .context("Could not parse int")
+                .map(|e| {
+                    e * match suffix {
+                        'k' => 1000,
+                        'M' => 1_000_000,
+                        'G' => 1_000_000_000,
+                        _ => panic!("impossible"),
+                    }
+                }),
+            _ => usize::from_str(s).context("Could not parse int"),
+        }
+    } else {
+        Err(format_err!("empty byte input"))
+    }
+}
+
 impl FromStr for CacheMaxBlobLen {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let suffix = s.chars().last();
-        if let Some(suffix) = suffix {
-            Ok(CacheMaxBlobLen(match suffix {
-                'k' | 'M' | 'G' => usize::from_str(s.trim_end_matches(suffix))
-                    .with_context(|| format!("Could not parse int"))
-                    .map(|e| {
-                        e * match suffix {
-                            'k' => 1000,
-                            'M' => 1000_000,
-                            'G' => 1000_000_000,
-                            _ => panic!("impossible"),
-                        }
-                    }),
-                _ => usize::from_str(s).with_context(|| format!("Could not parse int")),
-            }?))
-        } else {
-            Err(format_err!("empty byte input"))
+        Ok(CacheMaxBlobLen(parse_readable_bytes(s)?))
+    }
+}
+
+#[derive(JsonSchema, Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub struct CacheMaxTotal(pub usize);
+
+impl FromStr for CacheMaxTotal {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(CacheMaxTotal(parse_readable_bytes(s)?))
+    }
+}
+
+/// in seconds. parsed from a duration with a s, m, h or d suffix, seconds without one
+#[derive(JsonSchema, Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub struct CacheMaxAge(pub u64);
+
+impl FromStr for CacheMaxAge {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = match s.char_indices().last() {
+            Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c),
+            _ => (s, 's'),
+        };
+        let number = u64::from_str(number).context("Could not parse int")?;
+        let seconds = match unit {
+            's' => number,
+            'm' => number * 60,
+            'h' => number * 60 * 60,
+            'd' => number * 60 * 60 * 24,
+            _ => return Err(format_err!("unknown unit {}, expected s, m, h or d", unit)),
+        };
+        Ok(CacheMaxAge(seconds))
+    }
+}
+
+/// where the cache is stored, see --rga-cache-backend
+#[derive(JsonSchema, Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    #[default]
+    Lmdb,
+    Sqlite,
+    Redis,
+}
+
+impl std::fmt::Display for CacheBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CacheBackend::Lmdb => "lmdb",
+            CacheBackend::Sqlite => "sqlite",
+            CacheBackend::Redis => "redis",
+        })
+    }
+}
+
+impl FromStr for CacheBackend {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lmdb" => Ok(CacheBackend::Lmdb),
+            "sqlite" => Ok(CacheBackend::Sqlite),
+            "redis" => Ok(CacheBackend::Redis),
+            _ => Err(format_err!(
+                "unknown cache backend {}, expected lmdb, sqlite or redis",
+                s
+            )),
+        }
+    }
+}
+
+/// what to do with the cache, see --rga-cache
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CacheCommand {
+    Stats,
+    Clear,
+    Prune,
+}
+
+impl FromStr for CacheCommand {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stats" => Ok(CacheCommand::Stats),
+            "clear" => Ok(CacheCommand::Clear),
+            "prune" => Ok(CacheCommand::Prune),
+            _ => Err(format_err!(
+                "unknown cache command {}, expected stats, clear or prune",
+                s
+            )),
         }
     }
 }
@@ -108,6 +219,18 @@ pub struct RgaConfig {
     #[structopt(long = "--rga-no-cache")]
     pub no_cache: bool,
 
+    /// Do not cache the output of these adapters, e.g. --rga-no-cache-adapters=decompress,zip
+    ///
+    /// For adapters that are fast enough that caching their output only wastes space.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-no-cache-adapters",
+        require_equals = true,
+        require_delimiter = true,
+        hidden_short_help = true
+    )]
+    pub no_cache_adapters: Vec<String>,
+
     /// Use more accurate but slower matching by mime type
     ///
     /// By default, rga will match files using file extensions.
@@ -120,6 +243,104 @@ pub struct RgaConfig {
     #[structopt(long = "--rga-accurate")]
     pub accurate: bool,
 
+    /// Run OCR on PDFs that have no text layer
+    ///
+    /// If pdftotext finds (almost) no text in a PDF file, the pages are rendered
+    /// to images using pdftoppm and run through tesseract instead.
+    /// This makes scanned documents searchable, but is very slow.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-pdf-ocr")]
+    pub pdf_ocr: bool,
+
+    /// Write the fields of PDF forms
+    ///
+    /// The names and filled in values of AcroForm and XFA form fields are written
+    /// after the text, e.g. "Form: name (Your first name): Jane".
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-pdf-forms")]
+    pub pdf_forms: bool,
+
+    /// whisper.cpp model to transcribe audio and video files with
+    ///
+    /// Only used by the (opt-in) whisper adapter, e.g. --rga-whisper-model=/path/to/ggml-base.bin.
+    /// Models can be downloaded with the download-ggml-model.sh script of whisper.cpp.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-whisper-model",
+        require_equals = true,
+        hidden_short_help = true
+    )]
+    pub whisper_model: Option<String>,
+
+    /// Password for encrypted archive members
+    ///
+    /// Used to decrypt password protected entries in zip files.
+    /// Encrypted entries are skipped (with a notice in the output) if this is not given.
+    /// There is no interactive prompt, since rg runs rga-preproc for many files in parallel
+    /// without a terminal; use e.g. `--rga-archive-password="$(read -rs p && echo "$p")"` instead.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-archive-password", require_equals = true)]
+    pub archive_password: Option<String>,
+
+    /// Command that prints the passphrase for gpg encrypted files
+    ///
+    /// Only used by the (opt-in) decrypt adapter. The command is run with sh -c and
+    /// the first line of its output is used as passphrase.
+    /// Without it, gpg asks the running gpg-agent.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-decrypt-passphrase-command",
+        require_equals = true,
+        hidden_short_help = true
+    )]
+    pub decrypt_passphrase_command: Option<String>,
+
+    /// age identity file to decrypt .age files with
+    ///
+    /// Only used by the (opt-in) decrypt adapter. Passphrase encrypted age files are not supported.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-age-identity",
+        require_equals = true,
+        hidden_short_help = true
+    )]
+    pub age_identity: Option<String>,
+
+    /// Protobuf descriptor set to decode protobuf files with
+    ///
+    /// A FileDescriptorSet as written by `protoc --include_imports --descriptor_set_out=foo.desc`.
+    /// Without it, protobuf files are dumped as field numbers and values.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-proto-descriptor", require_equals = true)]
+    pub proto_descriptor: Option<String>,
+
+    /// Fully qualified name of the message type to decode protobuf files as
+    ///
+    /// Only used with --rga-proto-descriptor. Defaults to the first message type
+    /// of the last file in the descriptor set.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-proto-message",
+        require_equals = true,
+        hidden_short_help = true
+    )]
+    pub proto_message: Option<String>,
+
+    /// Show the targets of links in HTML documents
+    ///
+    /// Link targets are appended to the link text, e.g. "the docs (https://example.com/docs)".
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-html-links")]
+    pub html_links: bool,
+
+    /// Show comments and tracked changes in Word documents
+    ///
+    /// Reviewer comments and tracked insertions and deletions are written after the paragraph
+    /// they are in, with their author, e.g. "Comment (Jane) on "the term": too vague".
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-docx-revisions")]
+    pub docx_revisions: bool,
+
     /// Change which adapters to use and in which priority order (descending)
     ///
     /// "foo,bar" means use only adapters foo and bar.
@@ -148,17 +369,93 @@ pub struct RgaConfig {
 
     /// ZSTD compression level to apply to adapter outputs before storing in cache db
     ///
-    ///  Ranges from 1 - 22
+    /// Ranges from 1 - 22, negative levels compress even faster. Higher levels make the cache smaller,
+    /// e.g. 19 when indexing a large corpus once, but compress slower, e.g. 1 for the fastest first searches.
+    /// Together with --rga-cache-max-blob-len, this decides how much fits into the cache.
     #[serde(default, skip_serializing_if = "is_default")]
     #[structopt(
         default_value,
         long = "--rga-cache-compression-level",
         hidden_short_help = true,
-        require_equals = true,
-        help = ""
+        require_equals = true
     )]
     pub cache_compression_level: CacheCompressionLevel,
 
+    /// Max total size of the cache
+    ///
+    /// When the cached outputs get larger than this, the ones that were used least recently are deleted. Allowed suffixes: k M G.
+    /// Without it, the cache can grow up to 2 GB.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-cache-max-total",
+        hidden_short_help = true,
+        require_equals = true
+    )]
+    pub cache_max_total: Option<CacheMaxTotal>,
+
+    /// Max age of cache entries
+    ///
+    /// Cached outputs that were not used for this long are deleted, e.g. 30d. Allowed suffixes: s m h d
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-cache-max-age",
+        hidden_short_help = true,
+        require_equals = true
+    )]
+    pub cache_max_age: Option<CacheMaxAge>,
+
+    /// Database to store the cache in
+    ///
+    /// "lmdb" is the fastest. "sqlite" keeps the cache in a single file (cache.sqlite3 in the cache directory)
+    /// that can be inspected with the sqlite3 tool, and works better when the cache is on a network file system.
+    /// "redis" stores the cache on the redis server of --rga-cache-redis-url, e.g. to share it within a team.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        default_value,
+        long = "--rga-cache-backend",
+        require_equals = true,
+        possible_values = &["lmdb", "sqlite", "redis"],
+        hidden_short_help = true
+    )]
+    pub cache_backend: CacheBackend,
+
+    /// The redis server of --rga-cache-backend=redis: redis://[[user]:password@]host[:port][/db]
+    ///
+    /// --rga-cache-max-total has no effect with redis, configure maxmemory and an eviction policy
+    /// such as allkeys-lru on the server instead. Defaults to redis://127.0.0.1:6379
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(
+        long = "--rga-cache-redis-url",
+        require_equals = true,
+        hidden_short_help = true
+    )]
+    pub cache_redis_url: Option<String>,
+
+    /// Key the cache by the content of files instead of their path and modification time
+    ///
+    /// This way, files that were renamed, copied, or restored from a backup with a different mtime still hit the cache.
+    /// The whole file has to be read to hash it, which is slower than looking at the mtime.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-cache-content-hash", hidden_short_help = true)]
+    pub cache_content_hash: bool,
+
+    /// Only read from the cache, never write to it
+    ///
+    /// For caches on read-only media, or shared by several users of which only one (e.g. an indexing job) should write.
+    /// --rga-cache-max-total and --rga-cache-max-age have no effect, since the cache is not changed.
+    /// The lmdb cache still needs its lock file to be writable, unless the cache is on a read-only file system.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-cache-read-only", hidden_short_help = true)]
+    pub cache_read_only: bool,
+
+    /// Compress small cache entries with a dictionary
+    ///
+    /// Once an adapter has 100 small outputs in the cache, a zstd dictionary is trained from them and used to compress its new outputs.
+    /// The text extracted from many similar documents compresses much better with it.
+    #[serde(default, skip_serializing_if = "is_default")]
+    #[structopt(long = "--rga-cache-zstd-dict", hidden_short_help = true)]
+    pub cache_zstd_dict: bool,
+
     /// Maximum nestedness of archives to recurse into
     #[serde(default, skip_serializing_if = "is_default")]
     #[structopt(
@@ -194,6 +491,69 @@ pub struct RgaConfig {
     #[structopt(long = "--rga-list-adapters", help = "List all known adapters")]
     pub list_adapters: bool,
 
+    /// Show statistics about the cache or delete entries from it
+    ///
+    /// "stats" shows the size of the cache and the number of entries per adapter.
+    /// "clear" deletes the whole cache.
+    /// "prune" deletes the entries of outdated adapter versions and of files that changed or no longer exist.
+    /// The space of pruned entries is reused for new ones, use "clear" to shrink the cache on disk.
+    #[serde(skip)]
+    #[structopt(
+        long = "--rga-cache",
+        require_equals = true,
+        possible_values = &["stats", "clear", "prune"],
+        hidden_short_help = true
+    )]
+    pub cache_command: Option<CacheCommand>,
+
+    /// Write the cache to a file, to be imported on another machine with --rga-cache-import
+    ///
+    /// This way, a large corpus has to be extracted (e.g. with OCR or transcription) only once for a whole team.
+    /// Unless --rga-cache-content-hash is used, the cache keys contain the paths and modification times of the files,
+    /// so the imported entries are only used for the same files at the same paths.
+    #[serde(skip)]
+    #[structopt(
+        long = "--rga-cache-export",
+        require_equals = true,
+        hidden_short_help = true
+    )]
+    pub cache_export: Option<String>,
+
+    /// Add the entries of a file written by --rga-cache-export to the cache
+    #[serde(skip)]
+    #[structopt(
+        long = "--rga-cache-import",
+        require_equals = true,
+        hidden_short_help = true
+    )]
+    pub cache_import: Option<String>,
+
+    /// Preprocess all files in a directory to fill the cache, without searching
+    ///
+    /// The files are selected like rg does when searching (e.g. .gitignore and --hidden are respected),
+    /// and as many as there are CPUs are preprocessed at a time.
+    /// Afterwards, searching the directory only has to run the adapters of files that changed,
+    /// which makes slow adapters like OCR or transcription usable interactively.
+    #[serde(skip)]
+    #[structopt(long = "--rga-index", require_equals = true, hidden_short_help = true)]
+    pub index: Option<String>,
+
+    /// Like --rga-index, then keep preprocessing the files in the directory that are created or changed
+    ///
+    /// Keeps the cache up to date for directories that change often, like a mail archive.
+    /// Runs until it is killed.
+    #[serde(skip)]
+    #[structopt(long = "--rga-watch", require_equals = true, hidden_short_help = true)]
+    pub watch: Option<String>,
+
+    /// Print how many files were read from the cache or adapted, and the time and output of each adapter
+    ///
+    /// Printed to stderr after the search. Useful to find out which adapters are slow,
+    /// e.g. to disable them or to lower --rga-max-archive-recursion.
+    #[serde(skip)]
+    #[structopt(long = "--rga-stats", hidden_short_help = true)]
+    pub stats: bool,
+
     #[serde(skip)]
     #[structopt(
         long = "--rga-print-config-schema",
@@ -337,6 +697,12 @@ where
         // readd values with [serde(skip)]
         res.fzf_path = arg_matches.fzf_path;
         res.list_adapters = arg_matches.list_adapters;
+        res.cache_command = arg_matches.cache_command;
+        res.cache_export = arg_matches.cache_export;
+        res.cache_import = arg_matches.cache_import;
+        res.index = arg_matches.index;
+        res.watch = arg_matches.watch;
+        res.stats = arg_matches.stats;
         res.print_config_schema = arg_matches.print_config_schema;
         res.rg_help = arg_matches.rg_help;
         res.rg_version = arg_matches.rg_version;
@@ -4,8 +4,10 @@ use ripgrep_all as rga;
 
 use anyhow::Context;
 use std::fs::File;
+use std::time::Instant;
 
 fn main() -> anyhow::Result<()> {
+    let start = Instant::now();
     env_logger::init();
     let mut arg_arr: Vec<std::ffi::OsString> = std::env::args_os().collect();
     let last = arg_arr.pop().expect("No filename specified");
@@ -21,7 +23,7 @@ fn main() -> anyhow::Result<()> {
     let cache = if args.no_cache {
         None
     } else {
-        Some(rga::preproc_cache::open().context("could not open cache")?)
+        rga::preproc_cache::open(&args).context("could not open cache")?
     };
     let ai = AdaptInfo {
         inp: Box::new(i),
@@ -32,6 +34,7 @@ fn main() -> anyhow::Result<()> {
         config: PreprocConfig { cache, args },
     };
     let mut oup = rga_preproc(ai).context("during preprocessing")?;
-    std::io::copy(&mut oup, &mut o).context("copying adapter output to stdout")?;
+    let bytes = std::io::copy(&mut oup, &mut o).context("copying adapter output to stdout")?;
+    rga::stats::write(start.elapsed(), bytes)?;
     Ok(())
 }
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use rga::adapters::spawning::map_exe_error;
 use rga::adapters::*;
 use rga::args::*;
@@ -7,9 +7,18 @@ use rga::print_dur;
 use ripgrep_all as rga;
 use structopt::StructOpt;
 
+use notify::{EventKind, Watcher};
 use schemars::schema_for;
-use std::process::Command;
-use std::time::Instant;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+/// how long --rga-watch waits for more changes before indexing the changed files
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(1);
 
 fn list_adapters(args: RgaConfig) -> Result<()> {
     let (enabled_adapters, disabled_adapters) = get_all_adapters(args.custom_adapters.clone());
@@ -17,11 +26,22 @@ fn list_adapters(args: RgaConfig) -> Result<()> {
     println!("Adapters:\n");
     let print = |adapter: std::rc::Rc<dyn FileAdapter>| {
         let meta = adapter.metadata();
-        let matchers = meta
+        let extensions = meta
             .fast_matchers
             .iter()
-            .map(|m| match m {
-                FastMatcher::FileExtension(ext) => format!(".{}", ext),
+            .filter_map(|m| match m {
+                FastMatcher::FileExtension(ext) => Some(format!(".{}", ext)),
+                FastMatcher::FileName(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        // e.g. Dockerfile or the files in a .git directory, which have no extension
+        let file_names = meta
+            .fast_matchers
+            .iter()
+            .filter_map(|m| match m {
+                FastMatcher::FileName(name) => Some(name.clone()),
+                FastMatcher::FileExtension(_) => None,
             })
             .collect::<Vec<_>>()
             .join(", ");
@@ -36,24 +56,26 @@ fn list_adapters(args: RgaConfig) -> Result<()> {
             })
             .collect::<Vec<_>>()
             .join(", ");
-        let mime_text = if slow_matchers.is_empty() {
-            "".to_owned()
-        } else {
-            format!("Mime Types: {}", slow_matchers)
-        };
         print!(
-            " - **{name}**\n     {desc}  \n     Extensions: {matchers}  \n     {mime}  \n",
+            " - **{name}**\n     {desc}  \n",
             name = meta.name,
             desc = meta.description.replace("\n", "\n     "),
-            matchers = matchers,
-            mime = mime_text
         );
+        for (label, list) in &[
+            ("Extensions", extensions),
+            ("File names", file_names),
+            ("Mime Types", slow_matchers),
+        ] {
+            if !list.is_empty() {
+                println!("     {}: {}  ", label, list);
+            }
+        }
         println!("");
     };
     for adapter in enabled_adapters {
         print(adapter)
     }
-    println!("The following adapters are disabled by default, and can be enabled using '--rga-adapters=+pdfpages,tesseract':\n");
+    println!("The following adapters are disabled by default, and can be enabled using '--rga-adapters=+ocr':\n");
     for adapter in disabled_adapters {
         print(adapter)
     }
@@ -76,6 +98,36 @@ fn main() -> anyhow::Result<()> {
     if args.list_adapters {
         return list_adapters(args);
     }
+    if let Some(command) = args.cache_command {
+        if args.cache_read_only && command != CacheCommand::Stats {
+            anyhow::bail!("the cache is read-only (--rga-cache-read-only)");
+        }
+        let (mut adapters, disabled_adapters) = get_all_adapters(args.custom_adapters.clone());
+        adapters.extend(disabled_adapters);
+        return rga::preproc_cache::run_command(
+            command,
+            args.cache_backend,
+            &adapters,
+            &mut std::io::stdout(),
+        );
+    }
+    if let Some(file) = &args.cache_export {
+        return rga::preproc_cache::export(
+            args.cache_backend,
+            Path::new(file),
+            &mut std::io::stdout(),
+        );
+    }
+    if let Some(file) = &args.cache_import {
+        if args.cache_read_only {
+            anyhow::bail!("the cache is read-only (--rga-cache-read-only)");
+        }
+        return rga::preproc_cache::import(
+            args.cache_backend,
+            Path::new(file),
+            &mut std::io::stdout(),
+        );
+    }
     if let Some(path) = args.fzf_path {
         if path == "_" {
             // fzf found no result, ignore everything and return
@@ -85,7 +137,7 @@ fn main() -> anyhow::Result<()> {
         passthrough_args.push(std::ffi::OsString::from(&path[1..]));
     }
 
-    if passthrough_args.len() == 0 {
+    if passthrough_args.is_empty() && args.index.is_none() && args.watch.is_none() {
         // rg would show help. Show own help instead.
         RgaConfig::clap().print_help()?;
         println!("");
@@ -95,15 +147,19 @@ fn main() -> anyhow::Result<()> {
     let adapters = get_adapters_filtered(args.custom_adapters.clone(), &args.adapters)?;
 
     let pre_glob = if !args.accurate {
-        let extensions = adapters
+        let globs = adapters
             .iter()
             .flat_map(|a| &a.metadata().fast_matchers)
             .flat_map(|m| match m {
-                FastMatcher::FileExtension(ext) => vec![ext.clone(), ext.to_ascii_uppercase()],
+                FastMatcher::FileExtension(ext) => vec![
+                    format!("*.{}", ext),
+                    format!("*.{}", ext.to_ascii_uppercase()),
+                ],
+                FastMatcher::FileName(name) => vec![name.clone()],
             })
             .collect::<Vec<_>>()
             .join(",");
-        format!("*.{{{}}}", extensions)
+        format!("{{{}}}", globs)
     } else {
         "*".to_owned()
     };
@@ -120,6 +176,25 @@ fn main() -> anyhow::Result<()> {
     let exe = std::env::current_exe().expect("Could not get executable location");
     let preproc_exe = exe.with_file_name("rga-preproc");
 
+    if let Some(dir) = &args.index {
+        if args.no_cache || args.cache_read_only {
+            anyhow::bail!("--rga-index needs a cache that can be written to");
+        }
+        return index(
+            Path::new(dir),
+            &pre_glob,
+            &preproc_exe,
+            &passthrough_args,
+            None,
+        );
+    }
+    if let Some(dir) = &args.watch {
+        if args.no_cache || args.cache_read_only {
+            anyhow::bail!("--rga-watch needs a cache that can be written to");
+        }
+        return watch(Path::new(dir), &pre_glob, &preproc_exe, &passthrough_args);
+    }
+
     let before = Instant::now();
     let mut cmd = Command::new("rg");
     cmd.args(rg_args)
@@ -128,6 +203,14 @@ fn main() -> anyhow::Result<()> {
         .arg("--pre-glob")
         .arg(pre_glob)
         .args(passthrough_args);
+    // the rga-preproc processes append to it
+    let stats_file = if args.stats {
+        let file = tempfile::NamedTempFile::new()?.into_temp_path();
+        cmd.env(rga::stats::STATS_FILE, &file);
+        Some(file)
+    } else {
+        None
+    };
     log::debug!("rg command to run: {:?}", cmd);
     let mut child = cmd
         .spawn()
@@ -136,9 +219,157 @@ fn main() -> anyhow::Result<()> {
     child.wait()?;
 
     log::debug!("running rg took {}", print_dur(before));
+    if let Some(file) = stats_file {
+        eprint!(
+            "{}",
+            rga::stats::summary(rga::stats::read(&file)?.into_iter())
+        );
+    }
+    Ok(())
+}
+
+/// runs rga-preproc on every file in dir that rg would search, to fill the cache.
+/// with `only`, just on those of them that are in it
+fn index(
+    dir: &Path,
+    pre_glob: &str,
+    preproc_exe: &Path,
+    passthrough_args: &[std::ffi::OsString],
+    only: Option<&HashSet<PathBuf>>,
+) -> Result<()> {
+    let before = Instant::now();
+    let mut cmd = Command::new("rg");
+    cmd.arg("--files")
+        .arg("--null")
+        .arg("--glob")
+        .arg(pre_glob)
+        .args(passthrough_args)
+        .arg(dir)
+        .stdout(Stdio::piped());
+    log::debug!("rg command to run: {:?}", cmd);
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| map_exe_error(e, "rg", "Please make sure you have ripgrep installed."))?;
+    let (sender, receiver) = crossbeam_channel::unbounded::<PathBuf>();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let indexed = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    crossbeam::scope(|s| -> Result<()> {
+        for _ in 0..threads {
+            let receiver = receiver.clone();
+            let (indexed, failed) = (&indexed, &failed);
+            s.spawn(move |_| {
+                for path in receiver {
+                    log::debug!("indexing {}", path.display());
+                    // the output is only needed for the cache
+                    let status = Command::new(preproc_exe)
+                        .arg(&path)
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .status();
+                    match status {
+                        Ok(status) if status.success() => {
+                            indexed.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Ok(status) => {
+                            eprintln!("rga: could not index {}: {}", path.display(), status);
+                            failed.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(e) => {
+                            eprintln!("rga: could not index {}: {}", path.display(), e);
+                            failed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            });
+        }
+        let files = BufReader::new(child.stdout.take().expect("rg stdout is piped"));
+        for file in files.split(b'\0') {
+            let file = file?;
+            match String::from_utf8(file) {
+                Ok(file) => {
+                    let file = PathBuf::from(file);
+                    if only.is_none_or(|only| only.contains(&file)) {
+                        sender.send(file)?;
+                    }
+                }
+                Err(e) => eprintln!(
+                    "rga: skipping file with non-UTF-8 path {}",
+                    String::from_utf8_lossy(e.as_bytes())
+                ),
+            }
+        }
+        drop(sender);
+        Ok(())
+    })
+    .expect("index thread panicked")?;
+    if !child.wait()?.success() {
+        anyhow::bail!("rg could not list the files in {}", dir.display());
+    }
+    println!(
+        "indexed {} files in {}{}",
+        indexed.into_inner(),
+        print_dur(before),
+        match failed.into_inner() {
+            0 => "".to_string(),
+            failed => format!(", {} failed", failed),
+        }
+    );
     Ok(())
 }
 
+/// indexes dir, then keeps indexing the files in it that are created or changed until killed
+fn watch(
+    dir: &Path,
+    pre_glob: &str,
+    preproc_exe: &Path,
+    passthrough_args: &[std::ffi::OsString],
+) -> Result<()> {
+    // the events have absolute paths, so rg has to list absolute paths as well
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("could not watch {}", dir.display()))?;
+    // or writing to the cache would change the files that are watched
+    let cache_dir = rga::project_dirs()?.cache_dir().to_owned();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&dir, notify::RecursiveMode::Recursive)?;
+    index(&dir, pre_glob, preproc_exe, passthrough_args, None)?;
+    let mut changed = HashSet::new();
+    loop {
+        // a file is often written in several steps, so on the first event it might not be complete yet
+        let event = if changed.is_empty() {
+            receiver.recv()?
+        } else {
+            match receiver.recv_timeout(WATCH_DEBOUNCE) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    log::debug!("{} files changed", changed.len());
+                    index(
+                        &dir,
+                        pre_glob,
+                        preproc_exe,
+                        passthrough_args,
+                        Some(&changed),
+                    )?;
+                    changed.clear();
+                    continue;
+                }
+                Err(e) => Err(e)?,
+            }
+        };
+        let event = event.context("could not watch for changes")?;
+        if let EventKind::Create(_) | EventKind::Modify(_) = event.kind {
+            changed.extend(
+                event
+                    .paths
+                    .into_iter()
+                    .filter(|path| !path.starts_with(&cache_dir)),
+            );
+        }
+    }
+}
+
 /// add the directory that contains `rga` to PATH, so rga-preproc can find pandoc etc (if we are on Windows where we include dependent binaries)
 fn add_exe_to_path() -> Result<()> {
     use std::env;
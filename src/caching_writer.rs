@@ -1,5 +1,7 @@
+use crate::sections::SectionTracker;
 use anyhow::Result;
 use log::*;
+use std::convert::TryInto;
 use std::io::Write;
 
 /**
@@ -9,25 +11,137 @@ use std::io::Write;
  */
 pub struct CachingWriter<W: Write> {
     max_cache_size: usize,
+    compression_level: i32,
+    dictionary: Option<Vec<u8>>,
     zstd_writer: Option<zstd::stream::write::Encoder<Vec<u8>>>,
+    /// (compressed size, uncompressed size) of the frames that are finished
+    frames: Vec<(u32, u32)>,
+    /// the uncompressed size of the current frame
+    frame_size: usize,
+    sections: SectionTracker,
     out: W,
     bytes_written: u64,
 }
+
+/// the output is split into independent zstd frames of this (uncompressed) size, with a seek table
+/// after them in the zstd seekable format, so that a cache entry can be decompressed frame by frame
+const SEEKABLE_FRAME_SIZE: usize = 1 << 20;
+const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D_2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+/// number of frames (u32), descriptor (u8), seekable magic (u32)
+const SEEK_TABLE_FOOTER_LEN: usize = 9;
+
+fn new_encoder(
+    buf: Vec<u8>,
+    compression_level: i32,
+    dictionary: Option<&[u8]>,
+) -> std::io::Result<zstd::stream::write::Encoder<Vec<u8>>> {
+    match dictionary {
+        Some(dictionary) => {
+            zstd::stream::write::Encoder::with_dictionary(buf, compression_level, dictionary)
+        }
+        None => zstd::stream::write::Encoder::new(buf, compression_level),
+    }
+}
+
+/// the zstd frames of a cache entry, using the seek table if it has one.
+/// entries with a single frame (all that are smaller than SEEKABLE_FRAME_SIZE) have none
+pub fn frames(entry: &[u8]) -> Vec<&[u8]> {
+    let (entry, _) = crate::sections::split(entry);
+    seek_table(entry).unwrap_or_else(|| vec![entry])
+}
+
+fn seek_table(entry: &[u8]) -> Option<Vec<&[u8]>> {
+    let u32_at = |pos: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            entry.get(pos..pos + 4)?.try_into().ok()?,
+        ))
+    };
+    let footer = entry.len().checked_sub(SEEK_TABLE_FOOTER_LEN)?;
+    if u32_at(footer + 5)? != SEEKABLE_MAGIC || entry[footer + 4] != 0 {
+        return None;
+    }
+    let count = u32_at(footer)? as usize;
+    let table = footer.checked_sub(count * 8)?;
+    let header = table.checked_sub(8)?;
+    if u32_at(header)? != SKIPPABLE_FRAME_MAGIC
+        || u32_at(header + 4)? as usize != count * 8 + SEEK_TABLE_FOOTER_LEN
+    {
+        return None;
+    }
+    let mut frames = Vec::with_capacity(count);
+    let mut start = 0;
+    for i in 0..count {
+        let end = start + u32_at(table + i * 8)? as usize;
+        frames.push(entry.get(start..end)?);
+        start = end;
+    }
+    if start != header {
+        return None;
+    }
+    Some(frames)
+}
+
 impl<W: Write> CachingWriter<W> {
-    pub fn new(out: W, max_cache_size: usize, compression_level: i32) -> Result<CachingWriter<W>> {
+    /// the output is compressed with the dictionary if there is one, see --rga-cache-zstd-dict
+    pub fn new(
+        out: W,
+        max_cache_size: usize,
+        compression_level: i32,
+        dictionary: Option<&[u8]>,
+    ) -> Result<CachingWriter<W>> {
+        let zstd_writer = new_encoder(Vec::new(), compression_level, dictionary)?;
         Ok(CachingWriter {
             out,
             max_cache_size,
-            zstd_writer: Some(zstd::stream::write::Encoder::new(
-                Vec::new(),
-                compression_level,
-            )?),
+            compression_level,
+            dictionary: dictionary.map(|d| d.to_vec()),
+            zstd_writer: Some(zstd_writer),
+            frames: Vec::new(),
+            frame_size: 0,
+            sections: SectionTracker::default(),
             bytes_written: 0,
         })
     }
-    pub fn finish(self) -> std::io::Result<(u64, Option<Vec<u8>>)> {
-        if let Some(writer) = self.zstd_writer {
-            let res = writer.finish()?;
+
+    /// ends the current frame, returns the compressed output so far
+    fn finish_frame(
+        &mut self,
+        writer: zstd::stream::write::Encoder<Vec<u8>>,
+    ) -> std::io::Result<Vec<u8>> {
+        let previous: u32 = self.frames.iter().map(|(compressed, _)| compressed).sum();
+        let res = writer.finish()?;
+        self.frames
+            .push((res.len() as u32 - previous, self.frame_size as u32));
+        self.frame_size = 0;
+        Ok(res)
+    }
+
+    /// false once the output got larger than max_cache_size
+    pub fn is_caching(&self) -> bool {
+        self.zstd_writer.is_some()
+    }
+
+    pub fn finish(mut self) -> std::io::Result<(u64, Option<Vec<u8>>)> {
+        if let Some(writer) = self.zstd_writer.take() {
+            let mut res = self.finish_frame(writer)?;
+            if self.frames.len() > 1 {
+                res.extend_from_slice(&SKIPPABLE_FRAME_MAGIC.to_le_bytes());
+                res.extend_from_slice(
+                    &((self.frames.len() * 8 + SEEK_TABLE_FOOTER_LEN) as u32).to_le_bytes(),
+                );
+                for (compressed, uncompressed) in &self.frames {
+                    res.extend_from_slice(&compressed.to_le_bytes());
+                    res.extend_from_slice(&uncompressed.to_le_bytes());
+                }
+                res.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+                // no checksums
+                res.push(0);
+                res.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+            }
+            if let Some(sections) = self.sections.finish().map_err(std::io::Error::other)? {
+                res.extend(sections);
+            }
             if res.len() <= self.max_cache_size {
                 return Ok((self.bytes_written, Some(res)));
             }
@@ -39,13 +153,24 @@ impl<W: Write> Write for CachingWriter<W> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         let written_bytes = match self.zstd_writer.as_mut() {
             Some(writer) => {
-                let wrote = writer.write(buf)?;
+                let max = buf.len().min(SEEKABLE_FRAME_SIZE - self.frame_size);
+                let wrote = writer.write(&buf[..max])?;
+                self.frame_size += wrote;
+                self.sections.write(&buf[..wrote]);
                 let compressed_len = writer.get_ref().len();
                 trace!("wrote {} to zstd, len now {}", wrote, compressed_len);
                 if compressed_len > self.max_cache_size {
                     debug!("cache longer than max, dropping");
                     //writer.finish();
                     self.zstd_writer.take().unwrap().finish()?;
+                } else if self.frame_size == SEEKABLE_FRAME_SIZE {
+                    let writer = self.zstd_writer.take().unwrap();
+                    let res = self.finish_frame(writer)?;
+                    self.zstd_writer = Some(new_encoder(
+                        res,
+                        self.compression_level,
+                        self.dictionary.as_deref(),
+                    )?);
                 }
                 self.out.write_all(&buf[0..wrote])?;
                 Ok(wrote)
@@ -63,3 +188,45 @@ impl<W: Write> Write for CachingWriter<W> {
         self.out.flush()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seekable_frames() -> Result<()> {
+        let text: Vec<u8> = (0..SEEKABLE_FRAME_SIZE * 5 / 2)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let mut out = Vec::new();
+        let mut writer = CachingWriter::new(&mut out, 1 << 30, 3, None)?;
+        // in chunks that do not end at the frame boundaries
+        for chunk in text.chunks(100_000) {
+            writer.write_all(chunk)?;
+        }
+        let (written, cached) = writer.finish()?;
+        let cached = cached.unwrap();
+        assert_eq!(written, text.len() as u64);
+        assert_eq!(out, text);
+
+        let split = frames(&cached);
+        assert_eq!(split.len(), 3);
+        assert_eq!(
+            zstd::stream::decode_all(split[0])?,
+            &text[..SEEKABLE_FRAME_SIZE]
+        );
+        let mut decompressed = Vec::new();
+        crate::preproc_cache::decompress(&cached, None, &mut decompressed)?;
+        assert_eq!(decompressed, text);
+        // the seek table is a skippable frame, so other zstd decoders can read the entry as well
+        assert_eq!(zstd::stream::decode_all(&cached[..])?, text);
+
+        // small outputs stay a single frame without seek table
+        let mut writer = CachingWriter::new(std::io::sink(), 1 << 30, 3, None)?;
+        writer.write_all(b"hello")?;
+        let cached = writer.finish()?.1.unwrap();
+        assert_eq!(frames(&cached), vec![&cached[..]]);
+        assert_eq!(zstd::stream::decode_all(&cached[..])?, b"hello");
+        Ok(())
+    }
+}
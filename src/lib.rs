@@ -9,13 +9,15 @@ pub mod matching;
 pub mod pipe;
 pub mod preproc;
 pub mod preproc_cache;
+pub mod sections;
+pub mod stats;
 #[cfg(test)]
 pub mod test_utils;
 use anyhow::Context;
 use anyhow::Result;
 pub use caching_writer::CachingWriter;
 use directories_next::ProjectDirs;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 pub fn project_dirs() -> Result<ProjectDirs> {
     directories_next::ProjectDirs::from("", "", "ripgrep-all")
@@ -50,7 +52,11 @@ fn meh(float: f32, precision: usize) -> usize {
 }
 
 pub fn print_dur(start: Instant) -> String {
-    let mut dur = Instant::now().duration_since(start).as_secs_f32();
+    print_duration(Instant::now().duration_since(start))
+}
+
+pub fn print_duration(dur: Duration) -> String {
+    let mut dur = dur.as_secs_f32();
     let mut suffix = "";
     if dur < 0.1 {
         suffix = "m";
@@ -19,6 +19,11 @@ pub enum FastMatcher {
      *
      */
     FileExtension(String),
+    /**
+     * a glob for the whole file name, e.g. "HEAD" or "[0-9a-f][0-9a-f]*". Only `*`, `?` and `[...]` are special.
+     * Matched as /^HEAD$/
+     */
+    FileName(String),
     // todo: maybe add others, e.g. regex on whole filename or even paths
     // todo: maybe allow matching a directory (e.g. /var/lib/postgres)
 }
@@ -52,6 +57,35 @@ pub fn extension_to_regex(extension: &str) -> Regex {
         .expect("we know this regex compiles")
 }
 
+pub fn filename_to_regex(glob: &str) -> Regex {
+    let mut re = String::from("^");
+    let mut in_class = false;
+    for c in glob.chars() {
+        match c {
+            ']' if in_class => {
+                in_class = false;
+                re.push(c);
+            }
+            '!' if in_class && re.ends_with('[') => re.push('^'),
+            // the regex crate has set operations like `&&` in classes
+            '\\' | '[' | '&' | '~' if in_class => {
+                re.push('\\');
+                re.push(c);
+            }
+            _ if in_class => re.push(c),
+            '[' => {
+                in_class = true;
+                re.push(c);
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).expect("invalid file name glob")
+}
+
 pub fn adapter_matcher(
     adapters: &Vec<Rc<dyn FileAdapter>>,
     slow: bool,
@@ -73,16 +107,31 @@ pub fn adapter_matcher(
                     adapter.clone(),
                     Fast(FastMatcher::FileExtension(re.clone())),
                 )),
+                Fast(FastMatcher::FileName(name)) => fname_regexes.push((
+                    filename_to_regex(name),
+                    adapter.clone(),
+                    Fast(FastMatcher::FileName(name.clone())),
+                )),
             };
         }
     }
     let fname_regex_set = RegexSet::new(fname_regexes.iter().map(|p| p.0.as_str()))?;
     let mime_regex_set = RegexSet::new(mime_regexes.iter().map(|p| p.0.as_str()))?;
     Ok(move |meta: FileMeta| {
-        let fname_matches: Vec<_> = fname_regex_set
+        let mut fname_matches: Vec<_> = fname_regex_set
             .matches(&meta.lossy_filename)
             .into_iter()
             .collect();
+        // a whole file name is more specific than an extension (LMDB's data.mdb vs Access databases)
+        let is_file_name = |e: &usize| {
+            matches!(
+                fname_regexes[*e].2,
+                SlowMatcher::Fast(FastMatcher::FileName(_))
+            )
+        };
+        if fname_matches.iter().any(is_file_name) {
+            fname_matches.retain(is_file_name);
+        }
         let mime_matches: Vec<_> = if slow {
             mime_regex_set
                 .matches(&meta.mimetype.expect("No mimetype?"))
@@ -5,9 +5,7 @@ use crate::{print_bytes, print_dur, CachingWriter};
 use anyhow::*;
 use log::*;
 
-
-
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
 
 use std::{
     sync::{Arc, RwLock},
@@ -36,7 +34,7 @@ pub fn rga_preproc(ai: AdaptInfo) -> Result<ReadBox> {
         ..
     } = ai;
     debug!("path (hint) to preprocess: {:?}", filepath_hint);
-    let PreprocConfig { cache: _, args } = config;
+    let PreprocConfig { cache, args } = config;
     let filtered_adapters = get_adapters_filtered(args.custom_adapters.clone(), &args.adapters)?;
     let adapters = adapter_matcher(&filtered_adapters, args.accurate)?;
     let filename = filepath_hint
@@ -50,16 +48,16 @@ pub fn rga_preproc(ai: AdaptInfo) -> Result<ReadBox> {
 
     // todo: figure out when using a bufreader is a good idea and when it is not
     // seems to be good for File::open() reads, but not sure about within archives (tar, zip)
-    let inp = BufReader::with_capacity(1 << 16, inp);
-
-    let mimetype = None; /*if args.accurate {
-                             let buf = inp.fill_buf()?; // fill but do not consume!
-                             let mimetype = tree_magic::from_u8(buf);
-                             debug!("mimetype: {:?}", mimetype);
-                             Some(mimetype)
-                         } else {
-                             None
-                         };*/
+    let mut inp = BufReader::with_capacity(1 << 16, inp);
+
+    let mimetype = if args.accurate {
+        let buf = inp.fill_buf()?; // fill but do not consume!
+        let mimetype = tree_magic::from_u8(buf);
+        debug!("mimetype: {:?}", mimetype);
+        Some(mimetype)
+    } else {
+        None
+    };
     let adapter = adapters(FileMeta {
         mimetype,
         lossy_filename: filename.to_string_lossy().to_string(),
@@ -76,55 +74,38 @@ pub fn rga_preproc(ai: AdaptInfo) -> Result<ReadBox> {
                 filepath_hint.to_string_lossy(),
                 &meta.name
             );
-            let _db_name = format!("{}.v{}", meta.name, meta.version);
-            /*if let Some(cache) = cache.as_mut() {
-                let cache_key: Vec<u8> = {
-                    let clean_path = filepath_hint.to_owned().clean();
-                    let meta = std::fs::metadata(&filepath_hint)?;
-                    let modified = meta.modified().expect("weird OS that can't into mtime");
-
-                    if adapter.metadata().recurses {
-                        let key = (
-                            filtered_adapters
-                                .iter()
-                                .map(|a| (a.metadata().name.clone(), a.metadata().version))
-                                .collect::<Vec<_>>(),
-                            clean_path,
-                            modified,
-                        );
-                        debug!("Cache key (with recursion): {:?}", key);
-                        bincode::serialize(&key).expect("could not serialize path")
-                    } else {
-                        let key = (
-                            adapter.metadata().name.clone(),
-                            adapter.metadata().version,
-                            clean_path,
-                            modified,
-                        );
-                        debug!("Cache key (no recursion): {:?}", key);
-                        bincode::serialize(&key).expect("could not serialize path")
-                    }
-                };
-                cache.write().unwrap().get_or_run(
+            if archive_recursion_depth == 0 {
+                crate::stats::record_adapter(&meta.name);
+            }
+            let db_name = format!("{}.v{}", meta.name, meta.version);
+            if let Some(cache) = cache.filter(|_| crate::preproc_cache::caches(meta, &args)) {
+                let cache_key = crate::preproc_cache::cache_key(
+                    meta,
+                    &filtered_adapters,
+                    &filepath_hint,
+                    &args,
+                )?;
+                let mut cache = cache.write().unwrap();
+                let dictionary = cache.dictionary(&db_name)?;
+                let max_cache_size = args.cache_max_blob_len.0;
+                let compression_level = args.cache_compression_level.0;
+                // the output read on a miss, and the rest of it if it got too large to cache
+                let mut adapted = Vec::new();
+                let mut rest: Option<ReadBox> = None;
+                let mut cached = None;
+                cache.get_or_run(
                     &db_name,
                     &cache_key,
-                    &adapter.metadata().name,
+                    &meta.name,
                     Box::new(|| -> Result<Option<Vec<u8>>> {
-                        // wrapping BufWriter here gives ~10% perf boost
-                        let mut compbuf = BufWriter::new(CachingWriter::new(
-                            oup,
-                            args.cache_max_blob_len.0.try_into().unwrap(),
-                            args.cache_compression_level.0.try_into().unwrap(),
-                        )?);
                         debug!("adapting with caching...");
-                        adapter
+                        let mut oread = adapter
                             .adapt(
                                 AdaptInfo {
                                     line_prefix,
-                                    filepath_hint,
+                                    filepath_hint: filepath_hint.clone(),
                                     is_real_file,
-                                    inp,
-                                    oup: &mut compbuf,
+                                    inp: Box::new(inp),
                                     archive_recursion_depth,
                                     config: PreprocConfig { cache: None, args },
                                 },
@@ -137,58 +118,85 @@ pub fn rga_preproc(ai: AdaptInfo) -> Result<ReadBox> {
                                     meta.name
                                 )
                             })?;
-                        let (uncompressed_size, compressed) = compbuf
-                            .into_inner()
-                            .map_err(|_| anyhow!("could not finish zstd"))? // can't use with_context here
-                            .finish()?;
+                        let mut compbuf = CachingWriter::new(
+                            &mut adapted,
+                            max_cache_size,
+                            compression_level,
+                            dictionary.as_deref(),
+                        )?;
+                        // the output has to be read completely before it can be written to the cache.
+                        // once it is too large for that, the rest is read by rg as it goes
+                        let mut buf = vec![0; 1 << 16];
+                        loop {
+                            let n = match oread.read(&mut buf) {
+                                Ok(0) => break,
+                                Ok(n) => n,
+                                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                                Err(e) => return Err(e).context("reading adapter output"),
+                            };
+                            compbuf.write_all(&buf[..n])?;
+                            if !compbuf.is_caching() {
+                                rest = Some(oread);
+                                break;
+                            }
+                        }
+                        let (uncompressed_size, compressed) = compbuf.finish()?;
                         debug!(
                             "uncompressed output: {}",
                             print_bytes(uncompressed_size as f64)
                         );
-                        if let Some(cached) = compressed {
-                            debug!("compressed output: {}", print_bytes(cached.len() as f64));
-                            Ok(Some(cached))
-                        } else {
-                            Ok(None)
+                        if let Some(compressed) = &compressed {
+                            debug!(
+                                "compressed output: {}",
+                                print_bytes(compressed.len() as f64)
+                            );
                         }
+                        Ok(compressed)
                     }),
-                    Box::new(|cached| {
-                        let stdouti = std::io::stdout();
-                        zstd::stream::copy_decode(cached, stdouti.lock())?;
+                    Box::new(|entry| {
+                        crate::stats::record_cache_hit();
+                        cached = Some(crate::preproc_cache::EntryReader::new(
+                            entry.to_vec(),
+                            dictionary.clone(),
+                        ));
                         Ok(())
                     }),
                 )?;
-                Ok(())
-            } else { */
-            // no cache arc - probably within archive
-            debug!("adapting without caching...");
-            let start = Instant::now();
-            let oread = adapter
-                .adapt(
-                    AdaptInfo {
-                        line_prefix,
-                        filepath_hint: filepath_hint.clone(),
-                        is_real_file,
-                        inp: Box::new(inp),
-                        archive_recursion_depth,
-                        config: PreprocConfig { cache: None, args },
-                    },
-                    &detection_reason,
-                )
-                .with_context(|| {
-                    format!(
-                        "adapting {} via {} without caching failed",
-                        filepath_hint.to_string_lossy(),
-                        meta.name
+                Ok(match (cached, rest) {
+                    (Some(cached), _) => Box::new(cached),
+                    (None, Some(rest)) => Box::new(Cursor::new(adapted).chain(rest)),
+                    (None, None) => Box::new(Cursor::new(adapted)),
+                })
+            } else {
+                // no cache arc - probably within archive
+                debug!("adapting without caching...");
+                let start = Instant::now();
+                let oread = adapter
+                    .adapt(
+                        AdaptInfo {
+                            line_prefix,
+                            filepath_hint: filepath_hint.clone(),
+                            is_real_file,
+                            inp: Box::new(inp),
+                            archive_recursion_depth,
+                            config: PreprocConfig { cache: None, args },
+                        },
+                        &detection_reason,
                     )
-                })?;
-            debug!(
-                "running adapter {} took {}",
-                adapter.metadata().name,
-                print_dur(start)
-            );
-            Ok(oread)
-            /* }*/
+                    .with_context(|| {
+                        format!(
+                            "adapting {} via {} without caching failed",
+                            filepath_hint.to_string_lossy(),
+                            meta.name
+                        )
+                    })?;
+                debug!(
+                    "running adapter {} took {}",
+                    adapter.metadata().name,
+                    print_dur(start)
+                );
+                Ok(oread)
+            }
         }
         None => {
             // allow passthrough if the file is in an archive or accurate matching is enabled
@@ -205,3 +213,443 @@ pub fn rga_preproc(ai: AdaptInfo) -> Result<ReadBox> {
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::adapters::custom::CustomAdapterConfig;
+    use crate::preproc_cache::{CacheOptions, LmdbCache, PreprocCache};
+    use std::path::Path;
+
+    /// a custom adapter for .counted files that outputs its input and appends a line to `runs` every time it runs.
+    /// the input is written to a file first since the adapter writes all of it before reading any output
+    pub(crate) fn counting_args(runs: &Path) -> RgaConfig {
+        RgaConfig {
+            custom_adapters: Some(vec![CustomAdapterConfig {
+                name: "counting".to_string(),
+                description: "outputs its input".to_string(),
+                version: 1,
+                extensions: vec!["counted".to_string()],
+                binary: "sh".to_string(),
+                args: vec![
+                    "-c".to_string(),
+                    r#"echo >> "$0"; cat > "$0.in"; cat "$0.in""#.to_string(),
+                    runs.to_string_lossy().into_owned(),
+                ],
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn count_runs(runs: &Path) -> usize {
+        std::fs::read_to_string(runs).map_or(0, |runs| runs.lines().count())
+    }
+
+    pub(crate) fn preproc(
+        file: &Path,
+        cache: &Arc<RwLock<dyn PreprocCache>>,
+        args: &RgaConfig,
+    ) -> Result<Vec<u8>> {
+        let mut oread = rga_preproc(AdaptInfo {
+            filepath_hint: file.to_owned(),
+            is_real_file: true,
+            inp: Box::new(std::fs::File::open(file)?),
+            line_prefix: "".to_string(),
+            archive_recursion_depth: 0,
+            config: PreprocConfig {
+                cache: Some(cache.clone()),
+                args: args.clone(),
+            },
+        })?;
+        let mut oup = Vec::new();
+        oread.read_to_end(&mut oup)?;
+        Ok(oup)
+    }
+
+    /// the cache entry of a .counted file, without running the adapter on a miss
+    pub(crate) fn entry(
+        file: &Path,
+        cache: &Arc<RwLock<dyn PreprocCache>>,
+        args: &RgaConfig,
+    ) -> Result<Option<Vec<u8>>> {
+        adapter_entry("counting", file, cache, args)
+    }
+
+    /// the cache entry of a file adapted by the named adapter
+    pub(crate) fn adapter_entry(
+        name: &str,
+        file: &Path,
+        cache: &Arc<RwLock<dyn PreprocCache>>,
+        args: &RgaConfig,
+    ) -> Result<Option<Vec<u8>>> {
+        let adapters = get_adapters_filtered(args.custom_adapters.clone(), &args.adapters)?;
+        let meta = adapters
+            .iter()
+            .map(|a| a.metadata())
+            .find(|meta| meta.name == name)
+            .unwrap();
+        let key = crate::preproc_cache::cache_key(meta, &adapters, file, args)?;
+        let mut entry = None;
+        cache.write().unwrap().get_or_run(
+            &format!("{}.v{}", meta.name, meta.version),
+            &key,
+            &meta.name,
+            Box::new(|| Ok(None)),
+            Box::new(|e| {
+                entry = Some(e.to_vec());
+                Ok(())
+            }),
+        )?;
+        Ok(entry)
+    }
+
+    /// bytes that zstd can't compress, so that the sizes of the cache entries are known
+    fn incompressible(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed * 2 + 1;
+        (0..len)
+            .map(|_| {
+                // xorshift
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cached_and_evicted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = counting_args(&runs);
+        let cache: Arc<RwLock<dyn PreprocCache>> = Arc::new(RwLock::new(LmdbCache::open(
+            &dir.path().join("cache"),
+            CacheOptions {
+                max_total: Some(2500),
+                ..Default::default()
+            },
+        )?));
+        let files: Vec<_> = (0..3)
+            .map(|i| -> Result<_> {
+                let file = dir.path().join(format!("{}.counted", i));
+                std::fs::write(&file, incompressible(i, 1000))?;
+                Ok(file)
+            })
+            .collect::<Result<_>>()?;
+        // whether the adapter had to run
+        let get = |i: usize| -> Result<bool> {
+            let before = count_runs(&runs);
+            assert_eq!(
+                preproc(&files[i], &cache, &args)?,
+                incompressible(i as u64, 1000)
+            );
+            Ok(count_runs(&runs) > before)
+        };
+        assert!(get(0)?);
+        assert!(get(1)?);
+        assert!(!get(0)?);
+        // over the limit, 1 was used least recently
+        assert!(get(2)?);
+        assert!(!get(0)?);
+        assert!(!get(2)?);
+        assert!(get(1)?);
+        Ok(())
+    }
+
+    #[test]
+    fn expired() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = counting_args(&runs);
+        let cache: Arc<RwLock<dyn PreprocCache>> = Arc::new(RwLock::new(LmdbCache::open(
+            &dir.path().join("cache"),
+            CacheOptions {
+                max_age: Some(std::time::Duration::from_millis(500)),
+                ..Default::default()
+            },
+        )?));
+        let file = dir.path().join("a.counted");
+        std::fs::write(&file, "hello\n")?;
+        preproc(&file, &cache, &args)?;
+        preproc(&file, &cache, &args)?;
+        assert_eq!(count_runs(&runs), 1);
+        std::thread::sleep(std::time::Duration::from_millis(600));
+        assert_eq!(preproc(&file, &cache, &args)?, b"hello\n");
+        assert_eq!(count_runs(&runs), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn content_hash() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = RgaConfig {
+            cache_content_hash: true,
+            ..counting_args(&runs)
+        };
+        let cache: Arc<RwLock<dyn PreprocCache>> = Arc::new(RwLock::new(LmdbCache::open(
+            &dir.path().join("cache"),
+            CacheOptions::default(),
+        )?));
+        let file = dir.path().join("a.counted");
+        std::fs::write(&file, "hello\n")?;
+        preproc(&file, &cache, &args)?;
+        let copy = dir.path().join("copy of a.counted");
+        std::fs::copy(&file, &copy)?;
+        assert_eq!(preproc(&copy, &cache, &args)?, b"hello\n");
+        assert_eq!(count_runs(&runs), 1);
+        std::fs::write(&copy, "changed\n")?;
+        assert_eq!(preproc(&copy, &cache, &args)?, b"changed\n");
+        assert_eq!(count_runs(&runs), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_backend() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = RgaConfig {
+            cache_backend: crate::args::CacheBackend::Sqlite,
+            ..counting_args(&runs)
+        };
+        let cache = crate::preproc_cache::open_in(dir.path(), &args)?.unwrap();
+        let file = dir.path().join("a.counted");
+        std::fs::write(&file, "hello\n")?;
+        preproc(&file, &cache, &args)?;
+        assert_eq!(preproc(&file, &cache, &args)?, b"hello\n");
+        assert_eq!(count_runs(&runs), 1);
+        assert!(dir.path().join("cache.sqlite3").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn options_change_key() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = counting_args(&runs);
+        let cache = crate::preproc_cache::open_in(dir.path(), &args)?.unwrap();
+        let file = dir.path().join("a.counted");
+        std::fs::write(&file, "hello\n")?;
+        preproc(&file, &cache, &args)?;
+        // the counting adapter does not read these
+        let ocr = RgaConfig {
+            pdf_ocr: true,
+            ..args.clone()
+        };
+        let password = RgaConfig {
+            archive_password: Some("hunter2".to_string()),
+            ..args.clone()
+        };
+        preproc(&file, &cache, &ocr)?;
+        preproc(&file, &cache, &password)?;
+        assert_eq!(count_runs(&runs), 1);
+        // the definition of a custom adapter is part of its keys
+        let mut twice = args.clone();
+        twice.custom_adapters.as_mut().unwrap()[0].args[1] =
+            r#"echo >> "$0"; cat > "$0.in"; cat "$0.in" "$0.in""#.to_string();
+        assert_eq!(preproc(&file, &cache, &twice)?, b"hello\nhello\n");
+        assert_eq!(count_runs(&runs), 2);
+        // the outputs of each configuration stay cached
+        assert_eq!(preproc(&file, &cache, &args)?, b"hello\n");
+        assert_eq!(preproc(&file, &cache, &twice)?, b"hello\nhello\n");
+        assert_eq!(count_runs(&runs), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn dictionary() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = RgaConfig {
+            cache_zstd_dict: true,
+            ..counting_args(&runs)
+        };
+        let cache = crate::preproc_cache::open_in(dir.path(), &args)?.unwrap();
+        let text = |i: usize| {
+            format!(
+                "Invoice {}\nACME Corporation, 1 Main Street, Springfield\nDue date: 2024-01-{:02}\n\
+                 Description: consulting services for the migration of the accounting system\n\
+                 Total amount: {} EUR\nPlease transfer the amount within 30 days to the account below.\n",
+                i,
+                i % 28 + 1,
+                i * 17
+            )
+        };
+        // enough entries of the adapter to train the dictionary from
+        for i in 0..100 {
+            let compressed = zstd::stream::encode_all(text(i).as_bytes(), 12)?;
+            cache.write().unwrap().get_or_run(
+                "counting.v1",
+                &i.to_le_bytes(),
+                "counting",
+                Box::new(|| Ok(Some(compressed))),
+                Box::new(|_| Ok(())),
+            )?;
+        }
+        let file = dir.path().join("100.counted");
+        std::fs::write(&file, text(100))?;
+        preproc(&file, &cache, &args)?;
+        let entry = entry(&file, &cache, &args)?.unwrap();
+        assert_ne!(zstd_safe::get_dict_id_from_frame(&entry), 0);
+        assert_eq!(preproc(&file, &cache, &args)?, text(100).as_bytes());
+        assert_eq!(count_runs(&runs), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn read_only() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = RgaConfig {
+            cache_backend: crate::args::CacheBackend::Sqlite,
+            ..counting_args(&runs)
+        };
+        let read_only = RgaConfig {
+            cache_read_only: true,
+            ..args.clone()
+        };
+        assert!(crate::preproc_cache::open_in(dir.path(), &read_only)?.is_none());
+        let cached = dir.path().join("cached.counted");
+        std::fs::write(&cached, "cached\n")?;
+        preproc(
+            &cached,
+            &crate::preproc_cache::open_in(dir.path(), &args)?.unwrap(),
+            &args,
+        )?;
+
+        let cache = crate::preproc_cache::open_in(dir.path(), &read_only)?.unwrap();
+        assert_eq!(preproc(&cached, &cache, &read_only)?, b"cached\n");
+        assert_eq!(count_runs(&runs), 1);
+        let new = dir.path().join("new.counted");
+        std::fs::write(&new, "new\n")?;
+        assert_eq!(preproc(&new, &cache, &read_only)?, b"new\n");
+        assert_eq!(preproc(&new, &cache, &read_only)?, b"new\n");
+        assert_eq!(count_runs(&runs), 3);
+        assert!(entry(&new, &cache, &read_only)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn compression_options() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let text: String = (0..20000)
+            .map(|i| format!("line {}: value {}\n", i, i * i % 9973))
+            .collect();
+        let file = dir.path().join("a.counted");
+        std::fs::write(&file, &text)?;
+        let entry_len = |args: RgaConfig, cache_dir: &str| -> Result<Option<usize>> {
+            let args = RgaConfig {
+                cache_backend: crate::args::CacheBackend::Sqlite,
+                ..args
+            };
+            let cache_dir = dir.path().join(cache_dir);
+            std::fs::create_dir(&cache_dir)?;
+            let cache = crate::preproc_cache::open_in(&cache_dir, &args)?.unwrap();
+            assert_eq!(preproc(&file, &cache, &args)?, text.as_bytes());
+            assert_eq!(preproc(&file, &cache, &args)?, text.as_bytes());
+            Ok(entry(&file, &cache, &args)?.map(|e| e.len()))
+        };
+        let fast = entry_len(
+            RgaConfig {
+                cache_compression_level: crate::args::CacheCompressionLevel(1),
+                ..counting_args(&runs)
+            },
+            "fast",
+        )?
+        .unwrap();
+        let small = entry_len(
+            RgaConfig {
+                cache_compression_level: crate::args::CacheCompressionLevel(19),
+                ..counting_args(&runs)
+            },
+            "small",
+        )?
+        .unwrap();
+        assert!(small < fast);
+        assert_eq!(count_runs(&runs), 2);
+        // too large for the cache: the output is adapted again, and all of it is still returned
+        let uncached = entry_len(
+            RgaConfig {
+                cache_max_blob_len: crate::args::CacheMaxBlobLen(1000),
+                ..counting_args(&runs)
+            },
+            "uncached",
+        )?;
+        assert_eq!(uncached, None);
+        assert_eq!(count_runs(&runs), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn seekable_entry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = counting_args(&runs);
+        let cache = crate::preproc_cache::open_in(dir.path(), &args)?.unwrap();
+        // more than two frames
+        let text: String = (0..150_000u64)
+            .map(|i| format!("line {}: value {}\n", i, i * i % 9973))
+            .collect();
+        assert!(text.len() > 2 << 20);
+        let file = dir.path().join("large.counted");
+        std::fs::write(&file, &text)?;
+        preproc(&file, &cache, &args)?;
+        let entry = entry(&file, &cache, &args)?.unwrap();
+        let frames = crate::caching_writer::frames(&entry);
+        assert_eq!(frames.len(), (text.len() >> 20) + 1);
+        let mut first = Vec::new();
+        crate::preproc_cache::decompress(frames[0], None, &mut first)?;
+        assert_eq!(first, &text.as_bytes()[..1 << 20]);
+        let mut all = Vec::new();
+        crate::preproc_cache::decompress(&entry, None, &mut all)?;
+        assert_eq!(all, text.as_bytes());
+        assert_eq!(preproc(&file, &cache, &args)?, text.as_bytes());
+        assert_eq!(count_runs(&runs), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn sections() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = counting_args(&runs);
+        let cache = crate::preproc_cache::open_in(dir.path(), &args)?.unwrap();
+        let text = "Page 1: intro\nPage 1: more\nPage 2: end\n";
+        let file = dir.path().join("paged.counted");
+        std::fs::write(&file, text)?;
+        preproc(&file, &cache, &args)?;
+        let sections = crate::sections::read(&entry(&file, &cache, &args)?.unwrap())?;
+        let locations: Vec<_> = sections
+            .iter()
+            .map(|s| (&s.location[..], s.start, s.end))
+            .collect();
+        assert_eq!(locations, [("Page 1", 0, 27), ("Page 2", 27, 39)]);
+        assert_eq!(preproc(&file, &cache, &args)?, text.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn not_cached() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let file = dir.path().join("a.counted");
+        std::fs::write(&file, "hello\n")?;
+        let no_cache = RgaConfig {
+            no_cache_adapters: vec!["counting".to_string()],
+            ..counting_args(&runs)
+        };
+        let mut skip_cache = counting_args(&runs);
+        skip_cache.custom_adapters.as_mut().unwrap()[0].skip_cache = Some(true);
+        for args in &[no_cache, skip_cache] {
+            let cache = crate::preproc_cache::open_in(dir.path(), args)?.unwrap();
+            let before = count_runs(&runs);
+            assert_eq!(preproc(&file, &cache, args)?, b"hello\n");
+            assert_eq!(preproc(&file, &cache, args)?, b"hello\n");
+            assert_eq!(count_runs(&runs), before + 2);
+            assert!(entry(&file, &cache, args)?.is_none());
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,83 @@
+use crate::adapters::{AdapterMeta, AdapterOption, FileAdapter};
+use crate::args::{CacheBackend, CacheCommand, RgaConfig};
 use crate::{print_bytes, print_dur, project_dirs};
+use ::lmdb::{Cursor, Database, Environment, EnvironmentFlags, Transaction};
 use anyhow::{format_err, Context, Result};
 use log::*;
+use path_clean::PathClean;
+use serde::Serialize;
 use std::{
+    collections::{hash_map::Entry, HashMap},
     fmt::Display,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
     sync::{Arc, RwLock},
-    time::Instant,
+    time::{Duration, Instant, SystemTime},
 };
 
-pub fn open() -> Result<Arc<RwLock<dyn PreprocCache>>> {
-    Ok(Arc::new(RwLock::new(LmdbCache::open()?)))
+mod export;
+mod redis;
+mod sqlite;
+
+pub use export::{export, import};
+
+/// how the cache is used, from the --rga-cache-* flags
+#[derive(Default, Clone, Copy)]
+pub struct CacheOptions {
+    /// the least recently used entries are deleted when the cache gets larger than this
+    pub max_total: Option<usize>,
+    /// entries that were not used for this long are deleted
+    pub max_age: Option<Duration>,
+    /// whether dictionaries are trained, see --rga-cache-zstd-dict
+    pub zstd_dict: bool,
+    /// nothing is written to the cache, not even the access times
+    pub read_only: bool,
+}
+
+impl CacheOptions {
+    pub fn from_args(args: &RgaConfig) -> CacheOptions {
+        CacheOptions {
+            max_total: args.cache_max_total.map(|m| m.0),
+            max_age: args.cache_max_age.map(|m| Duration::from_secs(m.0)),
+            zstd_dict: args.cache_zstd_dict,
+            read_only: args.cache_read_only,
+        }
+    }
+}
+
+/// opens the cache. a read-only cache that does not exist yet is None
+pub fn open(args: &RgaConfig) -> Result<Option<Arc<RwLock<dyn PreprocCache>>>> {
+    open_in(project_dirs()?.cache_dir(), args)
+}
+
+/// opens the cache in cache_dir, which the redis backend does not use
+pub fn open_in(
+    cache_dir: &Path,
+    args: &RgaConfig,
+) -> Result<Option<Arc<RwLock<dyn PreprocCache>>>> {
+    let options = CacheOptions::from_args(args);
+    if options.read_only
+        && data_file(args.cache_backend, cache_dir).is_some_and(|file| !file.exists())
+    {
+        debug!(
+            "the read-only cache in {} does not exist",
+            cache_dir.display()
+        );
+        return Ok(None);
+    }
+    Ok(Some(match args.cache_backend {
+        CacheBackend::Lmdb => Arc::new(RwLock::new(LmdbCache::open(cache_dir, options)?)),
+        CacheBackend::Sqlite => {
+            Arc::new(RwLock::new(sqlite::SqliteCache::open(cache_dir, options)?))
+        }
+        CacheBackend::Redis => Arc::new(RwLock::new(redis::RedisCache::open(
+            args.cache_redis_url
+                .as_deref()
+                .unwrap_or(redis::DEFAULT_URL),
+            options,
+        )?)),
+    }))
 }
 pub trait PreprocCache: Send + Sync {
     // possible without second lambda?
@@ -20,13 +89,147 @@ pub trait PreprocCache: Send + Sync {
         runner: Box<dyn FnOnce() -> Result<Option<Vec<u8>>> + 'a>,
         callback: Box<dyn FnOnce(&[u8]) -> Result<()> + 'a>,
     ) -> Result<()>;
+    /// the zstd dictionary to compress new entries of the store with. with --rga-cache-zstd-dict,
+    /// it is trained from the small entries of the store once there are enough of them. a dictionary
+    /// that was trained before is returned without it too, since the entries compressed with it need it
+    fn dictionary(&mut self, db_name: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// the cache can grow up to this size, unless a larger --rga-cache-max-total is given
+const DEFAULT_MAP_SIZE: usize = 2 * 1024 * 1024 * 1024;
+
+/// the time an entry was last read or written and its size, by `store name \0 key`
+const ACCESS_STORE: &str = "rga.access";
+/// the zstd dictionary of every store, by store name
+const DICT_STORE: &str = "rga.dict";
+
+/// entries up to this size (compressed) are used to train the dictionary
+const DICT_SAMPLE_MAX_LEN: usize = 32 * 1024;
+/// the dictionary is trained once a store has this many small entries
+const DICT_MIN_SAMPLES: usize = 100;
+const DICT_MAX_SAMPLES: usize = 2000;
+/// the default of `zstd --train`
+const DICT_MAX_SIZE: usize = 110 * 1024;
+
+/// trains a dictionary from small compressed entries, None if there are not enough of them yet.
+/// training can fail e.g. if the outputs are too short, that is only logged and tried again later
+fn train_dictionary(entries: &[Vec<u8>]) -> Option<Vec<u8>> {
+    if entries.len() < DICT_MIN_SAMPLES {
+        return None;
+    }
+    let train = || -> Result<Vec<u8>> {
+        let samples = entries
+            .iter()
+            .map(|entry| zstd::stream::decode_all(&entry[..]))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        // zstd needs around ten times as much input as the size of the dictionary
+        let total: usize = samples.iter().map(Vec::len).sum();
+        Ok(zstd::dict::from_samples(
+            &samples,
+            DICT_MAX_SIZE.min(total / 10),
+        )?)
+    };
+    match train() {
+        Ok(dictionary) => {
+            debug!(
+                "trained a dictionary of {} from {} entries",
+                print_bytes(dictionary.len() as f64),
+                entries.len()
+            );
+            Some(dictionary)
+        }
+        Err(e) => {
+            debug!("could not train a dictionary: {:#}", e);
+            None
+        }
+    }
+}
+
+/// decompresses a cache entry. the entries that were written before the dictionary of their store
+/// was trained are compressed without it
+pub fn decompress(cached: &[u8], dictionary: Option<&[u8]>, oup: &mut dyn Write) -> Result<()> {
+    for frame in crate::caching_writer::frames(cached) {
+        oup.write_all(&decompress_frame(frame, dictionary)?)?;
+    }
+    Ok(())
+}
+
+fn decompress_frame(frame: &[u8], dictionary: Option<&[u8]>) -> std::io::Result<Vec<u8>> {
+    let mut oup = Vec::new();
+    match dictionary {
+        Some(dictionary) if zstd_safe::get_dict_id_from_frame(frame) != 0 => {
+            zstd::stream::read::Decoder::with_dictionary(frame, dictionary)?
+                .single_frame()
+                .read_to_end(&mut oup)?;
+        }
+        _ => {
+            zstd::stream::read::Decoder::new(frame)?
+                .single_frame()
+                .read_to_end(&mut oup)?;
+        }
+    }
+    Ok(oup)
+}
+
+/// reads a cache entry like decompress, but only decompresses a frame once the output before it
+/// was read, so that nothing more is decompressed once rg does not need more of the output
+pub struct EntryReader {
+    entry: Vec<u8>,
+    /// (start, end) in entry of the frames that were not decompressed yet, the next one last
+    frames: Vec<(usize, usize)>,
+    dictionary: Option<Vec<u8>>,
+    frame: std::io::Cursor<Vec<u8>>,
+}
+
+impl EntryReader {
+    pub fn new(entry: Vec<u8>, dictionary: Option<Vec<u8>>) -> EntryReader {
+        let start = entry.as_ptr() as usize;
+        let frames = crate::caching_writer::frames(&entry)
+            .iter()
+            .rev()
+            .map(|frame| {
+                let offset = frame.as_ptr() as usize - start;
+                (offset, offset + frame.len())
+            })
+            .collect();
+        EntryReader {
+            entry,
+            frames,
+            dictionary,
+            frame: std::io::Cursor::new(Vec::new()),
+        }
+    }
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let n = self.frame.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            match self.frames.pop() {
+                Some((start, end)) => {
+                    self.frame = std::io::Cursor::new(decompress_frame(
+                        &self.entry[start..end],
+                        self.dictionary.as_deref(),
+                    )?);
+                }
+                None => return Ok(0),
+            }
+        }
+    }
 }
 
 /// opens a LMDB cache
-fn open_cache_db() -> Result<std::sync::Arc<std::sync::RwLock<rkv::Rkv>>> {
-    let pd = project_dirs()?;
-    let app_cache = pd.cache_dir();
-    std::fs::create_dir_all(app_cache)?;
+fn open_cache_db(
+    app_cache: &Path,
+    map_size: usize,
+    read_only: bool,
+) -> Result<std::sync::Arc<std::sync::RwLock<rkv::Rkv>>> {
+    if !read_only {
+        std::fs::create_dir_all(app_cache)?;
+    }
 
     rkv::Manager::singleton()
         .write()
@@ -38,9 +241,14 @@ fn open_cache_db() -> Result<std::sync::Arc<std::sync::RwLock<rkv::Rkv>>> {
                 // i'm not sure why NO_TLS is needed. otherwise LMDB transactions (open readers) will keep piling up until it fails with
                 // LmdbError(ReadersFull). Those "open readers" stay even after the corresponding processes exit.
                 // hope setting this doesn't break integrity
-                .set_flags(rkv::EnvironmentFlags::NO_TLS)
+                // on a read-only file system, lmdb reads without the lock file
+                .set_flags(if read_only {
+                    rkv::EnvironmentFlags::NO_TLS | rkv::EnvironmentFlags::READ_ONLY
+                } else {
+                    rkv::EnvironmentFlags::NO_TLS
+                })
                 // sometimes, this seems to cause the data.mdb file to appear as 2GB in size (with holes), but sometimes not?
-                .set_map_size(2 * 1024 * 1024 * 1024)
+                .set_map_size(map_size)
                 .set_max_dbs(100)
                 .set_max_readers(128);
             rkv::Rkv::from_env(p, builder)
@@ -50,16 +258,152 @@ fn open_cache_db() -> Result<std::sync::Arc<std::sync::RwLock<rkv::Rkv>>> {
 
 pub struct LmdbCache {
     db_arc: std::sync::Arc<std::sync::RwLock<rkv::Rkv>>,
+    options: CacheOptions,
 }
 
 impl LmdbCache {
-    pub fn open() -> Result<LmdbCache> {
+    pub fn open(app_cache: &Path, options: CacheOptions) -> Result<LmdbCache> {
+        // leave some room for the lmdb pages that are not filled completely
+        let map_size = options
+            .max_total
+            .map_or(DEFAULT_MAP_SIZE, |max| DEFAULT_MAP_SIZE.max(max / 10 * 12));
         Ok(LmdbCache {
-            db_arc: open_cache_db()?,
+            db_arc: open_cache_db(app_cache, map_size, options.read_only)?,
+            options,
         })
     }
 }
 
+fn access_key(db_name: &str, key: &[u8]) -> Vec<u8> {
+    let mut access_key = db_name.as_bytes().to_vec();
+    access_key.push(0);
+    access_key.extend_from_slice(key);
+    access_key
+}
+
+/// (store name, key)
+fn split_access_key(access_key: &[u8]) -> (String, &[u8]) {
+    let name_len = access_key
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(access_key.len());
+    (
+        String::from_utf8_lossy(&access_key[..name_len]).to_string(),
+        access_key.get(name_len + 1..).unwrap_or_default(),
+    )
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+fn access_value(size: usize) -> Vec<u8> {
+    bincode::serialize(&(now_nanos(), size as u64)).expect("could not serialize access time")
+}
+
+/// whether the entry was last used longer than max_age ago
+fn is_expired(access_value: Option<rkv::Value>, max_age: Duration) -> bool {
+    match access_value {
+        Some(rkv::Value::Blob(value)) => match bincode::deserialize::<(u64, u64)>(value) {
+            Ok((last_used, _)) => {
+                now_nanos().saturating_sub(last_used) as u128 > max_age.as_nanos()
+            }
+            Err(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// deletes the entries that were not used for max_age and the least recently used entries
+/// until the cache is below 90% of max_total, so that not every write has to evict
+fn evict(
+    db_env: &rkv::Rkv,
+    access: rkv::SingleStore,
+    max_total: Option<usize>,
+    max_age: Option<Duration>,
+) -> Result<()> {
+    let mut entries = Vec::new();
+    {
+        let reader = db_env.read().map_err(RkvErrWrap)?;
+        for entry in access.iter_start(&reader).map_err(RkvErrWrap)? {
+            let (key, value) = entry.map_err(RkvErrWrap)?;
+            if let Some(rkv::Value::Blob(value)) = value {
+                if let Ok((last_used, size)) = bincode::deserialize::<(u64, u64)>(value) {
+                    entries.push((last_used, size as usize, key.to_vec()));
+                }
+            }
+        }
+    }
+    entries.sort();
+    let mut total: usize = entries.iter().map(|(_, size, _)| size).sum();
+    let oldest_allowed = max_age.map_or(0, |max_age| {
+        now_nanos().saturating_sub(max_age.as_nanos() as u64)
+    });
+    let target = max_total.map_or(usize::MAX, |max_total| {
+        if total > max_total {
+            max_total / 10 * 9
+        } else {
+            max_total
+        }
+    });
+    let mut evicted = Vec::new();
+    for (last_used, size, key) in entries {
+        if total <= target && last_used >= oldest_allowed {
+            break;
+        }
+        total -= size;
+        evicted.push(key);
+    }
+    if evicted.is_empty() {
+        return Ok(());
+    }
+    debug!("evicting {} entries from the cache", evicted.len());
+    // lmdb opens stores in a transaction of its own, so they are opened before the writer
+    let mut stores = HashMap::new();
+    for access_key in &evicted {
+        let (name, _) = split_access_key(access_key);
+        if let Entry::Vacant(entry) = stores.entry(name) {
+            let store = db_env
+                .open_single(entry.key().as_str(), rkv::store::Options::create())
+                .map_err(RkvErrWrap)?;
+            entry.insert(store);
+        }
+    }
+    let mut writer = db_env.write().map_err(RkvErrWrap)?;
+    for access_key in evicted {
+        let (name, key) = split_access_key(&access_key);
+        ignore_not_found(stores[&name].delete(&mut writer, key))?;
+        ignore_not_found(access.delete(&mut writer, &access_key))?;
+    }
+    writer.commit().map_err(RkvErrWrap)?;
+    Ok(())
+}
+
+/// the entry might already have been deleted by another rga process
+fn ignore_not_found(res: Result<(), rkv::StoreError>) -> Result<()> {
+    match res {
+        Err(rkv::StoreError::LmdbError(::lmdb::Error::NotFound)) => Ok(()),
+        r => Ok(r.map_err(RkvErrWrap)?),
+    }
+}
+
+/// opens a store, which is created unless the cache is read-only. None if it does not exist then
+fn open_store(db_env: &rkv::Rkv, name: &str, read_only: bool) -> Result<Option<rkv::SingleStore>> {
+    let options = if read_only {
+        rkv::store::Options::default()
+    } else {
+        rkv::store::Options::create()
+    };
+    match db_env.open_single(name, options) {
+        Ok(store) => Ok(Some(store)),
+        Err(rkv::StoreError::LmdbError(::lmdb::Error::NotFound)) if read_only => Ok(None),
+        Err(e) => Err(RkvErrWrap(e)).context("could not open cache db store"),
+    }
+}
+
 #[derive(Debug)]
 struct RkvErrWrap(rkv::StoreError);
 impl Display for RkvErrWrap {
@@ -81,34 +425,75 @@ impl PreprocCache for LmdbCache {
     ) -> Result<()> {
         let start = Instant::now();
         let db_env = self.db_arc.read().unwrap();
-        let db = db_env
-            .open_single(db_name, rkv::store::Options::create())
-            .map_err(RkvErrWrap)
-            .with_context(|| format_err!("could not open cache db store"))?;
+        let read_only = self.options.read_only;
+        let limited =
+            !read_only && (self.options.max_total.is_some() || self.options.max_age.is_some());
+        let db = open_store(&db_env, db_name, read_only)?;
+        let access = open_store(&db_env, ACCESS_STORE, read_only)?;
 
         let reader = db_env.read().expect("could not get reader");
-        let cached = db
-            .get(&reader, &key)
-            .map_err(RkvErrWrap)
-            .with_context(|| format_err!("could not read from db"))?;
+        let cached = match db {
+            Some(db) => db
+                .get(&reader, key)
+                .map_err(RkvErrWrap)
+                .with_context(|| format_err!("could not read from db"))?,
+            None => None,
+        };
+        let expired = match (self.options.max_age, access) {
+            (Some(max_age), Some(access)) => is_expired(
+                access
+                    .get(&reader, access_key(db_name, key))
+                    .map_err(RkvErrWrap)
+                    .with_context(|| format_err!("could not read from db"))?,
+                max_age,
+            ),
+            _ => false,
+        };
 
         match cached {
-            Some(rkv::Value::Blob(cached)) => {
+            Some(rkv::Value::Blob(cached)) if !expired => {
                 debug!(
                     "cache HIT, reading {} (compressed) from cache",
                     print_bytes(cached.len() as f64)
                 );
                 debug!("reading from cache took {}", print_dur(start));
+                let size = cached.len();
                 callback(cached)?;
+                drop(reader);
+                // only needed for eviction, so to not make every read a write otherwise
+                if let Some(access) = access.filter(|_| limited) {
+                    let mut writer = db_env
+                        .write()
+                        .map_err(RkvErrWrap)
+                        .with_context(|| format_err!("could not open write handle to cache"))?;
+                    access
+                        .put(
+                            &mut writer,
+                            access_key(db_name, key),
+                            &rkv::Value::Blob(&access_value(size)),
+                        )
+                        .map_err(RkvErrWrap)
+                        .with_context(|| format_err!("could not write to cache"))?;
+                    writer
+                        .commit()
+                        .map_err(RkvErrWrap)
+                        .context("could not write cache")?;
+                }
             }
-            Some(_) => Err(format_err!("Integrity: value not blob"))?,
-            None => {
-                debug!("cache MISS, running adapter");
+            Some(rkv::Value::Blob(_)) | None => {
+                if expired {
+                    debug!("cache entry EXPIRED, running adapter");
+                } else {
+                    debug!("cache MISS, running adapter");
+                }
                 drop(reader);
                 let runner_res = runner()?;
                 debug!("running adapter {} took {}", adapter_name, print_dur(start));
                 let start = Instant::now();
-                if let Some(got) = runner_res {
+                // the stores exist unless the cache is read-only
+                if let (Some(got), Some(db), Some(access), false) =
+                    (runner_res, db, access, read_only)
+                {
                     debug!("writing {} to cache", print_bytes(got.len() as f64));
                     let mut writer = db_env
                         .write()
@@ -117,16 +502,860 @@ impl PreprocCache for LmdbCache {
                     db.put(&mut writer, &key, &rkv::Value::Blob(&got))
                         .map_err(RkvErrWrap)
                         .with_context(|| format_err!("could not write to cache"))?;
+                    access
+                        .put(
+                            &mut writer,
+                            access_key(db_name, key),
+                            &rkv::Value::Blob(&access_value(got.len())),
+                        )
+                        .map_err(RkvErrWrap)
+                        .with_context(|| format_err!("could not write to cache"))?;
                     writer
                         .commit()
                         .map_err(RkvErrWrap)
                         .with_context(|| format!("could not write cache"))?;
                     debug!("writing to cache took {}", print_dur(start));
+                    if limited {
+                        evict(
+                            &db_env,
+                            access,
+                            self.options.max_total,
+                            self.options.max_age,
+                        )
+                        .with_context(|| format_err!("could not evict from cache"))?;
+                    }
                 } else {
                     debug!("not caching output");
                 }
             }
+            Some(_) => Err(format_err!("Integrity: value not blob"))?,
+        };
+        Ok(())
+    }
+
+    fn dictionary(&mut self, db_name: &str) -> Result<Option<Vec<u8>>> {
+        let db_env = self.db_arc.read().unwrap();
+        let read_only = self.options.read_only;
+        let (dictionaries, db) = match (
+            open_store(&db_env, DICT_STORE, read_only)?,
+            open_store(&db_env, db_name, read_only)?,
+        ) {
+            (Some(dictionaries), Some(db)) => (dictionaries, db),
+            _ => return Ok(None),
+        };
+        let mut samples = Vec::new();
+        {
+            let reader = db_env.read().map_err(RkvErrWrap)?;
+            if let Some(rkv::Value::Blob(dictionary)) =
+                dictionaries.get(&reader, db_name).map_err(RkvErrWrap)?
+            {
+                return Ok(Some(dictionary.to_vec()));
+            }
+            if !self.options.zstd_dict || read_only {
+                return Ok(None);
+            }
+            for entry in db.iter_start(&reader).map_err(RkvErrWrap)? {
+                if let (_, Some(rkv::Value::Blob(value))) = entry.map_err(RkvErrWrap)? {
+                    if value.len() <= DICT_SAMPLE_MAX_LEN {
+                        samples.push(value.to_vec());
+                        if samples.len() == DICT_MAX_SAMPLES {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let dictionary = match train_dictionary(&samples) {
+            Some(dictionary) => dictionary,
+            None => return Ok(None),
+        };
+        let mut writer = db_env.write().map_err(RkvErrWrap)?;
+        // another rga process might have trained one in the meantime, which its entries are compressed with
+        if let Some(rkv::Value::Blob(dictionary)) =
+            dictionaries.get(&writer, db_name).map_err(RkvErrWrap)?
+        {
+            return Ok(Some(dictionary.to_vec()));
+        }
+        dictionaries
+            .put(&mut writer, db_name, &rkv::Value::Blob(&dictionary))
+            .map_err(RkvErrWrap)
+            .context("could not write to cache")?;
+        writer.commit().map_err(RkvErrWrap)?;
+        Ok(Some(dictionary))
+    }
+}
+
+/// whether the output of the adapter is cached, see --rga-no-cache-adapters
+pub fn caches(adapter: &AdapterMeta, args: &RgaConfig) -> bool {
+    !adapter.skip_cache && !args.no_cache_adapters.contains(&adapter.name)
+}
+
+/// the cache key of the output of an adapter: (adapter name, adapter version, options hash, cleaned path, mtime).
+/// for adapters that recurse, the options hash also covers the versions of the active adapters.
+/// they are not listed in the key, since lmdb keys can't be longer than 511 bytes
+type AdapterKey = (String, i32, u64, PathBuf, SystemTime);
+/// with --rga-cache-content-hash: (adapter name, adapter version, options hash, xxh3 of the file)
+type ContentKey = (String, i32, u64, u128);
+
+/// the value of an option that changes the output of an adapter, see AdapterMeta::options
+#[derive(Serialize)]
+enum OptionValue<'a> {
+    Bool(bool),
+    String(&'a Option<String>),
+}
+
+fn option_value(option: AdapterOption, args: &RgaConfig) -> OptionValue<'_> {
+    use OptionValue::*;
+    match option {
+        AdapterOption::PdfOcr => Bool(args.pdf_ocr),
+        AdapterOption::PdfForms => Bool(args.pdf_forms),
+        AdapterOption::WhisperModel => String(&args.whisper_model),
+        AdapterOption::ArchivePassword => String(&args.archive_password),
+        AdapterOption::DecryptPassphraseCommand => String(&args.decrypt_passphrase_command),
+        AdapterOption::AgeIdentity => String(&args.age_identity),
+        AdapterOption::ProtoDescriptor => String(&args.proto_descriptor),
+        AdapterOption::ProtoMessage => String(&args.proto_message),
+        AdapterOption::HtmlLinks => Bool(args.html_links),
+        AdapterOption::DocxRevisions => Bool(args.docx_revisions),
+    }
+}
+
+/// the hash of the options that change the output of the adapter, so that changing one of them does
+/// not return the output of the old configuration from the cache, while the entries of the adapters
+/// that don't read it stay valid. only the hash is stored in the cache keys, to not write the archive
+/// password to the cache
+fn options_hash(
+    adapter: &AdapterMeta,
+    active_adapters: &[Rc<dyn FileAdapter>],
+    args: &RgaConfig,
+) -> Result<u64> {
+    let mut options = Vec::new();
+    let mut adapters = vec![adapter];
+    if adapter.recurses {
+        // the output also depends on how the files in it are adapted
+        options.extend(bincode::serialize(&(
+            args.accurate,
+            args.max_archive_recursion.0,
+        ))?);
+        adapters = active_adapters.iter().map(|a| a.metadata()).collect();
+    }
+    for adapter in adapters {
+        let values: Vec<_> = adapter
+            .options
+            .iter()
+            .map(|option| option_value(*option, args))
+            .collect();
+        let custom = args
+            .custom_adapters
+            .iter()
+            .flatten()
+            .find(|custom| custom.name == adapter.name);
+        options.extend(bincode::serialize(&(
+            &adapter.name,
+            adapter.version,
+            values,
+            custom,
+        ))?);
+    }
+    Ok(xxhash_rust::xxh3::xxh3_64(&options))
+}
+
+/// content keys start with this, so they can be told apart from path keys
+const CONTENT_KEY_PREFIX: &[u8] = b"xxh3:";
+
+fn hash_file(path: &Path) -> Result<u128> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(hasher.digest128()),
+            n => hasher.update(&buf[..n]),
+        }
+    }
+}
+
+/// the cache key of the output of `adapter` for the file at `path`
+pub fn cache_key(
+    adapter: &AdapterMeta,
+    active_adapters: &[Rc<dyn FileAdapter>],
+    path: &Path,
+    args: &RgaConfig,
+) -> Result<Vec<u8>> {
+    let options = options_hash(adapter, active_adapters, args)?;
+    if args.cache_content_hash {
+        let key: ContentKey = (
+            adapter.name.clone(),
+            adapter.version,
+            options,
+            hash_file(path)?,
+        );
+        debug!("Cache key (content): {:?}", key);
+        let mut res = CONTENT_KEY_PREFIX.to_vec();
+        res.extend(bincode::serialize(&key)?);
+        return Ok(res);
+    }
+    let clean_path = path.to_owned().clean();
+    let modified = std::fs::metadata(path)?.modified()?;
+    let key: AdapterKey = (
+        adapter.name.clone(),
+        adapter.version,
+        options,
+        clean_path,
+        modified,
+    );
+    debug!("Cache key: {:?}", key);
+    Ok(bincode::serialize(&key)?)
+}
+
+/// the current version of every adapter
+type AdapterVersions = HashMap<String, i32>;
+
+/// (store name, entries, bytes)
+type StoreStats = (String, usize, usize);
+
+/// opens the cache with lmdb directly, with the same settings as rkv. only used by the cache
+/// commands, which run without the rkv manager (the same environment can't be opened twice in a process)
+fn open_cache_env(cache_dir: &Path) -> Result<Environment> {
+    Environment::new()
+        // what open_cache_db ends up with, since set_flags replaces the flags set before
+        .set_flags(EnvironmentFlags::NO_TLS)
+        .set_map_size(DEFAULT_MAP_SIZE)
+        .set_max_dbs(100)
+        .set_max_readers(128)
+        .open(cache_dir)
+        .with_context(|| format!("could not open cache in {}", cache_dir.display()))
+}
+
+/// the stores are named `adapter.vN`, their names are the keys of the main database
+fn open_stores(env: &Environment) -> Result<Vec<(String, Database)>> {
+    let names = {
+        let main = env.open_db(None)?;
+        let txn = env.begin_ro_txn()?;
+        let mut cursor = txn.open_ro_cursor(main)?;
+        let mut names = Vec::new();
+        for entry in cursor.iter_start() {
+            let (key, _) = entry?;
+            names.push(String::from_utf8_lossy(key).to_string());
+        }
+        names
+    };
+    names
+        .into_iter()
+        .map(|name| {
+            let db = env.open_db(Some(&name))?;
+            Ok((name, db))
+        })
+        .collect()
+}
+
+fn store_stats(env: &Environment) -> Result<Vec<StoreStats>> {
+    let stores = open_stores(env)?;
+    let txn = env.begin_ro_txn()?;
+    let mut stats = Vec::new();
+    for (name, db) in stores {
+        let mut cursor = txn.open_ro_cursor(db)?;
+        let (mut entries, mut bytes) = (0, 0);
+        for entry in cursor.iter_start() {
+            let (_, value) = entry?;
+            entries += 1;
+            bytes += value.len();
+        }
+        stats.push((name, entries, bytes));
+    }
+    stats.sort_by_key(|(_, _, bytes)| std::cmp::Reverse(*bytes));
+    Ok(stats)
+}
+
+/// lmdb files are sparse, so this is the space they actually use
+fn size_on_disk(file: &Path) -> Result<u64> {
+    let meta = std::fs::metadata(file)?;
+    #[cfg(unix)]
+    return Ok(std::os::unix::fs::MetadataExt::blocks(&meta) * 512);
+    #[cfg(not(unix))]
+    return Ok(meta.len());
+}
+
+fn file_changed(path: &Path, modified: SystemTime) -> bool {
+    match std::fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(m) => m != modified,
+        Err(_) => true,
+    }
+}
+
+fn outdated(adapter: &str, version: i32, versions: &AdapterVersions) -> bool {
+    versions
+        .get(adapter)
+        .is_some_and(|current| *current != version)
+}
+
+/// whether the key belongs to a file that changed or no longer exists, or was made with an older
+/// version of the adapter. content keys stay valid wherever the file is now
+fn is_stale(key: &[u8], versions: &AdapterVersions) -> bool {
+    if let Some(key) = key.strip_prefix(CONTENT_KEY_PREFIX) {
+        match bincode::deserialize::<ContentKey>(key) {
+            Ok((adapter, version, _, _)) => outdated(&adapter, version, versions),
+            Err(_) => true,
+        }
+    } else {
+        match bincode::deserialize::<AdapterKey>(key) {
+            Ok((adapter, version, _, path, modified)) => {
+                outdated(&adapter, version, versions) || file_changed(&path, modified)
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+/// whether the store `adapter.vN` belongs to the current version of an adapter.
+/// stores of removed adapters or older versions are cleared completely by prune
+fn current_store(name: &str, versions: &AdapterVersions) -> bool {
+    name.rsplit_once(".v").is_some_and(|(adapter, version)| {
+        versions
+            .get(adapter)
+            .is_some_and(|current| version.parse() == Ok(*current))
+    })
+}
+
+/// deletes the stale entries, returns (entries, bytes)
+fn prune(env: &Environment, versions: &AdapterVersions) -> Result<(usize, usize)> {
+    let mut stores = open_stores(env)?;
+    let mut take_store = |store_name| {
+        stores
+            .iter()
+            .position(|(name, _)| name == store_name)
+            .map(|i| stores.remove(i).1)
+    };
+    let access = take_store(ACCESS_STORE);
+    let dictionaries = take_store(DICT_STORE);
+    let mut txn = env.begin_rw_txn()?;
+    let (mut entries, mut bytes) = (0, 0);
+    for (name, db) in stores {
+        let current = current_store(&name, versions);
+        if let (false, Some(dictionaries)) = (current, dictionaries) {
+            match txn.del(dictionaries, &name, None) {
+                Err(::lmdb::Error::NotFound) => {}
+                r => r?,
+            }
+        }
+        let mut stale = Vec::new();
+        {
+            let mut cursor = txn.open_ro_cursor(db)?;
+            for entry in cursor.iter_start() {
+                let (key, value) = entry?;
+                if !current || is_stale(key, versions) {
+                    stale.push(key.to_vec());
+                    bytes += value.len();
+                }
+            }
+        }
+        entries += stale.len();
+        for key in stale {
+            txn.del(db, &key, None)?;
+            if let Some(access) = access {
+                match txn.del(access, &access_key(&name, &key), None) {
+                    Err(::lmdb::Error::NotFound) => {}
+                    r => r?,
+                }
+            }
+        }
+    }
+    txn.commit()?;
+    Ok((entries, bytes))
+}
+
+/// None for the redis cache, which is stored by the server
+fn data_file(backend: CacheBackend, cache_dir: &Path) -> Option<PathBuf> {
+    match backend {
+        CacheBackend::Lmdb => Some(cache_dir.join("data.mdb")),
+        CacheBackend::Sqlite => Some(cache_dir.join(sqlite::DB_FILE)),
+        CacheBackend::Redis => None,
+    }
+}
+
+/// the data file of a cache that is on disk, for the commands that manage it
+fn local_data_file(backend: CacheBackend, cache_dir: &Path) -> Result<PathBuf> {
+    data_file(backend, cache_dir).with_context(|| {
+        format_err!(
+            "not supported with --rga-cache-backend={}, the cache is managed by the server",
+            backend
+        )
+    })
+}
+
+/// runs --rga-cache
+pub fn run_command(
+    command: CacheCommand,
+    backend: CacheBackend,
+    adapters: &[Rc<dyn FileAdapter>],
+    oup: &mut dyn Write,
+) -> Result<()> {
+    let pd = project_dirs()?;
+    let cache_dir = pd.cache_dir();
+    let data_file = local_data_file(backend, cache_dir)?;
+    if !data_file.exists() {
+        writeln!(oup, "The cache in {} is empty", cache_dir.display())?;
+        return Ok(());
+    }
+    match command {
+        CacheCommand::Stats => {
+            let stats = match backend {
+                CacheBackend::Lmdb => store_stats(&open_cache_env(cache_dir)?)?,
+                CacheBackend::Sqlite => sqlite::store_stats(&sqlite::open_db(cache_dir)?)?,
+                CacheBackend::Redis => Err(format_err!("redis has no data file"))?,
+            };
+            writeln!(
+                oup,
+                "Cache: {} ({} on disk)",
+                cache_dir.display(),
+                print_bytes(size_on_disk(&data_file)? as f64)
+            )?;
+            let (mut entries, mut bytes) = (0, 0);
+            for (name, store_entries, store_bytes) in stats {
+                if store_entries == 0 || name == ACCESS_STORE || name == DICT_STORE {
+                    continue;
+                }
+                writeln!(
+                    oup,
+                    "{}: {} entries, {}",
+                    name,
+                    store_entries,
+                    print_bytes(store_bytes as f64)
+                )?;
+                entries += store_entries;
+                bytes += store_bytes;
+            }
+            writeln!(
+                oup,
+                "Total: {} entries, {}",
+                entries,
+                print_bytes(bytes as f64)
+            )?;
+        }
+        CacheCommand::Clear => {
+            let size = size_on_disk(&data_file)?;
+            std::fs::remove_file(&data_file)?;
+            let lock_file = cache_dir.join("lock.mdb");
+            if backend == CacheBackend::Lmdb && lock_file.exists() {
+                std::fs::remove_file(lock_file)?;
+            }
+            writeln!(
+                oup,
+                "Deleted the cache in {} ({})",
+                cache_dir.display(),
+                print_bytes(size as f64)
+            )?;
+        }
+        CacheCommand::Prune => {
+            let versions = adapters
+                .iter()
+                .map(|a| (a.metadata().name.clone(), a.metadata().version))
+                .collect();
+            let (entries, bytes) = match backend {
+                CacheBackend::Lmdb => prune(&open_cache_env(cache_dir)?, &versions)?,
+                CacheBackend::Sqlite => sqlite::prune(&mut sqlite::open_db(cache_dir)?, &versions)?,
+                CacheBackend::Redis => Err(format_err!("redis has no data file"))?,
+            };
+            writeln!(
+                oup,
+                "Deleted {} entries ({}) from the cache in {}",
+                entries,
+                print_bytes(bytes as f64),
+                cache_dir.display()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::caching_writer::CachingWriter;
+    use ::lmdb::WriteFlags;
+
+    #[test]
+    fn evict_least_recently_used() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut cache = LmdbCache::open(
+            dir.path(),
+            CacheOptions {
+                max_total: Some(100),
+                ..Default::default()
+            },
+        )?;
+        // whether the adapter had to run
+        let mut get = |key: &str| -> Result<bool> {
+            let mut ran = false;
+            cache.get_or_run(
+                "test.v1",
+                key.as_bytes(),
+                "test",
+                Box::new(|| {
+                    ran = true;
+                    Ok(Some(vec![0; 40]))
+                }),
+                Box::new(|_| Ok(())),
+            )?;
+            Ok(ran)
+        };
+        assert!(get("a")?);
+        assert!(get("b")?);
+        assert!(!get("a")?);
+        // over the limit, b was used least recently
+        assert!(get("c")?);
+        assert!(!get("a")?);
+        assert!(!get("c")?);
+        assert!(get("b")?);
+        Ok(())
+    }
+
+    #[test]
+    fn expire() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut cache = LmdbCache::open(
+            dir.path(),
+            CacheOptions {
+                max_age: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+        )?;
+        let mut get = |key: &str| -> Result<bool> {
+            let mut ran = false;
+            cache.get_or_run(
+                "test.v1",
+                key.as_bytes(),
+                "test",
+                Box::new(|| {
+                    ran = true;
+                    Ok(Some(vec![0; 40]))
+                }),
+                Box::new(|_| Ok(())),
+            )?;
+            Ok(ran)
         };
+        assert!(get("a")?);
+        assert!(!get("a")?);
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(get("a")?);
+        assert!(!get("a")?);
+        Ok(())
+    }
+
+    #[test]
+    fn content_key() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (report, copy, other) = (
+            dir.path().join("report.db"),
+            dir.path().join("backup/report (1).db"),
+            dir.path().join("other.db"),
+        );
+        std::fs::create_dir(dir.path().join("backup"))?;
+        std::fs::write(&report, "SQLite format 3 report")?;
+        std::fs::write(&copy, "SQLite format 3 report")?;
+        std::fs::write(&other, "SQLite format 3 other")?;
+        let adapters = crate::adapters::get_all_adapters(None).0;
+        let find = |name: &str| adapters.iter().find(|a| a.metadata().name == name).unwrap();
+        let (sqlite, zip) = (find("sqlite").metadata(), find("zip").metadata());
+        let args = RgaConfig {
+            cache_content_hash: true,
+            ..Default::default()
+        };
+        for adapter in &[sqlite, zip] {
+            let key = |path: &Path| cache_key(adapter, &adapters, path, &args).unwrap();
+            assert_eq!(key(&report), key(&copy));
+            assert_ne!(key(&report), key(&other));
+            assert_ne!(
+                key(&report),
+                cache_key(adapter, &adapters, &report, &Default::default())?
+            );
+        }
+        assert_ne!(
+            cache_key(sqlite, &adapters, &report, &args)?,
+            cache_key(zip, &adapters, &report, &args)?
+        );
+        // lmdb keys can't be longer than 511 bytes, whatever the number of adapters
+        assert!(cache_key(zip, &adapters, &report, &Default::default())?.len() < 512);
+
+        // content keys are not pruned when the file is gone, only when the adapter changed
+        let key = cache_key(sqlite, &adapters, &report, &args)?;
+        std::fs::remove_file(&report)?;
+        let versions = |version| -> AdapterVersions {
+            vec![("sqlite".to_string(), version)].into_iter().collect()
+        };
+        assert!(!is_stale(&key, &versions(sqlite.version)));
+        assert!(is_stale(&key, &versions(sqlite.version + 1)));
+        Ok(())
+    }
+
+    fn check_dictionary(cache: &mut dyn PreprocCache) -> Result<()> {
+        let text = |i: usize| {
+            format!(
+                "Invoice {}\nACME Corporation, 1 Main Street, Springfield\nDue date: 2024-01-{:02}\n\
+                 Description: consulting services for the migration of the accounting system\n\
+                 Total amount: {} EUR\nPlease transfer the amount within 30 days to the account below.\n",
+                i,
+                i % 28 + 1,
+                i * 17
+            )
+        };
+        let get =
+            |cache: &mut dyn PreprocCache, i: usize, compressed: Vec<u8>| -> Result<Vec<u8>> {
+                let mut cached = None;
+                cache.get_or_run(
+                    "test.v1",
+                    &i.to_le_bytes(),
+                    "test",
+                    Box::new(|| Ok(Some(compressed))),
+                    Box::new(|c| {
+                        cached = Some(c.to_vec());
+                        Ok(())
+                    }),
+                )?;
+                Ok(cached.unwrap_or_default())
+            };
+        for i in 0..DICT_MIN_SAMPLES {
+            assert!(cache.dictionary("test.v1")?.is_none());
+            get(cache, i, zstd::stream::encode_all(text(i).as_bytes(), 12)?)?;
+        }
+        let dictionary = cache.dictionary("test.v1")?.context("no dictionary")?;
+        assert_eq!(cache.dictionary("test.v1")?, Some(dictionary.clone()));
+        assert!(cache.dictionary("other.v1")?.is_none());
+
+        let compress = |dictionary: Option<&[u8]>| -> Result<Vec<u8>> {
+            let mut writer = CachingWriter::new(std::io::sink(), 1 << 20, 12, dictionary)?;
+            writer.write_all(text(1000).as_bytes())?;
+            Ok(writer.finish()?.1.unwrap())
+        };
+        let compressed = compress(Some(&dictionary))?;
+        assert!(compressed.len() < compress(None)?.len() / 2);
+        get(cache, 1000, compressed)?;
+        // entries written before and after the dictionary was trained
+        for i in &[0, 1000] {
+            let mut out = Vec::new();
+            decompress(&get(cache, *i, vec![])?, Some(&dictionary), &mut out)?;
+            assert_eq!(String::from_utf8(out)?, text(*i));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn dictionary() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let options = CacheOptions {
+            zstd_dict: true,
+            ..Default::default()
+        };
+        check_dictionary(&mut LmdbCache::open(dir.path(), options)?)?;
+        check_dictionary(&mut sqlite::SqliteCache::open(dir.path(), options)?)?;
+        // without --rga-cache-zstd-dict, the dictionary that was trained is still used
+        let options = CacheOptions::default();
+        assert!(LmdbCache::open(dir.path(), options)?
+            .dictionary("test.v1")?
+            .is_some());
+        assert!(sqlite::SqliteCache::open(dir.path(), options)?
+            .dictionary("test.v1")?
+            .is_some());
+        Ok(())
+    }
+
+    fn check_read_only(
+        cache: &mut dyn PreprocCache,
+        read_only: &mut dyn PreprocCache,
+    ) -> Result<()> {
+        // whether the adapter had to run
+        let get = |cache: &mut dyn PreprocCache, db_name: &str, key: &str| -> Result<bool> {
+            let mut ran = false;
+            cache.get_or_run(
+                db_name,
+                key.as_bytes(),
+                "test",
+                Box::new(|| {
+                    ran = true;
+                    Ok(Some(vec![0; 40]))
+                }),
+                Box::new(|_| Ok(())),
+            )?;
+            Ok(ran)
+        };
+        assert!(get(cache, "test.v1", "a")?);
+        assert!(!get(read_only, "test.v1", "a")?);
+        assert!(get(read_only, "test.v1", "b")?);
+        assert!(get(read_only, "test.v1", "b")?);
+        // a store that does not exist is not created
+        assert!(get(read_only, "other.v1", "a")?);
+        assert!(read_only.dictionary("other.v1")?.is_none());
+        assert!(get(cache, "test.v1", "b")?);
+        Ok(())
+    }
+
+    #[test]
+    fn read_only() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let options = CacheOptions {
+            max_total: Some(1000),
+            zstd_dict: true,
+            ..Default::default()
+        };
+        let read_only = CacheOptions {
+            read_only: true,
+            ..options
+        };
+        check_read_only(
+            &mut LmdbCache::open(dir.path(), options)?,
+            &mut LmdbCache::open(dir.path(), read_only)?,
+        )?;
+        check_read_only(
+            &mut sqlite::SqliteCache::open(dir.path(), options)?,
+            &mut sqlite::SqliteCache::open(dir.path(), read_only)?,
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn options_key() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("page.html");
+        std::fs::write(&file, "<a href=\"https://example.com\">link</a>")?;
+        let adapters = crate::adapters::get_adapters_filtered(None, &vec!["+html"])?;
+        let html = adapters
+            .iter()
+            .find(|a| a.metadata().name == "html")
+            .unwrap()
+            .metadata();
+        let zip = adapters
+            .iter()
+            .find(|a| a.metadata().name == "zip")
+            .unwrap()
+            .metadata();
+        let key = |adapter, args: &RgaConfig| cache_key(adapter, &adapters, &file, args).unwrap();
+        let default = RgaConfig::default();
+        let links = RgaConfig {
+            html_links: true,
+            ..Default::default()
+        };
+        assert_eq!(key(html, &default), key(html, &RgaConfig::default()));
+        assert_ne!(key(html, &default), key(html, &links));
+        // zip recurses, so the files in it can be html
+        assert_ne!(key(zip, &default), key(zip, &links));
+        // options that the adapter does not read keep its entries
+        let password = RgaConfig {
+            archive_password: Some("hunter2".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(key(html, &default), key(html, &password));
+        assert_ne!(key(zip, &default), key(zip, &password));
+        // the password is hashed
+        assert!(!key(zip, &password)
+            .windows(b"hunter2".len())
+            .any(|w| w == b"hunter2"));
+        Ok(())
+    }
+
+    #[test]
+    fn no_cache_adapters() {
+        let adapters = crate::adapters::get_all_adapters(None).0;
+        let meta = |name: &str| {
+            adapters
+                .iter()
+                .find(|a| a.metadata().name == name)
+                .unwrap()
+                .metadata()
+        };
+        let args = RgaConfig {
+            no_cache_adapters: vec!["zip".to_string()],
+            ..Default::default()
+        };
+        assert!(!caches(meta("zip"), &args));
+        assert!(caches(meta("tar"), &args));
+        // faster than reading from the cache
+        assert!(!caches(meta("decompress"), &RgaConfig::default()));
+        let fast = crate::adapters::custom::CustomAdapterConfig {
+            name: "fast".to_string(),
+            skip_cache: Some(true),
+            ..Default::default()
+        }
+        .to_adapter();
+        assert!(!caches(
+            crate::adapters::GetMetadata::metadata(&fast),
+            &RgaConfig::default()
+        ));
+    }
+
+    #[test]
+    fn entry_reader() -> Result<()> {
+        let text: Vec<u8> = (0..3 << 20).map(|i| (i % 251) as u8).collect();
+        let mut writer = CachingWriter::new(std::io::sink(), 1 << 30, 3, None)?;
+        writer.write_all(&text)?;
+        let mut entry = writer.finish()?.1.unwrap();
+        let mut all = Vec::new();
+        EntryReader::new(entry.clone(), None).read_to_end(&mut all)?;
+        assert_eq!(all, text);
+        // the last frame is only decompressed once the output before it is read
+        let last =
+            crate::caching_writer::frames(&entry)[2].as_ptr() as usize - entry.as_ptr() as usize;
+        entry[last + 10] ^= 0xff;
+        let mut first = Vec::new();
+        EntryReader::new(entry.clone(), None)
+            .take(2 << 20)
+            .read_to_end(&mut first)?;
+        assert_eq!(first, &text[..2 << 20]);
+        assert!(EntryReader::new(entry, None)
+            .read_to_end(&mut Vec::new())
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn prune_stale() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("report.pdf");
+        std::fs::write(&file, "")?;
+        let modified = std::fs::metadata(&file)?.modified()?;
+        let deleted = dir.path().join("deleted.pdf");
+        let cache_dir = dir.path().join("cache");
+        std::fs::create_dir(&cache_dir)?;
+
+        let env = open_cache_env(&cache_dir)?;
+        let key = |adapter: &str, version: i32, path: &Path| {
+            bincode::serialize(&(
+                adapter.to_string(),
+                version,
+                0u64,
+                path.to_owned(),
+                modified,
+            ))
+            .unwrap()
+        };
+        for (store, key) in &[
+            ("executable.v2", key("executable", 2, &file)),
+            ("executable.v2", key("executable", 2, &deleted)),
+            ("executable.v1", key("executable", 1, &file)),
+            ("removed.v1", key("removed", 1, &file)),
+            ("zip.v1", key("zip", 1, &file)),
+            ("zip.v1", key("zip", 1, &deleted)),
+        ] {
+            let db = env.create_db(Some(store), Default::default())?;
+            let mut txn = env.begin_rw_txn()?;
+            txn.put(db, key, b"output", WriteFlags::empty())?;
+            txn.commit()?;
+        }
+
+        let versions: AdapterVersions = vec![("executable".to_string(), 2), ("zip".to_string(), 1)]
+            .into_iter()
+            .collect();
+        assert_eq!(prune(&env, &versions)?, (4, 24));
+        let mut stats = store_stats(&env)?;
+        stats.sort();
+        assert_eq!(
+            stats,
+            vec![
+                ("executable.v1".to_string(), 0, 0),
+                ("executable.v2".to_string(), 1, 6),
+                ("removed.v1".to_string(), 0, 0),
+                ("zip.v1".to_string(), 1, 6),
+            ]
+        );
         Ok(())
     }
 }
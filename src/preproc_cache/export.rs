@@ -0,0 +1,425 @@
+use super::{
+    access_key, access_value, local_data_file, now_nanos, open_cache_env, open_stores, sqlite,
+    ACCESS_STORE, DICT_STORE,
+};
+use crate::args::CacheBackend;
+use crate::{print_bytes, project_dirs};
+use ::lmdb::{Cursor, DatabaseFlags, Transaction, WriteFlags};
+use anyhow::{format_err, Context, Result};
+use rusqlite::{params, TransactionBehavior, NO_PARAMS};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8] = b"rga-cache-export\0";
+const FORMAT_VERSION: u32 = 1;
+
+/// the file starts with the magic and this header, then the entries follow as
+/// `Some((store, key, value))` and a `None` at the end, so that truncated files are noticed
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format_version: u32,
+    stores: Vec<String>,
+    /// (store, zstd dictionary), see --rga-cache-zstd-dict
+    dictionaries: Vec<(String, Vec<u8>)>,
+}
+
+type ExportEntry<'a> = Option<(&'a str, &'a [u8], &'a [u8])>;
+type ImportEntry = Option<(String, Vec<u8>, Vec<u8>)>;
+
+/// lmdb values are stored by rkv, which prefixes them with their type
+fn rkv_blob(value: &[u8]) -> Result<&[u8]> {
+    match rkv::Value::from_tagged_slice(value) {
+        Ok(rkv::Value::Blob(blob)) => Ok(blob),
+        _ => Err(format_err!("Integrity: value not blob")),
+    }
+}
+
+fn to_rkv_blob(blob: &[u8]) -> Result<Vec<u8>> {
+    rkv::Value::Blob(blob)
+        .to_bytes()
+        .map_err(|e| format_err!("could not serialize cache value: {}", e))
+}
+
+struct Written {
+    entries: usize,
+    bytes: usize,
+}
+
+fn write_entry(oup: &mut impl Write, written: &mut Written, entry: ExportEntry) -> Result<()> {
+    if let Some((_, _, value)) = entry {
+        written.entries += 1;
+        written.bytes += value.len();
+    }
+    bincode::serialize_into(oup, &entry)?;
+    Ok(())
+}
+
+fn export_lmdb(cache_dir: &Path, oup: &mut impl Write) -> Result<Written> {
+    let env = open_cache_env(cache_dir)?;
+    let stores = open_stores(&env)?;
+    let txn = env.begin_ro_txn()?;
+    let mut dictionaries = Vec::new();
+    if let Some((_, db)) = stores.iter().find(|(name, _)| name == DICT_STORE) {
+        let mut cursor = txn.open_ro_cursor(*db)?;
+        for entry in cursor.iter_start() {
+            let (store, dictionary) = entry?;
+            dictionaries.push((
+                String::from_utf8_lossy(store).to_string(),
+                rkv_blob(dictionary)?.to_vec(),
+            ));
+        }
+    }
+    let stores: Vec<_> = stores
+        .into_iter()
+        .filter(|(name, _)| name != ACCESS_STORE && name != DICT_STORE)
+        .collect();
+    bincode::serialize_into(
+        &mut *oup,
+        &Header {
+            format_version: FORMAT_VERSION,
+            stores: stores.iter().map(|(name, _)| name.clone()).collect(),
+            dictionaries,
+        },
+    )?;
+    let mut written = Written {
+        entries: 0,
+        bytes: 0,
+    };
+    for (name, db) in &stores {
+        let mut cursor = txn.open_ro_cursor(*db)?;
+        for entry in cursor.iter_start() {
+            let (key, value) = entry?;
+            write_entry(oup, &mut written, Some((name, key, rkv_blob(value)?)))?;
+        }
+    }
+    write_entry(oup, &mut written, None)?;
+    Ok(written)
+}
+
+fn export_sqlite(cache_dir: &Path, oup: &mut impl Write) -> Result<Written> {
+    let conn = sqlite::open_db(cache_dir)?;
+    let stores = conn
+        .prepare("SELECT DISTINCT store FROM cache")?
+        .query_map(NO_PARAMS, |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let dictionaries = conn
+        .prepare("SELECT store, dictionary FROM dictionaries")?
+        .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    bincode::serialize_into(
+        &mut *oup,
+        &Header {
+            format_version: FORMAT_VERSION,
+            stores,
+            dictionaries,
+        },
+    )?;
+    let mut written = Written {
+        entries: 0,
+        bytes: 0,
+    };
+    let mut stmt = conn.prepare("SELECT store, key, value FROM cache")?;
+    let mut rows = stmt.query(NO_PARAMS)?;
+    while let Some(row) = rows.next()? {
+        let (store, key, value): (String, Vec<u8>, Vec<u8>) =
+            (row.get(0)?, row.get(1)?, row.get(2)?);
+        write_entry(oup, &mut written, Some((&store, &key, &value)))?;
+    }
+    write_entry(oup, &mut written, None)?;
+    Ok(written)
+}
+
+fn export_cache(backend: CacheBackend, cache_dir: &Path, out: &mut impl Write) -> Result<Written> {
+    out.write_all(MAGIC)?;
+    match backend {
+        CacheBackend::Lmdb => export_lmdb(cache_dir, out),
+        CacheBackend::Sqlite => export_sqlite(cache_dir, out),
+        CacheBackend::Redis => Err(format_err!("exporting from redis is not supported")),
+    }
+}
+
+/// runs --rga-cache-export
+pub fn export(backend: CacheBackend, file: &Path, oup: &mut dyn Write) -> Result<()> {
+    let pd = project_dirs()?;
+    let cache_dir = pd.cache_dir();
+    if !local_data_file(backend, cache_dir)?.exists() {
+        return Err(format_err!("The cache in {} is empty", cache_dir.display()));
+    }
+    let mut out = BufWriter::new(
+        File::create(file).with_context(|| format!("could not create {}", file.display()))?,
+    );
+    let written = export_cache(backend, cache_dir, &mut out)?;
+    out.flush()?;
+    writeln!(
+        oup,
+        "Exported {} entries ({}) from the cache in {} to {}",
+        written.entries,
+        print_bytes(written.bytes as f64),
+        cache_dir.display(),
+        file.display()
+    )?;
+    Ok(())
+}
+
+/// the imported entries of a store compressed with a dictionary can only be used if the store has
+/// no dictionary here yet, or the same one
+fn check_dictionaries(
+    local: &HashMap<String, Vec<u8>>,
+    imported: Vec<(String, Vec<u8>)>,
+) -> (Vec<(String, Vec<u8>)>, HashSet<String>) {
+    let mut new = Vec::new();
+    let mut conflicting = HashSet::new();
+    for (store, dictionary) in imported {
+        match local.get(&store) {
+            None => new.push((store, dictionary)),
+            Some(local) if *local == dictionary => {}
+            Some(_) => {
+                conflicting.insert(store);
+            }
+        }
+    }
+    (new, conflicting)
+}
+
+/// reads the entries, returns (imported entries, bytes, skipped entries)
+fn read_entries(
+    inp: &mut impl Read,
+    conflicting: &HashSet<String>,
+    mut import: impl FnMut(&str, &[u8], &[u8]) -> Result<()>,
+) -> Result<(usize, usize, usize)> {
+    let (mut entries, mut bytes, mut skipped) = (0, 0, 0);
+    while let Some((store, key, value)) = bincode::deserialize_from::<_, ImportEntry>(&mut *inp)
+        .context("could not read cache export, the file might be truncated")?
+    {
+        if conflicting.contains(&store) && zstd_safe::get_dict_id_from_frame(&value) != 0 {
+            skipped += 1;
+            continue;
+        }
+        import(&store, &key, &value)?;
+        entries += 1;
+        bytes += value.len();
+    }
+    Ok((entries, bytes, skipped))
+}
+
+fn import_lmdb(
+    cache_dir: &Path,
+    header: Header,
+    inp: &mut impl Read,
+) -> Result<(usize, usize, usize)> {
+    let env = open_cache_env(cache_dir)?;
+    // lmdb creates databases in a transaction of their own, so they are created before the writer
+    let mut dbs = HashMap::new();
+    for name in header
+        .stores
+        .iter()
+        .map(|s| s.as_str())
+        .chain(vec![ACCESS_STORE, DICT_STORE])
+    {
+        dbs.insert(
+            name.to_string(),
+            env.create_db(Some(name), DatabaseFlags::empty())?,
+        );
+    }
+    let (access, dictionaries) = (dbs[ACCESS_STORE], dbs[DICT_STORE]);
+    let mut txn = env.begin_rw_txn()?;
+    let mut local = HashMap::new();
+    {
+        let mut cursor = txn.open_ro_cursor(dictionaries)?;
+        for entry in cursor.iter_start() {
+            let (store, dictionary) = entry?;
+            local.insert(
+                String::from_utf8_lossy(store).to_string(),
+                rkv_blob(dictionary)?.to_vec(),
+            );
+        }
+    }
+    let (new, conflicting) = check_dictionaries(&local, header.dictionaries);
+    for (store, dictionary) in new {
+        txn.put(
+            dictionaries,
+            &store,
+            &to_rkv_blob(&dictionary)?,
+            WriteFlags::empty(),
+        )?;
+    }
+    let res = read_entries(inp, &conflicting, |store, key, value| {
+        let db = *dbs
+            .get(store)
+            .with_context(|| format!("store {} is missing from the header", store))?;
+        txn.put(db, &key, &to_rkv_blob(value)?, WriteFlags::empty())?;
+        // without the access time, the entry would never be evicted
+        txn.put(
+            access,
+            &access_key(store, key),
+            &to_rkv_blob(&access_value(value.len()))?,
+            WriteFlags::empty(),
+        )?;
+        Ok(())
+    })?;
+    txn.commit()?;
+    Ok(res)
+}
+
+fn import_sqlite(
+    cache_dir: &Path,
+    header: Header,
+    inp: &mut impl Read,
+) -> Result<(usize, usize, usize)> {
+    let mut conn = sqlite::open_db(cache_dir)?;
+    let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let local = txn
+        .prepare("SELECT store, dictionary FROM dictionaries")?
+        .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let (new, conflicting) = check_dictionaries(&local, header.dictionaries);
+    for (store, dictionary) in new {
+        txn.execute(
+            "INSERT INTO dictionaries (store, dictionary) VALUES (?1, ?2)",
+            params![store, dictionary],
+        )?;
+    }
+    let now = now_nanos() as i64;
+    let res = {
+        let mut insert = txn.prepare(
+            "INSERT OR REPLACE INTO cache (store, key, value, last_used) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        read_entries(inp, &conflicting, |store, key, value| {
+            insert.execute(params![store, key, value, now])?;
+            Ok(())
+        })?
+    };
+    txn.commit()?;
+    Ok(res)
+}
+
+/// returns (imported entries, bytes, skipped entries)
+fn import_cache(
+    backend: CacheBackend,
+    cache_dir: &Path,
+    inp: &mut impl Read,
+) -> Result<(usize, usize, usize)> {
+    let mut magic = vec![0; MAGIC.len()];
+    inp.read_exact(&mut magic)
+        .ok()
+        .filter(|_| magic == MAGIC)
+        .context("not a cache export")?;
+    let header: Header = bincode::deserialize_from(&mut *inp)?;
+    if header.format_version != FORMAT_VERSION {
+        return Err(format_err!("exported by a different version of rga"));
+    }
+    std::fs::create_dir_all(cache_dir)?;
+    match backend {
+        CacheBackend::Lmdb => import_lmdb(cache_dir, header, inp),
+        CacheBackend::Sqlite => import_sqlite(cache_dir, header, inp),
+        CacheBackend::Redis => Err(format_err!("importing to redis is not supported")),
+    }
+}
+
+/// runs --rga-cache-import
+pub fn import(backend: CacheBackend, file: &Path, oup: &mut dyn Write) -> Result<()> {
+    let pd = project_dirs()?;
+    let cache_dir = pd.cache_dir();
+    local_data_file(backend, cache_dir)?;
+    let mut inp = BufReader::new(
+        File::open(file).with_context(|| format!("could not open {}", file.display()))?,
+    );
+    let (entries, bytes, skipped) = import_cache(backend, cache_dir, &mut inp)
+        .with_context(|| format!("could not import {}", file.display()))?;
+    writeln!(
+        oup,
+        "Imported {} entries ({}) from {} to the cache in {}",
+        entries,
+        print_bytes(bytes as f64),
+        file.display(),
+        cache_dir.display()
+    )?;
+    if skipped > 0 {
+        writeln!(
+            oup,
+            "Skipped {} entries that were compressed with a different dictionary than the one of their adapter here",
+            skipped
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the header of a frame that was compressed with the dictionary with the id 42
+    const DICT_FRAME: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd, 0x01, 0x00, 42];
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (lmdb_dir, sqlite_dir, lmdb_dir2) = (
+            dir.path().join("lmdb"),
+            dir.path().join("sqlite"),
+            dir.path().join("lmdb2"),
+        );
+        std::fs::create_dir(&lmdb_dir)?;
+        let plain = zstd::stream::encode_all(&b"extracted text"[..], 3)?;
+        {
+            let env = open_cache_env(&lmdb_dir)?;
+            for (store, key, value) in &[
+                ("zip.v1", &b"plain"[..], &plain[..]),
+                ("zip.v1", b"with dictionary", DICT_FRAME),
+                (DICT_STORE, b"zip.v1", b"dictionary a"),
+                (ACCESS_STORE, b"zip.v1\0plain", b"the access time"),
+            ] {
+                let db = env.create_db(Some(store), DatabaseFlags::empty())?;
+                let mut txn = env.begin_rw_txn()?;
+                txn.put(db, key, &to_rkv_blob(value)?, WriteFlags::empty())?;
+                txn.commit()?;
+            }
+        }
+        let export = |backend, cache_dir: &Path| -> Result<Vec<u8>> {
+            let mut out = Vec::new();
+            export_cache(backend, cache_dir, &mut out)?;
+            Ok(out)
+        };
+
+        let exported = export(CacheBackend::Lmdb, &lmdb_dir)?;
+        // the entry compressed with the dictionary can't be used with the other dictionary here
+        sqlite::open_db(&sqlite_dir)?.execute(
+            "INSERT INTO dictionaries (store, dictionary) VALUES ('zip.v1', ?1)",
+            params![b"dictionary b".to_vec()],
+        )?;
+        assert_eq!(
+            import_cache(CacheBackend::Sqlite, &sqlite_dir, &mut &exported[..])?,
+            (1, plain.len(), 1)
+        );
+        assert!(import_cache(
+            CacheBackend::Sqlite,
+            &sqlite_dir,
+            &mut &exported[..exported.len() - 1]
+        )
+        .is_err());
+
+        let exported = export(CacheBackend::Sqlite, &sqlite_dir)?;
+        assert_eq!(
+            import_cache(CacheBackend::Lmdb, &lmdb_dir2, &mut &exported[..])?,
+            (1, plain.len(), 0)
+        );
+        let env = open_cache_env(&lmdb_dir2)?;
+        let dbs: HashMap<_, _> = vec!["zip.v1", DICT_STORE, ACCESS_STORE]
+            .into_iter()
+            .map(|store| Ok((store, env.open_db(Some(store))?)))
+            .collect::<Result<_>>()?;
+        let txn = env.begin_ro_txn()?;
+        let get = |store, key: &[u8]| -> Result<Vec<u8>> {
+            Ok(rkv_blob(txn.get(dbs[store], &key)?)?.to_vec())
+        };
+        assert_eq!(get("zip.v1", b"plain")?, plain);
+        assert_eq!(get(DICT_STORE, b"zip.v1")?, b"dictionary b");
+        assert!(get(ACCESS_STORE, b"zip.v1\0plain").is_ok());
+        Ok(())
+    }
+}
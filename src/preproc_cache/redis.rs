@@ -0,0 +1,452 @@
+use super::{train_dictionary, CacheOptions, PreprocCache, DICT_MAX_SAMPLES, DICT_SAMPLE_MAX_LEN};
+use crate::{print_bytes, print_dur};
+use anyhow::{format_err, Context, Result};
+use log::*;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_URL: &str = "redis://127.0.0.1:6379";
+
+/// the entries are stored as `rga:<store>:<key>`, the dictionaries as `rga.dict:<store>`
+const KEY_PREFIX: &str = "rga:";
+const DICT_KEY_PREFIX: &str = "rga.dict:";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// adapter outputs can be large, and a remote server slow
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, PartialEq)]
+struct Url {
+    host: String,
+    port: u16,
+    /// (user, password) for AUTH
+    auth: Option<(Option<String>, String)>,
+    db: Option<u32>,
+}
+
+/// redis://[[user]:password@]host[:port][/db]
+fn parse_url(url: &str) -> Result<Url> {
+    let rest = url
+        .strip_prefix("redis://")
+        .with_context(|| format!("{} is not a redis:// url", url))?;
+    let (auth, rest) = match rest.rsplit_once('@') {
+        Some((auth, rest)) => {
+            let auth = match auth.split_once(':') {
+                Some(("", password)) => (None, password.to_string()),
+                Some((user, password)) => (Some(user.to_string()), password.to_string()),
+                None => (None, auth.to_string()),
+            };
+            (Some(auth), rest)
+        }
+        None => (None, rest),
+    };
+    let (address, db) = match rest.split_once('/') {
+        Some((address, "")) => (address, None),
+        Some((address, db)) => (
+            address,
+            Some(
+                db.parse()
+                    .with_context(|| format!("invalid db in {}", url))?,
+            ),
+        ),
+        None => (rest, None),
+    };
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .with_context(|| format!("invalid port in {}", url))?,
+        ),
+        None => (address, 6379),
+    };
+    Ok(Url {
+        host: host.to_string(),
+        port,
+        auth,
+        db,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Nil,
+    Int(i64),
+    /// bulk and simple strings
+    Data(Vec<u8>),
+    Array(Vec<Reply>),
+}
+
+fn read_reply(inp: &mut impl BufRead) -> Result<Reply> {
+    let mut line = Vec::new();
+    inp.read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\r\n") {
+        return Err(format_err!("connection to redis closed"));
+    }
+    let line = &line[..line.len() - 2];
+    let (kind, rest) = line.split_first().context("empty reply from redis")?;
+    let rest = std::str::from_utf8(rest)?;
+    let len = || -> Result<i64> {
+        rest.parse()
+            .with_context(|| format!("invalid reply from redis: {}", rest))
+    };
+    Ok(match kind {
+        b'+' => Reply::Data(rest.as_bytes().to_vec()),
+        b'-' => return Err(format_err!("redis: {}", rest)),
+        b':' => Reply::Int(len()?),
+        b'$' if len()? < 0 => Reply::Nil,
+        b'$' => {
+            let mut data = vec![0; len()? as usize + 2];
+            inp.read_exact(&mut data)?;
+            data.truncate(data.len() - 2);
+            Reply::Data(data)
+        }
+        b'*' if len()? < 0 => Reply::Nil,
+        b'*' => Reply::Array(
+            (0..len()?)
+                .map(|_| read_reply(inp))
+                .collect::<Result<_>>()?,
+        ),
+        _ => return Err(format_err!("invalid reply from redis")),
+    })
+}
+
+fn write_command(oup: &mut impl Write, args: &[&[u8]]) -> std::io::Result<()> {
+    let mut command = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        command.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        command.extend_from_slice(arg);
+        command.extend_from_slice(b"\r\n");
+    }
+    // in one write, so the command is sent in as few packets as possible
+    oup.write_all(&command)?;
+    oup.flush()
+}
+
+/// escapes a store name for the pattern of SCAN
+fn escape_pattern(name: &str) -> String {
+    name.chars()
+        .flat_map(|c| match c {
+            '*' | '?' | '[' | ']' | '\\' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect()
+}
+
+/// a cache on a redis server, shared by everyone that uses it. it is not evicted by rga:
+/// the server should be configured with a maxmemory and an eviction policy such as allkeys-lru
+pub struct RedisCache {
+    conn: Mutex<BufReader<TcpStream>>,
+    options: CacheOptions,
+}
+
+impl RedisCache {
+    pub fn open(url: &str, options: CacheOptions) -> Result<RedisCache> {
+        let parsed = parse_url(url)?;
+        let address = (&parsed.host[..], parsed.port)
+            .to_socket_addrs()?
+            .next()
+            .with_context(|| format!("could not resolve {}", parsed.host))?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+            .with_context(|| format!("could not connect to redis at {}", url))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut cache = RedisCache {
+            conn: Mutex::new(BufReader::new(stream)),
+            options,
+        };
+        match &parsed.auth {
+            Some((Some(user), password)) => {
+                cache.command(&[b"AUTH", user.as_bytes(), password.as_bytes()])?;
+            }
+            Some((None, password)) => {
+                cache.command(&[b"AUTH", password.as_bytes()])?;
+            }
+            None => {}
+        }
+        if let Some(db) = parsed.db {
+            cache.command(&[b"SELECT", db.to_string().as_bytes()])?;
+        }
+        Ok(cache)
+    }
+
+    fn command(&mut self, args: &[&[u8]]) -> Result<Reply> {
+        let conn = self.conn.get_mut().unwrap();
+        write_command(conn.get_mut(), args).context("could not send command to redis")?;
+        read_reply(conn)
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.command(&[b"GET", key])? {
+            Reply::Data(value) => Ok(Some(value)),
+            Reply::Nil => Ok(None),
+            r => Err(format_err!("unexpected reply from redis: {:?}", r)),
+        }
+    }
+
+    /// the small entries of a store, to train its dictionary
+    fn samples(&mut self, db_name: &str) -> Result<Vec<Vec<u8>>> {
+        let pattern = format!("{}{}:*", KEY_PREFIX, escape_pattern(db_name));
+        let mut samples = Vec::new();
+        let mut cursor = b"0".to_vec();
+        loop {
+            let (next, keys) = match self.command(&[
+                b"SCAN",
+                &cursor,
+                b"MATCH",
+                pattern.as_bytes(),
+                b"COUNT",
+                b"1000",
+            ])? {
+                Reply::Array(mut reply) if reply.len() == 2 => {
+                    match (reply.remove(0), reply.remove(0)) {
+                        (Reply::Data(next), Reply::Array(keys)) => (next, keys),
+                        r => return Err(format_err!("unexpected reply from redis: {:?}", r)),
+                    }
+                }
+                r => return Err(format_err!("unexpected reply from redis: {:?}", r)),
+            };
+            for key in keys {
+                if let Reply::Data(key) = key {
+                    if let Some(value) = self.get(&key)? {
+                        if value.len() <= DICT_SAMPLE_MAX_LEN {
+                            samples.push(value);
+                            if samples.len() == DICT_MAX_SAMPLES {
+                                return Ok(samples);
+                            }
+                        }
+                    }
+                }
+            }
+            if next == b"0" {
+                return Ok(samples);
+            }
+            cursor = next;
+        }
+    }
+}
+
+fn entry_key(db_name: &str, key: &[u8]) -> Vec<u8> {
+    let mut entry_key = format!("{}{}:", KEY_PREFIX, db_name).into_bytes();
+    entry_key.extend_from_slice(key);
+    entry_key
+}
+
+impl PreprocCache for RedisCache {
+    fn get_or_run<'a>(
+        &mut self,
+        db_name: &str,
+        key: &[u8],
+        adapter_name: &str,
+        runner: Box<dyn FnOnce() -> Result<Option<Vec<u8>>> + 'a>,
+        callback: Box<dyn FnOnce(&[u8]) -> Result<()> + 'a>,
+    ) -> Result<()> {
+        let start = Instant::now();
+        let key = entry_key(db_name, key);
+        // redis expires the entries itself, the time is reset whenever they are used
+        let max_age = self
+            .options
+            .max_age
+            .map(|max_age| max_age.as_millis().max(1).to_string());
+        match self.get(&key).context("could not read from cache")? {
+            Some(cached) => {
+                debug!(
+                    "cache HIT, reading {} (compressed) from cache",
+                    print_bytes(cached.len() as f64)
+                );
+                debug!("reading from cache took {}", print_dur(start));
+                callback(&cached)?;
+                if let (Some(max_age), false) = (&max_age, self.options.read_only) {
+                    self.command(&[b"PEXPIRE", &key, max_age.as_bytes()])
+                        .context("could not write to cache")?;
+                }
+            }
+            None => {
+                debug!("cache MISS, running adapter");
+                let runner_res = runner()?;
+                debug!("running adapter {} took {}", adapter_name, print_dur(start));
+                let start = Instant::now();
+                match runner_res {
+                    _ if self.options.read_only => debug!("read-only cache, not caching output"),
+                    Some(got) => {
+                        debug!("writing {} to cache", print_bytes(got.len() as f64));
+                        match &max_age {
+                            Some(max_age) => {
+                                self.command(&[b"SET", &key, &got, b"PX", max_age.as_bytes()])
+                            }
+                            None => self.command(&[b"SET", &key, &got]),
+                        }
+                        .context("could not write to cache")?;
+                        debug!("writing to cache took {}", print_dur(start));
+                    }
+                    None => debug!("not caching output"),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn dictionary(&mut self, db_name: &str) -> Result<Option<Vec<u8>>> {
+        let dict_key = format!("{}{}", DICT_KEY_PREFIX, db_name).into_bytes();
+        if let Some(dictionary) = self.get(&dict_key)? {
+            return Ok(Some(dictionary));
+        }
+        if !self.options.zstd_dict || self.options.read_only {
+            return Ok(None);
+        }
+        let samples = self.samples(db_name)?;
+        let dictionary = match train_dictionary(&samples) {
+            Some(dictionary) => dictionary,
+            None => return Ok(None),
+        };
+        // another rga process might have trained one in the meantime, which its entries are compressed with
+        self.command(&[b"SET", &dict_key, &dictionary, b"NX"])
+            .context("could not write to cache")?;
+        self.get(&dict_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, net::TcpListener};
+
+    /// the commands of a redis server that the cache uses, for one connection
+    fn fake_server() -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("redis://:secret@{}/2", listener.local_addr()?);
+        std::thread::spawn(move || -> Result<()> {
+            let (stream, _) = listener.accept()?;
+            let mut inp = BufReader::new(stream.try_clone()?);
+            let mut oup = stream;
+            let mut data = HashMap::<Vec<u8>, Vec<u8>>::new();
+            loop {
+                let args = match read_reply(&mut inp) {
+                    Ok(Reply::Array(args)) => args,
+                    _ => return Ok(()),
+                };
+                // errors are sent to the cache, so that the test fails with them
+                let args = args
+                    .into_iter()
+                    .map(|a| match a {
+                        Reply::Data(a) => Ok(a),
+                        a => Err(format!("not a bulk string: {:?}", a)),
+                    })
+                    .collect::<Result<Vec<Vec<u8>>, _>>();
+                let args = match args {
+                    Ok(args) => args,
+                    Err(e) => {
+                        oup.write_all(format!("-ERR {}\r\n", e).as_bytes())?;
+                        continue;
+                    }
+                };
+                let reply: Vec<u8> = match args.split_first() {
+                    None => b"-ERR empty command\r\n".to_vec(),
+                    Some((command, args)) => match (&command[..], args) {
+                        (b"AUTH", [password]) if password == b"secret" => b"+OK\r\n".to_vec(),
+                        (b"AUTH", _) => b"-WRONGPASS invalid password\r\n".to_vec(),
+                        (b"SELECT", [db]) if db == b"2" => b"+OK\r\n".to_vec(),
+                        (b"SELECT", _) => b"-ERR unexpected db\r\n".to_vec(),
+                        (b"GET", [key]) => match data.get(key) {
+                            Some(value) => {
+                                let mut reply = format!("${}\r\n", value.len()).into_bytes();
+                                reply.extend_from_slice(value);
+                                reply.extend_from_slice(b"\r\n");
+                                reply
+                            }
+                            None => b"$-1\r\n".to_vec(),
+                        },
+                        (b"SET", [key, value, ..]) => {
+                            data.insert(key.clone(), value.clone());
+                            b"+OK\r\n".to_vec()
+                        }
+                        (b"PEXPIRE", _) => b":1\r\n".to_vec(),
+                        _ => b"-ERR unknown command\r\n".to_vec(),
+                    },
+                };
+                oup.write_all(&reply)?;
+            }
+        });
+        Ok(url)
+    }
+
+    #[test]
+    fn redis_cache() -> Result<()> {
+        let mut cache = RedisCache::open(
+            &fake_server()?,
+            CacheOptions {
+                max_age: Some(Duration::from_secs(60)),
+                ..Default::default()
+            },
+        )?;
+        let mut get = |key: &str| -> Result<bool> {
+            let mut ran = false;
+            cache.get_or_run(
+                "test.v1",
+                key.as_bytes(),
+                "test",
+                Box::new(|| {
+                    ran = true;
+                    Ok(Some(b"binary\r\n\0output".to_vec()))
+                }),
+                Box::new(|cached| {
+                    assert_eq!(cached, b"binary\r\n\0output");
+                    Ok(())
+                }),
+            )?;
+            Ok(ran)
+        };
+        assert!(get("a")?);
+        assert!(!get("a")?);
+        assert!(get("b")?);
+        assert!(cache.dictionary("test.v1")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn rga_preproc() -> Result<()> {
+        use crate::args::{CacheBackend, RgaConfig};
+        use crate::preproc::tests::{count_runs, counting_args, preproc};
+        let dir = tempfile::tempdir()?;
+        let runs = dir.path().join("runs");
+        let args = RgaConfig {
+            cache_backend: CacheBackend::Redis,
+            cache_redis_url: Some(fake_server()?),
+            ..counting_args(&runs)
+        };
+        let cache = crate::preproc_cache::open_in(dir.path(), &args)?.unwrap();
+        let file = dir.path().join("a.counted");
+        std::fs::write(&file, "hello\n")?;
+        assert_eq!(preproc(&file, &cache, &args)?, b"hello\n");
+        assert_eq!(preproc(&file, &cache, &args)?, b"hello\n");
+        assert_eq!(count_runs(&runs), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn url() -> Result<()> {
+        assert_eq!(
+            parse_url(DEFAULT_URL)?,
+            Url {
+                host: "127.0.0.1".to_string(),
+                port: 6379,
+                auth: None,
+                db: None
+            }
+        );
+        assert_eq!(
+            parse_url("redis://rga:p@ss@cache.example.com/3")?,
+            Url {
+                host: "cache.example.com".to_string(),
+                port: 6379,
+                auth: Some((Some("rga".to_string()), "p@ss".to_string())),
+                db: Some(3)
+            }
+        );
+        assert!(parse_url("http://localhost").is_err());
+        Ok(())
+    }
+}
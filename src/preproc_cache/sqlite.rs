@@ -0,0 +1,332 @@
+use super::{
+    current_store, is_stale, now_nanos, train_dictionary, AdapterVersions, CacheOptions,
+    PreprocCache, StoreStats, DICT_MAX_SAMPLES, DICT_SAMPLE_MAX_LEN,
+};
+use crate::{print_bytes, print_dur};
+use anyhow::{Context, Result};
+use log::*;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, TransactionBehavior, NO_PARAMS};
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// the name of the database file in the cache directory
+pub const DB_FILE: &str = "cache.sqlite3";
+
+/// opens the database and creates the table if needed. the stores of the lmdb cache are the
+/// `store` column here, with the time it was last read or written (in nanoseconds) next to every entry
+pub fn open_db(app_cache: &Path) -> Result<Connection> {
+    std::fs::create_dir_all(app_cache)?;
+    let file = app_cache.join(DB_FILE);
+    let conn = Connection::open(&file)
+        .with_context(|| format!("could not open cache in {}", file.display()))?;
+    // rg runs one rga-preproc per file in parallel, so writers have to wait for each other.
+    // the default rollback journal is kept since WAL does not work on network file systems
+    conn.busy_timeout(Duration::from_secs(30))?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS cache (
+            store TEXT NOT NULL,
+            key BLOB NOT NULL,
+            value BLOB NOT NULL,
+            last_used INTEGER NOT NULL,
+            PRIMARY KEY (store, key)
+        ) WITHOUT ROWID;
+        CREATE INDEX IF NOT EXISTS cache_last_used ON cache (last_used);
+        CREATE TABLE IF NOT EXISTS dictionaries (
+            store TEXT PRIMARY KEY,
+            dictionary BLOB NOT NULL
+        );",
+    )?;
+    Ok(conn)
+}
+
+/// the cache of someone else, or on a read-only file system. the tables are not created
+fn open_db_read_only(app_cache: &Path) -> Result<Connection> {
+    let file = app_cache.join(DB_FILE);
+    let conn = Connection::open_with_flags(&file, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("could not open cache in {}", file.display()))?;
+    conn.busy_timeout(Duration::from_secs(30))?;
+    Ok(conn)
+}
+
+pub struct SqliteCache {
+    // the connection is not Sync
+    conn: Mutex<Connection>,
+    options: CacheOptions,
+}
+
+impl SqliteCache {
+    pub fn open(app_cache: &Path, options: CacheOptions) -> Result<SqliteCache> {
+        let conn = if options.read_only {
+            open_db_read_only(app_cache)?
+        } else {
+            open_db(app_cache)?
+        };
+        Ok(SqliteCache {
+            conn: Mutex::new(conn),
+            options,
+        })
+    }
+}
+
+/// same as the lmdb eviction: the expired entries, then the least recently used ones until
+/// the cache is below 90% of max_total
+fn evict(conn: &mut Connection, max_total: Option<usize>, max_age: Option<Duration>) -> Result<()> {
+    let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if let Some(max_age) = max_age {
+        let oldest_allowed = now_nanos().saturating_sub(max_age.as_nanos() as u64);
+        let expired = txn.execute(
+            "DELETE FROM cache WHERE last_used < ?1",
+            params![oldest_allowed as i64],
+        )?;
+        debug!("deleted {} expired entries from the cache", expired);
+    }
+    if let Some(max_total) = max_total {
+        let mut total = txn.query_row(
+            "SELECT coalesce(sum(length(value)), 0) FROM cache",
+            NO_PARAMS,
+            |row| row.get::<_, i64>(0),
+        )? as usize;
+        if total > max_total {
+            let target = max_total / 10 * 9;
+            let mut evicted = Vec::new();
+            {
+                let mut stmt =
+                    txn.prepare("SELECT store, key, length(value) FROM cache ORDER BY last_used")?;
+                let mut rows = stmt.query(NO_PARAMS)?;
+                while let Some(row) = rows.next()? {
+                    if total <= target {
+                        break;
+                    }
+                    total -= row.get::<_, i64>(2)? as usize;
+                    evicted.push((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?));
+                }
+            }
+            debug!("evicting {} entries from the cache", evicted.len());
+            for (store, key) in evicted {
+                txn.execute(
+                    "DELETE FROM cache WHERE store = ?1 AND key = ?2",
+                    params![store, key],
+                )?;
+            }
+        }
+    }
+    txn.commit()?;
+    Ok(())
+}
+
+impl PreprocCache for SqliteCache {
+    fn get_or_run<'a>(
+        &mut self,
+        db_name: &str,
+        key: &[u8],
+        adapter_name: &str,
+        runner: Box<dyn FnOnce() -> Result<Option<Vec<u8>>> + 'a>,
+        callback: Box<dyn FnOnce(&[u8]) -> Result<()> + 'a>,
+    ) -> Result<()> {
+        let start = Instant::now();
+        let conn = self.conn.get_mut().unwrap();
+        let cached: Option<(Vec<u8>, i64)> = conn
+            .query_row(
+                "SELECT value, last_used FROM cache WHERE store = ?1 AND key = ?2",
+                params![db_name, key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .context("could not read from cache")?;
+        let now = now_nanos() as i64;
+        let limited = self.options.max_total.is_some() || self.options.max_age.is_some();
+        match cached {
+            Some((cached, last_used))
+                if self.options.max_age.is_none_or(|max_age| {
+                    now.saturating_sub(last_used) as u128 <= max_age.as_nanos()
+                }) =>
+            {
+                debug!(
+                    "cache HIT, reading {} (compressed) from cache",
+                    print_bytes(cached.len() as f64)
+                );
+                debug!("reading from cache took {}", print_dur(start));
+                callback(&cached)?;
+                // only needed for eviction, so to not make every read a write otherwise
+                if limited && !self.options.read_only {
+                    conn.execute(
+                        "UPDATE cache SET last_used = ?1 WHERE store = ?2 AND key = ?3",
+                        params![now, db_name, key],
+                    )
+                    .context("could not write to cache")?;
+                }
+            }
+            cached => {
+                if cached.is_some() {
+                    debug!("cache entry EXPIRED, running adapter");
+                } else {
+                    debug!("cache MISS, running adapter");
+                }
+                let runner_res = runner()?;
+                debug!("running adapter {} took {}", adapter_name, print_dur(start));
+                let start = Instant::now();
+                if self.options.read_only {
+                    debug!("read-only cache, not caching output");
+                } else if let Some(got) = runner_res {
+                    debug!("writing {} to cache", print_bytes(got.len() as f64));
+                    conn.execute(
+                        "INSERT OR REPLACE INTO cache (store, key, value, last_used) VALUES (?1, ?2, ?3, ?4)",
+                        params![db_name, key, got, now],
+                    )
+                    .context("could not write to cache")?;
+                    debug!("writing to cache took {}", print_dur(start));
+                    if limited {
+                        evict(conn, self.options.max_total, self.options.max_age)
+                            .context("could not evict from cache")?;
+                    }
+                } else {
+                    debug!("not caching output");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn dictionary(&mut self, db_name: &str) -> Result<Option<Vec<u8>>> {
+        let conn = self.conn.get_mut().unwrap();
+        let get = |conn: &Connection| {
+            conn.query_row(
+                "SELECT dictionary FROM dictionaries WHERE store = ?1",
+                params![db_name],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+        };
+        if let Some(dictionary) = get(conn)? {
+            return Ok(Some(dictionary));
+        }
+        if !self.options.zstd_dict || self.options.read_only {
+            return Ok(None);
+        }
+        let samples = conn
+            .prepare("SELECT value FROM cache WHERE store = ?1 AND length(value) <= ?2 LIMIT ?3")?
+            .query_map(
+                params![db_name, DICT_SAMPLE_MAX_LEN as i64, DICT_MAX_SAMPLES as i64],
+                |row| row.get::<_, Vec<u8>>(0),
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let dictionary = match train_dictionary(&samples) {
+            Some(dictionary) => dictionary,
+            None => return Ok(None),
+        };
+        // another rga process might have trained one in the meantime, which its entries are compressed with
+        conn.execute(
+            "INSERT OR IGNORE INTO dictionaries (store, dictionary) VALUES (?1, ?2)",
+            params![db_name, dictionary],
+        )
+        .context("could not write to cache")?;
+        Ok(get(conn)?)
+    }
+}
+
+pub fn store_stats(conn: &Connection) -> Result<Vec<StoreStats>> {
+    let mut stmt = conn.prepare(
+        "SELECT store, count(*), sum(length(value)) FROM cache GROUP BY store ORDER BY 3 DESC",
+    )?;
+    let stats = stmt
+        .query_map(NO_PARAMS, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)? as usize,
+                row.get::<_, i64>(2)? as usize,
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(stats)
+}
+
+/// deletes the stale entries, returns (entries, bytes)
+pub fn prune(conn: &mut Connection, versions: &AdapterVersions) -> Result<(usize, usize)> {
+    let txn = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let mut stale = Vec::new();
+    let mut bytes = 0;
+    {
+        let mut stmt = txn.prepare("SELECT store, key, length(value) FROM cache")?;
+        let mut rows = stmt.query(NO_PARAMS)?;
+        while let Some(row) = rows.next()? {
+            let (store, key) = (row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?);
+            if !current_store(&store, versions) || is_stale(&key, versions) {
+                bytes += row.get::<_, i64>(2)? as usize;
+                stale.push((store, key));
+            }
+        }
+    }
+    for (store, key) in &stale {
+        txn.execute(
+            "DELETE FROM cache WHERE store = ?1 AND key = ?2",
+            params![store, key],
+        )?;
+    }
+    let dictionaries = txn
+        .prepare("SELECT store FROM dictionaries")?
+        .query_map(NO_PARAMS, |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for store in dictionaries {
+        if !current_store(&store, versions) {
+            txn.execute("DELETE FROM dictionaries WHERE store = ?1", params![store])?;
+        }
+    }
+    txn.commit()?;
+    Ok((stale.len(), bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqlite_cache() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut cache = SqliteCache::open(
+            dir.path(),
+            CacheOptions {
+                max_total: Some(100),
+                ..Default::default()
+            },
+        )?;
+        // whether the adapter had to run
+        let mut get = |key: &str| -> Result<bool> {
+            let mut ran = false;
+            cache.get_or_run(
+                "test.v1",
+                key.as_bytes(),
+                "test",
+                Box::new(|| {
+                    ran = true;
+                    Ok(Some(vec![0; 40]))
+                }),
+                Box::new(|cached| {
+                    assert_eq!(cached, &[0; 40][..]);
+                    Ok(())
+                }),
+            )?;
+            Ok(ran)
+        };
+        assert!(get("a")?);
+        assert!(!get("a")?);
+        assert!(get("b")?);
+        assert!(!get("a")?);
+        // over the limit, b was used least recently
+        assert!(get("c")?);
+        assert!(!get("a")?);
+        assert!(!get("c")?);
+        assert!(get("b")?);
+
+        let stats = store_stats(&open_db(dir.path())?)?;
+        assert_eq!(stats, vec![("test.v1".to_string(), 2, 80)]);
+        // the store of an adapter that no longer exists
+        assert_eq!(
+            prune(&mut open_db(dir.path())?, &AdapterVersions::new())?,
+            (2, 80)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! the pages, sheets and archive members that the output of an adapter comes from, stored next to the
+//! output in the cache so that e.g. a PDF can be opened at the page of a match without adapting it again.
+//!
+//! they are found from the line prefixes of the output: `Page 3: ` (pdf, djvu, comics, OCR),
+//! `Sheet1!B2: ` (spreadsheets), each after the `member: ` prefixes of the archives the file is in.
+//! lines of archive members without a page or sheet get the location of the member. the members are
+//! recognized by their file extension or directory, so `report.pdf: ` is one but `Subject: ` is not
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+
+/// a range of the output that comes from one location in the file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Section {
+    pub start: u64,
+    pub end: u64,
+    /// e.g. `Page 3`, `Sheet1` or `docs/report.pdf: Page 3`
+    pub location: String,
+}
+
+/// the sections are a zstd skippable frame after the output in a cache entry, so that zstd
+/// decoders ignore them. the frame ends with the length of the sections and SECTIONS_MAGIC
+const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D_2A51;
+const SECTIONS_MAGIC: u32 = 0x7267_6173;
+/// only the start of a line is needed to find its location
+const MAX_PREFIX_LEN: usize = 512;
+
+/// a line prefix segment that looks like the path of an archive member, e.g. `docs/report.pdf` or `bin/ls`
+fn is_member(segment: &[u8]) -> bool {
+    if segment.first().is_none_or(u8::is_ascii_whitespace) {
+        return false;
+    }
+    let extension = match memchr::memrchr(b'.', segment) {
+        Some(dot) if dot > 0 && !segment[dot - 1].is_ascii_whitespace() => &segment[dot + 1..],
+        _ => &[],
+    };
+    (extension.len() <= 8
+        && extension.iter().all(u8::is_ascii_alphanumeric)
+        && extension.iter().any(u8::is_ascii_alphabetic))
+        || (segment.contains(&b'/') && !segment.iter().any(u8::is_ascii_whitespace))
+}
+
+/// the location of a line: the prefix up to the first page or sheet in it,
+/// otherwise the archive members it starts with
+fn location(line: &[u8]) -> Option<&[u8]> {
+    let mut start = 0;
+    let mut members = None;
+    let mut in_members = true;
+    while let Some(len) = memchr::memmem::find(&line[start..], b": ") {
+        let segment = &line[start..start + len];
+        if let Some(page) = segment.strip_prefix(b"Page ") {
+            if !page.is_empty() && page.iter().all(u8::is_ascii_digit) {
+                return Some(&line[..start + len]);
+            }
+        }
+        // Sheet1!B2
+        if let Some(bang) = memchr::memrchr(b'!', segment) {
+            let cell = &segment[bang + 1..];
+            let letters = cell.iter().take_while(|c| c.is_ascii_uppercase()).count();
+            if bang > 0
+                && letters > 0
+                && cell.len() > letters
+                && cell[letters..].iter().all(u8::is_ascii_digit)
+            {
+                return Some(&line[..start + bang]);
+            }
+        }
+        if in_members && is_member(segment) {
+            members = Some(start + len);
+        } else {
+            in_members = false;
+        }
+        start += len + 2;
+    }
+    members.map(|end| &line[..end])
+}
+
+/// finds the sections in the output while it is written
+#[derive(Default)]
+pub(crate) struct SectionTracker {
+    sections: Vec<Section>,
+    /// the start of the current line, up to MAX_PREFIX_LEN
+    line: Vec<u8>,
+    line_start: u64,
+    written: u64,
+}
+
+impl SectionTracker {
+    pub fn write(&mut self, mut buf: &[u8]) {
+        while !buf.is_empty() {
+            let (part, newline) = match memchr::memchr(b'\n', buf) {
+                Some(i) => (&buf[..=i], true),
+                None => (buf, false),
+            };
+            let room = MAX_PREFIX_LEN - self.line.len();
+            self.line.extend_from_slice(&part[..part.len().min(room)]);
+            self.written += part.len() as u64;
+            if newline {
+                self.end_line();
+            }
+            buf = &buf[part.len()..];
+        }
+    }
+
+    fn end_line(&mut self) {
+        if let Some(location) = location(&self.line) {
+            match self.sections.last_mut() {
+                Some(last)
+                    if last.end == self.line_start && last.location.as_bytes() == location =>
+                {
+                    last.end = self.written
+                }
+                _ => self.sections.push(Section {
+                    start: self.line_start,
+                    end: self.written,
+                    location: String::from_utf8_lossy(location).into_owned(),
+                }),
+            }
+        }
+        self.line.clear();
+        self.line_start = self.written;
+    }
+
+    /// the skippable frame to append to the cache entry, if there are any sections
+    pub fn finish(mut self) -> Result<Option<Vec<u8>>> {
+        if !self.line.is_empty() {
+            self.end_line();
+        }
+        if self.sections.is_empty() {
+            return Ok(None);
+        }
+        let sections = bincode::serialize(&self.sections)?;
+        let mut frame = Vec::with_capacity(sections.len() + 16);
+        frame.extend_from_slice(&SKIPPABLE_FRAME_MAGIC.to_le_bytes());
+        frame.extend_from_slice(&(sections.len() as u32 + 8).to_le_bytes());
+        frame.extend_from_slice(&sections);
+        frame.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&SECTIONS_MAGIC.to_le_bytes());
+        Ok(Some(frame))
+    }
+}
+
+/// splits a cache entry into the compressed output and the encoded sections
+pub(crate) fn split(entry: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let split = || -> Option<(&[u8], &[u8])> {
+        let footer = entry.len().checked_sub(8)?;
+        if entry[footer + 4..] != SECTIONS_MAGIC.to_le_bytes() {
+            return None;
+        }
+        let len = u32::from_le_bytes(entry[footer..footer + 4].try_into().ok()?) as usize;
+        let header = footer.checked_sub(len)?.checked_sub(8)?;
+        if entry[header..header + 4] != SKIPPABLE_FRAME_MAGIC.to_le_bytes() {
+            return None;
+        }
+        Some((&entry[..header], &entry[header + 8..footer]))
+    };
+    match split() {
+        Some((output, sections)) => (output, Some(sections)),
+        None => (entry, None),
+    }
+}
+
+/// the sections of a cache entry. empty if it has none, e.g. because it was written before they were added
+pub fn read(entry: &[u8]) -> Result<Vec<Section>> {
+    match split(entry).1 {
+        Some(sections) => Ok(bincode::deserialize(sections)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections() -> Result<()> {
+        let output = "Page 1: intro\nPage 1: more\nPage 2: Note: a comment\n\
+                      report.pdf: Page 1: in an archive\nno location\nSheet1!A1: x\nSheet 2!AB10: y\n\
+                      docs/a.txt: b.db: Note: nested\ndocs/a.txt: b.db: more\nbin/ls: binary\nv1.2: no member";
+        let mut tracker = SectionTracker::default();
+        // split in the middle of lines
+        for chunk in output.as_bytes().chunks(5) {
+            tracker.write(chunk);
+        }
+        let mut entry = zstd::stream::encode_all(output.as_bytes(), 3)?;
+        entry.extend(tracker.finish()?.unwrap());
+        // other zstd decoders skip them
+        assert_eq!(zstd::stream::decode_all(&entry[..])?, output.as_bytes());
+
+        let sections = read(&entry)?;
+        let locations: Vec<(&str, &str)> = sections
+            .iter()
+            .map(|s| (&s.location[..], &output[s.start as usize..s.end as usize]))
+            .collect();
+        assert_eq!(
+            locations,
+            [
+                ("Page 1", "Page 1: intro\nPage 1: more\n"),
+                ("Page 2", "Page 2: Note: a comment\n"),
+                ("report.pdf: Page 1", "report.pdf: Page 1: in an archive\n"),
+                ("Sheet1", "Sheet1!A1: x\n"),
+                ("Sheet 2", "Sheet 2!AB10: y\n"),
+                (
+                    "docs/a.txt: b.db",
+                    "docs/a.txt: b.db: Note: nested\ndocs/a.txt: b.db: more\n"
+                ),
+                ("bin/ls", "bin/ls: binary\n"),
+            ]
+        );
+
+        let mut tracker = SectionTracker::default();
+        tracker.write(b"plain text: without locations\nSubject: e.g.: hi\n");
+        assert!(tracker.finish()?.is_none());
+        assert!(read(&zstd::stream::encode_all(&b"x"[..], 3)?)?.is_empty());
+        Ok(())
+    }
+}
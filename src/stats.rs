@@ -0,0 +1,161 @@
+//! --rga-stats: every rga-preproc appends what it did to a file, which rga sums up once rg is done
+
+use crate::{print_bytes, print_duration};
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+    time::Duration,
+};
+
+/// the env var with the file that rga-preproc writes its stats to
+pub const STATS_FILE: &str = "RGA_STATS_FILE";
+
+/// what rga-preproc did with one file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileStats {
+    /// None if no adapter matched
+    pub adapter: Option<String>,
+    pub cached: bool,
+    pub duration: Duration,
+    /// the size of the output
+    pub bytes: u64,
+}
+
+lazy_static! {
+    /// the adapter of the file that rga-preproc was called with, and whether its output came from the cache
+    static ref CURRENT: Mutex<(Option<String>, bool)> = Mutex::new((None, false));
+}
+
+/// called by rga_preproc for the file itself, not the ones within archives
+pub fn record_adapter(name: &str) {
+    CURRENT.lock().unwrap().0 = Some(name.to_string());
+}
+
+pub fn record_cache_hit() {
+    CURRENT.lock().unwrap().1 = true;
+}
+
+/// appends the stats of the file that was preprocessed to the stats file, if rga set one
+pub fn write(duration: Duration, bytes: u64) -> Result<()> {
+    let file = match std::env::var_os(STATS_FILE) {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+    let (adapter, cached) = CURRENT.lock().unwrap().clone();
+    let mut line = serde_json::to_vec(&FileStats {
+        adapter,
+        cached,
+        duration,
+        bytes,
+    })?;
+    line.push(b'\n');
+    // in one write, so that the lines of the rga-preproc processes running in parallel are not mixed up
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&file)
+        .and_then(|mut f| f.write_all(&line))
+        .with_context(|| format!("could not write stats to {:?}", file))?;
+    Ok(())
+}
+
+#[derive(Default)]
+struct AdapterStats {
+    files: usize,
+    cached: usize,
+    duration: Duration,
+    bytes: u64,
+}
+
+/// the table printed after a search with --rga-stats
+pub fn summary(stats: impl Iterator<Item = FileStats>) -> String {
+    let mut adapters = BTreeMap::<Option<String>, AdapterStats>::new();
+    for file in stats {
+        let adapter = adapters.entry(file.adapter).or_default();
+        adapter.files += 1;
+        adapter.cached += file.cached as usize;
+        adapter.duration += file.duration;
+        adapter.bytes += file.bytes;
+    }
+    let files: usize = adapters.values().map(|a| a.files).sum();
+    let cached: usize = adapters.values().map(|a| a.cached).sum();
+    let mut out = format!(
+        "rga: preprocessed {} files, {} from the cache, {} adapted\n",
+        files,
+        cached,
+        files - cached
+    );
+    writeln!(
+        out,
+        "{:<16} {:>8} {:>10} {:>10} {:>12}",
+        "adapter", "files", "cached", "time", "output"
+    )
+    .unwrap();
+    for (name, adapter) in adapters {
+        writeln!(
+            out,
+            "{:<16} {:>8} {:>10} {:>10} {:>12}",
+            name.as_deref().unwrap_or("(none)"),
+            adapter.files,
+            adapter.cached,
+            print_duration(adapter.duration),
+            print_bytes(adapter.bytes as f64)
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// reads the stats file written by the rga-preproc processes
+pub fn read(file: &Path) -> Result<Vec<FileStats>> {
+    let file = match std::fs::File::open(file) {
+        Ok(file) => file,
+        // no file was preprocessed
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("could not read stats"),
+    };
+    BufReader::new(file)
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_per_adapter() {
+        let file = |adapter: &str, cached, ms, bytes| FileStats {
+            adapter: Some(adapter.to_string()),
+            cached,
+            duration: Duration::from_millis(ms),
+            bytes,
+        };
+        let summary = summary(
+            vec![
+                file("poppler", false, 1500, 1000),
+                file("poppler", true, 500, 3000),
+                file("zip", false, 20, 10),
+            ]
+            .into_iter(),
+        );
+        let lines: Vec<Vec<&str>> = summary
+            .lines()
+            .map(|l| l.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            summary.lines().next(),
+            Some("rga: preprocessed 3 files, 1 from the cache, 2 adapted")
+        );
+        assert_eq!(lines[2], ["poppler", "2", "1", "2.00s", "4", "kB"]);
+        assert_eq!(lines[3], ["zip", "1", "0", "20.0ms", "10", "B"]);
+    }
+}
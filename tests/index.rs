@@ -0,0 +1,161 @@
+//! runs the rga binaries against a cache in a temp dir. rg is replaced by a script that lists all files,
+//! and the files are adapted by a custom adapter that counts how often it runs
+#![cfg(unix)]
+
+use anyhow::Result;
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+struct Env {
+    dir: tempfile::TempDir,
+}
+
+impl Env {
+    fn new() -> Result<Env> {
+        let env = Env {
+            dir: tempfile::tempdir()?,
+        };
+        std::fs::create_dir(env.data())?;
+        std::fs::create_dir(env.path("bin"))?;
+        let rg = env.path("bin/rg");
+        // `rg --files --null ... DIR`
+        std::fs::write(
+            &rg,
+            "#!/bin/sh\nfor dir; do :; done\nfind \"$dir\" -type f -print0\n",
+        )?;
+        std::fs::set_permissions(&rg, std::fs::Permissions::from_mode(0o755))?;
+        Ok(env)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn data(&self) -> PathBuf {
+        self.path("data")
+    }
+
+    fn runs(&self) -> usize {
+        std::fs::read_to_string(self.path("runs")).map_or(0, |runs| runs.lines().count())
+    }
+
+    fn command(&self, exe: &str) -> Command {
+        let config = serde_json::json!({
+            "custom_adapters": [{
+                "name": "counting",
+                "description": "outputs its input",
+                "version": 1,
+                "extensions": ["counted"],
+                "binary": "sh",
+                "args": ["-c", r#"echo >> "$0"; cat > "$0.in"; cat "$0.in""#, self.path("runs")],
+            }]
+        });
+        let path = std::env::join_paths(std::iter::once(self.path("bin")).chain(
+            std::env::split_paths(&std::env::var_os("PATH").unwrap_or_default()),
+        ))
+        .unwrap();
+        let mut cmd = Command::new(exe);
+        cmd.env("PATH", path)
+            .env("XDG_CACHE_HOME", self.path("cache"))
+            .env("XDG_CONFIG_HOME", self.path("config"))
+            .env("RGA_CONFIG", config.to_string())
+            .env_remove("RGA_STATS_FILE");
+        cmd
+    }
+
+    fn preproc(&self, file: &Path) -> Result<Output> {
+        let output = self
+            .command(env!("CARGO_BIN_EXE_rga-preproc"))
+            .arg(file)
+            .output()?;
+        assert!(output.status.success(), "{:?}", output);
+        Ok(output)
+    }
+}
+
+#[test]
+fn index() -> Result<()> {
+    let env = Env::new()?;
+    let file = env.data().join("a.counted");
+    std::fs::write(&file, "hello\n")?;
+    let output = env
+        .command(env!("CARGO_BIN_EXE_rga"))
+        .arg(format!("--rga-index={}", env.data().display()))
+        .output()?;
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8(output.stdout)?.starts_with("indexed 1 files"));
+    assert_eq!(env.runs(), 1);
+    // from the cache
+    assert_eq!(env.preproc(&file)?.stdout, b"hello\n");
+    assert_eq!(env.runs(), 1);
+    Ok(())
+}
+
+#[test]
+fn watch() -> Result<()> {
+    let env = Env::new()?;
+    std::fs::write(env.data().join("a.counted"), "a\n")?;
+    let mut child = env
+        .command(env!("CARGO_BIN_EXE_rga"))
+        .arg(format!("--rga-watch={}", env.data().display()))
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+    // rga prints a line every time it is done indexing
+    let (sender, indexed) = std::sync::mpsc::channel();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    std::thread::spawn(move || {
+        for line in stdout.lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let timeout = std::time::Duration::from_secs(30);
+    let res = (|| -> Result<()> {
+        assert!(indexed
+            .recv_timeout(timeout)??
+            .starts_with("indexed 1 files"));
+        assert_eq!(env.runs(), 1);
+        let file = env.data().join("b.counted");
+        std::fs::write(&file, "b\n")?;
+        assert!(indexed
+            .recv_timeout(timeout)??
+            .starts_with("indexed 1 files"));
+        assert_eq!(env.runs(), 2);
+        // from the cache
+        assert_eq!(env.preproc(&file)?.stdout, b"b\n");
+        assert_eq!(env.runs(), 2);
+        Ok(())
+    })();
+    child.kill()?;
+    child.wait()?;
+    res
+}
+
+#[test]
+fn stats() -> Result<()> {
+    let env = Env::new()?;
+    let file = env.data().join("a.counted");
+    std::fs::write(&file, "hello\n")?;
+    let stats_file = env.path("stats");
+    for _ in 0..2 {
+        let output = env
+            .command(env!("CARGO_BIN_EXE_rga-preproc"))
+            .env(ripgrep_all::stats::STATS_FILE, &stats_file)
+            .arg(&file)
+            .output()?;
+        assert!(output.status.success(), "{:?}", output);
+    }
+    let stats = ripgrep_all::stats::read(&stats_file)?;
+    let stats: Vec<_> = stats
+        .iter()
+        .map(|s| (s.adapter.as_deref(), s.cached, s.bytes))
+        .collect();
+    assert_eq!(
+        stats,
+        [(Some("counting"), false, 6), (Some("counting"), true, 6)]
+    );
+    Ok(())
+}